@@ -0,0 +1,133 @@
+use std::collections::{HashMap, VecDeque};
+
+use error::Result;
+use nfa::NfaState;
+
+pub struct DfaState {
+    pub trans: Vec<Option<(usize, bool)>>,
+    pub accept: Option<usize>,
+}
+
+pub fn epsilon_closure(nfa: &[NfaState], set: &mut Vec<usize>) {
+    let mut seen = vec![false; nfa.len()];
+    for &s in set.iter() {
+        seen[s] = true;
+    }
+
+    let mut stack = set.clone();
+    while let Some(s) = stack.pop() {
+        for &t in &nfa[s].epsilon {
+            if !seen[t] {
+                seen[t] = true;
+                set.push(t);
+                stack.push(t);
+            }
+        }
+    }
+    set.sort();
+}
+
+pub fn build_dfa(nfa: &[NfaState]) -> Result<Vec<DfaState>> {
+    let mut start = vec![0];
+    epsilon_closure(nfa, &mut start);
+
+    let mut index = HashMap::new();
+    let mut sets = vec![start.clone()];
+    let mut queue = VecDeque::new();
+    index.insert(start, 0);
+    queue.push_back(0);
+
+    let mut dfa = Vec::new();
+    while let Some(i) = queue.pop_front() {
+        let set = sets[i].clone();
+        let mut targets: Vec<Vec<usize>> = vec![Vec::new(); 256];
+        let mut voids = vec![(false, false); 256];
+
+        for &s in &set {
+            for edge in &nfa[s].edges {
+                for c in edge.bytes.iter() {
+                    let c = c as usize;
+                    if !targets[c].contains(&edge.target) {
+                        targets[c].push(edge.target);
+                    }
+                    if edge.void {
+                        voids[c].0 = true;
+                    } else {
+                        voids[c].1 = true;
+                    }
+                }
+            }
+        }
+
+        let mut trans = vec![None; 256];
+        for (c, mut target) in targets.into_iter().enumerate() {
+            if target.is_empty() {
+                continue;
+            }
+            if voids[c] == (true, true) {
+                custom_error!("Byte '{}' is both void and kept in the same position",
+                              (c as u8 as char).escape_default());
+            }
+
+            epsilon_closure(nfa, &mut target);
+            let next = match index.get(&target) {
+                Some(&next) => next,
+                None => {
+                    let next = sets.len();
+                    sets.push(target.clone());
+                    queue.push_back(next);
+                    index.insert(target, next);
+                    next
+                }
+            };
+            trans[c] = Some((next, voids[c].0));
+        }
+
+        dfa.push(DfaState {
+            trans,
+            accept: set.iter().filter_map(|&s| nfa[s].accept).min(),
+        });
+    }
+
+    Ok(dfa)
+}
+
+/// Merges equivalent states by partition refinement. State 0 stays the start
+/// state.
+pub fn minimize_dfa(dfa: Vec<DfaState>) -> Vec<DfaState> {
+    let mut class: Vec<usize> = Vec::new();
+    let mut class_count = 0;
+    let mut keys: HashMap<Vec<Option<(usize, bool)>>, usize> = HashMap::new();
+
+    loop {
+        let mut next_class = Vec::with_capacity(dfa.len());
+        keys.clear();
+        for state in &dfa {
+            let mut key = vec![state.accept.map(|a| (a, false))];
+            if !class.is_empty() {
+                key.extend(state.trans.iter().map(|t| t.map(|(next, void)| (class[next], void))));
+            }
+            let len = keys.len();
+            next_class.push(*keys.entry(key).or_insert(len));
+        }
+
+        let done = keys.len() == class_count;
+        class_count = keys.len();
+        class = next_class;
+        if done {
+            break;
+        }
+    }
+
+    let mut minimal: Vec<Option<DfaState>> = (0..class_count).map(|_| None).collect();
+    for (i, state) in dfa.into_iter().enumerate() {
+        if minimal[class[i]].is_none() {
+            minimal[class[i]] = Some(DfaState {
+                trans: state.trans.iter().map(|t| t.map(|(next, void)| (class[next], void))).collect(),
+                accept: state.accept,
+            });
+        }
+    }
+    minimal.into_iter().map(Option::unwrap).collect()
+}
+
@@ -0,0 +1,69 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::Error as IoError;
+use std::result::Result as StdResult;
+use std::string::FromUtf8Error;
+
+#[derive(Debug)]
+pub enum Error {
+    Custom(String),
+    Wrap(Box<dyn StdError>),
+}
+
+pub type Result<T> = StdResult<T, Error>;
+
+macro_rules! custom_error {
+    ( $( $vals: expr ),* ) => {
+        return Err(::error::Error::Custom(format!($( $vals ),*)))
+    };
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Custom(ref s) => f.write_str(s),
+            Error::Wrap(ref b) => b.fmt(f),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn cause(&self) -> Option<&dyn StdError> {
+        match *self {
+            Error::Custom(_) => None,
+            Error::Wrap(ref b) => Some(b.as_ref()),
+        }
+    }
+}
+
+impl From<Box<dyn StdError>> for Error {
+    fn from(x: Box<dyn StdError>) -> Error {
+        Error::Wrap(x)
+    }
+}
+
+macro_rules! impl_from_wrap {
+    ( $( $ty:ty ),* ) => {
+        $(
+            impl From<$ty> for Error {
+                fn from(x: $ty) -> Error {
+                    Error::Wrap(Box::new(x))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_wrap!(IoError, FromUtf8Error);
+
+impl From<String> for Error {
+    fn from(x: String) -> Error {
+        Error::Custom(x)
+    }
+}
+
+impl<'a> From<&'a str> for Error {
+    fn from(x: &'a str) -> Error {
+        Error::Custom(x.to_owned())
+    }
+}
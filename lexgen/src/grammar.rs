@@ -0,0 +1,519 @@
+use std::iter::Peekable;
+
+use regex_syntax::ParserBuilder;
+use regex_syntax::hir::{Class, HirKind};
+
+use error::Result;
+
+/// A set of bytes, used for character classes and transition labels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ByteSet([u64; 4]);
+
+impl ByteSet {
+    pub fn empty() -> ByteSet {
+        ByteSet([0; 4])
+    }
+
+    pub fn single(c: u8) -> ByteSet {
+        let mut set = ByteSet::empty();
+        set.insert(c);
+        set
+    }
+
+    pub fn insert(&mut self, c: u8) {
+        self.0[(c >> 6) as usize] |= 1 << (c & 63);
+    }
+
+    pub fn insert_range(&mut self, lo: u8, hi: u8) {
+        for c in lo as u16..hi as u16 + 1 {
+            self.insert(c as u8);
+        }
+    }
+
+    pub fn contains(&self, c: u8) -> bool {
+        self.0[(c >> 6) as usize] & (1 << (c & 63)) != 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=u8> + '_ {
+        (0..4).flat_map(move |word| {
+            let bits = self.0[word];
+            (0..64).filter(move |bit| bits & (1 << bit) != 0).map(move |bit| (word * 64 + bit) as u8)
+        })
+    }
+
+    pub fn union(&self, other: &ByteSet) -> ByteSet {
+        ByteSet([self.0[0] | other.0[0], self.0[1] | other.0[1],
+                 self.0[2] | other.0[2], self.0[3] | other.0[3]])
+    }
+
+    pub fn negate(&self) -> ByteSet {
+        ByteSet([!self.0[0], !self.0[1], !self.0[2], !self.0[3]])
+    }
+}
+
+/// A parsed `tokens` file.
+pub struct Grammar {
+    pub productions: Vec<Production>,
+    pub whitespace: Vec<ProductionInput>,
+    pub delimiters: Vec<ProductionInput>,
+}
+
+pub struct Production {
+    pub name: String,
+    pub param: Option<String>,
+    pub is_token: bool,
+    pub inputs: Vec<ProductionInput>,
+}
+
+pub struct ProductionInput {
+    pub output: Option<String>,
+    pub format: Vec<FormatPart>,
+}
+
+pub struct FormatPart {
+    pub attrs: Vec<MatcherAttribute>,
+    pub pattern: FormatPattern,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatcherAttribute {
+    Many,
+    Many1,
+    Optional,
+    Void,
+}
+
+pub enum FormatPattern {
+    Production(String),
+    Literal(Vec<u8>),
+    Class(ByteSet),
+    /// Code point ranges, matched as their UTF-8 encodings.
+    Unicode(Vec<(char, char)>),
+    Group(Vec<Vec<FormatPart>>),
+}
+
+/// A lexical item of the right-hand side of a production.
+enum Word {
+    Bare(Vec<u8>),
+    Quoted(Vec<u8>),
+    Class(ByteSet),
+    Unicode(Vec<(char, char)>),
+    Open,
+    Close,
+    Bar,
+    Arrow,
+    Suffix(MatcherAttribute),
+}
+
+fn suffix_attr(c: u8) -> Option<MatcherAttribute> {
+    match c {
+        b'*' => Some(MatcherAttribute::Many),
+        b'+' => Some(MatcherAttribute::Many1),
+        b'?' => Some(MatcherAttribute::Optional),
+        b'_' => Some(MatcherAttribute::Void),
+        _ => None,
+    }
+}
+
+fn is_special(c: u8) -> bool {
+    match c {
+        b'(' | b')' | b'|' | b'[' | b']' | b'"' => true,
+        _ => (c as char).is_whitespace(),
+    }
+}
+
+fn read_escape(src: &[u8], i: &mut usize) -> Result<u8> {
+    let c = match src.get(*i) {
+        Some(&c) => c,
+        None => custom_error!("Unterminated escape sequence"),
+    };
+    *i += 1;
+
+    Ok(match c {
+        b'n' => b'\n',
+        b't' => b'\t',
+        b'r' => b'\r',
+        b'v' => 0x0b,
+        b'f' => 0x0c,
+        b'0' => 0,
+        b'x' => {
+            if *i + 2 > src.len() {
+                custom_error!("Truncated \\x escape");
+            }
+            let hex = String::from_utf8(src[*i..*i + 2].to_owned())?;
+            *i += 2;
+            match u8::from_str_radix(&hex, 16) {
+                Ok(c) => c,
+                Err(_) => custom_error!("Invalid \\x escape '{}'", hex),
+            }
+        }
+        c => c,
+    })
+}
+
+/// Parses a class using Unicode properties, such as `\p{L}` or
+/// `[\p{Po}&&[^\x00-\x7f]]`, with regex syntax.
+fn parse_unicode(text: &[u8]) -> Result<Vec<(char, char)>> {
+    let text = String::from_utf8(text.to_owned())?;
+    let hir = match ParserBuilder::new().build().parse(&text) {
+        Ok(hir) => hir,
+        Err(e) => custom_error!("Invalid Unicode class '{}': {}", text, e),
+    };
+
+    match *hir.kind() {
+        HirKind::Class(Class::Unicode(ref class)) =>
+            Ok(class.ranges().iter().map(|r| (r.start(), r.end())).collect()),
+        _ => custom_error!("'{}' is not a character class", text),
+    }
+}
+
+/// Finds the end of the class starting at `src[start]`, which may nest, and
+/// whether it uses Unicode properties.
+fn scan_class(src: &[u8], start: usize) -> Result<(usize, bool)> {
+    let mut depth = 0;
+    let mut unicode = false;
+    let mut i = start;
+
+    while i < src.len() {
+        match src[i] {
+            b'\\' => {
+                unicode |= src.get(i + 1) == Some(&b'p') || src.get(i + 1) == Some(&b'P');
+                i += 1;
+            }
+            b'[' => depth += 1,
+            b']' if depth == 1 => return Ok((i + 1, unicode)),
+            b']' => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    custom_error!("Unterminated character class")
+}
+
+fn read_class(src: &[u8], i: &mut usize) -> Result<ByteSet> {
+    let mut set = ByteSet::empty();
+    let negated = src.get(*i) == Some(&b'^');
+    if negated {
+        *i += 1;
+    }
+
+    loop {
+        let lo = match src.get(*i) {
+            Some(&b']') => {
+                *i += 1;
+                break;
+            }
+            Some(&b'\\') => {
+                *i += 1;
+                read_escape(src, i)?
+            }
+            Some(&c) => {
+                *i += 1;
+                c
+            }
+            None => custom_error!("Unterminated character class"),
+        };
+
+        if src.get(*i) == Some(&b'-') && src.get(*i + 1).is_some_and(|&c| c != b']') {
+            *i += 1;
+            let hi = match src[*i] {
+                b'\\' => {
+                    *i += 1;
+                    read_escape(src, i)?
+                }
+                c => {
+                    *i += 1;
+                    c
+                }
+            };
+            if hi < lo {
+                custom_error!("Inverted range in character class");
+            }
+            set.insert_range(lo, hi);
+        } else {
+            set.insert(lo);
+        }
+    }
+
+    Ok(if negated { set.negate() } else { set })
+}
+
+fn read_quoted(src: &[u8], i: &mut usize) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        match src.get(*i) {
+            Some(&b'"') => {
+                *i += 1;
+                return Ok(out);
+            }
+            Some(&b'\\') => {
+                *i += 1;
+                out.push(read_escape(src, i)?);
+            }
+            Some(&c) => {
+                *i += 1;
+                out.push(c);
+            }
+            None => custom_error!("Unterminated quoted literal"),
+        }
+    }
+}
+
+fn read_suffixes(src: &[u8], i: &mut usize, words: &mut Vec<Word>) {
+    while let Some(attr) = src.get(*i).and_then(|&c| suffix_attr(c)) {
+        words.push(Word::Suffix(attr));
+        *i += 1;
+    }
+}
+
+fn split_words(src: &[u8]) -> Result<Vec<Word>> {
+    let mut words = Vec::new();
+    let mut i = 0;
+
+    while i < src.len() {
+        let c = src[i];
+        i += 1;
+        match c {
+            c if (c as char).is_whitespace() => {}
+            b'|' => words.push(Word::Bar),
+            b'(' => words.push(Word::Open),
+            b')' => {
+                words.push(Word::Close);
+                read_suffixes(src, &mut i, &mut words);
+            }
+            b'[' => {
+                match scan_class(src, i - 1) {
+                    Ok((end, true)) => {
+                        words.push(Word::Unicode(parse_unicode(&src[i - 1..end])?));
+                        i = end;
+                    }
+                    _ => words.push(Word::Class(read_class(src, &mut i)?)),
+                }
+                read_suffixes(src, &mut i, &mut words);
+            }
+            b'"' => {
+                words.push(Word::Quoted(read_quoted(src, &mut i)?));
+                read_suffixes(src, &mut i, &mut words);
+            }
+            b']' => custom_error!("Unbalanced ']'"),
+            _ => {
+                let start = i - 1;
+                while i < src.len() && !is_special(src[i]) {
+                    i += 1;
+                }
+                let word = &src[start..i];
+                if word == b"=>" {
+                    words.push(Word::Arrow);
+                } else {
+                    words.push(Word::Bare(word.to_owned()));
+                }
+            }
+        }
+    }
+
+    Ok(words)
+}
+
+fn parse_word(word: &[u8]) -> Result<FormatPart> {
+    let mut end = word.len();
+    let mut attrs = Vec::new();
+    while end > 1 {
+        match suffix_attr(word[end - 1]) {
+            Some(attr) => attrs.push(attr),
+            None => break,
+        }
+        end -= 1;
+    }
+    attrs.reverse();
+    let char_fmt = &word[..end];
+
+    let pattern = if char_fmt.len() == 4 && &char_fmt[1..3] == b".." {
+        let mut set = ByteSet::empty();
+        set.insert_range(char_fmt[0], char_fmt[3]);
+        FormatPattern::Class(set)
+    } else if char_fmt.starts_with(b"\\p{") || char_fmt.starts_with(b"\\P{") {
+        FormatPattern::Unicode(parse_unicode(char_fmt)?)
+    } else if char_fmt.len() > 1 && (char_fmt[0] as char).is_uppercase() {
+        FormatPattern::Production(String::from_utf8(char_fmt.to_owned())?)
+    } else {
+        FormatPattern::Literal(char_fmt.to_owned())
+    };
+
+    Ok(FormatPart {
+        attrs,
+        pattern,
+    })
+}
+
+fn parse_sequence<I>(words: &mut Peekable<I>) -> Result<Vec<FormatPart>>
+    where I: Iterator<Item=Word> {
+    let mut parts = Vec::new();
+
+    loop {
+        let mut part = match words.peek() {
+            None | Some(&Word::Bar) | Some(&Word::Close) | Some(&Word::Arrow) => return Ok(parts),
+            Some(&Word::Suffix(_)) => custom_error!("Dangling suffix"),
+            _ => match words.next().unwrap() {
+                Word::Bare(w) => parse_word(&w)?,
+                Word::Quoted(s) => FormatPart {
+                    attrs: Vec::new(),
+                    pattern: FormatPattern::Literal(s),
+                },
+                Word::Class(set) => FormatPart {
+                    attrs: Vec::new(),
+                    pattern: FormatPattern::Class(set),
+                },
+                Word::Unicode(ranges) => FormatPart {
+                    attrs: Vec::new(),
+                    pattern: FormatPattern::Unicode(ranges),
+                },
+                Word::Open => {
+                    let alts = parse_alternatives(words)?;
+                    match words.next() {
+                        Some(Word::Close) => {}
+                        _ => custom_error!("Unbalanced '('"),
+                    }
+                    FormatPart {
+                        attrs: Vec::new(),
+                        pattern: FormatPattern::Group(alts),
+                    }
+                }
+                _ => unreachable!(),
+            },
+        };
+
+        while let Some(&Word::Suffix(attr)) = words.peek() {
+            part.attrs.push(attr);
+            words.next();
+        }
+
+        parts.push(part);
+    }
+}
+
+fn parse_alternatives<I>(words: &mut Peekable<I>) -> Result<Vec<Vec<FormatPart>>>
+    where I: Iterator<Item=Word> {
+    let mut alts = vec![parse_sequence(words)?];
+    while let Some(&Word::Bar) = words.peek() {
+        words.next();
+        alts.push(parse_sequence(words)?);
+    }
+    if let Some(&Word::Arrow) = words.peek() {
+        custom_error!("Returns are only allowed at the top level");
+    }
+    Ok(alts)
+}
+
+fn parse_right(source: &[u8]) -> Result<Vec<ProductionInput>> {
+    let mut words = split_words(source)?.into_iter().peekable();
+    let mut inputs = Vec::new();
+
+    loop {
+        let format = parse_sequence(&mut words)?;
+        let output = if let Some(&Word::Arrow) = words.peek() {
+            words.next();
+            match words.next() {
+                Some(Word::Bare(w)) | Some(Word::Quoted(w)) => Some(String::from_utf8(w)?),
+                _ => custom_error!("Expected a return after '=>'"),
+            }
+        } else {
+            None
+        };
+
+        inputs.push(ProductionInput {
+            output,
+            format,
+        });
+
+        match words.next() {
+            None => return Ok(inputs),
+            Some(Word::Bar) => {}
+            Some(Word::Close) => custom_error!("Unbalanced ')'"),
+            _ => custom_error!("Returns must be single words"),
+        }
+    }
+}
+
+fn parse_line(line: &[u8]) -> Result<Production> {
+    let arrow = match line.windows(2).position(|w| w == b"->") {
+        Some(i) => i,
+        None => custom_error!("Expected '->'"),
+    };
+
+    let left = String::from_utf8(line[..arrow].to_owned())?;
+    let mut left = left.trim();
+    let is_token = !left.starts_with('_');
+    if !is_token {
+        left = &left[1..];
+    }
+
+    let (name, param) = match left.find('(') {
+        Some(i) => {
+            if !left.ends_with(')') {
+                custom_error!("Unbalanced parameter in '{}'", left);
+            }
+            (left[..i].trim(), Some(left[i + 1..left.len() - 1].trim().to_owned()))
+        }
+        None => (left, None),
+    };
+
+    Ok(Production {
+        name: name.to_owned(),
+        param,
+        is_token,
+        inputs: parse_right(&line[arrow + 2..])?,
+    })
+}
+
+fn parse_directive(line: &[u8], grammar: &mut Grammar) -> Result<()> {
+    let end = line.iter().position(|&c| (c as char).is_whitespace()).unwrap_or(line.len());
+    let inputs = parse_right(&line[end..])?;
+    if inputs.iter().any(|i| i.output.is_some()) {
+        custom_error!("Directives can't have returns");
+    }
+
+    match &line[1..end] {
+        b"whitespace" => grammar.whitespace.extend(inputs),
+        b"delimiter" => grammar.delimiters.extend(inputs),
+        name => custom_error!("Unknown directive '%{}'", String::from_utf8_lossy(name)),
+    }
+    Ok(())
+}
+
+impl Grammar {
+    pub fn parse(source: &[u8]) -> Result<Grammar> {
+        let mut grammar = Grammar {
+            productions: Vec::new(),
+            whitespace: Vec::new(),
+            delimiters: Vec::new(),
+        };
+
+        for (i, line) in source.split(|&c| c == b'\n').enumerate() {
+            let line = match line.iter().position(|&c| !(c as char).is_whitespace()) {
+                Some(start) => &line[start..],
+                None => continue,
+            };
+
+            let res = if line[0] == b'%' {
+                parse_directive(line, &mut grammar)
+            } else {
+                parse_line(line).map(|prod| grammar.productions.push(prod))
+            };
+            if let Err(e) = res {
+                custom_error!("line {}: {}", i + 1, e);
+            }
+        }
+
+        Ok(grammar)
+    }
+
+    pub fn production(&self, name: &str) -> Option<&Production> {
+        self.productions.iter().find(|p| p.name == name)
+    }
+
+    /// The productions that the lexer emits, in order of priority.
+    pub fn tokens(&self) -> impl Iterator<Item=&Production> {
+        self.productions.iter().filter(|p| p.is_token)
+    }
+}
+
@@ -0,0 +1,57 @@
+//! Compiles a token grammar into a byte-driven lexer transition table.
+//!
+//! Each line reads `Name -> alternatives`, where a leading `_` marks a fragment
+//! that is only used inside other productions and `Name(Type)` records the
+//! value type of the token. Alternatives are separated by `|` and may end in
+//! `=> word`, which replaces the matched lexeme. Items are bare words
+//! (`lambda`, `#t`), production names (`Digit`), `"quoted literals"`, byte
+//! ranges (`a..z`), classes (`[a-z!$]`, `[^"\\]`) and `( groups )`, each
+//! optionally followed by `*`, `+`, `?` or `_` (matched, but not kept in the
+//! lexeme).
+//!
+//! Unicode general categories are written `\p{L}` or inside classes, as in
+//! `[\p{Lu}\p{Ll}&&[^\x00-\x7f]]`, and match the UTF-8 encodings of their
+//! code points.
+//!
+//! Lines starting with `%` are directives. `%whitespace` patterns are skipped
+//! between tokens, and `%delimiter` patterns list what may follow a token that
+//! could otherwise keep growing; whitespace always counts as a delimiter. A
+//! token whose last byte is itself a delimiter, like `,` before `,@`, may be
+//! followed by anything.
+
+extern crate regex_syntax;
+
+#[macro_use] mod error;
+mod dfa;
+mod grammar;
+mod nfa;
+mod rust;
+mod samples;
+mod table;
+
+use std::io::Write;
+
+pub use error::{Error, Result};
+pub use grammar::*;
+pub use samples::Sample;
+pub use table::{Accept, Consume, Lexeme, Table, Transition};
+
+impl Grammar {
+    pub fn compile(&self) -> Result<Table> {
+        Table::build(self)
+    }
+
+    pub fn compile_to_rust(&self, output: &mut dyn Write) -> Result<()> {
+        rust::write_table(self, &self.compile()?, output)
+    }
+
+    /// Sample inputs for every token, checked against the compiled table.
+    pub fn samples(&self) -> Result<Vec<Sample>> {
+        Ok(samples::samples(self, &self.compile()?))
+    }
+
+    /// Writes `#[test]` functions that run `samples` through the real lexer.
+    pub fn compile_tests_to_rust(&self, output: &mut dyn Write) -> Result<()> {
+        rust::write_tests(self, &self.samples()?, output)
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+
+use regex_syntax::utf8::Utf8Sequences;
+
+use dfa::epsilon_closure;
+use error::Result;
+use grammar::{ByteSet, FormatPart, FormatPattern, MatcherAttribute, Production};
+use table::Accept;
+
+pub struct NfaEdge {
+    pub bytes: ByteSet,
+    pub void: bool,
+    pub target: usize,
+}
+
+#[derive(Default)]
+pub struct NfaState {
+    pub edges: Vec<NfaEdge>,
+    pub epsilon: Vec<usize>,
+    pub accept: Option<usize>,
+}
+
+/// Thompson construction over the productions of a grammar. State 0 is the
+/// start state, from which every root added with `add_root` is reachable.
+pub struct NfaBuilder<'a> {
+    productions: HashMap<&'a str, &'a Production>,
+    pub states: Vec<NfaState>,
+    pub accepts: Vec<Accept>,
+    expanding: Vec<&'a str>,
+}
+
+impl<'a> NfaBuilder<'a> {
+    pub fn new(productions: &'a [Production]) -> Result<Self> {
+        let mut map = HashMap::new();
+        for prod in productions {
+            if map.insert(prod.name.as_str(), prod).is_some() {
+                custom_error!("Production '{}' is defined twice", prod.name);
+            }
+        }
+
+        Ok(NfaBuilder {
+            productions: map,
+            states: vec![NfaState::default()],
+            accepts: Vec::new(),
+            expanding: Vec::new(),
+        })
+    }
+
+    /// Bytes that can start any of the patterns added so far.
+    pub fn first_bytes(&self) -> ByteSet {
+        let mut start = vec![0];
+        epsilon_closure(&self.states, &mut start);
+
+        let mut set = ByteSet::empty();
+        for &s in &start {
+            for edge in &self.states[s].edges {
+                set = set.union(&edge.bytes);
+            }
+        }
+        set
+    }
+
+    fn new_state(&mut self) -> usize {
+        self.states.push(NfaState::default());
+        self.states.len() - 1
+    }
+
+    fn epsilon(&mut self, from: usize, to: usize) {
+        self.states[from].epsilon.push(to);
+    }
+
+    pub fn add_root(&mut self, format: &'a [FormatPart], void: bool, accept: Accept) -> Result<()> {
+        let start = self.new_state();
+        self.epsilon(0, start);
+        let end = self.build_sequence(format, void, start)?;
+        self.states[end].accept = Some(self.accepts.len());
+        self.accepts.push(accept);
+        Ok(())
+    }
+
+    fn build_sequence(&mut self, parts: &'a [FormatPart], void: bool, start: usize) -> Result<usize> {
+        let mut end = start;
+        for part in parts {
+            end = self.build_part(part, void, end)?;
+        }
+        Ok(end)
+    }
+
+    fn build_part(&mut self, part: &'a FormatPart, void: bool, start: usize) -> Result<usize> {
+        let mut void = void;
+        let mut optional = false;
+        let mut repeat = false;
+        for attr in &part.attrs {
+            match *attr {
+                MatcherAttribute::Many => {
+                    optional = true;
+                    repeat = true;
+                }
+                MatcherAttribute::Many1 => repeat = true,
+                MatcherAttribute::Optional => optional = true,
+                MatcherAttribute::Void => void = true,
+            }
+        }
+
+        let inner_start = self.new_state();
+        self.epsilon(start, inner_start);
+        let inner_end = self.build_pattern(&part.pattern, void, inner_start)?;
+        let end = self.new_state();
+        self.epsilon(inner_end, end);
+
+        if repeat {
+            self.epsilon(inner_end, inner_start);
+        }
+        if optional {
+            self.epsilon(start, end);
+        }
+
+        Ok(end)
+    }
+
+    fn build_alternatives<I>(&mut self, alts: I, void: bool, start: usize) -> Result<usize>
+        where I: Iterator<Item=&'a Vec<FormatPart>> {
+        let end = self.new_state();
+        for alt in alts {
+            let alt_start = self.new_state();
+            self.epsilon(start, alt_start);
+            let alt_end = self.build_sequence(alt, void, alt_start)?;
+            self.epsilon(alt_end, end);
+        }
+        Ok(end)
+    }
+
+    fn build_pattern(&mut self, pattern: &'a FormatPattern, void: bool, start: usize) -> Result<usize> {
+        match *pattern {
+            FormatPattern::Literal(ref bytes) => {
+                let mut end = start;
+                for &c in bytes {
+                    let next = self.new_state();
+                    self.states[end].edges.push(NfaEdge {
+                        bytes: ByteSet::single(c),
+                        void,
+                        target: next,
+                    });
+                    end = next;
+                }
+                Ok(end)
+            }
+            FormatPattern::Class(set) => {
+                let end = self.new_state();
+                self.states[start].edges.push(NfaEdge {
+                    bytes: set,
+                    void,
+                    target: end,
+                });
+                Ok(end)
+            }
+            FormatPattern::Unicode(ref ranges) => {
+                let end = self.new_state();
+                for &(lo, hi) in ranges {
+                    for seq in Utf8Sequences::new(lo, hi) {
+                        let bytes = seq.as_slice();
+                        let mut from = start;
+                        for (i, range) in bytes.iter().enumerate() {
+                            let to = if i + 1 == bytes.len() { end } else { self.new_state() };
+                            let mut set = ByteSet::empty();
+                            set.insert_range(range.start, range.end);
+                            self.states[from].edges.push(NfaEdge {
+                                bytes: set,
+                                void,
+                                target: to,
+                            });
+                            from = to;
+                        }
+                    }
+                }
+                Ok(end)
+            }
+            FormatPattern::Group(ref alts) => self.build_alternatives(alts.iter(), void, start),
+            FormatPattern::Production(ref name) => {
+                let prod = match self.productions.get(name.as_str()) {
+                    Some(prod) => *prod,
+                    None => custom_error!("Unknown production '{}'", name),
+                };
+                if self.expanding.contains(&prod.name.as_str()) {
+                    custom_error!("Production '{}' is recursive", name);
+                }
+
+                self.expanding.push(&prod.name);
+                let end = self.build_alternatives(prod.inputs.iter().map(|i| &i.format), void, start)?;
+                self.expanding.pop();
+                Ok(end)
+            }
+        }
+    }
+}
+
@@ -0,0 +1,140 @@
+use std::io::Write;
+
+use error::Result;
+use grammar::Grammar;
+use samples::Sample;
+use table::{Accept, Table};
+
+fn byte_literal(c: usize) -> String {
+    let ch = c as u8 as char;
+    if ch.is_ascii_graphic() && ch != '\'' && ch != '\\' {
+        format!("b'{}'", ch)
+    } else {
+        format!("0x{:02x}", c)
+    }
+}
+
+/// Writes `table` as Rust source. The output expects `LexerState`,
+/// `TableTrans`, `TableResult`, `Consume`, `TokenType` and a `fill` function
+/// to be in scope where it is included.
+pub fn write_table(grammar: &Grammar, table: &Table, output: &mut dyn Write) -> Result<()> {
+    writeln!(output, "// Generated by build.rs from the `tokens` grammar. Do not edit.")?;
+    writeln!(output, "//")?;
+    writeln!(output, "// Tokens, in order of priority:")?;
+    for prod in grammar.tokens() {
+        match prod.param {
+            Some(ref param) => writeln!(output, "//     {}({})", prod.name, param)?,
+            None => writeln!(output, "//     {}", prod.name)?,
+        }
+    }
+    writeln!(output)?;
+    writeln!(output, "pub const READY: LexerState = 0;")?;
+    writeln!(output, "pub const STATE_COUNT: usize = {};", table.state_count())?;
+    writeln!(output)?;
+    writeln!(output, "pub fn fill_table(table: &mut [[TableResult; 256]]) {{")?;
+
+    for state in 0..table.state_count() {
+        let row: Vec<_> = (0..256).map(|c| table.transition(state, c as u8)).collect();
+        let mut lo = 0;
+        while lo < row.len() {
+            let mut hi = lo;
+            while hi + 1 < row.len() && row[hi + 1] == row[lo] {
+                hi += 1;
+            }
+
+            if let Some(trans) = row[lo] {
+                let (token, replace) = match trans.accept.map(|i| table.accept(i)) {
+                    Some(&Accept { token: Some(ref t), ref output }) => (
+                        format!("Some(TokenType::{})", t),
+                        match *output {
+                            Some(ref o) => format!("Some(b{:?})", o),
+                            None => "None".to_owned(),
+                        },
+                    ),
+                    _ => ("None".to_owned(), "None".to_owned()),
+                };
+
+                writeln!(output,
+                         "    fill(&mut table[{}], {}, {}, TableTrans {{ output: {}, replace: {}, \
+                          next_state: {}, consume: Consume::{:?} }});",
+                         state, byte_literal(lo), byte_literal(hi), token, replace,
+                         trans.next_state, trans.consume)?;
+            }
+            lo = hi + 1;
+        }
+    }
+
+    writeln!(output, "}}")?;
+    Ok(())
+}
+
+fn byte_string(bytes: &[u8]) -> String {
+    let mut out = String::from("b\"");
+    for &c in bytes {
+        match c {
+            b'"' | b'\\' => {
+                out.push('\\');
+                out.push(c as char);
+            }
+            b'\n' => out.push_str("\\n"),
+            0x20..=0x7e => out.push(c as char),
+            _ => out.push_str(&format!("\\x{:02x}", c)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn snake_case(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            out.push('_');
+        }
+        out.extend(c.to_lowercase());
+    }
+    out
+}
+
+/// Writes a test per token that feeds its samples to a `Lexer`. The output
+/// expects `Lexer`, `Token`, `TokenType` and `StreamExt` to be in scope where
+/// it is included.
+pub fn write_tests(grammar: &Grammar, samples: &[Sample], output: &mut dyn Write) -> Result<()> {
+    writeln!(output, "// Generated by build.rs from the `tokens` grammar. Do not edit.")?;
+    writeln!(output)?;
+    writeln!(output, "fn lex(input: &[u8]) -> Vec<Result<Token>> {{")?;
+    writeln!(output, "    input.iter().map(|&c| Ok(c)).then(Lexer::new(\"tokens\"))")?;
+    writeln!(output, "        .map(|r| r.map(|t| t.node)).collect()")?;
+    writeln!(output, "}}")?;
+    writeln!(output)?;
+    writeln!(output, "fn accepts(input: &[u8], expected: TokenType) {{")?;
+    writeln!(output, "    match lex(input).as_slice() {{")?;
+    writeln!(output, "        [Ok(ref token)] => assert_eq!(token.token_type(), expected, \"{{:?}}\", \
+                      String::from_utf8_lossy(input)),")?;
+    writeln!(output, "        res => panic!(\"{{:?}} should lex as one {{:?}}, got {{:?}}\", \
+                      String::from_utf8_lossy(input), expected, res),")?;
+    writeln!(output, "    }}")?;
+    writeln!(output, "}}")?;
+    writeln!(output)?;
+    writeln!(output, "fn rejects(input: &[u8]) {{")?;
+    writeln!(output, "    assert!(lex(input).iter().any(|r| r.is_err()), \"{{:?}} should be rejected\", \
+                      String::from_utf8_lossy(input));")?;
+    writeln!(output, "}}")?;
+
+    for prod in grammar.tokens() {
+        writeln!(output)?;
+        writeln!(output, "#[test]")?;
+        writeln!(output, "fn {}_token() {{", snake_case(&prod.name))?;
+        let prod_samples: Vec<_> = samples.iter().filter(|s| s.production == prod.name).collect();
+        for sample in &prod_samples {
+            if let Some(ref token) = sample.expected {
+                writeln!(output, "    accepts({}, TokenType::{});", byte_string(&sample.input), token)?;
+            }
+        }
+        for sample in prod_samples.iter().filter(|s| s.expected.is_none()) {
+            writeln!(output, "    rejects({});", byte_string(&sample.input))?;
+        }
+        writeln!(output, "}}")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,220 @@
+use grammar::{ByteSet, FormatPart, FormatPattern, Grammar, MatcherAttribute};
+use table::Table;
+
+/// Rejected samples kept per production, so the generated tests stay small.
+const MAX_REJECTS: usize = 16;
+
+/// Bytes tried after an accepted sample to build near misses.
+const SUFFIXES: &[u8] = b"#a0.\\";
+
+/// An input derived from a production. `expected` is the token the whole input
+/// lexes to, or `None` if the lexer must reject it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sample {
+    pub production: String,
+    pub input: Vec<u8>,
+    pub expected: Option<String>,
+}
+
+/// The byte a sample stands for: a letter or digit if the set has one, then
+/// any printable byte, then anything.
+fn representative(set: &ByteSet) -> Option<u8> {
+    let preferred = (b'a'..b'z' + 1).chain(b'0'..b'9' + 1).chain(b'A'..b'Z' + 1);
+    preferred.chain(0x21..0x7f).chain(b" ".iter().cloned()).chain(0..0xff)
+        .find(|&c| set.contains(c))
+}
+
+/// Like `representative`, for a set of code points. Returns the UTF-8
+/// encoding of the chosen one.
+fn unicode_representative(ranges: &[(char, char)]) -> Vec<u8> {
+    let contains = |c: char| ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi);
+    ('a'..='z').chain('0'..='9').chain('A'..='Z').find(|&c| contains(c))
+        .or_else(|| ranges.first().map(|r| r.0))
+        .map_or_else(Vec::new, |c| c.to_string().into_bytes())
+}
+
+fn outsider(set: &ByteSet) -> Option<u8> {
+    representative(&set.negate()).filter(|c| c.is_ascii_graphic())
+}
+
+struct Generator<'a> {
+    grammar: &'a Grammar,
+}
+
+impl<'a> Generator<'a> {
+    fn shortest_pattern(&self, pattern: &FormatPattern) -> Vec<u8> {
+        match *pattern {
+            FormatPattern::Literal(ref s) => s.clone(),
+            FormatPattern::Class(ref set) => representative(set).into_iter().collect(),
+            FormatPattern::Unicode(ref ranges) => unicode_representative(ranges),
+            FormatPattern::Group(ref alts) => self.shortest_alternative(alts.iter()),
+            FormatPattern::Production(ref name) => match self.grammar.production(name) {
+                Some(prod) => self.shortest_alternative(prod.inputs.iter().map(|i| &i.format)),
+                None => Vec::new(),
+            },
+        }
+    }
+
+    fn shortest_alternative<'b, I>(&self, alts: I) -> Vec<u8>
+        where I: Iterator<Item=&'b Vec<FormatPart>> {
+        alts.map(|alt| self.shortest_sequence(alt)).min_by_key(|s| s.len()).unwrap_or_default()
+    }
+
+    fn shortest_sequence(&self, parts: &[FormatPart]) -> Vec<u8> {
+        parts.iter().flat_map(|p| self.shortest_part(p)).collect()
+    }
+
+    fn shortest_part(&self, part: &FormatPart) -> Vec<u8> {
+        if part.attrs.iter().any(|&a| a == MatcherAttribute::Many || a == MatcherAttribute::Optional) {
+            Vec::new()
+        } else {
+            self.shortest_pattern(&part.pattern)
+        }
+    }
+
+    /// One level of alternatives for a pattern, each as short as possible.
+    fn pattern_variants(&self, pattern: &FormatPattern) -> Vec<Vec<u8>> {
+        match *pattern {
+            FormatPattern::Class(ref set) => {
+                let last = (0x21..0x7f).rev().find(|&c| set.contains(c));
+                representative(set).into_iter().chain(last).map(|c| vec![c]).collect()
+            }
+            FormatPattern::Unicode(ref ranges) => {
+                let last = ranges.last().map(|r| r.1.to_string().into_bytes());
+                Some(unicode_representative(ranges)).into_iter().chain(last).collect()
+            }
+            FormatPattern::Group(ref alts) => alts.iter().map(|a| self.shortest_sequence(a)).collect(),
+            FormatPattern::Production(ref name) => match self.grammar.production(name) {
+                Some(prod) => prod.inputs.iter().map(|i| self.shortest_sequence(&i.format)).collect(),
+                None => Vec::new(),
+            },
+            FormatPattern::Literal(_) => vec![self.shortest_pattern(pattern)],
+        }
+    }
+
+    /// The variants of a part: each alternative of its pattern, plus the
+    /// boundary repetition counts allowed by `*`, `+` and `?`.
+    fn part_variants(&self, part: &FormatPart) -> Vec<Vec<u8>> {
+        let mut min = 1;
+        let mut max = 1;
+        for attr in &part.attrs {
+            match *attr {
+                MatcherAttribute::Many => {
+                    min = 0;
+                    max = 2;
+                }
+                MatcherAttribute::Many1 => max = 2,
+                MatcherAttribute::Optional => min = 0,
+                MatcherAttribute::Void => {}
+            }
+        }
+
+        let once = self.shortest_pattern(&part.pattern);
+        let mut variants: Vec<Vec<u8>> = (min..max + 1).map(|n| once.repeat(n)).collect();
+        variants.extend(self.pattern_variants(&part.pattern));
+        variants
+    }
+
+    /// Inputs built from one alternative, varying a single part at a time.
+    fn sequence_samples(&self, parts: &[FormatPart]) -> Vec<Vec<u8>> {
+        let base: Vec<_> = parts.iter().map(|p| self.shortest_part(p)).collect();
+        let mut out = vec![base.concat()];
+        for (i, part) in parts.iter().enumerate() {
+            for variant in self.part_variants(part) {
+                let mut sample = base.clone();
+                sample[i] = variant;
+                out.push(sample.concat());
+            }
+        }
+        out
+    }
+
+    /// Inputs that differ from an accepted one in a single place: a byte
+    /// outside a class, a dropped last byte, or an extra byte at the end.
+    fn near_misses(&self, parts: &[FormatPart], accepted: &[u8]) -> Vec<Vec<u8>> {
+        let base: Vec<_> = parts.iter().map(|p| self.shortest_part(p)).collect();
+        let mut out = Vec::new();
+
+        for (i, part) in parts.iter().enumerate() {
+            if let FormatPattern::Class(ref set) = part.pattern {
+                if let Some(c) = outsider(set) {
+                    let mut sample = base.clone();
+                    sample[i] = vec![c];
+                    out.push(sample.concat());
+                }
+            }
+        }
+        if accepted.len() > 1 {
+            out.push(accepted[..accepted.len() - 1].to_owned());
+        }
+        for &c in SUFFIXES {
+            let mut sample = accepted.to_owned();
+            sample.push(c);
+            out.push(sample);
+        }
+        out
+    }
+}
+
+/// Generates accepted and rejected inputs for every token of `grammar`. The
+/// expected outcome comes from running `table`, so samples shadowed by a
+/// higher-priority token expect that token instead. Inputs that are merely
+/// unfinished, like an open string, are left out since the lexer can't tell
+/// them apart from a longer input.
+pub fn samples(grammar: &Grammar, table: &Table) -> Vec<Sample> {
+    let gen = Generator { grammar };
+    let terminator: Vec<u8> = grammar.whitespace.first()
+        .map(|w| gen.shortest_sequence(&w.format))
+        .unwrap_or_default();
+
+    let terminated = |input: &[u8]| {
+        let mut full = input.to_owned();
+        full.extend_from_slice(&terminator);
+        full
+    };
+    let run = |input: &[u8]| -> Option<Option<String>> {
+        match table.lex_partial(&terminated(input)) {
+            Ok((ref lexemes, 0)) if lexemes.len() == 1 => Some(Some(lexemes[0].token.clone())),
+            Ok(_) => None,
+            Err(_) => Some(None),
+        }
+    };
+
+    let mut out: Vec<Sample> = Vec::new();
+    for prod in grammar.tokens() {
+        let mut rejects = 0;
+        for inp in &prod.inputs {
+            for input in gen.sequence_samples(&inp.format) {
+                let expected = match run(&input) {
+                    Some(Some(token)) => token,
+                    _ => continue,
+                };
+                if out.iter().any(|s| s.input == terminated(&input)) {
+                    continue;
+                }
+
+                for miss in gen.near_misses(&inp.format, &input) {
+                    if rejects == MAX_REJECTS || run(&miss) != Some(None) {
+                        continue;
+                    }
+                    let miss = terminated(&miss);
+                    if !out.iter().any(|s| s.input == miss) {
+                        rejects += 1;
+                        out.push(Sample {
+                            production: prod.name.clone(),
+                            input: miss,
+                            expected: None,
+                        });
+                    }
+                }
+
+                out.push(Sample {
+                    production: prod.name.clone(),
+                    input: terminated(&input),
+                    expected: Some(expected),
+                });
+            }
+        }
+    }
+    out
+}
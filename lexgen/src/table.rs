@@ -0,0 +1,188 @@
+use dfa::{build_dfa, minimize_dfa, DfaState};
+use error::Result;
+use grammar::{ByteSet, Grammar};
+use nfa::NfaBuilder;
+
+/// What the lexer does on reaching an accepting state: emit `token` (with its
+/// lexeme replaced by `output`, if any), or go back to `READY` when `token` is
+/// `None`. When several apply, the one declared first wins.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Accept {
+    pub token: Option<String>,
+    pub output: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Consume {
+    Append,
+    Skip,
+    Ungetc,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transition {
+    pub consume: Consume,
+    pub next_state: usize,
+    pub accept: Option<usize>,
+}
+
+/// A token produced by `Table::lex`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lexeme {
+    pub token: String,
+    pub bytes: Vec<u8>,
+}
+
+/// The byte-driven transition table of a compiled grammar. State 0 is `READY`.
+pub struct Table {
+    rows: Vec<Vec<Option<Transition>>>,
+    accepts: Vec<Accept>,
+}
+
+impl Table {
+    pub fn build(grammar: &Grammar) -> Result<Table> {
+        let productions = &grammar.productions;
+
+        let mut delimiters = NfaBuilder::new(productions)?;
+        for inp in grammar.whitespace.iter().chain(&grammar.delimiters) {
+            delimiters.add_root(&inp.format, false, Accept {
+                token: None,
+                output: None,
+            })?;
+        }
+        let delimiters = delimiters.first_bytes();
+
+        let mut builder = NfaBuilder::new(productions)?;
+        for prod in grammar.tokens() {
+            for inp in &prod.inputs {
+                builder.add_root(&inp.format, false, Accept {
+                    token: Some(prod.name.clone()),
+                    output: inp.output.clone(),
+                })?;
+            }
+        }
+        for inp in &grammar.whitespace {
+            builder.add_root(&inp.format, true, Accept {
+                token: None,
+                output: None,
+            })?;
+        }
+
+        let dfa = minimize_dfa(build_dfa(&builder.states)?);
+        if let Some(i) = dfa[0].accept {
+            custom_error!("Production '{}' matches the empty string",
+                          builder.accepts[i].token.as_ref().map_or("%whitespace", |t| t.as_str()));
+        }
+
+        let self_delimiting = self_delimiting(&dfa, &delimiters);
+        Ok(Table {
+            rows: (0..dfa.len())
+                .map(|s| table_row(&dfa, &builder.accepts, s, &delimiters, self_delimiting[s]))
+                .collect(),
+            accepts: builder.accepts,
+        })
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn transition(&self, state: usize, c: u8) -> Option<&Transition> {
+        self.rows[state][c as usize].as_ref()
+    }
+
+    pub fn accept(&self, index: usize) -> &Accept {
+        &self.accepts[index]
+    }
+
+    /// Runs the table over `input` the same way the generated lexer does. The
+    /// input must end in `READY`, e.g. by finishing with whitespace.
+    pub fn lex(&self, input: &[u8]) -> Result<Vec<Lexeme>> {
+        match self.lex_partial(input)? {
+            (lexemes, 0) => Ok(lexemes),
+            _ => custom_error!("Unexpected end of input"),
+        }
+    }
+
+    /// Like `lex`, but also returns the state the input ends in.
+    pub(crate) fn lex_partial(&self, input: &[u8]) -> Result<(Vec<Lexeme>, usize)> {
+        let mut state = 0;
+        let mut current = Vec::new();
+        let mut out = Vec::new();
+
+        for (offset, &c) in input.iter().enumerate() {
+            loop {
+                let trans = match self.transition(state, c) {
+                    Some(trans) => trans,
+                    None => custom_error!("Invalid character '{}' at offset {}",
+                                          (c as char).escape_default(), offset),
+                };
+
+                if trans.consume == Consume::Append {
+                    current.push(c);
+                }
+                if let Some(accept) = trans.accept.map(|i| &self.accepts[i]) {
+                    if let Some(ref token) = accept.token {
+                        out.push(Lexeme {
+                            token: token.clone(),
+                            bytes: accept.output.as_ref().map_or(current.clone(), |o| o.clone().into_bytes()),
+                        });
+                    }
+                    current.clear();
+                }
+
+                state = trans.next_state;
+                if trans.consume != Consume::Ungetc {
+                    break;
+                }
+            }
+        }
+
+        Ok((out, state))
+    }
+}
+
+/// The states only ever entered on a delimiter byte. A token ending in one of
+/// them is already delimited.
+fn self_delimiting(dfa: &[DfaState], delimiters: &ByteSet) -> Vec<bool> {
+    let mut entered = vec![false; dfa.len()];
+    let mut only_delimiters = vec![true; dfa.len()];
+    for state in dfa {
+        for (c, trans) in state.trans.iter().enumerate() {
+            if let Some((next, _)) = *trans {
+                entered[next] = true;
+                only_delimiters[next] &= delimiters.contains(c as u8);
+            }
+        }
+    }
+    entered.iter().zip(only_delimiters).map(|(&e, d)| e && d).collect()
+}
+
+/// A token ends before a delimiter, or before anything once delimited, while
+/// whitespace ends before any byte it can't consume.
+fn table_row(dfa: &[DfaState], accepts: &[Accept], state: usize, delimiters: &ByteSet,
+             self_delimiting: bool) -> Vec<Option<Transition>> {
+    let ends = |accept: usize, c: usize| {
+        accepts[accept].token.is_none() || self_delimiting || delimiters.contains(c as u8)
+    };
+
+    let is_final = |s: usize| dfa[s].accept.is_some() && dfa[s].trans.iter().all(Option::is_none);
+
+    (0..256).map(|c| {
+        match dfa[state].trans[c] {
+            Some((next, void)) => {
+                let consume = if void { Consume::Skip } else { Consume::Append };
+                Some(if is_final(next) {
+                    Transition { consume, next_state: 0, accept: dfa[next].accept }
+                } else {
+                    Transition { consume, next_state: next, accept: None }
+                })
+            }
+            None => match dfa[state].accept {
+                Some(accept) if state != 0 && ends(accept, c) =>
+                    Some(Transition { consume: Consume::Ungetc, next_state: 0, accept: Some(accept) }),
+                _ => None,
+            },
+        }
+    }).collect()
+}
@@ -0,0 +1,131 @@
+extern crate lexgen;
+
+use lexgen::{FormatPattern, Grammar, MatcherAttribute};
+
+fn parse(source: &str) -> Grammar {
+    Grammar::parse(source.as_bytes()).unwrap()
+}
+
+fn parse_err(source: &str) -> String {
+    match Grammar::parse(source.as_bytes()) {
+        Ok(_) => panic!("{:?} should not parse", source),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn header() {
+    let grammar = parse("Bool(bool) -> #t => true | #f => false\n_Digit -> 0..9\n");
+    assert_eq!(grammar.productions.len(), 2);
+
+    let bool_prod = grammar.production("Bool").unwrap();
+    assert!(bool_prod.is_token);
+    assert_eq!(bool_prod.param, Some("bool".to_owned()));
+    assert_eq!(bool_prod.inputs.len(), 2);
+    assert_eq!(bool_prod.inputs[0].output, Some("true".to_owned()));
+
+    let digit = grammar.production("Digit").unwrap();
+    assert!(!digit.is_token);
+    assert_eq!(digit.param, None);
+    assert_eq!(grammar.tokens().count(), 1);
+}
+
+#[test]
+fn words() {
+    let grammar = parse(r#"A -> lambda Digit+ \_ "a b|c" [^"\\]? ("(" | x)*"#);
+    let parts = &grammar.productions[0].inputs[0].format;
+    assert_eq!(parts.len(), 6);
+
+    match parts[0].pattern {
+        FormatPattern::Literal(ref s) => assert_eq!(s, b"lambda"),
+        _ => panic!("expected a literal"),
+    }
+    match parts[1].pattern {
+        FormatPattern::Production(ref s) => assert_eq!(s, "Digit"),
+        _ => panic!("expected a production"),
+    }
+    assert_eq!(parts[1].attrs, vec![MatcherAttribute::Many1]);
+    assert_eq!(parts[2].attrs, vec![MatcherAttribute::Void]);
+    match parts[3].pattern {
+        FormatPattern::Literal(ref s) => assert_eq!(s, b"a b|c"),
+        _ => panic!("expected a quoted literal"),
+    }
+    match parts[4].pattern {
+        FormatPattern::Class(ref set) => {
+            assert!(set.contains(b'a'));
+            assert!(!set.contains(b'"'));
+            assert!(!set.contains(b'\\'));
+        }
+        _ => panic!("expected a class"),
+    }
+    assert_eq!(parts[4].attrs, vec![MatcherAttribute::Optional]);
+    match parts[5].pattern {
+        FormatPattern::Group(ref alts) => assert_eq!(alts.len(), 2),
+        _ => panic!("expected a group"),
+    }
+    assert_eq!(parts[5].attrs, vec![MatcherAttribute::Many]);
+}
+
+#[test]
+fn classes() {
+    let grammar = parse(r"A -> [a-c\-\]\x41] [^] [-+]");
+    let parts = &grammar.productions[0].inputs[0].format;
+    let sets: Vec<_> = parts.iter().map(|p| match p.pattern {
+        FormatPattern::Class(set) => set,
+        _ => panic!("expected a class"),
+    }).collect();
+
+    for &c in b"abc-]A" {
+        assert!(sets[0].contains(c));
+    }
+    assert!(!sets[0].contains(b'd'));
+    assert!((0..256).all(|c| sets[1].contains(c as u8)));
+    assert!(sets[2].contains(b'-') && sets[2].contains(b'+') && !sets[2].contains(b','));
+}
+
+#[test]
+fn directives() {
+    let grammar = parse("%whitespace [ \\n] | ; [^\\n]* \"\\n\"\n%delimiter [()]\nA -> a\n");
+    assert_eq!(grammar.whitespace.len(), 2);
+    assert_eq!(grammar.delimiters.len(), 1);
+    assert_eq!(grammar.productions.len(), 1);
+}
+
+#[test]
+fn errors() {
+    assert!(parse_err("A -> (a").contains("Unbalanced"));
+    assert!(parse_err("A -> a)").contains("Unbalanced"));
+    assert!(parse_err("A -> [a").contains("Unterminated"));
+    assert!(parse_err("A -> \"a").contains("Unterminated"));
+    assert!(parse_err("A -> a => x y").contains("single words"));
+    assert!(parse_err("A -> (a => x)").contains("top level"));
+    assert!(parse_err("A a").contains("'->'"));
+    assert!(parse_err("%foo a").contains("Unknown directive"));
+    assert!(parse_err("A -> a\n\nB -> [z-a]").starts_with("line 3:"));
+}
+
+#[test]
+fn project_grammar() {
+    let grammar = Grammar::parse(include_bytes!("../../tokens")).unwrap();
+    assert!(grammar.production("Ident").is_some());
+    grammar.compile().unwrap();
+}
+
+#[test]
+fn unicode_classes() {
+    let grammar = parse(r"A -> \p{Lu}+ [\p{Ll}&&[^a-z]] [a\p{Nd}]");
+    let parts = &grammar.productions[0].inputs[0].format;
+
+    let ranges: Vec<_> = parts.iter().map(|p| match p.pattern {
+        FormatPattern::Unicode(ref ranges) => ranges.clone(),
+        _ => panic!("expected a Unicode class"),
+    }).collect();
+    let contains = |i: usize, c: char| ranges[i].iter().any(|&(lo, hi)| lo <= c && c <= hi);
+
+    assert_eq!(parts[0].attrs, vec![MatcherAttribute::Many1]);
+    assert!(contains(0, 'Λ') && !contains(0, 'λ'));
+    assert!(contains(1, 'λ') && !contains(1, 'a'));
+    assert!(contains(2, 'a') && contains(2, '٣') && !contains(2, 'b'));
+
+    assert!(parse_err(r"A -> \p{Nope}").contains("Invalid Unicode class"));
+}
@@ -0,0 +1,59 @@
+extern crate lexgen;
+
+use lexgen::Grammar;
+
+const GRAMMAR: &str = r#"
+%whitespace " "
+Lambda -> lambda
+Int(i64) -> [+-]? [0-9]+
+Ident(String) -> [a-z]+
+"#;
+
+#[test]
+fn boundaries() {
+    let samples = Grammar::parse(GRAMMAR.as_bytes()).unwrap().samples().unwrap();
+    let inputs: Vec<_> = samples.iter()
+        .filter(|s| s.production == "Int" && s.expected.is_some())
+        .map(|s| String::from_utf8(s.input.clone()).unwrap())
+        .collect();
+
+    for expected in &["0 ", "+0 ", "00 ", "9 "] {
+        assert!(inputs.contains(&expected.to_string()), "{:?} missing from {:?}", expected, inputs);
+    }
+}
+
+#[test]
+fn near_misses() {
+    let samples = Grammar::parse(GRAMMAR.as_bytes()).unwrap().samples().unwrap();
+    let rejected: Vec<_> = samples.iter()
+        .filter(|s| s.expected.is_none())
+        .map(|s| String::from_utf8(s.input.clone()).unwrap())
+        .collect();
+
+    assert!(rejected.contains(&"0a ".to_owned()));
+    assert!(!rejected.contains(&"lambd ".to_owned()));
+}
+
+#[test]
+fn shadowed_samples() {
+    let grammar = Grammar::parse(GRAMMAR.as_bytes()).unwrap();
+    let table = grammar.compile().unwrap();
+
+    for sample in grammar.samples().unwrap() {
+        match sample.expected {
+            Some(ref token) => assert_eq!(&table.lex(&sample.input).unwrap()[0].token, token),
+            None => assert!(table.lex(&sample.input).is_err()),
+        }
+    }
+}
+
+#[test]
+fn rust_tests() {
+    let mut out = Vec::new();
+    Grammar::parse(GRAMMAR.as_bytes()).unwrap().compile_tests_to_rust(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.contains("fn int_token()"));
+    assert!(out.contains("accepts(b\"lambda \", TokenType::Lambda);"));
+    assert!(out.contains("rejects(b\"0a \");"));
+}
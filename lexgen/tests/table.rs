@@ -0,0 +1,106 @@
+extern crate lexgen;
+
+use lexgen::{Grammar, Table};
+
+const GRAMMAR: &str = r#"
+%whitespace [ \n] | ; [^\n]* "\n"
+%delimiter [()"]
+OpenParen -> "("_
+CloseParen -> ")"_
+If -> if
+Bool(bool) -> #t => true | #f => false
+Int(i64) -> [+-]? Digit+
+String(String) -> "\""_ ( \_ [^] | [^"\\] )* "\""_
+Ident(String) -> Letter ( Letter | Digit )* | [+-]
+_Letter -> a..z
+_Digit -> 0..9
+"#;
+
+fn table(source: &str) -> Table {
+    Grammar::parse(source.as_bytes()).unwrap().compile().unwrap()
+}
+
+fn lex(input: &str) -> Vec<(String, String)> {
+    table(GRAMMAR).lex(input.as_bytes()).unwrap().into_iter()
+        .map(|l| (l.token, String::from_utf8(l.bytes).unwrap()))
+        .collect()
+}
+
+fn tokens(input: &str) -> Vec<String> {
+    lex(input).into_iter().map(|(t, _)| t).collect()
+}
+
+#[test]
+fn delimited_tokens() {
+    assert_eq!(tokens("(if x 12)\n"), vec!["OpenParen", "If", "Ident", "Int", "CloseParen"]);
+    assert_eq!(lex("ifx -3 + \n"), vec![
+        ("Ident".to_owned(), "ifx".to_owned()),
+        ("Int".to_owned(), "-3".to_owned()),
+        ("Ident".to_owned(), "+".to_owned()),
+    ]);
+}
+
+#[test]
+fn void_and_outputs() {
+    assert_eq!(lex("\"a\\\"b\" #t #f "), vec![
+        ("String".to_owned(), "a\"b".to_owned()),
+        ("Bool".to_owned(), "true".to_owned()),
+        ("Bool".to_owned(), "false".to_owned()),
+    ]);
+}
+
+#[test]
+fn whitespace_and_comments() {
+    assert_eq!(tokens("a ; b c\n  d\n"), vec!["Ident", "Ident"]);
+}
+
+#[test]
+fn rejects() {
+    let table = table(GRAMMAR);
+    assert!(table.lex(b"12a ").is_err());
+    assert!(table.lex(b"#x ").is_err());
+    assert!(table.lex(b"\"abc").is_err());
+}
+
+#[test]
+fn priority() {
+    let table = table("%whitespace \" \"\nKeyword -> if\nIdent(String) -> [a-z]+\n");
+    let lexemes = table.lex(b"if ifs ").unwrap();
+    assert_eq!(lexemes[0].token, "Keyword");
+    assert_eq!(lexemes[1].token, "Ident");
+}
+
+#[test]
+fn grammar_errors() {
+    let compile = |source: &str| Grammar::parse(source.as_bytes()).unwrap().compile().err().map(|e| e.to_string());
+    assert!(compile("A -> a?").unwrap().contains("empty string"));
+    assert!(compile("A -> Bs\n_Bs -> b Bs").unwrap().contains("recursive"));
+    assert!(compile("A -> Cc").unwrap().contains("Unknown production"));
+    assert!(compile("A -> a\nA -> b").unwrap().contains("twice"));
+    assert!(compile("A -> a_ | a").unwrap().contains("void"));
+}
+
+#[test]
+fn unicode_classes() {
+    let table = table("%whitespace \" \"\nIdent(String) -> [\\p{L}&&[^A-Z]] \\p{Nd}*\n");
+    let lexemes = table.lex("λ ab١٢ é9 ".as_bytes());
+    assert!(lexemes.is_err());
+
+    let lexemes = table.lex("λ a١٢ é9 ".as_bytes()).unwrap();
+    let words: Vec<_> = lexemes.into_iter().map(|l| String::from_utf8(l.bytes).unwrap()).collect();
+    assert_eq!(words, vec!["λ", "a١٢", "é9"]);
+
+    assert!(table.lex("A ".as_bytes()).is_err());
+    assert!(table.lex(b"\xce ").is_err());
+}
+
+#[test]
+fn self_delimiting_tokens() {
+    let table = table("%whitespace \" \"\n%delimiter [(,]\nUnquote -> ,_\nSplice -> \",@\"_\nOpen -> \"#(\"_ | \"#(\"_ x\nIdent(String) -> [a-z]+\n");
+    let tokens = |input: &str| -> Vec<String> {
+        table.lex(input.as_bytes()).unwrap().into_iter().map(|l| l.token).collect()
+    };
+    assert_eq!(tokens(",a ,@b "), vec!["Unquote", "Ident", "Splice", "Ident"]);
+    assert_eq!(tokens("#(a #(x "), vec!["Open", "Ident", "Open"]);
+    assert!(table.lex(b"ab@ ").is_err());
+}
@@ -0,0 +1,164 @@
+//! Conversion from the core IR after closure conversion, where the only
+//! calls are `Op::Invoke`s in tail position, each with the operands it
+//! needs already computed except for nested operations. Those are named
+//! one by one, innermost first, in the order they would be evaluated.
+
+use std::collections::HashMap;
+
+use anf::{Atom, Block, Function, Local, Program, Stmt, Tail, Value};
+use ir::{self, Expr, ExprKind, Op, Var};
+use symbol::Symbol;
+
+/// Converts the functions made by lowering a program and its lowered
+/// top-level forms, which are closures of some of them.
+pub fn convert(functions: Vec<ir::Lambda>, forms: Vec<Expr>) -> Program {
+    let main = forms
+        .into_iter()
+        .map(|form| match form.kind {
+            ExprKind::Op(Op::Closure(index), ref captured) if captured.is_empty() => index,
+            _ => panic!("top-level forms are lowered to static closures"),
+        })
+        .collect();
+    Program {
+        functions: functions.into_iter().map(function).collect(),
+        main,
+    }
+}
+
+fn function(lambda: ir::Lambda) -> Function {
+    let mut converter = Converter::default();
+    for &param in lambda.params.iter().chain(&lambda.rest) {
+        converter.bind(param);
+    }
+    let mut body = lambda.body;
+    assert_eq!(body.len(), 1, "CPS leaves a single expression in each body");
+    let body = converter.block(body.remove(0));
+    Function {
+        locals: converter.locals,
+        params: lambda.params.len(),
+        rest: lambda.rest.is_some(),
+        body,
+    }
+}
+
+#[derive(Debug, Default)]
+struct Converter {
+    locals: Vec<Symbol>,
+    vars: HashMap<Var, Local>,
+}
+
+impl Converter {
+    fn bind(&mut self, var: Var) -> Local {
+        let local = self.local(var.symbol());
+        self.vars.insert(var, local);
+        local
+    }
+
+    fn local(&mut self, name: Symbol) -> Local {
+        self.locals.push(name);
+        Local(self.locals.len() - 1)
+    }
+
+    fn block(&mut self, expr: Expr) -> Block {
+        let mut stmts = Vec::new();
+        let tail = self.tail(expr, &mut stmts);
+        Block {
+            stmts,
+            tail,
+        }
+    }
+
+    /// Converts `expr` in tail position, adding the statements before its
+    /// tail to `stmts`.
+    fn tail(&mut self, expr: Expr, stmts: &mut Vec<Stmt>) -> Tail {
+        match expr.kind {
+            ExprKind::Op(Op::Invoke, operands) => {
+                let mut atoms = self.atoms(operands, stmts);
+                let operator = atoms.remove(0);
+                Tail::Call(operator, atoms)
+            }
+            ExprKind::If(test, consequent, alternate) => {
+                let test = self.atom(*test, stmts);
+                let alternate = *alternate.expect("CPS gives every `if` an alternate");
+                Tail::If(test, Box::new(self.block(*consequent)), Box::new(self.block(alternate)))
+            }
+            ExprKind::Letrec(bindings, mut body) => {
+                for (var, init) in bindings {
+                    let value = self.value(init, stmts);
+                    let local = self.bind(var);
+                    stmts.push(Stmt::Let(local, value));
+                }
+                assert_eq!(body.len(), 1, "CPS leaves a single expression in each body");
+                self.tail(body.remove(0), stmts)
+            }
+            ExprKind::Begin(mut exprs) => {
+                let last = exprs.pop().expect("`begin`s aren't empty");
+                for expr in exprs {
+                    let value = self.value(expr, stmts);
+                    stmts.push(Stmt::Do(value));
+                }
+                self.tail(last, stmts)
+            }
+            kind => panic!("CPS leaves only calls in tail position, not {}", Expr::new(kind, expr.span)),
+        }
+    }
+
+    fn atoms(&mut self, exprs: Vec<Expr>, stmts: &mut Vec<Stmt>) -> Vec<Atom> {
+        exprs.into_iter().map(|e| self.atom(e, stmts)).collect()
+    }
+
+    /// `expr` as an atom, naming it first if it isn't one.
+    fn atom(&mut self, expr: Expr, stmts: &mut Vec<Stmt>) -> Atom {
+        let name = match expr.kind {
+            ExprKind::Ref(var) if var.is_global() => var.symbol(),
+            _ => Symbol::intern("t"),
+        };
+        match self.value(expr, stmts) {
+            Value::Atom(atom) => atom,
+            value => {
+                let local = self.local(name);
+                stmts.push(Stmt::Let(local, value));
+                Atom::Local(local)
+            }
+        }
+    }
+
+    fn value(&mut self, expr: Expr, stmts: &mut Vec<Stmt>) -> Value {
+        match expr.kind {
+            ExprKind::Const(datum) => Value::Atom(Atom::Const(datum)),
+            ExprKind::Ref(var) if var.is_global() => Value::Global(var.symbol()),
+            // Only a closure made ahead of a variable it captures reads it
+            // before it is bound, and it is patched before it is called.
+            ExprKind::Ref(var) => Value::Atom(self.vars.get(&var).map_or(Atom::Unassigned, |&l| Atom::Local(l))),
+            ExprKind::Set(var, value) | ExprKind::Define(var, value) if var.is_global() =>
+                Value::SetGlobal(var.symbol(), self.atom(*value, stmts)),
+            ExprKind::Set(var, value) => {
+                let value = self.atom(*value, stmts);
+                Value::SetLocal(self.vars[&var], value)
+            }
+            ExprKind::Op(op, operands) => {
+                let mut atoms = self.atoms(operands, stmts);
+                match op {
+                    Op::Box => Value::Box(atoms.pop()),
+                    Op::Unbox => Value::Unbox(atoms.remove(0)),
+                    Op::SetBox => {
+                        let value = atoms.pop().expect("`#%set-box!` has a value");
+                        Value::SetBox(atoms.remove(0), value)
+                    }
+                    Op::Check(var) => Value::Check(var.symbol(), atoms.remove(0)),
+                    Op::Closure(index) => Value::Closure(index, atoms),
+                    Op::ClosureRef(slot) => Value::ClosureRef(atoms.remove(0), slot),
+                    Op::ClosureSet(slot) => {
+                        let value = atoms.pop().expect("`#%closure-set!` has a value");
+                        Value::ClosureSet(atoms.remove(0), slot, value)
+                    }
+                    Op::Primitive(p) => Value::Primitive(p, atoms),
+                    Op::Unspecified => Value::Atom(Atom::Unspecified),
+                    Op::Unassigned => Value::Atom(Atom::Unassigned),
+                    Op::Invoke => panic!("calls are only in tail position"),
+                }
+            }
+            kind => panic!("CPS names everything but operations and constants, not {}", Expr::new(kind, expr.span)),
+        }
+    }
+}
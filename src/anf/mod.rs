@@ -0,0 +1,309 @@
+//! The A-normal form the backend compiles, after CPS and closure
+//! conversion. A program is a list of functions, which are called only
+//! through closures, and the functions of its top-level forms in order.
+//! In a function, every intermediate value is bound to a local, operands
+//! are only locals and constants, and control flow is explicit: a body is
+//! a list of statements ending in a tail call or a branch between two more
+//! bodies. Nothing returns, since every call is in tail position.
+//!
+//! Programs are printed as text that `parse` reads back, so that passes
+//! can be tested on programs written by hand, and `--input=anf` compiles
+//! them. A function with two locals looks like this:
+//!
+//! ```text
+//! (function 1 (closure.0 k.1 x.2)
+//!   (let t.3 (prim car x.2))
+//!   (if t.3
+//!     (call k.1 'yes)
+//!     (begin
+//!       (do (set-global! seen x.2))
+//!       (call k.1 unspecified))))
+//! ```
+
+mod convert;
+pub mod parse;
+
+use std::fmt;
+
+use primitive::Primitive;
+use reader::{Datum, DatumKind};
+use span::Span;
+use symbol::Symbol;
+
+pub use self::convert::convert;
+
+/// A local of a function, by its index in `Function::locals`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Local(pub usize);
+
+#[derive(Clone, Debug)]
+pub enum Atom {
+    Local(Local),
+    Const(Datum),
+    Unspecified,
+    /// The value of a local that is read before it is initialised. Closures
+    /// that capture a variable bound after them hold it until they are
+    /// patched.
+    Unassigned,
+}
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Atom(Atom),
+    Primitive(Primitive, Vec<Atom>),
+    Global(Symbol),
+    SetGlobal(Symbol, Atom),
+    SetLocal(Local, Atom),
+    /// A closure of the function with the index, capturing the values.
+    Closure(usize, Vec<Atom>),
+    ClosureRef(Atom, usize),
+    ClosureSet(Atom, usize, Atom),
+    /// A box holding the value, or nothing yet.
+    Box(Option<Atom>),
+    Unbox(Atom),
+    SetBox(Atom, Atom),
+    /// The value, which is an error if it is unassigned, naming the
+    /// variable it is the value of.
+    Check(Symbol, Atom),
+}
+
+#[derive(Clone, Debug)]
+pub enum Stmt {
+    Let(Local, Value),
+    /// A value computed only for its effects.
+    Do(Value),
+}
+
+#[derive(Clone, Debug)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    pub tail: Tail,
+}
+
+#[derive(Clone, Debug)]
+pub enum Tail {
+    /// Calls the function of the closure, with the closure and the
+    /// arguments.
+    Call(Atom, Vec<Atom>),
+    If(Atom, Box<Block>, Box<Block>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Function {
+    /// The name of each local, which only matters to people reading it.
+    pub locals: Vec<Symbol>,
+    /// How many of the first locals are parameters, the closure first.
+    pub params: usize,
+    /// Whether the local after the parameters is a rest parameter.
+    pub rest: bool,
+    pub body: Block,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    /// The functions of the top-level forms, which take just a
+    /// continuation, in the order they run.
+    pub main: Vec<usize>,
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, function) in self.functions.iter().enumerate() {
+            function.write(f, index)?;
+            f.write_str("\n")?;
+        }
+        f.write_str("(main")?;
+        for index in &self.main {
+            write!(f, " {}", index)?;
+        }
+        f.write_str(")\n")
+    }
+}
+
+impl Function {
+    fn write(&self, f: &mut fmt::Formatter, index: usize) -> fmt::Result {
+        let local = |i| Named(self, Local(i));
+        write!(f, "(function {} (", index)?;
+        for i in 0..self.params {
+            write!(f, "{}{}", if i > 0 { " " } else { "" }, local(i))?;
+        }
+        if self.rest {
+            write!(f, " . {}", local(self.params))?;
+        }
+        f.write_str(")")?;
+        self.write_block(f, &self.body, 1)?;
+        f.write_str(")")
+    }
+
+    /// Writes `block` as the statements and tail of a body, each on a line
+    /// of its own, indented to `depth`.
+    fn write_block(&self, f: &mut fmt::Formatter, block: &Block, depth: usize) -> fmt::Result {
+        for stmt in &block.stmts {
+            newline(f, depth)?;
+            let value = match *stmt {
+                Stmt::Let(local, ref value) => {
+                    write!(f, "(let {} ", Named(self, local))?;
+                    value
+                }
+                Stmt::Do(ref value) => {
+                    f.write_str("(do ")?;
+                    value
+                }
+            };
+            self.write_value(f, value)?;
+            f.write_str(")")?;
+        }
+        newline(f, depth)?;
+        match block.tail {
+            Tail::Call(ref operator, ref operands) => {
+                write!(f, "(call {}", self.atom(operator))?;
+                for operand in operands {
+                    write!(f, " {}", self.atom(operand))?;
+                }
+                f.write_str(")")
+            }
+            Tail::If(ref test, ref consequent, ref alternate) => {
+                write!(f, "(if {}", self.atom(test))?;
+                for branch in &[consequent, alternate] {
+                    if branch.stmts.is_empty() {
+                        self.write_block(f, branch, depth + 1)?;
+                    } else {
+                        newline(f, depth + 1)?;
+                        f.write_str("(begin")?;
+                        self.write_block(f, branch, depth + 2)?;
+                        f.write_str(")")?;
+                    }
+                }
+                f.write_str(")")
+            }
+        }
+    }
+
+    fn write_value(&self, f: &mut fmt::Formatter, value: &Value) -> fmt::Result {
+        let (name, operands): (String, Vec<&Atom>) = match *value {
+            Value::Atom(ref atom) => return write!(f, "{}", self.atom(atom)),
+            Value::Primitive(p, ref operands) => (format!("prim {}", p.name()), operands.iter().collect()),
+            Value::Global(name) => (format!("global {}", symbol(name)), Vec::new()),
+            Value::SetGlobal(name, ref value) => (format!("set-global! {}", symbol(name)), vec![value]),
+            Value::SetLocal(local, ref value) => (format!("set! {}", Named(self, local)), vec![value]),
+            Value::Closure(index, ref captured) => (format!("closure {}", index), captured.iter().collect()),
+            Value::ClosureRef(ref closure, slot) => return write!(f, "(closure-ref {} {})", self.atom(closure), slot),
+            Value::ClosureSet(ref closure, slot, ref value) =>
+                return write!(f, "(closure-set! {} {} {})", self.atom(closure), slot, self.atom(value)),
+            Value::Box(ref value) => ("box".to_string(), value.iter().collect()),
+            Value::Unbox(ref b) => ("unbox".to_string(), vec![b]),
+            Value::SetBox(ref b, ref value) => ("set-box!".to_string(), vec![b, value]),
+            Value::Check(name, ref value) => (format!("check {}", symbol(name)), vec![value]),
+        };
+        write!(f, "({}", name)?;
+        for operand in operands {
+            write!(f, " {}", self.atom(operand))?;
+        }
+        f.write_str(")")
+    }
+
+    fn atom<'a>(&'a self, atom: &'a Atom) -> AtomText<'a> {
+        AtomText(self, atom)
+    }
+}
+
+/// A local printed as one symbol of its name and index.
+struct Named<'a>(&'a Function, Local);
+
+impl<'a> fmt::Display for Named<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Local(index) = self.1;
+        write!(f, "{}", symbol(Symbol::intern(&format!("{}.{}", self.0.locals[index], index))))
+    }
+}
+
+struct AtomText<'a>(&'a Function, &'a Atom);
+
+impl<'a> fmt::Display for AtomText<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self.1 {
+            Atom::Local(local) => write!(f, "{}", Named(self.0, local)),
+            Atom::Const(ref datum) => match datum.kind {
+                DatumKind::Symbol(_) | DatumKind::Pair(..) | DatumKind::Nil | DatumKind::Labeled(..) =>
+                    write!(f, "'{}", datum),
+                _ => write!(f, "{}", datum),
+            },
+            Atom::Unspecified => f.write_str("unspecified"),
+            Atom::Unassigned => f.write_str("unassigned"),
+        }
+    }
+}
+
+/// `name` as it is written in a datum, between bars if it has to be.
+fn symbol(name: Symbol) -> Datum {
+    Datum::new(DatumKind::Symbol(name), Span::default())
+}
+
+fn newline(f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+    write!(f, "\n{:1$}", "", depth * 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use ir;
+    use pipeline;
+
+    /// The program `input` is lowered to, without the prelude.
+    fn converted(input: &str) -> String {
+        let mut functions = Vec::new();
+        let forms = pipeline::resolve("test", input.as_bytes())
+            .map(|r| ir::lower(r.unwrap(), &mut functions))
+            .collect();
+        super::convert(functions, forms).to_string()
+    }
+
+    #[test]
+    fn names_intermediate_values() {
+        assert_eq!(converted("(define (f x) (if (car x) (g (cdr x) 'a) \"b\")) (define (g x y) x)"), "\
+(function 0 (closure.0 k.1 x.2)
+  (let t.3 (prim car x.2))
+  (if t.3
+    (begin
+      (let g.4 (global g))
+      (let t.5 (prim cdr x.2))
+      (call g.4 k.1 t.5 'a))
+    (call k.1 \"b\")))
+(function 1 (closure.0 k.1)
+  (let t.2 (closure 0))
+  (let t.3 (set-global! f t.2))
+  (call k.1 t.3))
+(function 2 (closure.0 k.1 x.2 y.3)
+  (call k.1 x.2))
+(function 3 (closure.0 k.1)
+  (let t.2 (closure 2))
+  (let t.3 (set-global! g t.2))
+  (call k.1 t.3))
+(main 1 3)
+");
+    }
+
+    #[test]
+    fn letrec() {
+        // `f` captures `g` before it is bound, and is patched after.
+        assert_eq!(converted("(lambda () (define (f) (g)) (define (g) (f)) (define x 1) (set! x 2) f)"), "\
+(function 0 (closure.0 k.1)
+  (let t.2 (closure-ref closure.0 0))
+  (call t.2 k.1))
+(function 1 (closure.0 k.1)
+  (let t.2 (closure-ref closure.0 0))
+  (call t.2 k.1))
+(function 2 (closure.0 k.1)
+  (let f.2 (closure 0 unassigned))
+  (let g.3 (closure 1 f.2))
+  (let _.4 (closure-set! f.2 0 g.3))
+  (let x.5 1)
+  (do (set! x.5 2))
+  (call k.1 f.2))
+(function 3 (closure.0 k.1)
+  (let t.2 (closure 2))
+  (call k.1 t.2))
+(main 3)
+");
+    }
+}
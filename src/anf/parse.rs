@@ -0,0 +1,737 @@
+//! Reads programs back from the text `Program` prints them as, so that
+//! passes can be tested from text to text and programs written by hand can
+//! be compiled. The text has a small reader of its own rather than going
+//! through the lexer: it only needs the syntax data are printed in, plus
+//! `;` comments, and any layout is fine.
+
+use std::f64;
+use std::fmt;
+use std::mem;
+use std::vec;
+
+use anf::{Atom, Block, Function, Local, Program, Stmt, Tail, Value};
+use error::{Error, Result};
+use primitive::Primitive;
+use reader::{Datum, DatumKind, FIXNUMS};
+use span::{Position, Span};
+use symbol::Symbol;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Something other than what has to be there, which is described.
+    Expected(&'static str),
+    /// A function whose index isn't the next one, which comes first.
+    MisnumberedFunction(usize, usize),
+    /// A parameter that isn't the local with its position as its index.
+    MisnumberedParameter(usize),
+    /// A local written with another name than it first was.
+    RenamedLocal(usize, Symbol, Symbol),
+    UnknownPrimitive(Symbol),
+    UnknownOperation(Symbol),
+    TooFewOperands(Symbol),
+    TooManyOperands(Symbol),
+    /// A `(`, `#(`, `#u8(`, `"` or `|` that the text ends inside.
+    Unclosed(&'static str),
+    /// A `)` or `.` where it can't be.
+    Unexpected(char),
+    /// A number, character, escape or `#` syntax that isn't one, which is
+    /// named.
+    Invalid(&'static str),
+    UndefinedLabel(u32),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::Expected(what) => write!(f, "Expected {}", what),
+            ErrorKind::MisnumberedFunction(next, index) => write!(f, "Function {} is numbered {}", next, index),
+            ErrorKind::MisnumberedParameter(i) => write!(f, "Parameter {} isn't local {}", i, i),
+            ErrorKind::RenamedLocal(index, first, other) =>
+                write!(f, "Local {} is named both '{}' and '{}'", index, first, other),
+            ErrorKind::UnknownPrimitive(name) => write!(f, "'{}' is not a primitive", name),
+            ErrorKind::UnknownOperation(name) => write!(f, "'{}' is not an operation", name),
+            ErrorKind::TooFewOperands(name) => write!(f, "Too few operands for '{}'", name),
+            ErrorKind::TooManyOperands(name) => write!(f, "Too many operands for '{}'", name),
+            ErrorKind::Unclosed(opener) => write!(f, "'{}' is never closed", opener),
+            ErrorKind::Unexpected(c) => write!(f, "Unexpected '{}'", c),
+            ErrorKind::Invalid(what) => write!(f, "Invalid {}", what),
+            ErrorKind::UndefinedLabel(n) => write!(f, "Datum label #{}# is not defined", n),
+        }
+    }
+}
+
+/// Parses a whole program from `text`, naming it `file_name` in the first
+/// error in it.
+pub fn parse(file_name: &str, text: &str) -> Result<Program> {
+    let mut parser = Parser {
+        file_name,
+        locals: Vec::new(),
+    };
+    let mut scanner = Scanner {
+        file_name,
+        text,
+        pos: Position::start(),
+    };
+    let mut program = Program::default();
+    while let Some(datum) = scanner.next()? {
+        let (head, operands, span) = parser.form(datum)?;
+        let mut operands = operands.into_iter();
+        match head.as_str() {
+            "function" => {
+                let datum = parser.operand(&mut operands, head, span)?;
+                let (index, next) = (parser.number(&datum)?, program.functions.len());
+                if index != next {
+                    return Err(parser.error(datum.span, ErrorKind::MisnumberedFunction(next, index)));
+                }
+                program.functions.push(parser.function(operands.collect(), span)?);
+            }
+            "main" => program.main = operands.map(|o| parser.number(&o)).collect::<Result<_>>()?,
+            _ => return Err(parser.error(span, ErrorKind::Expected("'function' or 'main'"))),
+        }
+    }
+    Ok(program)
+}
+
+/// Parses functions, with the names of the locals of the one it is in as
+/// they are found.
+#[derive(Debug)]
+struct Parser<'a> {
+    file_name: &'a str,
+    locals: Vec<Option<Symbol>>,
+}
+
+impl<'a> Parser<'a> {
+    /// A function spanning `span`, from its parameters and body.
+    fn function(&mut self, mut items: Vec<Datum>, span: Span) -> Result<Function> {
+        if items.is_empty() {
+            return Err(self.error(span, ErrorKind::Expected("parameters")));
+        }
+        let (params, rest) = self.dotted(items.remove(0))?;
+        let (count, has_rest) = (params.len(), rest.is_some());
+        for (i, param) in params.iter().chain(&rest).enumerate() {
+            if self.local(param)? != Local(i) {
+                return Err(self.error(param.span, ErrorKind::MisnumberedParameter(i)));
+            }
+        }
+        let body = self.block(items, span)?;
+        let locals = mem::take(&mut self.locals).into_iter().map(|l| l.unwrap_or_else(|| Symbol::intern("_")));
+        Ok(Function {
+            locals: locals.collect(),
+            params: count,
+            rest: has_rest,
+            body,
+        })
+    }
+
+    fn block(&mut self, mut items: Vec<Datum>, span: Span) -> Result<Block> {
+        let tail = items.pop().ok_or_else(|| self.error(span, ErrorKind::Expected("a tail call or 'if'")))?;
+        let stmts = items.into_iter().map(|s| self.stmt(s)).collect::<Result<_>>()?;
+        Ok(Block {
+            stmts,
+            tail: self.tail(tail)?,
+        })
+    }
+
+    /// A branch of an `if`: a tail, or statements and a tail in a `begin`.
+    fn branch(&mut self, datum: Datum) -> Result<Block> {
+        if head(&datum) == Some("begin") {
+            let span = datum.span;
+            let mut items = self.list(datum)?;
+            items.remove(0);
+            return self.block(items, span);
+        }
+        Ok(Block {
+            stmts: Vec::new(),
+            tail: self.tail(datum)?,
+        })
+    }
+
+    fn stmt(&mut self, datum: Datum) -> Result<Stmt> {
+        let (head, mut operands, span) = self.form(datum)?;
+        match (head.as_str(), operands.len()) {
+            ("let", 2) => {
+                let value = operands.pop().expect("two operands");
+                let local = self.local(&operands[0])?;
+                Ok(Stmt::Let(local, self.value(value)?))
+            }
+            ("do", 1) => Ok(Stmt::Do(self.value(operands.remove(0))?)),
+            _ => Err(self.error(span, ErrorKind::Expected("'let' or 'do'"))),
+        }
+    }
+
+    fn tail(&mut self, datum: Datum) -> Result<Tail> {
+        let (head, operands, span) = self.form(datum)?;
+        let mut operands = operands.into_iter();
+        match head.as_str() {
+            "call" => {
+                let operator = self.atom(self.operand(&mut operands, head, span)?)?;
+                Ok(Tail::Call(operator, self.atoms(operands)?))
+            }
+            "if" if operands.len() == 3 => {
+                let test = self.atom(operands.next().expect("three operands"))?;
+                let consequent = self.branch(operands.next().expect("three operands"))?;
+                let alternate = self.branch(operands.next().expect("three operands"))?;
+                Ok(Tail::If(test, Box::new(consequent), Box::new(alternate)))
+            }
+            _ => Err(self.error(span, ErrorKind::Expected("'call' or 'if'"))),
+        }
+    }
+
+    fn value(&mut self, datum: Datum) -> Result<Value> {
+        if head(&datum).is_none_or(|head| head == "quote") {
+            return Ok(Value::Atom(self.atom(datum)?));
+        }
+        let (head, operands, span) = self.form(datum)?;
+        let mut operands = operands.into_iter();
+        let o = &mut operands;
+        let value = match head.as_str() {
+            "prim" => {
+                let datum = self.operand(o, head, span)?;
+                let name = self.name(&datum)?;
+                let p = Primitive::named(name.as_str())
+                    .ok_or_else(|| self.error(datum.span, ErrorKind::UnknownPrimitive(name)))?;
+                return Ok(Value::Primitive(p, self.atoms(operands)?));
+            }
+            "closure" => {
+                let index = self.number(&self.operand(o, head, span)?)?;
+                return Ok(Value::Closure(index, self.atoms(operands)?));
+            }
+            "box" => Value::Box(o.next().map(|d| self.atom(d)).transpose()?),
+            "global" => Value::Global(self.name(&self.operand(o, head, span)?)?),
+            "set-global!" => {
+                let name = self.name(&self.operand(o, head, span)?)?;
+                Value::SetGlobal(name, self.atom(self.operand(o, head, span)?)?)
+            }
+            "set!" => {
+                let local = self.local(&self.operand(o, head, span)?)?;
+                Value::SetLocal(local, self.atom(self.operand(o, head, span)?)?)
+            }
+            "closure-ref" => {
+                let closure = self.atom(self.operand(o, head, span)?)?;
+                Value::ClosureRef(closure, self.number(&self.operand(o, head, span)?)?)
+            }
+            "closure-set!" => {
+                let closure = self.atom(self.operand(o, head, span)?)?;
+                let slot = self.number(&self.operand(o, head, span)?)?;
+                Value::ClosureSet(closure, slot, self.atom(self.operand(o, head, span)?)?)
+            }
+            "unbox" => Value::Unbox(self.atom(self.operand(o, head, span)?)?),
+            "set-box!" => {
+                let b = self.atom(self.operand(o, head, span)?)?;
+                Value::SetBox(b, self.atom(self.operand(o, head, span)?)?)
+            }
+            "check" => {
+                let name = self.name(&self.operand(o, head, span)?)?;
+                Value::Check(name, self.atom(self.operand(o, head, span)?)?)
+            }
+            _ => return Err(self.error(span, ErrorKind::UnknownOperation(head))),
+        };
+        match operands.next() {
+            Some(_) => Err(self.error(span, ErrorKind::TooManyOperands(head))),
+            None => Ok(value),
+        }
+    }
+
+    fn atoms(&mut self, data: vec::IntoIter<Datum>) -> Result<Vec<Atom>> {
+        data.map(|d| self.atom(d)).collect()
+    }
+
+    fn atom(&mut self, datum: Datum) -> Result<Atom> {
+        match datum.kind {
+            DatumKind::Symbol(s) if s.as_str() == "unspecified" => Ok(Atom::Unspecified),
+            DatumKind::Symbol(s) if s.as_str() == "unassigned" => Ok(Atom::Unassigned),
+            DatumKind::Symbol(_) => Ok(Atom::Local(self.local(&datum)?)),
+            DatumKind::Pair(..) if head(&datum) == Some("quote") => {
+                let span = datum.span;
+                let mut items = self.list(datum)?;
+                match items.len() {
+                    2 => self.constant(items.pop().expect("two items")),
+                    _ => Err(self.error(span, ErrorKind::Expected("an atom"))),
+                }
+            }
+            DatumKind::Pair(..) | DatumKind::Nil | DatumKind::Labeled(..) | DatumKind::Reference(_) =>
+                Err(self.error(datum.span, ErrorKind::Expected("an atom"))),
+            _ => self.constant(datum),
+        }
+    }
+
+    /// `datum` as a constant, whose labels are its own: each constant is
+    /// printed with labels counting from 0.
+    fn constant(&self, datum: Datum) -> Result<Atom> {
+        let labels = datum.labels();
+        let mut stack = vec![&datum];
+        while let Some(d) = stack.pop() {
+            match d.kind {
+                DatumKind::Pair(ref head, ref tail) => stack.extend(&[&**head, &**tail]),
+                DatumKind::Vector(ref items) => stack.extend(items),
+                DatumKind::Labeled(_, ref inner) => stack.push(inner),
+                DatumKind::Reference(n) if !labels.contains_key(&n) =>
+                    return Err(self.error(d.span, ErrorKind::UndefinedLabel(n))),
+                _ => {}
+            }
+        }
+        Ok(Atom::Const(datum))
+    }
+
+    /// The local written as `name.index`, which has to have the same name
+    /// everywhere.
+    fn local(&mut self, datum: &Datum) -> Result<Local> {
+        let expected = || self.error(datum.span, ErrorKind::Expected("a local"));
+        let text = match datum.kind {
+            DatumKind::Symbol(s) => s.as_str(),
+            _ => return Err(expected()),
+        };
+        let mut parts = text.rsplitn(2, '.');
+        let index = parts.next().and_then(|i| i.parse().ok());
+        let (index, name): (usize, _) = match (index, parts.next()) {
+            (Some(index), Some(name)) => (index, Symbol::intern(name)),
+            _ => return Err(expected()),
+        };
+        if self.locals.len() <= index {
+            self.locals.resize(index + 1, None);
+        }
+        match self.locals[index] {
+            Some(first) if first != name =>
+                return Err(self.error(datum.span, ErrorKind::RenamedLocal(index, first, name))),
+            _ => self.locals[index] = Some(name),
+        }
+        Ok(Local(index))
+    }
+
+    /// The next operand of the operation `head`, which spans `span`.
+    fn operand(&self, operands: &mut vec::IntoIter<Datum>, head: Symbol, span: Span) -> Result<Datum> {
+        operands.next().ok_or_else(|| self.error(span, ErrorKind::TooFewOperands(head)))
+    }
+
+    fn name(&self, datum: &Datum) -> Result<Symbol> {
+        match datum.kind {
+            DatumKind::Symbol(s) => Ok(s),
+            _ => Err(self.error(datum.span, ErrorKind::Expected("a name"))),
+        }
+    }
+
+    fn number(&self, datum: &Datum) -> Result<usize> {
+        match datum.kind {
+            DatumKind::Fixnum(n) if n >= 0 => Ok(n as usize),
+            _ => Err(self.error(datum.span, ErrorKind::Expected("an index"))),
+        }
+    }
+
+    /// The name of an operation, its operands and the span of them all.
+    fn form(&self, datum: Datum) -> Result<(Symbol, Vec<Datum>, Span)> {
+        let span = datum.span;
+        let mut items = self.list(datum)?;
+        match items.first().map(|head| &head.kind) {
+            Some(&DatumKind::Symbol(head)) => {
+                items.remove(0);
+                Ok((head, items, span))
+            }
+            _ => Err(self.error(span, ErrorKind::Expected("a form"))),
+        }
+    }
+
+    fn list(&self, datum: Datum) -> Result<Vec<Datum>> {
+        let span = datum.span;
+        match self.dotted(datum)? {
+            (items, None) => Ok(items),
+            (_, Some(_)) => Err(self.error(span, ErrorKind::Expected("a proper list"))),
+        }
+    }
+
+    /// The items of a list, and what it ends in if it isn't proper.
+    fn dotted(&self, mut datum: Datum) -> Result<(Vec<Datum>, Option<Datum>)> {
+        let mut items = Vec::new();
+        loop {
+            match datum.kind {
+                DatumKind::Pair(head, tail) => {
+                    items.push(*head);
+                    datum = *tail;
+                }
+                DatumKind::Nil => return Ok((items, None)),
+                _ if items.is_empty() => return Err(self.error(datum.span, ErrorKind::Expected("a list"))),
+                _ => return Ok((items, Some(datum))),
+            }
+        }
+    }
+
+    fn error(&self, span: Span, kind: ErrorKind) -> Error {
+        Error::new(self.file_name, span, kind)
+    }
+}
+
+/// The name of the form `datum` if it is one.
+fn head(datum: &Datum) -> Option<&'static str> {
+    match datum.kind {
+        DatumKind::Pair(ref head, _) => match head.kind {
+            DatumKind::Symbol(s) => Some(s.as_str()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// What the scanner finds next.
+enum Item {
+    Datum(Datum),
+    Close(Span),
+    Dot(Span),
+    End,
+}
+
+/// Reads the data a program is written as. Only the syntax the datum
+/// printer writes is read: lists, vectors, bytevectors, `'`, datum labels
+/// and the simple data.
+#[derive(Debug)]
+struct Scanner<'a> {
+    file_name: &'a str,
+    text: &'a str,
+    pos: Position,
+}
+
+impl<'a> Scanner<'a> {
+    /// The next top-level datum, if there is one.
+    fn next(&mut self) -> Result<Option<Datum>> {
+        match self.item()? {
+            Item::Datum(datum) => Ok(Some(datum)),
+            Item::Close(span) => Err(self.error(span, ErrorKind::Unexpected(')'))),
+            Item::Dot(span) => Err(self.error(span, ErrorKind::Unexpected('.'))),
+            Item::End => Ok(None),
+        }
+    }
+
+    /// A datum that has to come next.
+    fn datum(&mut self) -> Result<Datum> {
+        match self.item()? {
+            Item::Datum(datum) => Ok(datum),
+            Item::Close(span) => Err(self.error(span, ErrorKind::Unexpected(')'))),
+            Item::Dot(span) => Err(self.error(span, ErrorKind::Unexpected('.'))),
+            Item::End => Err(self.error(Span::at(self.pos), ErrorKind::Expected("a datum"))),
+        }
+    }
+
+    fn item(&mut self) -> Result<Item> {
+        self.skip_space();
+        let start = self.pos;
+        let c = match self.bump() {
+            Some(c) => c,
+            None => return Ok(Item::End),
+        };
+        let kind = match c {
+            '(' => {
+                let (items, tail, close) = self.items(self.span(start), "(", true)?;
+                let tail = tail.unwrap_or_else(|| Datum::new(DatumKind::Nil, close));
+                let mut list = Datum::list(items, tail);
+                list.span = Span::new(start, close.end);
+                return Ok(Item::Datum(list));
+            }
+            ')' => return Ok(Item::Close(self.span(start))),
+            '\'' => {
+                let quote = Datum::new(DatumKind::Symbol(Symbol::intern("quote")), self.span(start));
+                let datum = self.datum()?;
+                let end = datum.span.end;
+                let mut list = Datum::list(vec![quote, datum], Datum::new(DatumKind::Nil, Span::at(end)));
+                list.span = Span::new(start, end);
+                return Ok(Item::Datum(list));
+            }
+            '"' => DatumKind::String(self.quoted(self.span(start), "\"")?),
+            '|' => DatumKind::Symbol(Symbol::intern(&self.quoted(self.span(start), "|")?)),
+            '#' => match self.peek() {
+                Some('(') => {
+                    self.bump();
+                    DatumKind::Vector(self.items(self.span(start), "#(", false)?.0)
+                }
+                Some('\\') => {
+                    self.bump();
+                    self.character(start)?
+                }
+                Some(c) if c.is_ascii_digit() => return self.label(start).map(Item::Datum),
+                _ => match self.token(self.pos) {
+                    "t" | "true" => DatumKind::Bool(true),
+                    "f" | "false" => DatumKind::Bool(false),
+                    "u8" if self.peek() == Some('(') => {
+                        self.bump();
+                        let (items, _, _) = self.items(self.span(start), "#u8(", false)?;
+                        let bytes = items.iter().map(|d| match d.kind {
+                            DatumKind::Fixnum(n @ 0..=255) => Ok(n as u8),
+                            _ => Err(self.error(d.span, ErrorKind::Expected("a byte"))),
+                        });
+                        DatumKind::Bytevector(bytes.collect::<Result<_>>()?)
+                    }
+                    _ => return Err(self.error(self.span(start), ErrorKind::Invalid("'#' syntax"))),
+                },
+            },
+            _ => match self.token(start) {
+                "." => return Ok(Item::Dot(self.span(start))),
+                token => number(token).ok_or_else(|| self.error(self.span(start), ErrorKind::Invalid("number")))?,
+            },
+        };
+        Ok(Item::Datum(Datum::new(kind, self.span(start))))
+    }
+
+    /// The items of a list or vector opened by `open`, up to its `)` and its
+    /// span, and the datum after a `.` if the list is `dotted`.
+    fn items(&mut self, open: Span, opener: &'static str, dotted: bool) -> Result<(Vec<Datum>, Option<Datum>, Span)> {
+        let mut items = Vec::new();
+        loop {
+            match self.item()? {
+                Item::Datum(datum) => items.push(datum),
+                Item::Close(close) => return Ok((items, None, close)),
+                Item::Dot(_) if dotted && !items.is_empty() => {
+                    let tail = self.datum()?;
+                    return match self.item()? {
+                        Item::Close(close) => Ok((items, Some(tail), close)),
+                        Item::End => Err(self.error(open, ErrorKind::Unclosed(opener))),
+                        Item::Datum(Datum { span, .. }) | Item::Dot(span) =>
+                            Err(self.error(span, ErrorKind::Expected("')'"))),
+                    };
+                }
+                Item::Dot(dot) => return Err(self.error(dot, ErrorKind::Unexpected('.'))),
+                Item::End => return Err(self.error(open, ErrorKind::Unclosed(opener))),
+            }
+        }
+    }
+
+    /// `#n=` and the datum it labels, or `#n#`, from just after the `#` at
+    /// `start`.
+    fn label(&mut self, start: Position) -> Result<Datum> {
+        let digits = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.bump();
+        }
+        let n = self.text[digits.offset..self.pos.offset].parse();
+        match (n, self.bump()) {
+            (Ok(n), Some('=')) => {
+                let datum = self.datum()?;
+                let span = Span::new(start, datum.span.end);
+                Ok(Datum::new(DatumKind::Labeled(n, Box::new(datum)), span))
+            }
+            (Ok(n), Some('#')) => Ok(Datum::new(DatumKind::Reference(n), self.span(start))),
+            _ => Err(self.error(self.span(start), ErrorKind::Invalid("datum label"))),
+        }
+    }
+
+    /// A character, from just after the `#\\` at `start`.
+    fn character(&mut self, start: Position) -> Result<DatumKind> {
+        let first = self.pos;
+        let invalid = |s: &Self| s.error(s.span(start), ErrorKind::Invalid("character"));
+        if self.bump().is_none() {
+            return Err(invalid(self));
+        }
+        self.token(self.pos);
+        let name = &self.text[first.offset..self.pos.offset];
+        let mut chars = name.chars();
+        let c = match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            (Some('x'), Some(_)) => u32::from_str_radix(&name[1..], 16).ok().and_then(char::from_u32),
+            _ => match name {
+                "alarm" => Some('\u{7}'),
+                "backspace" => Some('\u{8}'),
+                "delete" => Some('\u{7f}'),
+                "escape" => Some('\u{1b}'),
+                "newline" => Some('\n'),
+                "null" => Some('\0'),
+                "return" => Some('\r'),
+                "space" => Some(' '),
+                "tab" => Some('\t'),
+                _ => None,
+            },
+        };
+        c.map(DatumKind::Char).ok_or_else(|| invalid(self))
+    }
+
+    /// The text of a string or a symbol between bars, opened by `open`, up
+    /// to its closing `quote`, with escapes replaced.
+    fn quoted(&mut self, open: Span, quote: &'static str) -> Result<String> {
+        let mut s = String::new();
+        loop {
+            let escape = self.pos;
+            match self.bump() {
+                None => return Err(self.error(open, ErrorKind::Unclosed(quote))),
+                Some(c) if quote.starts_with(c) => return Ok(s),
+                Some('\\') => {
+                    let c = match self.bump() {
+                        Some('a') => Some('\u{7}'),
+                        Some('b') => Some('\u{8}'),
+                        Some('t') => Some('\t'),
+                        Some('n') => Some('\n'),
+                        Some('r') => Some('\r'),
+                        Some('x') => {
+                            let digits = self.pos;
+                            while self.peek().is_some_and(|c| c != ';' && !quote.starts_with(c)) {
+                                self.bump();
+                            }
+                            let code = u32::from_str_radix(&self.text[digits.offset..self.pos.offset], 16);
+                            match self.bump() {
+                                Some(';') => code.ok().and_then(char::from_u32),
+                                _ => None,
+                            }
+                        }
+                        Some(c @ '\\') | Some(c @ '"') | Some(c @ '|') => Some(c),
+                        _ => None,
+                    };
+                    s.push(c.ok_or_else(|| self.error(self.span(escape), ErrorKind::Invalid("escape")))?);
+                }
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /// The text from `start` to the next delimiter, which is left unread.
+    fn token(&mut self, start: Position) -> &'a str {
+        while self.peek().is_some_and(|c| !c.is_whitespace() && !"()\";'|".contains(c)) {
+            self.bump();
+        }
+        &self.text[start.offset..self.pos.offset]
+    }
+
+    fn skip_space(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                ';' => while self.peek().is_some_and(|c| c != '\n') {
+                    self.bump();
+                },
+                c if c.is_whitespace() => {}
+                _ => return,
+            }
+            self.bump();
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos.offset..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        for &b in c.encode_utf8(&mut [0; 4]).as_bytes() {
+            self.pos = self.pos.advance(b);
+        }
+        Some(c)
+    }
+
+    /// The span from `start` to what has been read.
+    fn span(&self, start: Position) -> Span {
+        Span::new(start, self.pos)
+    }
+
+    fn error(&self, span: Span, kind: ErrorKind) -> Error {
+        Error::new(self.file_name, span, kind)
+    }
+}
+
+/// The number `token` is, a symbol if it isn't one, or `None` if it looks
+/// like a number but isn't one a datum can hold.
+fn number(token: &str) -> Option<DatumKind> {
+    match token {
+        "+inf.0" => return Some(DatumKind::Flonum(f64::INFINITY)),
+        "-inf.0" => return Some(DatumKind::Flonum(f64::NEG_INFINITY)),
+        "+nan.0" | "-nan.0" => return Some(DatumKind::Flonum(f64::NAN)),
+        _ => {}
+    }
+    let unsigned = token.strip_prefix(['+', '-']).unwrap_or(token);
+    let mut chars = unsigned.chars();
+    match (chars.next(), chars.next()) {
+        (Some('0'..='9'), _) | (Some('.'), Some('0'..='9')) => {}
+        _ => return Some(DatumKind::Symbol(Symbol::intern(token))),
+    }
+    if unsigned.bytes().all(|b| b.is_ascii_digit()) {
+        token.parse().ok().filter(|n| FIXNUMS.contains(n)).map(DatumKind::Fixnum)
+    } else {
+        token.parse().ok().map(DatumKind::Flonum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error;
+    use pipeline;
+
+    fn round_trip(text: &str) {
+        assert_eq!(parse("test", text).unwrap().to_string(), text);
+    }
+
+    fn error(text: &str) -> (ErrorKind, String) {
+        match parse("test", text).unwrap_err() {
+            error::Error { kind: error::ErrorKind::Anf(kind), span, .. } =>
+                (kind, format!("{}-{}", span.start, span.end)),
+            e => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn reads_what_is_printed() {
+        let source = "(define (f . xs) (+ (car xs) (length xs)))
+                      (write (list (f 1 2) 'a \"b\" #\\c 1.5 '(1 . 2) #u8(1)))";
+        round_trip(&pipeline::compile("test", source.as_bytes()).unwrap().to_string());
+    }
+
+    #[test]
+    fn constants() {
+        round_trip("\
+(function 0 (closure.0 k.1 . rest.2)
+  (let t.3 (prim cons '|a b| '(1 . #(2.5 -0.0 +nan.0)) #u8(0 255)))
+  (let |1.4| (prim cons \"a\\tb\\\\\\\"\" \"\\x1;\"))
+  (let t.5 (prim cons #\\( #\\space))
+  (let t.6 (prim cons #\\x1 '#0=(a . #0#)))
+  (let t.7 (prim cons '#0=(#0#) '#0=#(b #0#)))
+  (call k.1 #t #f '() unspecified unassigned))
+(main 0)
+");
+    }
+
+    #[test]
+    fn written_by_hand() {
+        let program = parse("test", "
+            ; Comments and any layout are fine.
+            (function 0 (c.0 k.1 x.2)
+              (if x.2 (call k.1 x.2) (begin (do (box)) (call k.1 #f))))
+            (main)").unwrap();
+        assert_eq!(program.to_string(), "\
+(function 0 (c.0 k.1 x.2)
+  (if x.2
+    (call k.1 x.2)
+    (begin
+      (do (box))
+      (call k.1 #f))))
+(main)
+");
+    }
+
+    #[test]
+    fn errors() {
+        let kar = Symbol::intern("kar");
+        let unbox = Symbol::intern("unbox");
+        assert_eq!(error("(function 1 (c.0))"), (ErrorKind::MisnumberedFunction(0, 1), "1:11-1:12".to_owned()));
+        assert_eq!(error("(function 0 (c.0 k.2) (call k.2))"),
+                   (ErrorKind::MisnumberedParameter(1), "1:18-1:21".to_owned()));
+        assert_eq!(error("(function 0 (c.0) (call x.0))"),
+                   (ErrorKind::RenamedLocal(0, Symbol::intern("c"), Symbol::intern("x")), "1:25-1:28".to_owned()));
+        assert_eq!(error("(function 0 (c.0) (call x))"), (ErrorKind::Expected("a local"), "1:25-1:26".to_owned()));
+        assert_eq!(error("(function 0 (c.0) (let t.1 (prim kar c.0)) (call c.0))"),
+                   (ErrorKind::UnknownPrimitive(kar), "1:34-1:37".to_owned()));
+        assert_eq!(error("(function 0 (c.0) (do (unbox)) (call c.0))"),
+                   (ErrorKind::TooFewOperands(unbox), "1:23-1:30".to_owned()));
+        assert_eq!(error("(function 0 (c.0) (do (unbox c.0 c.0)) (call c.0))"),
+                   (ErrorKind::TooManyOperands(unbox), "1:23-1:38".to_owned()));
+        assert_eq!(error("(function 0 (c.0) (return c.0))"),
+                   (ErrorKind::Expected("'call' or 'if'"), "1:19-1:31".to_owned()));
+        assert_eq!(error("(main 0) (return)"), (ErrorKind::Expected("'function' or 'main'"), "1:10-1:18".to_owned()));
+        assert_eq!(error("(function 0 (c.0) (call c.0 '#1#))"), (ErrorKind::UndefinedLabel(1), "1:30-1:33".to_owned()));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(error("(main\n (main)"), (ErrorKind::Unclosed("("), "1:1-1:2".to_owned()));
+        assert_eq!(error("(main 0) \"a\\\"b"), (ErrorKind::Unclosed("\""), "1:10-1:11".to_owned()));
+        assert_eq!(error("(main 0))"), (ErrorKind::Unexpected(')'), "1:9-1:10".to_owned()));
+        assert_eq!(error("(. 0)"), (ErrorKind::Unexpected('.'), "1:2-1:3".to_owned()));
+        assert_eq!(error("(main 0 . 1 2)"), (ErrorKind::Expected("')'"), "1:13-1:14".to_owned()));
+        assert_eq!(error("(main 1152921504606846976)"), (ErrorKind::Invalid("number"), "1:7-1:26".to_owned()));
+        assert_eq!(error("(main #\\bell)"), (ErrorKind::Invalid("character"), "1:7-1:13".to_owned()));
+        assert_eq!(error("(main \"\\q\")"), (ErrorKind::Invalid("escape"), "1:8-1:10".to_owned()));
+        assert_eq!(error("(main #u8(256))"), (ErrorKind::Expected("a byte"), "1:11-1:14".to_owned()));
+        assert_eq!(error("(main #q)"), (ErrorKind::Invalid("'#' syntax"), "1:7-1:9".to_owned()));
+        assert_eq!(error("(main ')"), (ErrorKind::Unexpected(')'), "1:8-1:9".to_owned()));
+    }
+}
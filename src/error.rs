@@ -0,0 +1,129 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+use anf;
+use expander;
+use lexer;
+use reader;
+use resolve;
+use span::Span;
+use syntax;
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// A diagnostic from any stage of the pipeline, pointing at the source it is
+/// about. `notes` point at other places that help explain it.
+#[derive(Clone, Debug)]
+pub struct Error {
+    pub file_name: String,
+    pub span: Span,
+    pub kind: ErrorKind,
+    pub notes: Vec<Note>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Note {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Error {
+    pub fn new<K: Into<ErrorKind>>(file_name: &str, span: Span, kind: K) -> Self {
+        Error {
+            file_name: file_name.to_owned(),
+            span,
+            kind: kind.into(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_note<S: Into<String>>(mut self, span: Span, message: S) -> Self {
+        self.notes.push(Note {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error ({}:{}): {}", self.file_name, self.span.start, self.kind)?;
+        for note in &self.notes {
+            write!(f, "\n  {}:{}: {}", self.file_name, note.span.start, note.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Error {}
+
+#[derive(Clone, Debug)]
+pub enum ErrorKind {
+    Io(String),
+    Lex(lexer::ErrorKind),
+    Read(reader::ErrorKind),
+    Syntax(syntax::ErrorKind),
+    Expand(expander::ErrorKind),
+    Resolve(resolve::ErrorKind),
+    /// From reading back a printed program.
+    Anf(anf::parse::ErrorKind),
+}
+
+impl From<io::Error> for ErrorKind {
+    fn from(e: io::Error) -> Self {
+        ErrorKind::Io(e.to_string())
+    }
+}
+
+impl From<lexer::ErrorKind> for ErrorKind {
+    fn from(kind: lexer::ErrorKind) -> Self {
+        ErrorKind::Lex(kind)
+    }
+}
+
+impl From<reader::ErrorKind> for ErrorKind {
+    fn from(kind: reader::ErrorKind) -> Self {
+        ErrorKind::Read(kind)
+    }
+}
+
+impl From<syntax::ErrorKind> for ErrorKind {
+    fn from(kind: syntax::ErrorKind) -> Self {
+        ErrorKind::Syntax(kind)
+    }
+}
+
+impl From<expander::ErrorKind> for ErrorKind {
+    fn from(kind: expander::ErrorKind) -> Self {
+        ErrorKind::Expand(kind)
+    }
+}
+
+impl From<resolve::ErrorKind> for ErrorKind {
+    fn from(kind: resolve::ErrorKind) -> Self {
+        ErrorKind::Resolve(kind)
+    }
+}
+
+impl From<anf::parse::ErrorKind> for ErrorKind {
+    fn from(kind: anf::parse::ErrorKind) -> Self {
+        ErrorKind::Anf(kind)
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::Io(ref msg) => write!(f, "Read failed: {}", msg),
+            ErrorKind::Lex(ref kind) => write!(f, "{}", kind),
+            ErrorKind::Read(ref kind) => write!(f, "{}", kind),
+            ErrorKind::Syntax(ref kind) => write!(f, "{}", kind),
+            ErrorKind::Expand(ref kind) => write!(f, "{}", kind),
+            ErrorKind::Resolve(ref kind) => write!(f, "{}", kind),
+            ErrorKind::Anf(ref kind) => write!(f, "{}", kind),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use symbol::Symbol;
+
+use super::rules::Macro;
+
+/// What an identifier means where it is used.
+#[derive(Clone)]
+pub enum Binding {
+    /// A variable, by the name it has in the expanded output. Local
+    /// variables get fresh names, so no two of them share one.
+    Variable(Symbol),
+    /// A special form or auxiliary keyword like `else`, by its usual name.
+    Special(Symbol),
+    Macro(Rc<Macro>),
+}
+
+/// Bindings are the same when they are the same variable, keyword or macro
+/// definition, as R7RS literals are matched.
+impl PartialEq for Binding {
+    fn eq(&self, other: &Binding) -> bool {
+        match (self, other) {
+            (Binding::Variable(a), Binding::Variable(b)) | (Binding::Special(a), Binding::Special(b)) => a == b,
+            (Binding::Macro(a), Binding::Macro(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+pub type Env = Rc<Scope>;
+
+/// One level of lexical scope. Bodies add to their scope as definitions
+/// are found in them, so bindings are behind a `RefCell`.
+pub struct Scope {
+    bindings: RefCell<HashMap<Symbol, Binding>>,
+    parent: Option<Env>,
+}
+
+/// The keywords every program starts out with.
+const SPECIAL: &[Symbol] = &[
+    Symbol::QUOTE, Symbol::LAMBDA, Symbol::IF, Symbol::SET, Symbol::DEFINE, Symbol::BEGIN, Symbol::LET,
+    Symbol::LET_STAR, Symbol::LETREC, Symbol::LETREC_STAR, Symbol::COND, Symbol::CASE, Symbol::AND, Symbol::OR,
+    Symbol::WHEN, Symbol::UNLESS, Symbol::DO, Symbol::ELSE, Symbol::ARROW, Symbol::DEFINE_SYNTAX,
+    Symbol::LET_SYNTAX, Symbol::LETREC_SYNTAX, Symbol::SYNTAX_RULES, Symbol::ELLIPSIS, Symbol::UNDERSCORE,
+    Symbol::DEFINE_RECORD_TYPE, Symbol::LET_VALUES, Symbol::LET_STAR_VALUES, Symbol::RECEIVE, Symbol::DEFINE_VALUES,
+];
+
+impl Scope {
+    pub fn global() -> Env {
+        let bindings = SPECIAL.iter().map(|&k| (k, Binding::Special(k))).collect();
+        Rc::new(Scope {
+            bindings: RefCell::new(bindings),
+            parent: None,
+        })
+    }
+
+    pub fn child(parent: &Env) -> Env {
+        Rc::new(Scope {
+            bindings: RefCell::new(HashMap::new()),
+            parent: Some(parent.clone()),
+        })
+    }
+
+    pub fn bind(&self, id: Symbol, binding: Binding) {
+        self.bindings.borrow_mut().insert(id, binding);
+    }
+
+    /// The binding of `id` in this scope alone.
+    pub fn local(&self, id: Symbol) -> Option<Binding> {
+        self.bindings.borrow().get(&id).cloned()
+    }
+
+    /// The binding of `id` in this scope or the innermost one around it.
+    pub fn lookup(&self, id: Symbol) -> Option<Binding> {
+        let mut scope = self;
+        loop {
+            if let Some(binding) = scope.local(id) {
+                return Some(binding);
+            }
+            scope = scope.parent.as_ref()?;
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::fmt;
+
+use symbol::Symbol;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A `define-syntax`, `let-syntax`, `letrec-syntax` or `syntax-rules`
+    /// form with the wrong shape, named by its keyword.
+    MalformedForm(Symbol),
+    /// A keyword or macro name where an expression or variable was expected.
+    MisusedKeyword(Symbol),
+    NoMatchingRule(Symbol),
+    DuplicatePatternVariable(Symbol),
+    /// A second ellipsis in the same list or vector of a pattern.
+    ExtraEllipsis,
+    /// An ellipsis with nothing before it to repeat.
+    MisplacedEllipsis,
+    /// A pattern variable used in a template under fewer ellipses than it
+    /// has in the pattern.
+    MissingEllipsis(Symbol),
+    /// A template ellipsis following nothing that was matched repeatedly.
+    EllipsisWithoutVariables,
+    /// Pattern variables repeated by the same template ellipsis that
+    /// matched different numbers of forms.
+    MismatchedEllipsis,
+}
+
+/// How a form is meant to be written, for `MalformedForm`.
+fn usage(keyword: Symbol) -> &'static str {
+    match keyword {
+        Symbol::DEFINE_SYNTAX => "(define-syntax keyword (syntax-rules ...))",
+        Symbol::LET_SYNTAX => "(let-syntax ((keyword (syntax-rules ...))...) body...)",
+        Symbol::LETREC_SYNTAX => "(letrec-syntax ((keyword (syntax-rules ...))...) body...)",
+        Symbol::SYNTAX_RULES => "(syntax-rules [ellipsis] (literal...) (pattern template)...)",
+        _ => "",
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::MalformedForm(keyword) => write!(f, "Malformed '{}', expected {}", keyword, usage(keyword)),
+            ErrorKind::MisusedKeyword(keyword) => write!(f, "Syntactic keyword '{}' can't be used here", keyword),
+            ErrorKind::NoMatchingRule(name) => write!(f, "No rule of macro '{}' matches this use of it", name),
+            ErrorKind::DuplicatePatternVariable(name) =>
+                write!(f, "Pattern variable '{}' is bound more than once in the same pattern", name),
+            ErrorKind::ExtraEllipsis => f.write_str("A pattern can have only one ellipsis in each list or vector"),
+            ErrorKind::MisplacedEllipsis => f.write_str("An ellipsis must follow the pattern or template it repeats"),
+            ErrorKind::MissingEllipsis(name) =>
+                write!(f, "Pattern variable '{}' must be followed by as many ellipses as in its pattern", name),
+            ErrorKind::EllipsisWithoutVariables =>
+                f.write_str("This ellipsis follows no pattern variable that was matched under an ellipsis"),
+            ErrorKind::MismatchedEllipsis =>
+                f.write_str("Pattern variables repeated together matched different numbers of forms"),
+        }
+    }
+}
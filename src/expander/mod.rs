@@ -0,0 +1,959 @@
+//! Expands macros in data as they come from the reader, before they are
+//! parsed. The output uses only core and derived forms, which the parser
+//! then recognises by name.
+//!
+//! Hygiene is by renaming. Each identifier a macro's template inserts
+//! becomes a fresh alias, remembering where the macro was defined; an alias
+//! that nothing in the output binds means what its original identifier
+//! means there. Every local variable is renamed to a fresh symbol as well,
+//! so no variable in the output can capture another, whatever names they
+//! were written with. Fresh symbols print as the names they were made from.
+
+mod env;
+mod error;
+mod rules;
+
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+use error::{Error, Result};
+use iter::StreamMap;
+use reader::{Datum, DatumKind};
+use span::Span;
+use symbol::Symbol;
+use syntax;
+
+use self::env::{Binding, Env, Scope};
+
+pub use self::error::*;
+
+/// What an identifier inserted by a macro stands for: `name` as it is
+/// bound in `env`, where the macro was defined.
+struct Alias {
+    name: Symbol,
+    env: Env,
+}
+
+/// Expands top-level forms one at a time. Macros defined at top level stay
+/// defined for the forms after them.
+pub struct Expander<'a> {
+    file_name: &'a str,
+    global: Env,
+    aliases: HashMap<Symbol, Alias>,
+}
+
+/// The value in a `define`: an expression, or the formals and body of a
+/// procedure.
+enum Value<'d> {
+    Expr(&'d Datum),
+    Procedure(&'d Datum, Vec<&'d Datum>),
+}
+
+/// A body form once definitions have been found.
+enum BodyForm<'d> {
+    Define(Cow<'d, Datum>, Symbol),
+    Record(Cow<'d, Datum>),
+    Values(Cow<'d, Datum>),
+    Expr(Cow<'d, Datum>),
+}
+
+impl<'a> Expander<'a> {
+    pub fn new(file_name: &'a str) -> Self {
+        Expander {
+            file_name,
+            global: Scope::global(),
+            aliases: HashMap::new(),
+        }
+    }
+
+    /// Expands a top-level form, or returns `None` for one that only
+    /// defines syntax.
+    pub fn expand_toplevel(&mut self, datum: &Datum) -> Result<Option<Datum>> {
+        let global = self.global.clone();
+        let expanded = self.head_expand(datum, &global)?;
+        let datum = expanded.as_ref().unwrap_or(datum);
+
+        match self.head_binding(datum, &global) {
+            Some(Binding::Special(Symbol::DEFINE)) => if let Some((id, value)) = split_define(datum) {
+                let name = self.global_name(id);
+                global.bind(id, Binding::Variable(name));
+                return self.definition(datum, name, value, &global).map(Some);
+            },
+            Some(Binding::Special(Symbol::DEFINE_RECORD_TYPE)) => if let Some(ids) = record_names(datum) {
+                for id in ids {
+                    let name = self.global_name(id);
+                    global.bind(id, Binding::Variable(name));
+                }
+                return self.record_type(datum, &global).map(Some);
+            },
+            Some(Binding::Special(Symbol::DEFINE_VALUES)) => if let Some(ids) = values_names(datum) {
+                for id in ids {
+                    let name = self.global_name(id);
+                    global.bind(id, Binding::Variable(name));
+                }
+                return self.define_values(datum, &global).map(Some);
+            },
+            Some(Binding::Special(Symbol::DEFINE_SYNTAX)) => {
+                self.define_syntax(datum, &global)?;
+                return Ok(None);
+            }
+            Some(Binding::Special(Symbol::BEGIN)) => if let Some(items) = datum.items() {
+                let mut forms = vec![symbol_at(Symbol::BEGIN, items[0].span)];
+                for item in &items[1..] {
+                    forms.extend(self.expand_toplevel(item)?);
+                }
+                return Ok(Some(list_at(datum.span, forms)));
+            },
+            _ => {}
+        }
+        self.expand(datum, &global).map(Some)
+    }
+
+    /// Expands `datum` as an expression in `env`.
+    fn expand(&mut self, datum: &Datum, env: &Env) -> Result<Datum> {
+        match datum.kind {
+            DatumKind::Symbol(id) => match self.resolve(id, env) {
+                Binding::Variable(name) => Ok(Datum::new(DatumKind::Symbol(name), datum.span)),
+                _ => Err(self.error(datum.span, ErrorKind::MisusedKeyword(self.strip(id)))),
+            },
+            DatumKind::Pair(..) => {
+                if let Some(expanded) = self.head_expand(datum, env)? {
+                    return self.expand(&expanded, env);
+                }
+                let items = match datum.items() {
+                    Some(items) => items,
+                    None => return Ok(self.strip_datum(datum)),
+                };
+                match self.head_binding(datum, env) {
+                    Some(Binding::Special(keyword)) => self.special_form(keyword, datum, &items, env),
+                    _ => Ok(list_at(datum.span, self.expand_all(&items, env)?)),
+                }
+            }
+            _ => Ok(self.strip_datum(datum)),
+        }
+    }
+
+    fn expand_all(&mut self, data: &[&Datum], env: &Env) -> Result<Vec<Datum>> {
+        data.iter().map(|d| self.expand(d, env)).collect()
+    }
+
+    /// Expands a use of a special form. A form with the wrong shape is
+    /// left for the parser to report, with its spans as they were.
+    fn special_form(&mut self, keyword: Symbol, datum: &Datum, items: &[&Datum], env: &Env) -> Result<Datum> {
+        let span = datum.span;
+        let args = &items[1..];
+        let malformed = |expander: &Self| Ok(expander.strip_datum(datum));
+        let rest = match keyword {
+            Symbol::QUOTE => args.iter().map(|d| self.strip_datum(d)).collect(),
+            Symbol::IF | Symbol::BEGIN | Symbol::AND | Symbol::OR | Symbol::WHEN | Symbol::UNLESS =>
+                self.expand_all(args, env)?,
+            Symbol::SET => match (args.len(), args.first().map(|d| &d.kind)) {
+                (2, Some(&DatumKind::Symbol(_))) => {
+                    let target = self.expand(args[0], env)?;
+                    vec![target, self.expand(args[1], env)?]
+                }
+                _ => return malformed(self),
+            },
+            Symbol::LAMBDA if !args.is_empty() => match self.lambda(args[0], &args[1..], span, env)? {
+                Some(lambda) => return Ok(lambda),
+                None => return malformed(self),
+            },
+            Symbol::LET => match self.let_(args, env)? {
+                Some(rest) => rest,
+                None => return malformed(self),
+            },
+            Symbol::LET_STAR => match self.let_star(args, env)? {
+                Some(rest) => rest,
+                None => return malformed(self),
+            },
+            Symbol::LETREC | Symbol::LETREC_STAR => match self.letrec(args, env)? {
+                Some(rest) => rest,
+                None => return malformed(self),
+            },
+            Symbol::COND => match self.clauses(args, env, None)? {
+                Some(rest) => rest,
+                None => return malformed(self),
+            },
+            Symbol::CASE if !args.is_empty() => {
+                let key = self.expand(args[0], env)?;
+                match self.clauses(&args[1..], env, Some(key))? {
+                    Some(rest) => rest,
+                    None => return malformed(self),
+                }
+            }
+            Symbol::DO => match self.do_(args, env)? {
+                Some(rest) => rest,
+                None => return malformed(self),
+            },
+            Symbol::LET_VALUES | Symbol::LET_STAR_VALUES =>
+                match self.let_values(args, env, keyword == Symbol::LET_STAR_VALUES)? {
+                    Some(rest) => rest,
+                    None => return malformed(self),
+                },
+            Symbol::RECEIVE => match self.receive(args, env)? {
+                Some(rest) => rest,
+                None => return malformed(self),
+            },
+            Symbol::LET_SYNTAX | Symbol::LETREC_SYNTAX => return self.let_syntax(keyword, datum, args, env),
+            Symbol::DEFINE_SYNTAX =>
+                return Err(self.error(span, syntax::ErrorKind::DefinitionInExpression)),
+            // A definition here is an error the parser reports.
+            Symbol::DEFINE | Symbol::DEFINE_RECORD_TYPE | Symbol::DEFINE_VALUES | Symbol::LAMBDA | Symbol::CASE =>
+                return malformed(self),
+            _ => return Err(self.error(items[0].span, ErrorKind::MisusedKeyword(keyword))),
+        };
+        let mut forms = vec![symbol_at(keyword, items[0].span)];
+        forms.extend(rest);
+        Ok(list_at(span, forms))
+    }
+
+    /// Expands `(lambda formals body...)` spanning `span`, or returns `None`
+    /// if `formals` are malformed.
+    fn lambda(&mut self, formals: &Datum, body: &[&Datum], span: Span, env: &Env) -> Result<Option<Datum>> {
+        let scope = Scope::child(env);
+        let formals = match self.bind_formals(formals, &scope) {
+            Some(formals) => formals,
+            None => return Ok(None),
+        };
+        let mut forms = vec![symbol_at(Symbol::LAMBDA, span), formals];
+        forms.extend(self.expand_body(body, &scope)?);
+        Ok(Some(list_at(span, forms)))
+    }
+
+    /// Binds the variables in a `lambda` parameter list in `scope`, and
+    /// returns the list with them renamed.
+    fn bind_formals(&mut self, formals: &Datum, scope: &Env) -> Option<Datum> {
+        let (params, rest) = elements(formals);
+        let mut ids: Vec<&Datum> = params.clone();
+        if rest.kind != DatumKind::Nil {
+            ids.push(rest);
+        }
+        if !distinct(&ids) {
+            return None;
+        }
+        let params = params.iter().map(|d| self.bind_variable(d, scope)).collect::<Option<Vec<_>>>()?;
+        let rest = match rest.kind {
+            DatumKind::Nil => rest.clone(),
+            _ => self.bind_variable(rest, scope)?,
+        };
+        let span = formals.span;
+        let mut formals = Datum::list(params, rest);
+        formals.span = span;
+        Some(formals)
+    }
+
+    /// Binds the identifier `id` as a new local variable in `scope`, and
+    /// returns its new name.
+    fn bind_variable(&mut self, id: &Datum, scope: &Env) -> Option<Datum> {
+        match id.kind {
+            DatumKind::Symbol(symbol) => {
+                let name = Symbol::fresh(self.strip(symbol).as_str());
+                scope.bind(symbol, Binding::Variable(name));
+                Some(Datum::new(DatumKind::Symbol(name), id.span))
+            }
+            _ => None,
+        }
+    }
+
+    /// `(let [name] ((variable init)...) body...)`, without the keyword.
+    fn let_(&mut self, args: &[&Datum], env: &Env) -> Result<Option<Vec<Datum>>> {
+        let (name, bindings, body) = match args.first().map(|d| &d.kind) {
+            Some(&DatumKind::Symbol(_)) if args.len() > 1 => (Some(args[0]), args[1], &args[2..]),
+            Some(_) => (None, args[0], &args[1..]),
+            None => return Ok(None),
+        };
+        let list = bindings;
+        let bindings = match binding_list(list, 2) {
+            Some(bindings) if distinct_names(&bindings) => bindings,
+            _ => return Ok(None),
+        };
+
+        let inits = bindings.iter().map(|b| self.expand(b.1[1], env)).collect::<Result<Vec<_>>>()?;
+        let mut out = Vec::new();
+        let mut scope = env.clone();
+        if let Some(name) = name {
+            scope = Scope::child(&scope);
+            out.push(self.bind_variable(name, &scope).unwrap());
+        }
+        let scope = Scope::child(&scope);
+        let bindings = bindings.iter().zip(inits).map(|(&(span, ref binding), init)| {
+            list_at(span, vec![self.bind_variable(binding[0], &scope).unwrap(), init])
+        }).collect();
+        out.push(list_at(list.span, bindings));
+        out.extend(self.expand_body(body, &scope)?);
+        Ok(Some(out))
+    }
+
+    /// `(let* ((variable init)...) body...)`, without the keyword.
+    fn let_star(&mut self, args: &[&Datum], env: &Env) -> Result<Option<Vec<Datum>>> {
+        let bindings = match args.first().and_then(|d| binding_list(d, 2)) {
+            Some(bindings) => bindings,
+            None => return Ok(None),
+        };
+        let mut scope = env.clone();
+        let mut out = Vec::new();
+        for (span, binding) in bindings {
+            let init = self.expand(binding[1], &scope)?;
+            scope = Scope::child(&scope);
+            out.push(list_at(span, vec![self.bind_variable(binding[0], &scope).unwrap(), init]));
+        }
+        Ok(Some(vec![list_at(args[0].span, out)].into_iter().chain(self.expand_body(&args[1..], &scope)?).collect()))
+    }
+
+    /// `(letrec ((variable init)...) body...)`, without the keyword.
+    fn letrec(&mut self, args: &[&Datum], env: &Env) -> Result<Option<Vec<Datum>>> {
+        let bindings = match args.first().and_then(|d| binding_list(d, 2)) {
+            Some(bindings) if distinct_names(&bindings) => bindings,
+            _ => return Ok(None),
+        };
+        let scope = Scope::child(env);
+        let names: Vec<_> = bindings.iter().map(|b| self.bind_variable(b.1[0], &scope).unwrap()).collect();
+        let mut out = Vec::new();
+        for ((span, binding), name) in bindings.into_iter().zip(names) {
+            out.push(list_at(span, vec![name, self.expand(binding[1], &scope)?]));
+        }
+        Ok(Some(vec![list_at(args[0].span, out)].into_iter().chain(self.expand_body(&args[1..], &scope)?).collect()))
+    }
+
+    /// The clauses of a `cond`, or of a `case` after its `key`, with `else`
+    /// and `=>` in them recognised by what they mean rather than by name.
+    fn clauses(&mut self, clauses: &[&Datum], env: &Env, key: Option<Datum>) -> Result<Option<Vec<Datum>>> {
+        let mut out: Vec<Datum> = key.into_iter().collect();
+        let is_case = !out.is_empty();
+        for clause in clauses {
+            let items = match clause.items() {
+                Some(ref items) if !items.is_empty() => items.clone(),
+                _ => return Ok(None),
+            };
+            let mut forms = Vec::new();
+            if self.names(items[0], env, Symbol::ELSE) {
+                forms.push(symbol_at(Symbol::ELSE, items[0].span));
+            } else if is_case {
+                forms.push(self.strip_datum(items[0]));
+            } else {
+                forms.push(self.expand(items[0], env)?);
+            }
+            let arrow = (is_case || forms[0].kind != DatumKind::Symbol(Symbol::ELSE)) &&
+                items.len() == 3 && self.names(items[1], env, Symbol::ARROW);
+            if arrow {
+                forms.push(symbol_at(Symbol::ARROW, items[1].span));
+                forms.push(self.expand(items[2], env)?);
+            } else {
+                forms.extend(self.expand_all(&items[1..], env)?);
+            }
+            out.push(list_at(clause.span, forms));
+        }
+        Ok(Some(out))
+    }
+
+    /// `(do ((variable init [step])...) (test expression...) command...)`,
+    /// without the keyword.
+    fn do_(&mut self, args: &[&Datum], env: &Env) -> Result<Option<Vec<Datum>>> {
+        let specs = match args.first().and_then(|d| binding_list(d, 3)) {
+            Some(specs) if distinct_names(&specs) => specs,
+            _ => return Ok(None),
+        };
+        let exit = match args.get(1).and_then(|d| d.items()) {
+            Some(ref exit) if !exit.is_empty() => exit.clone(),
+            _ => return Ok(None),
+        };
+
+        let inits = specs.iter().map(|s| self.expand(s.1[1], env)).collect::<Result<Vec<_>>>()?;
+        let scope = Scope::child(env);
+        let names: Vec<_> = specs.iter().map(|s| self.bind_variable(s.1[0], &scope).unwrap()).collect();
+        let mut out = Vec::new();
+        for (((span, spec), name), init) in specs.iter().zip(names).zip(inits) {
+            let mut forms = vec![name, init];
+            forms.extend(spec.get(2).map(|step| self.expand(step, &scope)).transpose()?);
+            out.push(list_at(*span, forms));
+        }
+        let mut out = vec![list_at(args[0].span, out), list_at(args[1].span, self.expand_all(&exit, &scope)?)];
+        out.extend(self.expand_all(&args[2..], &scope)?);
+        Ok(Some(out))
+    }
+
+    /// `(let-values ((formals init)...) body...)`, without the keyword, or
+    /// `let*-values` if `sequential`, where each init is in the scope of
+    /// the formals before it.
+    fn let_values(&mut self, args: &[&Datum], env: &Env, sequential: bool) -> Result<Option<Vec<Datum>>> {
+        let bindings = match args.first().and_then(|d| d.items()) {
+            Some(bindings) => bindings,
+            None => return Ok(None),
+        };
+        let mut specs = Vec::new();
+        for binding in &bindings {
+            match binding.items() {
+                Some(items) if items.len() == 2 => specs.push((binding.span, items[0], items[1])),
+                _ => return Ok(None),
+            }
+        }
+        if !sequential {
+            let ids: Vec<_> = specs.iter().flat_map(|s| {
+                let (mut ids, rest) = elements(s.1);
+                ids.push(rest);
+                ids
+            }).collect();
+            if !distinct(&ids) {
+                return Ok(None);
+            }
+        }
+
+        let inits = match sequential {
+            true => Vec::new(),
+            false => specs.iter().map(|s| self.expand(s.2, env)).collect::<Result<_>>()?,
+        };
+        let mut inits = inits.into_iter();
+        let mut scope = if sequential { env.clone() } else { Scope::child(env) };
+        let mut out = Vec::new();
+        for (span, formals, init) in specs {
+            let init = match inits.next() {
+                Some(init) => init,
+                None => self.expand(init, &scope)?,
+            };
+            if sequential {
+                scope = Scope::child(&scope);
+            }
+            match self.bind_formals(formals, &scope) {
+                Some(formals) => out.push(list_at(span, vec![formals, init])),
+                None => return Ok(None),
+            }
+        }
+        let mut out = vec![list_at(args[0].span, out)];
+        out.extend(self.expand_body(&args[1..], &scope)?);
+        Ok(Some(out))
+    }
+
+    /// `(receive formals expression body...)`, without the keyword.
+    fn receive(&mut self, args: &[&Datum], env: &Env) -> Result<Option<Vec<Datum>>> {
+        if args.len() < 2 {
+            return Ok(None);
+        }
+        let init = self.expand(args[1], env)?;
+        let scope = Scope::child(env);
+        let formals = match self.bind_formals(args[0], &scope) {
+            Some(formals) => formals,
+            None => return Ok(None),
+        };
+        let mut out = vec![formals, init];
+        out.extend(self.expand_body(&args[2..], &scope)?);
+        Ok(Some(out))
+    }
+
+    /// `(let-syntax ((keyword spec)...) body...)` and `letrec-syntax`,
+    /// whose macros are only seen by their own specs. Both become `(let ()
+    /// body...)` once the macros in the body are expanded.
+    fn let_syntax(&mut self, kind: Symbol, datum: &Datum, args: &[&Datum], env: &Env) -> Result<Datum> {
+        let malformed = |d: &Datum| self.error(d.span, ErrorKind::MalformedForm(kind));
+        let specs = match args.first().map(|d| (d, binding_list(d, 2))) {
+            Some((_, Some(specs))) => specs,
+            Some((d, None)) => return Err(malformed(d)),
+            None => return Err(malformed(datum)),
+        };
+        let scope = Scope::child(env);
+        let macro_env = if kind == Symbol::LETREC_SYNTAX { scope.clone() } else { env.clone() };
+        for (_, spec) in specs {
+            let name = match spec[0].kind {
+                DatumKind::Symbol(name) => name,
+                _ => return Err(malformed(spec[0])),
+            };
+            let m = self.syntax_rules(self.strip(name), spec[1], &macro_env)?;
+            scope.bind(name, Binding::Macro(Rc::new(m)));
+        }
+        let mut forms = vec![symbol_at(Symbol::LET, datum.span), Datum::new(DatumKind::Nil, args[0].span)];
+        forms.extend(self.expand_body(&args[1..], &scope)?);
+        Ok(list_at(datum.span, forms))
+    }
+
+    /// Binds the macro a `(define-syntax keyword spec)` defines in `env`.
+    fn define_syntax(&mut self, datum: &Datum, env: &Env) -> Result<()> {
+        match datum.items() {
+            Some(ref items) if items.len() == 3 => match items[1].kind {
+                DatumKind::Symbol(name) => {
+                    let m = self.syntax_rules(self.strip(name), items[2], env)?;
+                    env.bind(name, Binding::Macro(Rc::new(m)));
+                    Ok(())
+                }
+                _ => Err(self.error(items[1].span, ErrorKind::MalformedForm(Symbol::DEFINE_SYNTAX))),
+            },
+            _ => Err(self.error(datum.span, ErrorKind::MalformedForm(Symbol::DEFINE_SYNTAX))),
+        }
+    }
+
+    /// Expands the forms of a body in a new scope inside `env`. All the
+    /// definitions in a body are in scope throughout it, so they are found
+    /// first, expanding macros and splicing `begin`s to find them, and only
+    /// then is anything else expanded.
+    fn expand_body(&mut self, body: &[&Datum], env: &Env) -> Result<Vec<Datum>> {
+        let scope = Scope::child(env);
+        let mut pending: VecDeque<Cow<Datum>> = body.iter().map(|&d| Cow::Borrowed(d)).collect();
+        let mut forms = Vec::new();
+        while let Some(form) = pending.pop_front() {
+            let form = match self.head_expand(&form, &scope)? {
+                Some(expanded) => Cow::Owned(expanded),
+                None => form,
+            };
+            match self.head_binding(&form, &scope) {
+                Some(Binding::Special(Symbol::DEFINE)) => if let Some(id) = split_define(&form).map(|d| d.0) {
+                    let name = self.define_local(id, &scope);
+                    forms.push(BodyForm::Define(form, name));
+                    continue;
+                },
+                Some(Binding::Special(Symbol::DEFINE_RECORD_TYPE)) => if let Some(ids) = record_names(&form) {
+                    for id in ids {
+                        self.define_local(id, &scope);
+                    }
+                    forms.push(BodyForm::Record(form));
+                    continue;
+                },
+                Some(Binding::Special(Symbol::DEFINE_VALUES)) => if let Some(ids) = values_names(&form) {
+                    for id in ids {
+                        self.define_local(id, &scope);
+                    }
+                    forms.push(BodyForm::Values(form));
+                    continue;
+                },
+                Some(Binding::Special(Symbol::DEFINE_SYNTAX)) => {
+                    self.define_syntax(&form, &scope)?;
+                    continue;
+                }
+                Some(Binding::Special(Symbol::BEGIN)) => if let Some(items) = form.items() {
+                    let items: Vec<Datum> = items[1..].iter().map(|&d| d.clone()).collect();
+                    for item in items.into_iter().rev() {
+                        pending.push_front(Cow::Owned(item));
+                    }
+                    continue;
+                },
+                _ => {}
+            }
+            forms.push(BodyForm::Expr(form));
+        }
+
+        forms.into_iter().map(|form| match form {
+            BodyForm::Define(form, name) => {
+                let (_, value) = split_define(&form).unwrap();
+                self.definition(&form, name, value, &scope)
+            }
+            BodyForm::Record(form) => self.record_type(&form, &scope),
+            BodyForm::Values(form) => self.define_values(&form, &scope),
+            BodyForm::Expr(form) => self.expand(&form, &scope),
+        }).collect()
+    }
+
+    /// Binds `id` as a variable defined in the body whose scope is `scope`,
+    /// and returns its name. A name defined twice keeps one name, for the
+    /// resolver to report.
+    fn define_local(&self, id: Symbol, scope: &Env) -> Symbol {
+        let name = match scope.local(id) {
+            Some(Binding::Variable(name)) => name,
+            _ => Symbol::fresh(self.strip(id).as_str()),
+        };
+        scope.bind(id, Binding::Variable(name));
+        name
+    }
+
+    /// The name a global defined as `id` gets: its own, unless a macro
+    /// inserted it, when only that macro's output can refer to it.
+    fn global_name(&self, id: Symbol) -> Symbol {
+        match self.aliases.contains_key(&id) {
+            true => Symbol::fresh(self.strip(id).as_str()),
+            false => id,
+        }
+    }
+
+    /// `(define-record-type name (constructor field...) predicate (field
+    /// accessor [modifier])...)`, once the variables it defines are bound
+    /// in `env`. Fields aren't variables, so they are left as written.
+    fn record_type(&mut self, datum: &Datum, env: &Env) -> Result<Datum> {
+        let items = datum.items().unwrap();
+        let constructor = items[2].items().unwrap();
+        let mut fields = vec![self.expand(constructor[0], env)?];
+        fields.extend(constructor[1..].iter().map(|d| self.strip_datum(d)));
+        let mut forms = vec![
+            symbol_at(Symbol::DEFINE_RECORD_TYPE, items[0].span),
+            self.expand(items[1], env)?,
+            list_at(items[2].span, fields),
+            self.expand(items[3], env)?,
+        ];
+        for spec in &items[4..] {
+            let spec_items = spec.items().unwrap();
+            let mut field = vec![self.strip_datum(spec_items[0])];
+            field.extend(self.expand_all(&spec_items[1..], env)?);
+            forms.push(list_at(spec.span, field));
+        }
+        Ok(list_at(datum.span, forms))
+    }
+
+    /// `(define-values formals expression)`, once the variables in `formals`
+    /// are bound in `env`.
+    fn define_values(&mut self, datum: &Datum, env: &Env) -> Result<Datum> {
+        let items = datum.items().unwrap();
+        let (params, rest) = elements(items[1]);
+        let params = params.iter().map(|d| self.expand(d, env)).collect::<Result<_>>()?;
+        let rest = match rest.kind {
+            DatumKind::Nil => rest.clone(),
+            _ => self.expand(rest, env)?,
+        };
+        let mut formals = Datum::list(params, rest);
+        formals.span = items[1].span;
+        Ok(list_at(datum.span, vec![symbol_at(Symbol::DEFINE_VALUES, items[0].span), formals,
+                                    self.expand(items[2], env)?]))
+    }
+
+    /// `(define name value)`, with `value` expanded in `env`.
+    fn definition(&mut self, datum: &Datum, name: Symbol, value: Value, env: &Env) -> Result<Datum> {
+        let value = match value {
+            Value::Expr(expr) => self.expand(expr, env)?,
+            Value::Procedure(formals, body) => match self.lambda(formals, &body, datum.span, env)? {
+                Some(lambda) => lambda,
+                None => return Ok(self.strip_datum(datum)),
+            },
+        };
+        let items = datum.items().unwrap();
+        let name_span = match items[1].kind {
+            DatumKind::Pair(ref name, _) => name.span,
+            _ => items[1].span,
+        };
+        Ok(list_at(datum.span, vec![
+            symbol_at(Symbol::DEFINE, items[0].span),
+            Datum::new(DatumKind::Symbol(name), name_span),
+            value,
+        ]))
+    }
+
+    /// Expands `datum` for as long as it is a macro use, or returns `None`
+    /// if it isn't one to begin with.
+    fn head_expand(&mut self, datum: &Datum, env: &Env) -> Result<Option<Datum>> {
+        let mut expanded: Option<Datum> = None;
+        loop {
+            let m = match self.head_binding(expanded.as_ref().unwrap_or(datum), env) {
+                Some(Binding::Macro(m)) => m,
+                _ => return Ok(expanded),
+            };
+            expanded = Some(self.apply(&m, expanded.as_ref().unwrap_or(datum), env)?);
+        }
+    }
+
+    /// What the operator of `datum` means in `env`, if it is a list headed
+    /// by an identifier.
+    fn head_binding(&self, datum: &Datum, env: &Env) -> Option<Binding> {
+        match datum.kind {
+            DatumKind::Pair(ref head, _) => match head.kind {
+                DatumKind::Symbol(id) => Some(self.resolve(id, env)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// What `id` means in `env`. An alias no binding in `env` covers means
+    /// what its original identifier does where its macro was defined. An
+    /// identifier bound nowhere is a global variable.
+    fn resolve(&self, id: Symbol, env: &Env) -> Binding {
+        let (mut id, mut env) = (id, env);
+        loop {
+            if let Some(binding) = env.lookup(id) {
+                return binding;
+            }
+            match self.aliases.get(&id) {
+                Some(alias) => {
+                    id = alias.name;
+                    env = &alias.env;
+                }
+                None => return Binding::Variable(id),
+            }
+        }
+    }
+
+    /// A new alias for `id`, inserted by a macro defined in `env`.
+    fn alias(&mut self, id: Symbol, env: &Env) -> Symbol {
+        let alias = Symbol::fresh(self.strip(id).as_str());
+        self.aliases.insert(alias, Alias {
+            name: id,
+            env: env.clone(),
+        });
+        alias
+    }
+
+    /// The identifier `id` was written as, before any macro renamed it.
+    fn strip(&self, mut id: Symbol) -> Symbol {
+        while let Some(alias) = self.aliases.get(&id) {
+            id = alias.name;
+        }
+        id
+    }
+
+    /// `datum` as data, with every identifier in it as it was written.
+    fn strip_datum(&self, datum: &Datum) -> Datum {
+        let kind = match datum.kind {
+            DatumKind::Symbol(id) => DatumKind::Symbol(self.strip(id)),
+            DatumKind::Pair(ref head, ref tail) =>
+                DatumKind::Pair(Box::new(self.strip_datum(head)), Box::new(self.strip_datum(tail))),
+            DatumKind::Vector(ref items) => DatumKind::Vector(items.iter().map(|d| self.strip_datum(d)).collect()),
+            DatumKind::Labeled(n, ref inner) => DatumKind::Labeled(n, Box::new(self.strip_datum(inner))),
+            ref kind => kind.clone(),
+        };
+        Datum::new(kind, datum.span)
+    }
+
+    fn names(&self, datum: &Datum, env: &Env, keyword: Symbol) -> bool {
+        match datum.kind {
+            DatumKind::Symbol(id) => self.resolve(id, env) == Binding::Special(keyword),
+            _ => false,
+        }
+    }
+
+    fn error<K: Into<::error::ErrorKind>>(&self, span: Span, kind: K) -> Error {
+        Error::new(self.file_name, span, kind)
+    }
+}
+
+/// The identifier and value of a `define`, if it has the shape of one.
+fn split_define(datum: &Datum) -> Option<(Symbol, Value<'_>)> {
+    let items = datum.items()?;
+    match items.get(1)?.kind {
+        DatumKind::Symbol(id) if items.len() == 3 => Some((id, Value::Expr(items[2]))),
+        DatumKind::Pair(ref name, ref formals) => match name.kind {
+            DatumKind::Symbol(id) => Some((id, Value::Procedure(formals, items[2..].to_vec()))),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The identifiers a `define-record-type` defines, if it has the shape of
+/// one: the name of the type, its constructor and predicate, and the
+/// accessor and any modifier of each field.
+fn record_names(datum: &Datum) -> Option<Vec<Symbol>> {
+    let id = |d: &Datum| match d.kind {
+        DatumKind::Symbol(id) => Some(id),
+        _ => None,
+    };
+    let items = datum.items().filter(|items| items.len() >= 4)?;
+    let constructor = items[2].items()?;
+    let mut names = vec![id(items[1])?, id(constructor.first()?)?, id(items[3])?];
+    if !constructor[1..].iter().all(|d| id(d).is_some()) {
+        return None;
+    }
+    for spec in &items[4..] {
+        match spec.items() {
+            Some(ref spec) if (spec.len() == 2 || spec.len() == 3) && id(spec[0]).is_some() =>
+                names.extend(spec[1..].iter().map(|d| id(d)).collect::<Option<Vec<_>>>()?),
+            _ => return None,
+        }
+    }
+    Some(names)
+}
+
+/// The identifiers a `define-values` defines, if it has the shape of one.
+fn values_names(datum: &Datum) -> Option<Vec<Symbol>> {
+    let items = datum.items().filter(|items| items.len() == 3)?;
+    let (mut ids, rest) = elements(items[1]);
+    if rest.kind != DatumKind::Nil {
+        ids.push(rest);
+    }
+    ids.into_iter().map(|d| match d.kind {
+        DatumKind::Symbol(id) => Some(id),
+        _ => None,
+    }).collect()
+}
+
+/// The bindings in `((variable init)...)`, or in `do`'s `((variable init
+/// [step])...)` when `max` is 3, with the span of each.
+fn binding_list(datum: &Datum, max: usize) -> Option<Vec<(Span, Vec<&Datum>)>> {
+    datum.items()?.into_iter().map(|binding| match binding.items() {
+        Some(items) if items.len() >= 2 && items.len() <= max && matches!(items[0].kind, DatumKind::Symbol(_)) =>
+            Some((binding.span, items)),
+        _ => None,
+    }).collect()
+}
+
+/// Whether the bindings from `binding_list` bind different names.
+fn distinct_names(bindings: &[(Span, Vec<&Datum>)]) -> bool {
+    distinct(&bindings.iter().map(|b| b.1[0]).collect::<Vec<_>>())
+}
+
+/// Whether the identifiers `ids` are all different.
+fn distinct(ids: &[&Datum]) -> bool {
+    ids.iter().enumerate().all(|(i, a)| match a.kind {
+        DatumKind::Symbol(_) => !ids[..i].iter().any(|b| b.kind == a.kind),
+        _ => true,
+    })
+}
+
+/// The elements of a list, proper or not, and the datum ending it.
+fn elements(datum: &Datum) -> (Vec<&Datum>, &Datum) {
+    let mut items = Vec::new();
+    let mut datum = datum;
+    while let DatumKind::Pair(ref head, ref tail) = datum.kind {
+        items.push(&**head);
+        datum = tail;
+    }
+    (items, datum)
+}
+
+fn symbol_at(keyword: Symbol, span: Span) -> Datum {
+    Datum::new(DatumKind::Symbol(keyword), span)
+}
+
+/// A proper list of `items` spanning `span`.
+fn list_at(span: Span, items: Vec<Datum>) -> Datum {
+    let mut list = Datum::list(items, Datum::new(DatumKind::Nil, Span::at(span.end)));
+    list.span = span;
+    list
+}
+
+impl<'a> StreamMap<Result<Datum>, Result<Datum>> for Expander<'a> {
+    fn produce(&mut self, datum: Result<Datum>, out: &mut impl Extend<Result<Datum>>) {
+        out.extend(datum.and_then(|d| self.expand_toplevel(&d)).transpose());
+    }
+
+    fn max_outputs(&self) -> Option<usize> {
+        Some(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use error;
+    use pipeline;
+
+    /// Expands `input` and prints the result with every fresh symbol
+    /// numbered, so that different variables with the same name can be told
+    /// apart.
+    fn expanded(input: &str) -> String {
+        let mut numbers = HashMap::new();
+        pipeline::expand("test", input.as_bytes())
+            .map(|r| numbered(&r.unwrap(), &mut numbers).to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn numbered(datum: &Datum, numbers: &mut HashMap<Symbol, String>) -> Datum {
+        let kind = match datum.kind {
+            DatumKind::Symbol(s) if Symbol::intern(s.as_str()) != s => {
+                let count = numbers.keys().filter(|k| k.as_str() == s.as_str()).count();
+                let name = numbers.entry(s).or_insert_with(|| format!("{}.{}", s, count + 1));
+                DatumKind::Symbol(Symbol::intern(name))
+            }
+            DatumKind::Pair(ref head, ref tail) =>
+                DatumKind::Pair(Box::new(numbered(head, numbers)), Box::new(numbered(tail, numbers))),
+            ref kind => kind.clone(),
+        };
+        Datum::new(kind, datum.span)
+    }
+
+    fn error(input: &str) -> error::Error {
+        pipeline::expand("test", input.as_bytes()).find(|r| r.is_err()).unwrap().unwrap_err()
+    }
+
+    #[test]
+    fn locals_are_renamed() {
+        assert_eq!(expanded("(lambda (x . y) (let ((x x)) (f x y)))"),
+                   "(lambda (x.1 . y.1) (let ((x.2 x.1)) (f x.2 y.1)))");
+        assert_eq!(expanded("(define (f x) x) (define g f) f"), "(define f (lambda (x.1) x.1)) (define g f) f");
+        assert_eq!(expanded("(let loop ((i 0)) (loop i))"), "(let loop.1 ((i.1 0)) (loop.1 i.1))");
+        assert_eq!(expanded("(do ((i 0 (+ i 1))) ((= i 3) i) (f i))"),
+                   "(do ((i.1 0 (+ i.1 1))) ((= i.1 3) i.1) (f i.1))");
+        assert_eq!(expanded("(let ((if list)) (if 1 2))"), "(let ((if.1 list)) (if.1 1 2))");
+        assert_eq!(expanded("'(lambda (x) x)"), "(quote (lambda (x) x))");
+    }
+
+    #[test]
+    fn inserted_bindings_do_not_capture() {
+        assert_eq!(expanded("(define-syntax swap!
+                               (syntax-rules ()
+                                 ((_ a b) (let ((tmp a)) (set! a b) (set! b tmp)))))
+                             (let ((tmp 1) (y 2)) (swap! tmp y))"),
+                   "(let ((tmp.1 1) (y.1 2)) (let ((tmp.2 tmp.1)) (set! tmp.1 y.1) (set! y.1 tmp.2)))");
+        assert_eq!(expanded("(define-syntax def-tmp (syntax-rules () ((_ v) (begin (define tmp v) tmp))))
+                             (lambda () (def-tmp 1) tmp)"),
+                   "(lambda () (define tmp.1 1) tmp.1 tmp)");
+    }
+
+    #[test]
+    fn inserted_references_are_not_captured() {
+        assert_eq!(expanded("(define-syntax get-x (syntax-rules () ((_) x))) (let ((x 1)) (get-x))"),
+                   "(let ((x.1 1)) x)");
+        assert_eq!(expanded("(define-syntax my-if (syntax-rules () ((_ c a b) (cond (c a) (else b)))))
+                             (let ((else #f)) (my-if #f 1 2))"),
+                   "(let ((else.1 #f)) (cond (#f 1) (else 2)))");
+        assert_eq!(expanded("(lambda (x)
+                               (let-syntax ((get-x (syntax-rules () ((_) x))))
+                                 (lambda (x) (get-x))))"),
+                   "(lambda (x.1) (let () (lambda (x.2) x.1)))");
+    }
+
+    #[test]
+    fn recursive_macros() {
+        assert_eq!(expanded("(letrec-syntax
+                                 ((my-or (syntax-rules ()
+                                           ((_) #f)
+                                           ((_ e) e)
+                                           ((_ e r ...) (let ((t e)) (if t t (my-or r ...)))))))
+                               (let ((t 5)) (my-or #f t)))"),
+                   "(let () (let ((t.1 5)) (let ((t.2 #f)) (if t.2 t.2 t.1))))");
+    }
+
+    #[test]
+    fn bodies() {
+        assert_eq!(expanded("(lambda ()
+                               (define-syntax two (syntax-rules () ((_ a b) (begin (define a 1) (define b 2)))))
+                               (two x y)
+                               (+ x y))"),
+                   "(lambda () (define x.1 1) (define y.1 2) (+ x.1 y.1))");
+        assert_eq!(expanded("(define-syntax m (syntax-rules () ((_) 1))) (begin (define-syntax n (syntax-rules () \
+                             ((_) (m)))) (define a (n)))"),
+                   "(begin (define a 1))");
+        // A use of a macro before its definition in the same body still sees it.
+        assert_eq!(expanded("(lambda () (define (f) (later)) (define-syntax later (syntax-rules () ((_) 2))) (f))"),
+                   "(lambda () (define f.1 (lambda () 2)) (f.1))");
+    }
+
+    #[test]
+    fn record_types() {
+        // The type and procedures are variables, while fields are only names.
+        assert_eq!(expanded("(define-record-type point (make-point x) point? (x point-x set-point-x!))"),
+                   "(define-record-type point (make-point x) point? (x point-x set-point-x!))");
+        assert_eq!(expanded("(lambda (x) (define-record-type point (make-point x) point? (x point-x)) \
+                             (point-x (make-point x)))"),
+                   "(lambda (x.1) (define-record-type point.1 (make-point.1 x) point?.1 (x point-x.1)) \
+                    (point-x.1 (make-point.1 x.1)))");
+    }
+
+    #[test]
+    fn multiple_values() {
+        // The inits of `let-values` can't see its formals, while those of
+        // `let*-values` see the ones before them.
+        assert_eq!(expanded("(lambda (a) (let-values (((a b) (f a)) ((c) a)) (list a b c)))"),
+                   "(lambda (a.1) (let-values (((a.2 b.1) (f a.1)) ((c.1) a.1)) (list a.2 b.1 c.1)))");
+        assert_eq!(expanded("(lambda (a) (let*-values (((a b) (f a)) ((c) a)) (list a b c)))"),
+                   "(lambda (a.1) (let*-values (((a.2 b.1) (f a.1)) ((c.1) a.2)) (list a.2 b.1 c.1)))");
+        assert_eq!(expanded("(receive (a . b) (f) b)"), "(receive (a.1 . b.1) (f) b.1)");
+        assert_eq!(expanded("(define-values (a . b) (f)) (lambda () (define-values (a) (f)) a)"),
+                   "(define-values (a . b) (f)) (lambda () (define-values (a.1) (f)) a.1)");
+    }
+
+    #[test]
+    fn misused_keywords() {
+        let e = error("(f if)");
+        assert_eq!(e.to_string(), "Error (test:1:4): Syntactic keyword 'if' can't be used here");
+        let e = error("(define-syntax m (syntax-rules () ((_) 1))) (list m)");
+        assert_eq!(e.to_string(), "Error (test:1:51): Syntactic keyword 'm' can't be used here");
+        let e = error("(f (define-syntax m (syntax-rules ())))");
+        assert_eq!(e.to_string(),
+                   "Error (test:1:4): Definitions are only allowed at top level or at the start of a body");
+        let e = error("(else 1)");
+        assert_eq!(e.to_string(), "Error (test:1:2): Syntactic keyword 'else' can't be used here");
+    }
+
+    #[test]
+    fn errors_in_expansions_point_at_the_use() {
+        let mut data = pipeline::parse("test", "(define-syntax bad (syntax-rules () ((_ x) (if))))\n(bad 1)".as_bytes());
+        let e = data.next().unwrap().unwrap_err();
+        assert_eq!(e.to_string(), "Error (test:2:1): Malformed 'if', expected (if test consequent [alternate])");
+    }
+}
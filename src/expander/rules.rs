@@ -0,0 +1,565 @@
+//! `syntax-rules` macros: compiling their patterns and templates, matching
+//! uses against the patterns and transcribing the templates.
+
+use std::collections::HashMap;
+
+use error::Result;
+use reader::{Datum, DatumKind};
+use span::Span;
+use symbol::Symbol;
+
+use super::env::Env;
+use super::{elements, ErrorKind, Expander};
+
+/// A macro defined by `syntax-rules`.
+pub struct Macro {
+    name: Symbol,
+    /// The `syntax-rules` form.
+    span: Span,
+    /// A custom ellipsis, if one is given before the literals.
+    ellipsis: Option<Symbol>,
+    literals: Vec<Symbol>,
+    rules: Vec<Rule>,
+    /// Where the macro was defined, in which the identifiers its templates
+    /// insert are looked up.
+    env: Env,
+}
+
+struct Rule {
+    pattern: Pattern,
+    template: Template,
+}
+
+enum Pattern {
+    /// `_`.
+    Any,
+    Var(Symbol),
+    Literal(Symbol),
+    Const(Datum),
+    List(Sequence),
+    Vector(Sequence),
+}
+
+/// The elements of a list or vector pattern: `before... repeat <ellipsis>
+/// after... . tail`.
+struct Sequence {
+    before: Vec<Pattern>,
+    repeat: Option<Box<Pattern>>,
+    after: Vec<Pattern>,
+    /// The pattern after a dot, for a list.
+    tail: Option<Box<Pattern>>,
+}
+
+enum Template {
+    Var(Symbol),
+    /// An identifier the macro inserts.
+    Ident(Symbol),
+    Const(Datum),
+    List(Vec<Element>, Option<Box<Template>>),
+    Vector(Vec<Element>),
+}
+
+/// A template in a list or vector, followed by `ellipses` ellipses.
+struct Element {
+    template: Template,
+    ellipses: usize,
+    /// The pattern variables in `template`.
+    vars: Vec<Symbol>,
+}
+
+/// What a pattern variable matched: a form, or a list of matches for each
+/// time the ellipsis it is under repeated.
+enum Match {
+    One(Datum),
+    Many(Vec<Match>),
+}
+
+/// Pattern variables with the ellipsis depth and span of each.
+type Vars = HashMap<Symbol, (usize, Span)>;
+
+impl<'a> Expander<'a> {
+    /// Compiles `spec`, a `syntax-rules` form defining the macro `name`
+    /// in `env`.
+    pub(super) fn syntax_rules(&self, name: Symbol, spec: &Datum, env: &Env) -> Result<Macro> {
+        let malformed = |d: &Datum| self.error(d.span, ErrorKind::MalformedForm(Symbol::SYNTAX_RULES));
+        let items = match spec.items() {
+            Some(ref items) if items.len() >= 2 && self.names(items[0], env, Symbol::SYNTAX_RULES) => items.clone(),
+            _ => return Err(malformed(spec)),
+        };
+        let (ellipsis, rest) = match items[1].kind {
+            DatumKind::Symbol(ellipsis) => (Some(ellipsis), &items[2..]),
+            _ => (None, &items[1..]),
+        };
+        let literals = match rest.first().and_then(|d| d.items()) {
+            Some(literals) => literals.iter().map(|d| match d.kind {
+                DatumKind::Symbol(literal) => Ok(literal),
+                _ => Err(malformed(d)),
+            }).collect::<Result<_>>()?,
+            None => return Err(malformed(spec)),
+        };
+
+        let mut m = Macro {
+            name,
+            span: spec.span,
+            ellipsis,
+            literals,
+            rules: Vec::new(),
+            env: env.clone(),
+        };
+        for rule in &rest[1..] {
+            // The keyword position of a pattern is never matched, so only
+            // the rest of it is kept.
+            let (pattern, template) = match rule.items() {
+                Some(ref items) if items.len() == 2 => match items[0].kind {
+                    DatumKind::Pair(_, ref rest) => (&**rest, items[1]),
+                    _ => return Err(malformed(items[0])),
+                },
+                _ => return Err(malformed(rule)),
+            };
+            let mut vars = Vars::new();
+            let pattern = self.pattern(&m, pattern, 0, &mut vars)?;
+            let template = self.template(&m, template, &vars, 0, false)?;
+            m.rules.push(Rule {
+                pattern,
+                template,
+            });
+        }
+        Ok(m)
+    }
+
+    /// Expands `form`, a use of `m` in `env`.
+    pub(super) fn apply(&mut self, m: &Macro, form: &Datum, env: &Env) -> Result<Datum> {
+        for rule in &m.rules {
+            let mut matches = HashMap::new();
+            let operands = match form.kind {
+                DatumKind::Pair(_, ref operands) => operands,
+                _ => form,
+            };
+            if self.matches(m, &rule.pattern, operands, env, &mut matches) {
+                let matches = matches.iter().map(|(&var, matched)| (var, matched)).collect();
+                let mut transcriber = Transcriber {
+                    m,
+                    span: form.span,
+                    aliases: HashMap::new(),
+                };
+                return transcriber.template(self, &rule.template, &matches);
+            }
+        }
+        Err(self.error(form.span, ErrorKind::NoMatchingRule(m.name))
+            .with_note(m.span, format!("'{}' is defined here", m.name)))
+    }
+
+    fn is_ellipsis(&self, m: &Macro, datum: &Datum) -> bool {
+        match (m.ellipsis, &datum.kind) {
+            (Some(ellipsis), &DatumKind::Symbol(id)) => id == ellipsis,
+            (None, _) => self.names(datum, &m.env, Symbol::ELLIPSIS),
+            _ => false,
+        }
+    }
+
+    fn pattern(&self, m: &Macro, datum: &Datum, depth: usize, vars: &mut Vars) -> Result<Pattern> {
+        Ok(match datum.kind {
+            DatumKind::Symbol(_) if self.is_ellipsis(m, datum) =>
+                return Err(self.error(datum.span, ErrorKind::MisplacedEllipsis)),
+            DatumKind::Symbol(id) if m.literals.contains(&id) => Pattern::Literal(id),
+            DatumKind::Symbol(_) if self.names(datum, &m.env, Symbol::UNDERSCORE) => Pattern::Any,
+            DatumKind::Symbol(id) => {
+                if let Some(&(_, first)) = vars.get(&id) {
+                    return Err(self.error(datum.span, ErrorKind::DuplicatePatternVariable(id))
+                        .with_note(first, "it is first bound here"));
+                }
+                vars.insert(id, (depth, datum.span));
+                Pattern::Var(id)
+            }
+            DatumKind::Pair(..) => {
+                let (items, tail) = elements(datum);
+                Pattern::List(self.sequence(m, &items, Some(tail), depth, vars)?)
+            }
+            DatumKind::Vector(ref items) => {
+                let items: Vec<_> = items.iter().collect();
+                Pattern::Vector(self.sequence(m, &items, None, depth, vars)?)
+            }
+            _ => Pattern::Const(datum.clone()),
+        })
+    }
+
+    fn sequence(&self, m: &Macro, items: &[&Datum], tail: Option<&Datum>, depth: usize, vars: &mut Vars)
+        -> Result<Sequence> {
+        let mut sequence = Sequence {
+            before: Vec::new(),
+            repeat: None,
+            after: Vec::new(),
+            tail: None,
+        };
+        let mut i = 0;
+        while i < items.len() {
+            if self.is_ellipsis(m, items[i]) {
+                return Err(self.error(items[i].span, ErrorKind::MisplacedEllipsis));
+            }
+            if items.get(i + 1).is_some_and(|d| self.is_ellipsis(m, d)) {
+                if sequence.repeat.is_some() {
+                    return Err(self.error(items[i + 1].span, ErrorKind::ExtraEllipsis));
+                }
+                sequence.repeat = Some(Box::new(self.pattern(m, items[i], depth + 1, vars)?));
+                i += 2;
+            } else {
+                let pattern = self.pattern(m, items[i], depth, vars)?;
+                if sequence.repeat.is_none() {
+                    sequence.before.push(pattern);
+                } else {
+                    sequence.after.push(pattern);
+                }
+                i += 1;
+            }
+        }
+        if let Some(tail) = tail {
+            if tail.kind != DatumKind::Nil {
+                sequence.tail = Some(Box::new(self.pattern(m, tail, depth, vars)?));
+            }
+        }
+        Ok(sequence)
+    }
+
+    /// Compiles `datum` as a template at ellipsis depth `depth`. Inside
+    /// `(... template)`, `escaped` makes ellipses ordinary identifiers.
+    fn template(&self, m: &Macro, datum: &Datum, vars: &Vars, depth: usize, escaped: bool) -> Result<Template> {
+        Ok(match datum.kind {
+            DatumKind::Symbol(id) => match vars.get(&id) {
+                Some(&(pattern_depth, _)) if depth < pattern_depth =>
+                    return Err(self.error(datum.span, ErrorKind::MissingEllipsis(id))),
+                Some(_) => Template::Var(id),
+                None if !escaped && self.is_ellipsis(m, datum) =>
+                    return Err(self.error(datum.span, ErrorKind::MisplacedEllipsis)),
+                None => Template::Ident(id),
+            },
+            DatumKind::Pair(..) => {
+                let (items, tail) = elements(datum);
+                if !escaped && self.is_ellipsis(m, items[0]) {
+                    // `(... template)`
+                    return match (items.len(), &tail.kind) {
+                        (2, &DatumKind::Nil) => self.template(m, items[1], vars, depth, true),
+                        _ => Err(self.error(items[0].span, ErrorKind::MisplacedEllipsis)),
+                    };
+                }
+                let elements = self.template_elements(m, &items, vars, depth, escaped)?;
+                let tail = match tail.kind {
+                    DatumKind::Nil => None,
+                    _ => Some(Box::new(self.template(m, tail, vars, depth, escaped)?)),
+                };
+                Template::List(elements, tail)
+            }
+            DatumKind::Vector(ref items) => {
+                let items: Vec<_> = items.iter().collect();
+                Template::Vector(self.template_elements(m, &items, vars, depth, escaped)?)
+            }
+            _ => Template::Const(datum.clone()),
+        })
+    }
+
+    fn template_elements(&self, m: &Macro, items: &[&Datum], vars: &Vars, depth: usize, escaped: bool)
+        -> Result<Vec<Element>> {
+        let mut elements = Vec::new();
+        let mut i = 0;
+        while i < items.len() {
+            let ellipses = match escaped {
+                true => 0,
+                false => items[i + 1..].iter().take_while(|d| self.is_ellipsis(m, d)).count(),
+            };
+            let template = self.template(m, items[i], vars, depth + ellipses, escaped)?;
+            let mut used = Vec::new();
+            template_vars(&template, &mut used);
+            if ellipses > 0 && !used.iter().any(|var| vars[var].0 > depth) {
+                return Err(self.error(items[i + 1].span, ErrorKind::EllipsisWithoutVariables));
+            }
+            elements.push(Element {
+                template,
+                ellipses,
+                vars: used,
+            });
+            i += 1 + ellipses;
+        }
+        Ok(elements)
+    }
+
+    /// Matches `datum` in `env` against `pattern`, adding what each pattern
+    /// variable matched to `matches`.
+    fn matches(&self, m: &Macro, pattern: &Pattern, datum: &Datum, env: &Env, matches: &mut HashMap<Symbol, Match>)
+        -> bool {
+        match *pattern {
+            Pattern::Any => true,
+            Pattern::Var(var) => {
+                matches.insert(var, Match::One(datum.clone()));
+                true
+            }
+            Pattern::Literal(literal) => match datum.kind {
+                DatumKind::Symbol(id) => self.resolve(id, env) == self.resolve(literal, &m.env),
+                _ => false,
+            },
+            Pattern::Const(ref constant) => constant == datum,
+            Pattern::List(ref sequence) => {
+                if !matches!(datum.kind, DatumKind::Pair(..) | DatumKind::Nil) {
+                    return false;
+                }
+                let (items, tail) = elements(datum);
+                self.sequence_matches(m, sequence, &items, Some(tail), env, matches)
+            }
+            Pattern::Vector(ref sequence) => match datum.kind {
+                DatumKind::Vector(ref items) => {
+                    let items: Vec<_> = items.iter().collect();
+                    self.sequence_matches(m, sequence, &items, None, env, matches)
+                }
+                _ => false,
+            },
+        }
+    }
+
+    fn sequence_matches(&self, m: &Macro, sequence: &Sequence, items: &[&Datum], tail: Option<&Datum>, env: &Env,
+                        matches: &mut HashMap<Symbol, Match>) -> bool {
+        let fixed = sequence.before.len() + sequence.after.len();
+        if items.len() < fixed {
+            return false;
+        }
+        let (before, rest) = items.split_at(sequence.before.len());
+        if !sequence.before.iter().zip(before).all(|(p, d)| self.matches(m, p, d, env, matches)) {
+            return false;
+        }
+
+        let repeat = match sequence.repeat {
+            Some(ref repeat) => repeat,
+            // Without an ellipsis, a dotted tail matches whatever is left,
+            // and otherwise nothing may be.
+            None => return match (&sequence.tail, tail) {
+                (Some(pattern), Some(tail)) => {
+                    let rest = rest.iter().map(|&d| d.clone()).collect();
+                    self.matches(m, pattern, &Datum::list(rest, tail.clone()), env, matches)
+                }
+                (None, tail) => rest.is_empty() && tail.is_none_or(|t| t.kind == DatumKind::Nil),
+                (Some(_), None) => false,
+            },
+        };
+
+        let (repeated, after) = rest.split_at(rest.len() - sequence.after.len());
+        let mut vars = Vec::new();
+        pattern_vars(repeat, &mut vars);
+        let mut each: Vec<Vec<Match>> = vars.iter().map(|_| Vec::new()).collect();
+        for d in repeated {
+            let mut inner = HashMap::new();
+            if !self.matches(m, repeat, d, env, &mut inner) {
+                return false;
+            }
+            for (var, list) in vars.iter().zip(&mut each) {
+                list.push(inner.remove(var).unwrap());
+            }
+        }
+        for (var, list) in vars.into_iter().zip(each) {
+            matches.insert(var, Match::Many(list));
+        }
+
+        if !sequence.after.iter().zip(after).all(|(p, d)| self.matches(m, p, d, env, matches)) {
+            return false;
+        }
+        // With an ellipsis, a dotted tail matches only the list's last cdr.
+        match (&sequence.tail, tail) {
+            (Some(pattern), Some(tail)) => self.matches(m, pattern, tail, env, matches),
+            (None, tail) => tail.is_none_or(|t| t.kind == DatumKind::Nil),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+/// The state of one expansion of a macro.
+struct Transcriber<'m> {
+    m: &'m Macro,
+    /// The use being expanded, which everything the template builds spans.
+    span: Span,
+    /// The alias each inserted identifier gets, the same for every time it
+    /// is inserted.
+    aliases: HashMap<Symbol, Symbol>,
+}
+
+impl<'m> Transcriber<'m> {
+    fn template(&mut self, expander: &mut Expander, template: &Template, matches: &HashMap<Symbol, &Match>)
+        -> Result<Datum> {
+        Ok(match *template {
+            Template::Var(var) => match *matches[&var] {
+                Match::One(ref datum) => datum.clone(),
+                Match::Many(_) => return Err(expander.error(self.span, ErrorKind::MissingEllipsis(var))),
+            },
+            Template::Ident(id) => {
+                let m = self.m;
+                let alias = *self.aliases.entry(id).or_insert_with(|| expander.alias(id, &m.env));
+                Datum::new(DatumKind::Symbol(alias), self.span)
+            }
+            Template::Const(ref datum) => Datum::new(datum.kind.clone(), self.span),
+            Template::List(ref elements, ref tail) => {
+                let items = self.elements(expander, elements, matches)?;
+                let tail = match *tail {
+                    Some(ref tail) => self.template(expander, tail, matches)?,
+                    None => Datum::new(DatumKind::Nil, self.span),
+                };
+                let mut list = Datum::list(items, tail);
+                list.span = self.span;
+                list
+            }
+            Template::Vector(ref elements) =>
+                Datum::new(DatumKind::Vector(self.elements(expander, elements, matches)?), self.span),
+        })
+    }
+
+    fn elements(&mut self, expander: &mut Expander, elements: &[Element], matches: &HashMap<Symbol, &Match>)
+        -> Result<Vec<Datum>> {
+        let mut out = Vec::new();
+        for element in elements {
+            self.repeat(expander, element, element.ellipses, matches, &mut out)?;
+        }
+        Ok(out)
+    }
+
+    /// Transcribes `element` under `ellipses` more ellipses, once for each
+    /// form matched by the pattern variables in it that are still repeated.
+    fn repeat(&mut self, expander: &mut Expander, element: &Element, ellipses: usize,
+              matches: &HashMap<Symbol, &Match>, out: &mut Vec<Datum>) -> Result<()> {
+        if ellipses == 0 {
+            out.push(self.template(expander, &element.template, matches)?);
+            return Ok(());
+        }
+        let repeated: Vec<(Symbol, &Vec<Match>)> = element.vars.iter().filter_map(|var| match *matches[var] {
+            Match::Many(ref list) => Some((*var, list)),
+            Match::One(_) => None,
+        }).collect();
+        let count = match repeated.first() {
+            Some(&(_, list)) => list.len(),
+            None => return Err(expander.error(self.span, ErrorKind::EllipsisWithoutVariables)),
+        };
+        if repeated.iter().any(|&(_, list)| list.len() != count) {
+            return Err(expander.error(self.span, ErrorKind::MismatchedEllipsis)
+                .with_note(self.m.span, format!("'{}' is defined here", self.m.name)));
+        }
+        for i in 0..count {
+            let mut inner = matches.clone();
+            for &(var, list) in &repeated {
+                inner.insert(var, &list[i]);
+            }
+            self.repeat(expander, element, ellipses - 1, &inner, out)?;
+        }
+        Ok(())
+    }
+}
+
+fn pattern_vars(pattern: &Pattern, out: &mut Vec<Symbol>) {
+    match *pattern {
+        Pattern::Var(var) => out.push(var),
+        Pattern::List(ref sequence) | Pattern::Vector(ref sequence) => {
+            let patterns = sequence.before.iter().chain(sequence.repeat.as_deref()).chain(&sequence.after)
+                .chain(sequence.tail.as_deref());
+            for pattern in patterns {
+                pattern_vars(pattern, out);
+            }
+        }
+        Pattern::Any | Pattern::Literal(_) | Pattern::Const(_) => {}
+    }
+}
+
+fn template_vars(template: &Template, out: &mut Vec<Symbol>) {
+    match *template {
+        Template::Var(var) if !out.contains(&var) => out.push(var),
+        Template::List(ref elements, ref tail) => {
+            for element in elements {
+                template_vars(&element.template, out);
+            }
+            if let Some(ref tail) = *tail {
+                template_vars(tail, out);
+            }
+        }
+        Template::Vector(ref elements) => {
+            for element in elements {
+                template_vars(&element.template, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pipeline;
+
+    /// Defines a macro `m` by `rules` and prints the expansion of `uses`.
+    fn expand(rules: &str, uses: &str) -> String {
+        let input = format!("(define-syntax m (syntax-rules {})) {}", rules, uses);
+        pipeline::expand("test", input.as_bytes())
+            .map(|r| r.map(|d| d.to_string()).unwrap_or_else(|e| e.to_string()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!(expand("() ((_ a (b c) . d) '(d c b a))", "(m 1 (2 3) 4 5)"), "(quote ((4 5) 3 2 1))");
+        assert_eq!(expand("() ((_ _ _) 'two) ((_ . _) 'other)", "(m 1 2) (m 1)"), "(quote two) (quote other)");
+        assert_eq!(expand("() ((_ 1 \"s\" #\\c) 'yes) ((_ . x) 'no)", "(m 1 \"s\" #\\c) (m 1 \"t\" #\\c)"),
+                   "(quote yes) (quote no)");
+        assert_eq!(expand("() ((_ #(a b ... c)) '(c b ... a))", "(m #(1 2 3 4)) (m #(1 2))"),
+                   "(quote (4 2 3 1)) (quote (2 1))");
+        assert_eq!(expand("() ((_ #(a ...)) '#(a ... a ...))", "(m #(1 2))"), "(quote #(1 2 1 2))");
+    }
+
+    #[test]
+    fn literals() {
+        let rules = "(=>) ((_ a => b) '(arrow a b)) ((_ a b c) '(other a b c))";
+        assert_eq!(expand(rules, "(m 1 => 2) (m 1 2 3)"), "(quote (arrow 1 2)) (quote (other 1 2 3))");
+        // A literal that is rebound where the macro is used doesn't match.
+        assert_eq!(expand(rules, "(lambda (=>) (m 1 => 2))"), "(lambda (=>) (quote (other 1 => 2)))");
+    }
+
+    #[test]
+    fn ellipses() {
+        assert_eq!(expand("() ((_ a ... z) '(z a ...))", "(m 1 2 3) (m 1)"), "(quote (3 1 2)) (quote (1))");
+        assert_eq!(expand("() ((_ (a b ...) ...) '((b ... a) ...))", "(m (1 2 3) (4))"),
+                   "(quote ((2 3 1) (4)))");
+        assert_eq!(expand("() ((_ (a ...) ...) '(a ... ...))", "(m (1 2) () (3))"), "(quote (1 2 3))");
+        assert_eq!(expand("() ((_ x (y ...)) '((x y) ...))", "(m 0 (1 2))"), "(quote ((0 1) (0 2)))");
+        assert_eq!(expand("() ((_ a ... . r) '(r a ...))", "(m 1 2 . 3)"), "(quote (3 1 2))");
+        assert_eq!(expand("::: () ((_ a :::) '(a ::: ...))", "(m 1 2)"), "(quote (1 2 ...))");
+        assert_eq!(expand("() ((_ a) '(a (... ...)))", "(m 1)"), "(quote (1 ...))");
+        assert_eq!(expand("() ((_ a) '(... (a ...)))", "(m 1)"), "(quote (1 ...))");
+    }
+
+    #[test]
+    fn macro_defining_macros() {
+        let input = "(define-syntax def-list
+                       (syntax-rules ()
+                         ((_ name) (define-syntax name (syntax-rules () ((_ x (... ...)) '(x (... ...))))))))
+                     (def-list l)
+                     (l 1 2 3)";
+        let output: Vec<_> = pipeline::expand("test", input.as_bytes()).map(|r| r.unwrap().to_string()).collect();
+        assert_eq!(output, ["(quote (1 2 3))"]);
+    }
+
+    #[test]
+    fn unmatched_uses() {
+        let e = expand("() ((_ a) a)", "(list\n  (m))");
+        assert_eq!(e, "Error (test:2:3): No rule of macro 'm' matches this use of it\n  test:1:18: 'm' is defined here");
+        assert_eq!(expand("() ((_ (a ...) (b ...)) '((a b) ...))", "(m (1 2) (3))"),
+                   "Error (test:1:72): Pattern variables repeated together matched different numbers of forms\n  \
+                    test:1:18: 'm' is defined here");
+    }
+
+    #[test]
+    fn malformed_rules() {
+        assert_eq!(expand("() ((_ a a) a)", ""),
+                   "Error (test:1:41): Pattern variable 'a' is bound more than once in the same pattern\n  \
+                    test:1:39: it is first bound here");
+        assert_eq!(expand("() ((_ a ... b ...) a)", ""),
+                   "Error (test:1:47): A pattern can have only one ellipsis in each list or vector");
+        assert_eq!(expand("() ((_ ... a) a)", ""),
+                   "Error (test:1:39): An ellipsis must follow the pattern or template it repeats");
+        assert_eq!(expand("() ((_ a ...) a)", ""),
+                   "Error (test:1:46): Pattern variable 'a' must be followed by as many ellipses as in its pattern");
+        assert_eq!(expand("() ((_ a) (a ...))", ""),
+                   "Error (test:1:45): This ellipsis follows no pattern variable that was matched under an ellipsis");
+        assert_eq!(expand("(1) ((_) 1)", ""),
+                   "Error (test:1:33): Malformed 'syntax-rules', expected \
+                    (syntax-rules [ellipsis] (literal...) (pattern template)...)");
+    }
+}
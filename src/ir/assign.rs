@@ -0,0 +1,210 @@
+//! Assignment conversion. Closures copy the values of the variables they
+//! capture, so a variable that is both captured and `set!` has to live in a
+//! box that the closures share instead: it is bound to the box, references
+//! become `Op::Unbox` and `set!`s become `Op::SetBox`. Every other local
+//! keeps holding its value directly, and is never written to memory.
+//!
+//! A variable of a `letrec*` also counts as set, by its initialisation,
+//! when a closure created before then refers to it. Those are the
+//! references in an `Op::Check` inside a `lambda`. The boxes of a
+//! `letrec*` are all made before its first init, so that they exist for
+//! whatever refers to them early, and are filled where the variables used
+//! to be initialised.
+
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use ir::{Expr, ExprKind, Lambda, Op, Var};
+use span::Span;
+
+/// Boxes the locals in the top-level form `expr` that need it.
+pub fn convert(expr: &mut Expr) {
+    let mut analysis = Analysis::default();
+    analysis.expr(expr, 0);
+    let boxed = analysis.assigned.intersection(&analysis.captured).cloned().chain(analysis.captured_early).collect();
+    Boxer { boxed }.expr(expr);
+}
+
+/// Which locals are set and which are captured. Locals are captured when
+/// they are used in a `lambda` other than the one that binds them, with
+/// `lambda`s numbered in the order they are found and top level as 0.
+#[derive(Debug, Default)]
+struct Analysis {
+    owners: HashMap<Var, usize>,
+    lambdas: usize,
+    assigned: HashSet<Var>,
+    captured: HashSet<Var>,
+    captured_early: HashSet<Var>,
+}
+
+impl Analysis {
+    fn expr(&mut self, expr: &Expr, lambda: usize) {
+        match expr.kind {
+            ExprKind::Ref(var) => self.use_var(var, lambda),
+            ExprKind::Set(var, _) => {
+                self.use_var(var, lambda);
+                if !var.is_global() {
+                    self.assigned.insert(var);
+                }
+            }
+            ExprKind::Lambda(ref l) => {
+                self.lambdas += 1;
+                let id = self.lambdas;
+                for &param in l.params.iter().chain(&l.rest) {
+                    self.owners.insert(param, id);
+                }
+                for e in &l.body {
+                    self.expr(e, id);
+                }
+                return;
+            }
+            ExprKind::Letrec(ref bindings, _) => {
+                for binding in bindings {
+                    self.owners.insert(binding.0, lambda);
+                }
+            }
+            ExprKind::Op(Op::Check(var), ref operands)
+                if is_ref(&operands[0], var) && self.owners.get(&var) != Some(&lambda) => {
+                self.captured_early.insert(var);
+            }
+            _ => {}
+        }
+        for child in expr.children() {
+            self.expr(child, lambda);
+        }
+    }
+
+    fn use_var(&mut self, var: Var, lambda: usize) {
+        match self.owners.get(&var) {
+            Some(&owner) if owner != lambda => {
+                self.captured.insert(var);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_ref(expr: &Expr, var: Var) -> bool {
+    match expr.kind {
+        ExprKind::Ref(v) => v == var,
+        _ => false,
+    }
+}
+
+#[derive(Debug)]
+struct Boxer {
+    boxed: HashSet<Var>,
+}
+
+impl Boxer {
+    fn expr(&mut self, expr: &mut Expr) {
+        for child in expr.children_mut() {
+            self.expr(child);
+        }
+        let span = expr.span;
+        let reference = |var| Expr::new(ExprKind::Ref(var), span);
+        expr.kind = match mem::replace(&mut expr.kind, ExprKind::Begin(Vec::new())) {
+            ExprKind::Ref(var) if self.boxed.contains(&var) => ExprKind::Op(Op::Unbox, vec![reference(var)]),
+            ExprKind::Set(var, value) if self.boxed.contains(&var) =>
+                ExprKind::Op(Op::SetBox, vec![reference(var), *value]),
+            ExprKind::Lambda(lambda) => ExprKind::Lambda(self.lambda(lambda, span)),
+            ExprKind::Letrec(bindings, body) => ExprKind::Letrec(self.letrec(bindings, span), body),
+            kind => kind,
+        };
+    }
+
+    /// Renames the boxed parameters of `lambda` and binds the original
+    /// variables to boxes holding the arguments around its body.
+    fn lambda(&self, mut lambda: Lambda, span: Span) -> Lambda {
+        let mut boxes = Vec::new();
+        {
+            let params = lambda.params.iter_mut().chain(&mut lambda.rest);
+            for param in params.filter(|p| self.boxed.contains(p)) {
+                let argument = Var::local(param.symbol().as_str());
+                let value = Expr::new(ExprKind::Ref(argument), span);
+                boxes.push((*param, Expr::new(ExprKind::Op(Op::Box, vec![value]), span)));
+                *param = argument;
+            }
+        }
+        if !boxes.is_empty() {
+            let body = mem::take(&mut lambda.body);
+            lambda.body = vec![Expr::new(ExprKind::Letrec(boxes, body), span)];
+        }
+        lambda
+    }
+
+    /// Binds the boxed variables among `bindings` to empty boxes ahead of
+    /// the rest, and fills them in place of their inits.
+    fn letrec(&self, bindings: Vec<(Var, Expr)>, span: Span) -> Vec<(Var, Expr)> {
+        let mut boxes = Vec::new();
+        let mut rest = Vec::with_capacity(bindings.len());
+        for (var, init) in bindings {
+            if self.boxed.contains(&var) {
+                boxes.push((var, Expr::new(ExprKind::Op(Op::Box, Vec::new()), span)));
+                let target = Expr::new(ExprKind::Ref(var), init.span);
+                let init_span = init.span;
+                rest.push((Var::local("_"), Expr::new(ExprKind::Op(Op::SetBox, vec![target, init]), init_span)));
+            } else {
+                rest.push((var, init));
+            }
+        }
+        boxes.extend(rest);
+        boxes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ir;
+    use pipeline;
+
+    fn converted(input: &str) -> String {
+        let mut numbers = HashMap::new();
+        pipeline::resolve("test", input.as_bytes())
+            .map(|r| {
+                let mut expr = r.unwrap();
+                super::convert(&mut expr);
+                ir::numbered(&expr, &mut numbers).to_string()
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn set_and_captured() {
+        assert_eq!(converted("(lambda (n) (lambda () (set! n (+ n 1)) n))"),
+                   "(lambda (n.1) (letrec* ((n.2 (#%box n.1))) \
+                    (lambda () (#%set-box! n.2 (+ (#%unbox n.2) 1)) (#%unbox n.2))))");
+        assert_eq!(converted("(lambda (n) (lambda () n) (set! n 1))"),
+                   "(lambda (n.1) (letrec* ((n.2 (#%box n.1))) (lambda () (#%unbox n.2)) (#%set-box! n.2 1)))");
+        assert_eq!(converted("(lambda (a . rest) (lambda () (set! rest a)))"),
+                   "(lambda (a.1 . rest.2) (letrec* ((rest.3 (#%box rest.2))) (lambda () (#%set-box! rest.3 a.1))))");
+    }
+
+    #[test]
+    fn unboxed() {
+        // Set but never captured, captured but never set, and globals.
+        assert_eq!(converted("(lambda (n) (set! n 1) n)"), "(lambda (n.1) (set! n.1 1) n.1)");
+        assert_eq!(converted("(lambda (n) (lambda () n))"), "(lambda (n.1) (lambda () n.1))");
+        assert_eq!(converted("(define n 1) (lambda () (set! n 2))"), "(define n 1) (lambda () (set! n 2))");
+        // Procedures defined in the same body refer to each other freely.
+        assert_eq!(converted("(lambda () (define (f) (g)) (define (g) (f)) (f))"),
+                   "(lambda () (letrec* ((f.1 (lambda () (g.2))) (g.2 (lambda () (f.1)))) (f.1)))");
+    }
+
+    #[test]
+    fn letrec() {
+        assert_eq!(converted("(lambda () (define x 1) (lambda () (set! x 2)))"),
+                   "(lambda () (letrec* ((x.1 (#%box)) (_.2 (#%set-box! x.1 1))) (lambda () (#%set-box! x.1 2))))");
+        // `f` may read `x` before it is initialised, so it has to share the
+        // box `x` is initialised in.
+        assert_eq!(converted("(lambda () (define (f) x) (define x (f)) x)"),
+                   "(lambda () (letrec* ((x.1 (#%box)) (f.2 (lambda () (#%check x.1 (#%unbox x.1)))) \
+                    (_.3 (#%set-box! x.1 (f.2)))) (#%unbox x.1)))");
+        // An early reference outside any `lambda` reads the variable itself.
+        assert_eq!(converted("(lambda () (define x (+ x 1)) x)"),
+                   "(lambda () (letrec* ((x.1 (+ (#%check x.1 x.1) 1))) x.1))");
+    }
+}
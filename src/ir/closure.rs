@@ -0,0 +1,239 @@
+//! Closure conversion. Each `lambda` becomes a function of its own, taking
+//! the closure it is called through ahead of its arguments, and an
+//! `Op::Closure` where it was: a flat record of the function's index and
+//! the values of the variables it uses from outside, in the order they
+//! first appear. Inside the function, those are read from the record with
+//! `Op::ClosureRef`, and every call becomes an `Op::Invoke` of the closure
+//! called. Assignment conversion has boxed any of them that are `set!`,
+//! so copying their values into the record is safe.
+//!
+//! A `lambda` bound by a `letrec*` refers to itself through its closure
+//! parameter instead of capturing its own variable, so a loop that uses
+//! nothing else from outside needs no record. When it captures a variable
+//! of the same `letrec*` bound after it, the record is made holding the
+//! variable's unassigned value and is patched with `Op::ClosureSet` as soon
+//! as the variable is initialised, before anything can call the closure.
+
+use std::collections::HashSet;
+
+use ir::{Expr, ExprKind, Lambda, Op, Var};
+use span::Span;
+
+/// Converts the `lambda`s in the top-level form `expr`, adding their
+/// functions to `functions`, where `Op::Closure` indexes them.
+pub fn convert(expr: Expr, functions: &mut Vec<Lambda>) -> Expr {
+    Converter { functions }.expr(expr, &Env::default())
+}
+
+/// Where the variables used in a function are: the closure parameter,
+/// which `this` is bound to in the `letrec*` around the `lambda`, if any,
+/// and the captured variables in the order of their slots in the closure.
+/// Anything else is a local or a global of its own.
+#[derive(Debug, Default)]
+struct Env {
+    closure: Option<Var>,
+    this: Option<Var>,
+    free: Vec<Var>,
+}
+
+impl Env {
+    fn reference(&self, var: Var, span: Span) -> Expr {
+        let closure = match self.closure {
+            Some(closure) => Expr::new(ExprKind::Ref(closure), span),
+            None => return Expr::new(ExprKind::Ref(var), span),
+        };
+        if self.this == Some(var) {
+            return closure;
+        }
+        match self.free.iter().position(|&v| v == var) {
+            Some(slot) => Expr::new(ExprKind::Op(Op::ClosureRef(slot), vec![closure]), span),
+            None => Expr::new(ExprKind::Ref(var), span),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Converter<'a> {
+    functions: &'a mut Vec<Lambda>,
+}
+
+impl<'a> Converter<'a> {
+    fn expr(&mut self, expr: Expr, env: &Env) -> Expr {
+        let span = expr.span;
+        let kind = match expr.kind {
+            ExprKind::Ref(var) => return env.reference(var, span),
+            ExprKind::Set(var, value) => {
+                assert!(env.this != Some(var) && !env.free.contains(&var), "captured variables that are set are boxed");
+                ExprKind::Set(var, Box::new(self.expr(*value, env)))
+            }
+            ExprKind::Define(var, value) => ExprKind::Define(var, Box::new(self.expr(*value, env))),
+            ExprKind::Lambda(lambda) => return self.lambda(lambda, None, span, env).0,
+            ExprKind::If(test, consequent, alternate) => ExprKind::If(
+                Box::new(self.expr(*test, env)),
+                Box::new(self.expr(*consequent, env)),
+                alternate.map(|e| Box::new(self.expr(*e, env))),
+            ),
+            ExprKind::Begin(body) => ExprKind::Begin(self.exprs(body, env)),
+            ExprKind::Call(operator, mut operands) => {
+                operands.insert(0, *operator);
+                ExprKind::Op(Op::Invoke, self.exprs(operands, env))
+            }
+            ExprKind::Letrec(bindings, body) => ExprKind::Letrec(self.letrec(bindings, env), self.exprs(body, env)),
+            ExprKind::Op(op, operands) => ExprKind::Op(op, self.exprs(operands, env)),
+            kind @ ExprKind::Const(_) => kind,
+        };
+        Expr::new(kind, span)
+    }
+
+    fn exprs(&mut self, exprs: Vec<Expr>, env: &Env) -> Vec<Expr> {
+        exprs.into_iter().map(|e| self.expr(e, env)).collect()
+    }
+
+    /// The closure `lambda` becomes, made in `env`, and the variables it
+    /// captures. `this` is the variable the closure is bound to, if any.
+    fn lambda(&mut self, lambda: Lambda, this: Option<Var>, span: Span, env: &Env) -> (Expr, Vec<Var>) {
+        let closure = Var::local("closure");
+        let inner = Env {
+            closure: Some(closure),
+            this,
+            free: free_variables(&lambda).into_iter().filter(|&v| Some(v) != this).collect(),
+        };
+        let mut params = vec![closure];
+        params.extend(lambda.params);
+        let body = self.exprs(lambda.body, &inner);
+        self.functions.push(Lambda {
+            params,
+            rest: lambda.rest,
+            body,
+        });
+        let captured = inner.free.iter().map(|&v| env.reference(v, span)).collect();
+        (Expr::new(ExprKind::Op(Op::Closure(self.functions.len() - 1), captured), span), inner.free)
+    }
+
+    /// Converts the inits of a `letrec*`, patching closures that capture
+    /// variables bound after them once those are initialised.
+    fn letrec(&mut self, bindings: Vec<(Var, Expr)>, env: &Env) -> Vec<(Var, Expr)> {
+        let vars: Vec<_> = bindings.iter().map(|b| b.0).collect();
+        // The patches still to make, as the index of the binding to make
+        // each after, the closure, the slot and the variable for it.
+        let mut patches = Vec::new();
+        let mut converted = Vec::with_capacity(bindings.len());
+        for (i, (var, init)) in bindings.into_iter().enumerate() {
+            let span = init.span;
+            let init = match init.kind {
+                ExprKind::Lambda(lambda) => {
+                    let (closure, free) = self.lambda(lambda, Some(var), span, env);
+                    for (slot, v) in free.into_iter().enumerate() {
+                        if let Some(j) = vars[i + 1..].iter().position(|&w| w == v) {
+                            patches.push((i + 1 + j, var, slot, v, span));
+                        }
+                    }
+                    closure
+                }
+                kind => self.expr(Expr::new(kind, span), env),
+            };
+            converted.push((var, init));
+            for &(_, closure, slot, value, span) in patches.iter().filter(|p| p.0 == i) {
+                let operands = vec![Expr::new(ExprKind::Ref(closure), span), Expr::new(ExprKind::Ref(value), span)];
+                converted.push((Var::local("_"), Expr::new(ExprKind::Op(Op::ClosureSet(slot), operands), span)));
+            }
+        }
+        converted
+    }
+}
+
+/// The locals `lambda` uses but doesn't bind, in the order they first
+/// appear, including those used by the `lambda`s inside it.
+fn free_variables(lambda: &Lambda) -> Vec<Var> {
+    fn collect(expr: &Expr, bound: &mut HashSet<Var>, used: &mut Vec<Var>) {
+        match expr.kind {
+            ExprKind::Ref(var) | ExprKind::Set(var, _) if !var.is_global() && !used.contains(&var) => used.push(var),
+            ExprKind::Lambda(ref lambda) => bound.extend(lambda.params.iter().chain(&lambda.rest)),
+            ExprKind::Letrec(ref bindings, _) => bound.extend(bindings.iter().map(|b| b.0)),
+            _ => {}
+        }
+        for child in expr.children() {
+            collect(child, bound, used);
+        }
+    }
+
+    // Every local is bound once, so whether one is bound inside `lambda`
+    // doesn't depend on where it is used.
+    let mut bound: HashSet<_> = lambda.params.iter().chain(&lambda.rest).cloned().collect();
+    let mut used = Vec::new();
+    for expr in &lambda.body {
+        collect(expr, &mut bound, &mut used);
+    }
+    used.retain(|v| !bound.contains(v));
+    used
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ir::{self, assign, Expr, ExprKind};
+    use pipeline;
+
+    /// The functions and then the forms `input` is lowered to.
+    fn converted(input: &str) -> Vec<String> {
+        let mut functions = Vec::new();
+        let forms: Vec<_> = pipeline::resolve("test", input.as_bytes())
+            .map(|r| {
+                let mut expr = r.unwrap();
+                assign::convert(&mut expr);
+                super::convert(expr, &mut functions)
+            })
+            .collect();
+        let mut numbers = HashMap::new();
+        let span = forms[0].span;
+        let functions = functions.into_iter().map(|f| Expr::new(ExprKind::Lambda(f), span));
+        functions.chain(forms).map(|e| ir::numbered(&e, &mut numbers).to_string()).collect()
+    }
+
+    #[test]
+    fn flat_closures() {
+        assert_eq!(converted("(define (adder n) (lambda (x) (+ x n)))"), vec![
+            "(lambda (closure.1 x.2) (#%invoke + x.2 (#%closure-ref 0 closure.1)))",
+            "(lambda (closure.3 n.4) (#%closure 0 n.4))",
+            "(define adder (#%closure 1))",
+        ]);
+        // Variables captured from further out are copied into every record
+        // on the way in.
+        assert_eq!(converted("(lambda (a b) (lambda () (lambda () (cons b a))))"), vec![
+            "(lambda (closure.1) (#%invoke cons (#%closure-ref 0 closure.1) (#%closure-ref 1 closure.1)))",
+            "(lambda (closure.2) (#%closure 0 (#%closure-ref 0 closure.2) (#%closure-ref 1 closure.2)))",
+            "(lambda (closure.3 a.4 b.5) (#%closure 1 b.5 a.4))",
+            "(#%closure 2)",
+        ]);
+    }
+
+    #[test]
+    fn boxes_are_captured() {
+        assert_eq!(converted("(lambda (n) (lambda () (set! n 1)))"), vec![
+            "(lambda (closure.1) (#%set-box! (#%closure-ref 0 closure.1) 1))",
+            "(lambda (closure.2 n.3) (letrec* ((n.4 (#%box n.3))) (#%closure 0 n.4)))",
+            "(#%closure 1)",
+        ]);
+    }
+
+    #[test]
+    fn recursion() {
+        // A loop calls itself through its closure, so it is static.
+        assert_eq!(converted("(lambda (n) (let loop ((i 0)) (if (< i n) (loop (+ i 1)))))"), vec![
+            "(lambda (closure.1 i.2) (if (#%invoke < i.2 (#%closure-ref 0 closure.1)) \
+             (#%invoke closure.1 (#%invoke + i.2 1))))",
+            "(lambda (closure.3) (letrec* ((loop.4 (#%closure 0 (#%closure-ref 0 closure.3)))) loop.4))",
+            "(lambda (closure.5 n.6) (#%invoke (#%invoke (#%closure 1 n.6)) 0))",
+            "(#%closure 2)",
+        ]);
+        // `f` is patched with `g` once `g` exists.
+        assert_eq!(converted("(lambda () (define (f) (g)) (define (g) (f)) f)"), vec![
+            "(lambda (closure.1) (#%invoke (#%closure-ref 0 closure.1)))",
+            "(lambda (closure.2) (#%invoke (#%closure-ref 0 closure.2)))",
+            "(lambda (closure.3) (letrec* ((f.4 (#%closure 0 g.5)) (g.5 (#%closure 1 f.4)) \
+             (_.6 (#%closure-set! 0 f.4 g.5))) f.4))",
+            "(#%closure 2)",
+        ]);
+    }
+}
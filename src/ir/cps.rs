@@ -0,0 +1,510 @@
+//! CPS conversion. Every procedure takes its continuation, the procedure
+//! to pass its result to, as an extra first parameter, and every call that
+//! isn't to a primitive becomes a tail call passing one. Nothing is ever
+//! left to return to, so `call-with-current-continuation` only has to pass
+//! on the continuation it is given, as a procedure, and that continuation
+//! can be called any number of times, from anywhere, long after the call
+//! it belongs to has finished.
+//!
+//! A top-level form becomes a `lambda` taking the continuation that runs
+//! the forms after it.
+//!
+//! The conversion is one pass in the style of Danvy and Filinski: while it
+//! converts a subexpression, the continuation is a Rust closure building
+//! whatever uses the value, rather than a `lambda` that would be applied
+//! straight away. So the only continuation `lambda`s made are those some
+//! call takes, and the joins after `if`s. A value is bound to a variable
+//! only where it has effects that would otherwise run out of order: for an
+//! operand with a call in an operand after it.
+//!
+//! Multiple values are just more arguments to a continuation, not
+//! WebAssembly multi-value returns: no call returns once converted, so
+//! there is nothing to return them from. Where `call-with-values` is given
+//! a `lambda` for its consumer, that `lambda` is the continuation of the
+//! producer: values passed with `values` in the producer's own body bind
+//! its parameters in place, allocating nothing, and values from a call are
+//! the arguments of a call to it, in the parameters of its function, like
+//! any others. That makes it a closure, as is the continuation of any call
+//! not in tail position, but the values themselves are never put on the
+//! heap. Only a consumer that isn't known is left to the procedure, which
+//! gathers the values into a list to call it with.
+
+use std::collections::{HashSet, VecDeque};
+use std::mem;
+
+use ir::{Expr, ExprKind, Lambda, Op, Var};
+use primitive::Primitive;
+use reader::{Datum, DatumKind};
+use span::Span;
+
+/// Converts the top-level form `expr`.
+pub fn convert(expr: Expr) -> Expr {
+    let span = expr.span;
+    let k = Var::local("k");
+    let body = vec![cps(expr, Cont::Var(k))];
+    Expr::new(ExprKind::Lambda(Lambda { params: vec![k], rest: None, body }), span)
+}
+
+/// Where the value of an expression goes: to the continuation in a
+/// variable, to a Rust closure building the code that uses it, or to the
+/// converted consumer of a `call-with-values`, which takes any number.
+enum Cont {
+    Var(Var),
+    Meta(Box<dyn FnOnce(Expr) -> Expr>),
+    Receiver(Lambda),
+}
+
+impl Cont {
+    fn meta<F: FnOnce(Expr) -> Expr + 'static>(f: F) -> Self {
+        Cont::Meta(Box::new(f))
+    }
+
+    /// Passes the value `value`.
+    fn apply(self, value: Expr) -> Expr {
+        match self {
+            Cont::Var(k) => {
+                let span = value.span;
+                call(Expr::new(ExprKind::Ref(k), span), vec![value], span)
+            }
+            Cont::Meta(f) => f(value),
+            Cont::Receiver(_) => {
+                let span = value.span;
+                self.pass(vec![value], span)
+            }
+        }
+    }
+
+    /// Passes the values `values`, for a call to `values` spanning `span`.
+    /// A receiver taking that many binds its parameters to them. Passing a
+    /// continuation a number of values it doesn't take is left for the
+    /// call to report.
+    fn pass(self, mut values: Vec<Expr>, span: Span) -> Expr {
+        match self {
+            Cont::Var(k) => call(Expr::new(ExprKind::Ref(k), span), values, span),
+            Cont::Meta(f) if values.len() == 1 => f(values.pop().unwrap()),
+            Cont::Receiver(lambda) if values.len() == lambda.params.len() ||
+                                      lambda.rest.is_some() && values.len() > lambda.params.len() => {
+                let rest = values.split_off(lambda.params.len());
+                let mut bindings: Vec<_> = lambda.params.into_iter().zip(values).collect();
+                if let Some(var) = lambda.rest {
+                    let nil = Expr::new(ExprKind::Const(Datum::new(DatumKind::Nil, span)), span);
+                    let list = rest.into_iter().rev().fold(nil, |list, value| {
+                        Expr::new(ExprKind::Op(Op::Primitive(Primitive::Cons), vec![value, list]), span)
+                    });
+                    bindings.push((var, list));
+                }
+                Expr::new(ExprKind::Letrec(bindings, lambda.body), span)
+            }
+            k => k.with_var(span, |k| call(Expr::new(ExprKind::Ref(k), span), values, span)),
+        }
+    }
+
+    /// The continuation as a value. A `lambda` that just passes its
+    /// argument on to another continuation is that continuation instead.
+    fn reify(self, span: Span) -> Expr {
+        let f = match self {
+            Cont::Var(k) => return Expr::new(ExprKind::Ref(k), span),
+            Cont::Meta(f) => f,
+            Cont::Receiver(lambda) => return Expr::new(ExprKind::Lambda(lambda), span),
+        };
+        let v = Var::local("v");
+        let body = f(Expr::new(ExprKind::Ref(v), span));
+        if let ExprKind::Call(ref operator, ref operands) = body.kind {
+            match (&operator.kind, &operands[..]) {
+                (&ExprKind::Ref(k), [operand]) if k != v && is_ref(operand, v) => return (**operator).clone(),
+                _ => {}
+            }
+        }
+        Expr::new(ExprKind::Lambda(Lambda { params: vec![v], rest: None, body: vec![body] }), span)
+    }
+
+    /// The code `f` builds with the continuation in a variable, so that it
+    /// can be used more than once.
+    fn with_var<F: FnOnce(Var) -> Expr>(self, span: Span, f: F) -> Expr {
+        let k = match self.reify(span) {
+            Expr { kind: ExprKind::Ref(k), .. } => return f(k),
+            k => k,
+        };
+        let var = Var::local("k");
+        Expr::new(ExprKind::Letrec(vec![(var, k)], vec![f(var)]), span)
+    }
+}
+
+fn cps(expr: Expr, k: Cont) -> Expr {
+    let span = expr.span;
+    match expr.kind {
+        ExprKind::Const(_) | ExprKind::Ref(_) => k.apply(expr),
+        ExprKind::Lambda(lambda) => k.apply(Expr::new(ExprKind::Lambda(cps_lambda(lambda, span)), span)),
+        ExprKind::Set(var, value) =>
+            cps(*value, Cont::meta(move |value| k.apply(Expr::new(ExprKind::Set(var, Box::new(value)), span)))),
+        ExprKind::Define(var, value) =>
+            cps(*value, Cont::meta(move |value| k.apply(Expr::new(ExprKind::Define(var, Box::new(value)), span)))),
+        ExprKind::If(test, consequent, alternate) => {
+            let alternate = alternate.map_or_else(|| unspecified(span), |e| *e);
+            cps(*test, Cont::meta(move |test| k.with_var(span, move |k| {
+                let consequent = cps(*consequent, Cont::Var(k));
+                let alternate = cps(alternate, Cont::Var(k));
+                Expr::new(ExprKind::If(Box::new(test), Box::new(consequent), Some(Box::new(alternate))), span)
+            })))
+        }
+        ExprKind::Begin(body) => sequence(body, k, span),
+        ExprKind::Call(operator, operands) => match operator.kind {
+            // A `lambda` called straight away, as for `let`, just binds its
+            // parameters.
+            ExprKind::Lambda(lambda) if lambda.rest.is_none() && lambda.params.len() == operands.len() => {
+                cps_all(operands, move |operands| {
+                    let body = sequence(lambda.body, k, span);
+                    Expr::new(ExprKind::Letrec(lambda.params.into_iter().zip(operands).collect(), vec![body]), span)
+                })
+            }
+            kind => call_operator(Expr::new(kind, operator.span), operands, k, span),
+        },
+        ExprKind::Letrec(bindings, body) => letrec(bindings.into_iter().collect(), body, k, span),
+        ExprKind::Op(op, operands) =>
+            cps_all(operands, move |operands| k.apply(Expr::new(ExprKind::Op(op, operands), span))),
+    }
+}
+
+/// Converts a call to `operator`, which isn't a `lambda`.
+fn call_operator(operator: Expr, mut operands: Vec<Expr>, k: Cont, span: Span) -> Expr {
+    match primitive_call(&operator, &operands) {
+        Some(Primitive::CallWithCurrentContinuation) => cps_all(operands, move |mut operands| {
+            let receiver = operands.remove(0);
+            k.with_var(span, |k| call(receiver, vec![Expr::new(ExprKind::Ref(k), span), escape(k, span)], span))
+        }),
+        Some(Primitive::Values) => cps_all(operands, move |operands| k.pass(operands, span)),
+        Some(Primitive::CallWithValues) => {
+            let (consumer, producer) = (operands.pop().unwrap(), operands.pop().unwrap());
+            let (consumer, consumer_span) = match consumer.kind {
+                ExprKind::Lambda(lambda) => (lambda, consumer.span),
+                _ => unreachable!(),
+            };
+            let receiver = Cont::Receiver(Lambda {
+                params: consumer.params,
+                rest: consumer.rest,
+                body: vec![sequence(consumer.body, k, span)],
+            });
+            match producer.kind {
+                // A thunk's body is converted in place, as for `let`.
+                ExprKind::Lambda(lambda) if lambda.params.is_empty() && lambda.rest.is_none() =>
+                    sequence(lambda.body, receiver, span),
+                kind => cps(Expr::new(kind, producer.span), Cont::meta(move |producer| {
+                    call(producer, vec![receiver.reify(consumer_span)], span)
+                })),
+            }
+        }
+        Some(p) => cps_all(operands, move |operands| k.apply(primitive(p, operands, span))),
+        None => {
+            operands.insert(0, operator);
+            cps_all(operands, move |mut operands| {
+                let operator = operands.remove(0);
+                operands.insert(0, k.reify(span));
+                call(operator, operands, span)
+            })
+        }
+    }
+}
+
+fn cps_lambda(lambda: Lambda, span: Span) -> Lambda {
+    let k = Var::local("k");
+    let mut params = vec![k];
+    params.extend(lambda.params);
+    Lambda {
+        params,
+        rest: lambda.rest,
+        body: vec![sequence(lambda.body, Cont::Var(k), span)],
+    }
+}
+
+/// Converts the forms of a body or `begin` that spans `span`. Values
+/// thrown away are kept as statements if they may have effects.
+fn sequence(mut body: Vec<Expr>, k: Cont, span: Span) -> Expr {
+    if body.len() <= 1 {
+        return match body.pop() {
+            Some(expr) => cps(expr, k),
+            None => k.apply(unspecified(span)),
+        };
+    }
+    let first = body.remove(0);
+    cps(first, Cont::meta(move |value| {
+        let rest = sequence(body, k, span);
+        if is_atomic(&value) {
+            return rest;
+        }
+        let rest_span = rest.span;
+        let statements = match rest.kind {
+            ExprKind::Begin(mut statements) => {
+                statements.insert(0, value);
+                statements
+            }
+            kind => vec![value, Expr::new(kind, rest_span)],
+        };
+        Expr::new(ExprKind::Begin(statements), span)
+    }))
+}
+
+/// Converts `exprs` from left to right and builds what uses their values
+/// with `then`.
+fn cps_all<F: FnOnce(Vec<Expr>) -> Expr + 'static>(exprs: Vec<Expr>, then: F) -> Expr {
+    operands(exprs.into_iter().collect(), Vec::new(), Box::new(then))
+}
+
+fn operands(mut rest: VecDeque<Expr>, mut done: Vec<Expr>, then: Box<dyn FnOnce(Vec<Expr>) -> Expr>) -> Expr {
+    let expr = match rest.pop_front() {
+        Some(expr) => expr,
+        None => return then(done),
+    };
+    // The continuation of a call in a later operand would run the effects
+    // of a value left in place after the call, and again every time the
+    // continuation is called.
+    let named = rest.iter().any(is_serious);
+    cps(expr, Cont::meta(move |value| {
+        if !named || is_atomic(&value) {
+            done.push(value);
+            return operands(rest, done, then);
+        }
+        let span = value.span;
+        let t = Var::local("t");
+        done.push(Expr::new(ExprKind::Ref(t), span));
+        Expr::new(ExprKind::Letrec(vec![(t, value)], vec![operands(rest, done, then)]), span)
+    }))
+}
+
+/// Converts a `letrec*`. Each run of `lambda`s stays a `letrec*` of its
+/// own, while every other init is converted in order, with the variable
+/// bound to its value in the continuation. References in it to variables
+/// of the `letrec*` that aren't bound yet never see them initialised, so
+/// they become checks of an unassigned value. References from closures
+/// made before then would see the variable once it is, but those have
+/// been boxed by assignment conversion, and the boxes are bound first.
+fn letrec(mut bindings: VecDeque<(Var, Expr)>, body: Vec<Expr>, k: Cont, span: Span) -> Expr {
+    let mut run = Vec::new();
+    while bindings.front().is_some_and(|b| matches!(b.1.kind, ExprKind::Lambda(_))) {
+        let (var, init) = bindings.pop_front().unwrap();
+        match init.kind {
+            ExprKind::Lambda(lambda) => {
+                let lambda = cps_lambda(lambda, init.span);
+                run.push((var, Expr::new(ExprKind::Lambda(lambda), init.span)));
+            }
+            _ => unreachable!(),
+        }
+    }
+    if !run.is_empty() {
+        return Expr::new(ExprKind::Letrec(run, vec![letrec(bindings, body, k, span)]), span);
+    }
+    let (var, mut init) = match bindings.pop_front() {
+        Some(binding) => binding,
+        None => return sequence(body, k, span),
+    };
+    let pending: HashSet<_> = Some(var).into_iter().chain(bindings.iter().map(|b| b.0)).collect();
+    forget(&mut init, &pending);
+    cps(init, Cont::meta(move |value| {
+        Expr::new(ExprKind::Letrec(vec![(var, value)], vec![letrec(bindings, body, k, span)]), span)
+    }))
+}
+
+/// Turns the references in `expr` to the variables in `pending` into checks
+/// of an unassigned value, and `set!`s of them into checks after the value.
+fn forget(expr: &mut Expr, pending: &HashSet<Var>) {
+    let span = expr.span;
+    let unassigned = Expr::new(ExprKind::Op(Op::Unassigned, Vec::new()), span);
+    match expr.kind {
+        ExprKind::Op(Op::Check(var), ref mut operands) if pending.contains(&var) && is_ref(&operands[0], var) => {
+            operands[0] = unassigned;
+            return;
+        }
+        ExprKind::Set(var, ref mut value) if pending.contains(&var) => {
+            forget(value, pending);
+            let value = mem::replace(&mut **value, unspecified(span));
+            let check = Expr::new(ExprKind::Op(Op::Check(var), vec![unassigned]), span);
+            expr.kind = ExprKind::Begin(vec![value, check]);
+            return;
+        }
+        _ => {}
+    }
+    for child in expr.children_mut() {
+        forget(child, pending);
+    }
+}
+
+/// The primitive a call to `operator` with `operands` is compiled as, if
+/// any.
+fn primitive_call(operator: &Expr, operands: &[Expr]) -> Option<Primitive> {
+    let count = operands.len();
+    let p = match operator.kind {
+        ExprKind::Ref(var) if var.is_global() => Primitive::named(var.symbol().as_str())?,
+        _ => return None,
+    };
+    match p {
+        Primitive::Add | Primitive::Multiply | Primitive::Values => Some(p),
+        Primitive::Subtract if count >= 1 => Some(p),
+        Primitive::CallWithCurrentContinuation => Some(p).filter(|_| count == 1),
+        // Only a `lambda` is known to take the values where they are made.
+        Primitive::CallWithValues =>
+            Some(p).filter(|_| count == 2 && matches!(operands[1].kind, ExprKind::Lambda(_))),
+        _ => Some(p).filter(|p| p.accepts(count)),
+    }
+}
+
+/// The operation for a call to `p`, with calls to `+`, `-` and `*` folded
+/// into ones with two operands.
+fn primitive(p: Primitive, mut operands: Vec<Expr>, span: Span) -> Expr {
+    let op = |operands| Expr::new(ExprKind::Op(Op::Primitive(p), operands), span);
+    let identity = match p {
+        Primitive::Add | Primitive::Subtract => 0,
+        Primitive::Multiply => 1,
+        _ => return op(operands),
+    };
+    let identity = Expr::new(ExprKind::Const(Datum::new(DatumKind::Fixnum(identity), span)), span);
+    match operands.len() {
+        0 => identity,
+        1 => op(vec![identity, operands.remove(0)]),
+        _ => {
+            let mut operands = operands.into_iter();
+            let first = operands.next().unwrap();
+            operands.fold(first, |left, right| op(vec![left, right]))
+        }
+    }
+}
+
+/// Whether `expr` makes a call that takes a continuation, outside any
+/// `lambda` in it.
+fn is_serious(expr: &Expr) -> bool {
+    match expr.kind {
+        ExprKind::Lambda(_) => false,
+        ExprKind::Call(ref operator, ref operands) => match primitive_call(operator, operands) {
+            Some(Primitive::CallWithCurrentContinuation) | Some(Primitive::CallWithValues) | None => true,
+            // More or fewer than one value may be passed by a call.
+            Some(Primitive::Values) if operands.len() != 1 => true,
+            Some(_) => operands.iter().any(is_serious),
+        },
+        _ => expr.children().into_iter().any(is_serious),
+    }
+}
+
+/// Whether `value` has no effects, so that it doesn't matter when it is
+/// computed or whether it is at all.
+fn is_atomic(value: &Expr) -> bool {
+    matches!(value.kind, ExprKind::Const(_) | ExprKind::Ref(_) | ExprKind::Lambda(_)
+                         | ExprKind::Op(Op::Unspecified, _) | ExprKind::Op(Op::Unassigned, _))
+}
+
+fn is_ref(expr: &Expr, var: Var) -> bool {
+    match expr.kind {
+        ExprKind::Ref(v) => v == var,
+        _ => false,
+    }
+}
+
+fn call(operator: Expr, operands: Vec<Expr>, span: Span) -> Expr {
+    Expr::new(ExprKind::Call(Box::new(operator), operands), span)
+}
+
+fn unspecified(span: Span) -> Expr {
+    Expr::new(ExprKind::Op(Op::Unspecified, Vec::new()), span)
+}
+
+/// The procedure `call-with-current-continuation` passes for the
+/// continuation `k`. Like every procedure, it is called with a
+/// continuation, but ignores it to pass its argument to `k` instead.
+fn escape(k: Var, span: Span) -> Expr {
+    let v = Var::local("v");
+    let body = call(Expr::new(ExprKind::Ref(k), span), vec![Expr::new(ExprKind::Ref(v), span)], span);
+    Expr::new(ExprKind::Lambda(Lambda { params: vec![Var::local("k"), v], rest: None, body: vec![body] }), span)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ir::{self, assign};
+    use pipeline;
+
+    /// Converts the forms of `input`, where `f` and `g` are defined.
+    fn converted(input: &str) -> String {
+        let mut numbers = HashMap::new();
+        let source = format!("(define (f . x) x) (define (g . x) x) {}", input);
+        pipeline::resolve("test", source.as_bytes())
+            .skip(2)
+            .map(|r| {
+                let mut expr = r.unwrap();
+                assign::convert(&mut expr);
+                ir::numbered(&super::convert(expr), &mut numbers).to_string()
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn calls_take_continuations() {
+        assert_eq!(converted("(f (g 1) 2)"), "(lambda (k.1) (g (lambda (v.2) (f k.1 v.2 2)) 1))");
+        assert_eq!(converted("(define (f x) (g x))"), "(lambda (k.1) (k.1 (define f (lambda (k.2 x.3) (g k.2 x.3)))))");
+        assert_eq!(converted("(lambda (x) (car (cdr x)))"),
+                   "(lambda (k.1) (k.1 (lambda (k.2 x.3) (k.2 (#%car (#%cdr x.3))))))");
+    }
+
+    #[test]
+    fn primitives() {
+        assert_eq!(converted("(+ 1 2 3) (- 1) (*)"),
+                   "(lambda (k.1) (k.1 (#%+ (#%+ 1 2) 3))) (lambda (k.2) (k.2 (#%- 0 1))) (lambda (k.3) (k.3 1))");
+        // Wrong numbers of arguments are left for the procedure to report.
+        assert_eq!(converted("(car 1 2)"), "(lambda (k.1) (car k.1 1 2))");
+        // An effect before a call is made first, once.
+        assert_eq!(converted("(list (write-char #\\a) (f))"),
+                   "(lambda (k.1) (letrec* ((t.2 (#%write-char #\\a))) (f (lambda (v.3) (list k.1 t.2 v.3)))))");
+    }
+
+    #[test]
+    fn administrative_redexes() {
+        // Continuations that only pass their value on are not made at all.
+        assert_eq!(converted("(lambda () (f) (g))"), "(lambda (k.1) (k.1 (lambda (k.2) (f (lambda (v.3) (g k.2))))))");
+        assert_eq!(converted("(lambda (x) (if x (f) (g)))"),
+                   "(lambda (k.1) (k.1 (lambda (k.2 x.3) (if x.3 (f k.2) (g k.2)))))");
+        // Nor is a procedure for `let`.
+        assert_eq!(converted("(lambda (x) (let ((y (f x))) (car y)))"),
+                   "(lambda (k.1) (k.1 (lambda (k.2 x.3) \
+                    (f (lambda (v.4) (letrec* ((y.5 v.4)) (k.2 (#%car y.5)))) x.3))))");
+        // A join is made once for both branches.
+        assert_eq!(converted("(lambda (x) (car (if x (f) 1)))"),
+                   "(lambda (k.1) (k.1 (lambda (k.2 x.3) (letrec* ((k.4 (lambda (v.5) (k.2 (#%car v.5))))) \
+                    (if x.3 (f k.4) (k.4 1))))))");
+    }
+
+    #[test]
+    fn call_cc() {
+        assert_eq!(converted("(lambda () (+ 1 (call-with-current-continuation f)))"),
+                   "(lambda (k.1) (k.1 (lambda (k.2) (letrec* ((k.3 (lambda (v.4) (k.2 (#%+ 1 v.4))))) \
+                    (f k.3 (lambda (k.5 v.6) (k.3 v.6)))))))");
+        assert_eq!(converted("(lambda () (call/cc f))"), "(lambda (k.1) (k.1 (lambda (k.2) (call/cc k.2 f))))");
+    }
+
+    #[test]
+    fn multiple_values() {
+        // A consumer that is a `lambda` binds the values it is passed in
+        // place, or is the continuation of a call.
+        assert_eq!(converted("(lambda () (call-with-values (lambda () (values 1 2)) (lambda (a . b) (f a b))))"),
+                   "(lambda (k.1) (k.1 (lambda (k.2) \
+                    (letrec* ((a.3 1) (b.4 (#%cons 2 (quote ())))) (f k.2 a.3 b.4)))))");
+        assert_eq!(converted("(lambda () (+ 1 (call-with-values f (lambda (a b) (* a b)))))"),
+                   "(lambda (k.1) (k.1 (lambda (k.2) (f (lambda (a.3 b.4) (k.2 (#%+ 1 (#%* a.3 b.4))))))))");
+        assert_eq!(converted("(lambda () (values 1 2))"), "(lambda (k.1) (k.1 (lambda (k.2) (k.2 1 2))))");
+        // A continuation that takes one value is called to report more.
+        assert_eq!(converted("(lambda () (car (values 1 2)))"),
+                   "(lambda (k.1) (k.1 (lambda (k.2) (letrec* ((k.3 (lambda (v.4) (k.2 (#%car v.4))))) (k.3 1 2)))))");
+        // Any other consumer is left to the procedure.
+        assert_eq!(converted("(lambda () (call-with-values f g))"),
+                   "(lambda (k.1) (k.1 (lambda (k.2) (call-with-values k.2 f g))))");
+    }
+
+    #[test]
+    fn letrec() {
+        assert_eq!(converted("(lambda () (define (f) (g)) (define (g) (f)) (define x (f)) (set! x 1) x)"),
+                   "(lambda (k.1) (k.1 (lambda (k.2) (letrec* ((f.3 (lambda (k.5) (g.4 k.5))) \
+                    (g.4 (lambda (k.6) (f.3 k.6)))) (f.3 (lambda (v.7) (letrec* ((x.8 v.7)) \
+                    (begin (set! x.8 1) (k.2 x.8)))))))))");
+        // `x` can only be read too early here.
+        assert_eq!(converted("(lambda () (define x (+ x 1)) x)"),
+                   "(lambda (k.1) (k.1 (lambda (k.2) \
+                    (letrec* ((x.3 (#%+ (#%check x.3 (#%unassigned)) 1))) (k.2 x.3)))))");
+    }
+}
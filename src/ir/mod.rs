@@ -0,0 +1,313 @@
+//! The core language that the passes after the front end work on. It has
+//! the same forms as `syntax::Expr`, except that every variable is resolved
+//! to the binding it refers to, and that the passes add `Op`s of their own.
+
+pub mod assign;
+pub mod closure;
+pub mod cps;
+
+#[cfg(test)]
+use std::collections::HashMap;
+use std::fmt;
+
+use primitive::Primitive;
+use reader::{Datum, DatumKind};
+use span::Span;
+use symbol::Symbol;
+
+/// A variable, named by the binding that introduces it. Every local binding
+/// gets a `Var` of its own, even where the same name is bound twice, while
+/// a global is the same `Var` wherever its name refers to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Var {
+    symbol: Symbol,
+    global: bool,
+}
+
+impl Var {
+    pub fn global(name: Symbol) -> Self {
+        Var {
+            symbol: name,
+            global: true,
+        }
+    }
+
+    /// A new local variable named `name`, distinct from every other one.
+    pub fn local(name: &str) -> Self {
+        Var {
+            symbol: Symbol::fresh(name),
+            global: false,
+        }
+    }
+
+    pub fn is_global(self) -> bool {
+        self.global
+    }
+
+    pub fn symbol(self) -> Symbol {
+        self.symbol
+    }
+}
+
+/// Runs the passes that work on one top-level form at a time on `expr`,
+/// adding the functions its `lambda`s become to `functions`.
+pub fn lower(mut expr: Expr, functions: &mut Vec<Lambda>) -> Expr {
+    assign::convert(&mut expr);
+    let mut expr = cps::convert(expr);
+    // Code after a call ends up in a continuation, so variables `set!`
+    // there are captured by one, and need boxes just the same.
+    assign::convert(&mut expr);
+    closure::convert(expr, functions)
+}
+
+impl fmt::Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.symbol)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug)]
+pub enum ExprKind {
+    /// A quoted or self-evaluating datum.
+    Const(Datum),
+    Ref(Var),
+    Set(Var, Box<Expr>),
+    /// A definition of a global at top level.
+    Define(Var, Box<Expr>),
+    Lambda(Lambda),
+    If(Box<Expr>, Box<Expr>, Option<Box<Expr>>),
+    Begin(Vec<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    /// `(letrec* ((var init)...) body...)`, which the definitions at the
+    /// start of a body become. The body is never empty.
+    Letrec(Vec<(Var, Expr)>, Vec<Expr>),
+    /// An operation the compiler introduces, which programs can't name.
+    Op(Op, Vec<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    /// A new box holding the operand, or nothing yet if there is none.
+    Box,
+    /// The contents of the box that is the operand.
+    Unbox,
+    /// Stores the second operand in the box that is the first.
+    SetBox,
+    /// The operand, which is the value of the variable, as long as the
+    /// variable has been initialised. Reading a variable of a `letrec*`
+    /// before it is initialised is an error, so references that may do so
+    /// are checked at run time.
+    Check(Var),
+    /// A closure of the function with the index, capturing the operands.
+    /// One that captures nothing is static: it is made once, in the data
+    /// segment, so this allocates nothing.
+    Closure(usize),
+    /// The captured value in the slot of the closure that is the operand.
+    ClosureRef(usize),
+    /// Stores the second operand in the slot of the closure that is the
+    /// first.
+    ClosureSet(usize),
+    /// Calls the function of the closure that is the first operand, with
+    /// the closure and the rest of the operands.
+    Invoke,
+    /// A call to a primitive with the operands.
+    Primitive(Primitive),
+    /// The value of expressions whose value is unspecified.
+    Unspecified,
+    /// The value a variable has before it is initialised, which `Check`
+    /// catches.
+    Unassigned,
+}
+
+#[derive(Clone, Debug)]
+pub struct Lambda {
+    pub params: Vec<Var>,
+    pub rest: Option<Var>,
+    pub body: Vec<Expr>,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr {
+            kind,
+            span,
+        }
+    }
+
+    /// The expressions directly inside this one.
+    pub fn children(&self) -> Vec<&Expr> {
+        match self.kind {
+            ExprKind::Const(_) | ExprKind::Ref(_) => Vec::new(),
+            ExprKind::Set(_, ref value) | ExprKind::Define(_, ref value) => vec![value],
+            ExprKind::Lambda(ref lambda) => lambda.body.iter().collect(),
+            ExprKind::If(ref test, ref consequent, ref alternate) => {
+                let mut children = vec![&**test, &**consequent];
+                children.extend(alternate.as_ref().map(|e| &**e));
+                children
+            }
+            ExprKind::Begin(ref body) => body.iter().collect(),
+            ExprKind::Call(ref operator, ref operands) => Some(&**operator).into_iter().chain(operands).collect(),
+            ExprKind::Letrec(ref bindings, ref body) => bindings.iter().map(|b| &b.1).chain(body).collect(),
+            ExprKind::Op(_, ref operands) => operands.iter().collect(),
+        }
+    }
+
+    /// Like `children`, but mutable.
+    pub fn children_mut(&mut self) -> Vec<&mut Expr> {
+        match self.kind {
+            ExprKind::Const(_) | ExprKind::Ref(_) => Vec::new(),
+            ExprKind::Set(_, ref mut value) | ExprKind::Define(_, ref mut value) => vec![value],
+            ExprKind::Lambda(ref mut lambda) => lambda.body.iter_mut().collect(),
+            ExprKind::If(ref mut test, ref mut consequent, ref mut alternate) => {
+                let mut children = vec![&mut **test, &mut **consequent];
+                children.extend(alternate.as_mut().map(|e| &mut **e));
+                children
+            }
+            ExprKind::Begin(ref mut body) => body.iter_mut().collect(),
+            ExprKind::Call(ref mut operator, ref mut operands) =>
+                Some(&mut **operator).into_iter().chain(operands).collect(),
+            ExprKind::Letrec(ref mut bindings, ref mut body) =>
+                bindings.iter_mut().map(|b| &mut b.1).chain(body).collect(),
+            ExprKind::Op(_, ref mut operands) => operands.iter_mut().collect(),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ExprKind::Const(ref datum) => match datum.kind {
+                DatumKind::Symbol(_) | DatumKind::Pair(..) | DatumKind::Nil => write!(f, "(quote {})", datum),
+                _ => write!(f, "{}", datum),
+            },
+            ExprKind::Ref(var) => write!(f, "{}", var),
+            ExprKind::Set(var, ref value) => write!(f, "(set! {} {})", var, value),
+            ExprKind::Define(var, ref value) => write!(f, "(define {} {})", var, value),
+            ExprKind::Lambda(ref lambda) => write!(f, "{}", lambda),
+            ExprKind::If(ref test, ref consequent, None) => write!(f, "(if {} {})", test, consequent),
+            ExprKind::If(ref test, ref consequent, Some(ref alternate)) =>
+                write!(f, "(if {} {} {})", test, consequent, alternate),
+            ExprKind::Begin(ref body) => {
+                f.write_str("(begin")?;
+                write_all(f, body)?;
+                f.write_str(")")
+            }
+            ExprKind::Call(ref operator, ref operands) => {
+                write!(f, "({}", operator)?;
+                write_all(f, operands)?;
+                f.write_str(")")
+            }
+            ExprKind::Letrec(ref bindings, ref body) => {
+                let bindings: Vec<_> = bindings.iter().map(|b| format!("({} {})", b.0, b.1)).collect();
+                write!(f, "(letrec* ({})", bindings.join(" "))?;
+                write_all(f, body)?;
+                f.write_str(")")
+            }
+            ExprKind::Op(op, ref operands) => {
+                write!(f, "({}", op)?;
+                write_all(f, operands)?;
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+/// Prints the operation with a `#%` prefix, which no identifier can have.
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Op::Box => f.write_str("#%box"),
+            Op::Unbox => f.write_str("#%unbox"),
+            Op::SetBox => f.write_str("#%set-box!"),
+            Op::Check(var) => write!(f, "#%check {}", var),
+            Op::Closure(index) => write!(f, "#%closure {}", index),
+            Op::ClosureRef(slot) => write!(f, "#%closure-ref {}", slot),
+            Op::ClosureSet(slot) => write!(f, "#%closure-set! {}", slot),
+            Op::Invoke => f.write_str("#%invoke"),
+            Op::Primitive(p) => write!(f, "#%{}", p.name()),
+            Op::Unspecified => f.write_str("#%unspecified"),
+            Op::Unassigned => f.write_str("#%unassigned"),
+        }
+    }
+}
+
+impl fmt::Display for Lambda {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("(lambda ")?;
+        match (self.params.is_empty(), self.rest) {
+            (true, Some(rest)) => write!(f, "{}", rest)?,
+            (_, rest) => {
+                let params: Vec<_> = self.params.iter().map(|p| p.to_string()).collect();
+                write!(f, "({}", params.join(" "))?;
+                if let Some(rest) = rest {
+                    write!(f, " . {}", rest)?;
+                }
+                f.write_str(")")?;
+            }
+        }
+        write_all(f, &self.body)?;
+        f.write_str(")")
+    }
+}
+
+/// Writes each expression preceded by a space.
+fn write_all(f: &mut fmt::Formatter, exprs: &[Expr]) -> fmt::Result {
+    for expr in exprs {
+        write!(f, " {}", expr)?;
+    }
+    Ok(())
+}
+
+/// A copy of `expr` with every local renamed after its name and a number,
+/// counting in `numbers`, so that tests can print different variables with
+/// the same name differently.
+#[cfg(test)]
+pub fn numbered(expr: &Expr, numbers: &mut HashMap<Var, Var>) -> Expr {
+    let mut var = |var: Var| match var.symbol() == Symbol::intern(var.symbol().as_str()) {
+        true => var,
+        false => {
+            let count = numbers.len() + 1;
+            *numbers.entry(var).or_insert_with(|| Var::global(Symbol::intern(&format!("{}.{}", var, count))))
+        }
+    };
+    let kind = match expr.kind {
+        ExprKind::Ref(v) => ExprKind::Ref(var(v)),
+        ExprKind::Set(v, ref value) => ExprKind::Set(var(v), Box::new(numbered(value, numbers))),
+        ExprKind::Define(v, ref value) => ExprKind::Define(var(v), Box::new(numbered(value, numbers))),
+        ExprKind::Lambda(ref lambda) => ExprKind::Lambda(Lambda {
+            params: lambda.params.iter().map(|&v| var(v)).collect(),
+            rest: lambda.rest.map(var),
+            body: lambda.body.iter().map(|e| numbered(e, numbers)).collect(),
+        }),
+        ExprKind::If(ref test, ref consequent, ref alternate) => ExprKind::If(
+            Box::new(numbered(test, numbers)),
+            Box::new(numbered(consequent, numbers)),
+            alternate.as_ref().map(|e| Box::new(numbered(e, numbers))),
+        ),
+        ExprKind::Begin(ref body) => ExprKind::Begin(body.iter().map(|e| numbered(e, numbers)).collect()),
+        ExprKind::Call(ref operator, ref operands) => ExprKind::Call(
+            Box::new(numbered(operator, numbers)),
+            operands.iter().map(|e| numbered(e, numbers)).collect(),
+        ),
+        ExprKind::Letrec(ref bindings, ref body) => {
+            let vars: Vec<_> = bindings.iter().map(|b| var(b.0)).collect();
+            let inits = bindings.iter().map(|b| numbered(&b.1, numbers));
+            ExprKind::Letrec(vars.into_iter().zip(inits).collect(), body.iter().map(|e| numbered(e, numbers)).collect())
+        }
+        ExprKind::Op(op, ref operands) => {
+            let op = match op {
+                Op::Check(v) => Op::Check(var(v)),
+                op => op,
+            };
+            ExprKind::Op(op, operands.iter().map(|e| numbered(e, numbers)).collect())
+        }
+        ExprKind::Const(_) => expr.kind.clone(),
+    };
+    Expr::new(kind, expr.span)
+}
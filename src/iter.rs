@@ -1,39 +1,171 @@
-use std::vec;
+use std::collections::VecDeque;
+use std::iter::FusedIterator;
 
+/// A stateful transformation from a stream of `T` to a stream of `U`. Each
+/// input item may produce any number of outputs, which are pushed into `out`.
 pub trait StreamMap<T, U> {
-    fn produce(&mut self, T) -> Vec<U>;
+    fn produce(&mut self, item: T, out: &mut impl Extend<U>);
+
+    /// Called once the input is exhausted, to flush anything still pending.
+    fn finish(&mut self, _out: &mut impl Extend<U>) {}
+
+    /// The most outputs a single input can produce, if there is such a bound.
+    /// Used for `StreamAdapter::size_hint`.
+    fn max_outputs(&self) -> Option<usize> {
+        None
+    }
 }
 
-pub struct StreamAdapter<M, I, T> {
+pub struct StreamAdapter<M, I, U> {
     map: M,
     source: I,
-    buffer: vec::IntoIter<T>,
+    buffer: VecDeque<U>,
+    done: bool,
 }
 
-impl<M, I, T> StreamAdapter<M, I, T> {
+impl<M, I, U> StreamAdapter<M, I, U> {
     pub fn new(map: M, iter: I) -> Self {
         StreamAdapter {
-            map: map,
+            map,
             source: iter,
-            buffer: Vec::new().into_iter(),
+            buffer: VecDeque::new(),
+            done: false,
+        }
+    }
+}
+
+impl<M, I, T, U> StreamAdapter<M, I, U>
+    where I: Iterator<Item=T>, M: StreamMap<T, U> {
+    /// Returns the next item without consuming it.
+    pub fn peek(&mut self) -> Option<&U> {
+        self.peek_nth(0)
+    }
+
+    /// Returns the item `n` places ahead without consuming anything, pulling
+    /// as much input as that takes.
+    pub fn peek_nth(&mut self, n: usize) -> Option<&U> {
+        while self.buffer.len() <= n && self.fill() {}
+        self.buffer.get(n)
+    }
+
+    /// Feeds one more input item to the map. Returns false once there is no
+    /// more input.
+    fn fill(&mut self) -> bool {
+        if self.done {
+            return false;
+        }
+        match self.source.next() {
+            Some(x) => self.map.produce(x, &mut self.buffer),
+            None => {
+                self.map.finish(&mut self.buffer);
+                self.done = true;
+            }
         }
+        true
+    }
+}
+
+/// Chains stages onto an iterator: `bytes.then(lexer).then(reader)`.
+pub trait StreamExt: Iterator + Sized {
+    fn then<M, U>(self, map: M) -> StreamAdapter<M, Self, U>
+        where M: StreamMap<Self::Item, U> {
+        StreamAdapter::new(map, self)
     }
 }
 
+impl<I: Iterator> StreamExt for I {}
+
 impl<M, I, T, U> Iterator for StreamAdapter<M, I, U>
     where I: Iterator<Item=T>, M: StreamMap<T, U> {
     type Item = U;
 
     fn next(&mut self) -> Option<U> {
-        match self.buffer.next() {
-            s@Some(_) => s,
-            None => match self.source.next() {
-                Some(x) => {
-                    self.buffer = self.map.produce(x).into_iter();
-                    self.next()
-                }
-                None => None
+        loop {
+            if let Some(x) = self.buffer.pop_front() {
+                return Some(x);
+            }
+            if !self.fill() {
+                return None;
             }
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let buffered = self.buffer.len();
+        if self.done {
+            return (buffered, Some(buffered));
+        }
+
+        // `finish` may produce up to `max_outputs` items as well.
+        let (_, source_max) = self.source.size_hint();
+        let max = match (source_max, self.map.max_outputs()) {
+            (Some(n), Some(k)) => (n + 1).checked_mul(k).and_then(|m| m.checked_add(buffered)),
+            _ => None,
+        };
+        (buffered, max)
+    }
+}
+
+impl<M, I, T, U> FusedIterator for StreamAdapter<M, I, U>
+    where I: Iterator<Item=T>, M: StreamMap<T, U> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Emits the running sum every time it reaches a multiple of `every`.
+    struct Sums {
+        every: u64,
+        sum: u64,
+    }
+
+    impl StreamMap<u64, u64> for Sums {
+        fn produce(&mut self, item: u64, out: &mut impl Extend<u64>) {
+            self.sum += item;
+            if self.sum.is_multiple_of(self.every) {
+                out.extend(Some(self.sum));
+            }
+        }
+
+        fn finish(&mut self, out: &mut impl Extend<u64>) {
+            if !self.sum.is_multiple_of(self.every) {
+                out.extend(Some(self.sum));
+            }
+        }
+
+        fn max_outputs(&self) -> Option<usize> {
+            Some(1)
+        }
+    }
+
+    #[test]
+    fn long_silent_runs() {
+        let sums = Sums { every: 1 << 21, sum: 0 };
+        let out: Vec<_> = StreamAdapter::new(sums, (0..1 << 22).map(|_| 1)).collect();
+        assert_eq!(out, vec![1 << 21, 1 << 22]);
+    }
+
+    #[test]
+    fn size_hint_and_fused() {
+        let mut adapter = vec![1, 1, 1].into_iter().then(Sums { every: 2, sum: 0 });
+        assert_eq!(adapter.size_hint(), (0, Some(4)));
+        assert_eq!(adapter.next(), Some(2));
+        assert_eq!(adapter.size_hint(), (0, Some(2)));
+        assert_eq!(adapter.next(), Some(3));
+        assert_eq!(adapter.size_hint(), (0, Some(0)));
+        assert_eq!(adapter.next(), None);
+        assert_eq!(adapter.next(), None);
+    }
+
+    #[test]
+    fn peeking() {
+        let mut adapter = vec![1, 1, 2, 4].into_iter().then(Sums { every: 2, sum: 0 });
+        assert_eq!(adapter.peek_nth(1), Some(&4));
+        assert_eq!(adapter.size_hint(), (2, Some(4)));
+        assert_eq!(adapter.peek(), Some(&2));
+        assert_eq!(adapter.next(), Some(2));
+        assert_eq!(adapter.peek_nth(1), Some(&8));
+        assert_eq!(adapter.peek_nth(2), None);
+        assert_eq!(adapter.collect::<Vec<_>>(), vec![4, 8]);
+    }
 }
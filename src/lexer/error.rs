@@ -1,44 +1,23 @@
-use std::error;
 use std::fmt;
-use std::result;
 
-pub type Result<T> = result::Result<T, Error>;
+use lexer::TokenType;
 
-#[derive(Clone, Debug)]
-pub struct Error {
-    pub file_name: String,
-    pub line: u32,
-    pub kind: ErrorKind,
-}
-
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Error ({}:{}): {}", self.file_name, self.line, self.kind)
-    }
-}
-
-impl error::Error for Error {
-    fn description(&self) -> &str {
-        match self.kind {
-            ErrorKind::InvalidCharacter(_) => "Lexer Error: Invalid Character",
-        }
-    }
-
-    fn cause(&self) -> Option<&error::Error> {
-        None
-    }
-}
-
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ErrorKind {
     InvalidCharacter(u8),
+    UnexpectedEof,
+    /// A token the grammar accepts but whose value is out of range or has a
+    /// bad escape.
+    InvalidLiteral(TokenType),
 }
 
 impl fmt::Display for ErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            &ErrorKind::InvalidCharacter(c) =>
+        match *self {
+            ErrorKind::InvalidCharacter(c) =>
                 write!(f, "Invalid character '{}'", (c as char).escape_default()),
+            ErrorKind::UnexpectedEof => f.write_str("Unexpected end of input in token"),
+            ErrorKind::InvalidLiteral(token) => write!(f, "Invalid {:?} literal", token),
         }
     }
 }
@@ -12,6 +12,7 @@ macro_rules! tokens {
             @variants []
             @token_variants []
             @parse_match []
+            @type_match []
         }
     }
 }
@@ -25,6 +26,7 @@ macro_rules! tokens_impl {
         @variants [ $( $variants:tt )* ]
         @token_variants [ $( $tokens:tt )* ]
         @parse_match [ $( $parse_match:tt )* ]
+        @type_match [ $( $type_match:tt )* ]
     ) => {
         #[derive(Clone, PartialEq, Debug)]
         pub enum $name {
@@ -43,6 +45,16 @@ macro_rules! tokens_impl {
                 }
             }
         }
+
+        // Only the tests generated from the grammar check token types.
+        #[cfg(test)]
+        impl $name {
+            pub fn token_type(&self) -> $tag_name {
+                match *self {
+                    $( $type_match )*
+                }
+            }
+        }
     };
 
     (
@@ -56,6 +68,7 @@ macro_rules! tokens_impl {
         @variants [ $( $variants:tt )+ ]
         @token_variants [ $( $tokens:tt )+ ]
         @parse_match [ $( $parse_match:tt )+ ]
+        @type_match [ $( $type_match:tt )+ ]
     ) => {
         tokens_impl! {
             @name $name
@@ -69,6 +82,10 @@ macro_rules! tokens_impl {
                 $tag_name::$variant_name =>
                     Ok($name::$variant_name(TokenFromBytes::from_bytes($bytes)?)),
             ]
+            @type_match [
+                $( $type_match )*
+                $name::$variant_name(_) => $tag_name::$variant_name,
+            ]
         }
     };
 
@@ -83,6 +100,7 @@ macro_rules! tokens_impl {
         @variants []
         @token_variants []
         @parse_match []
+        @type_match []
     ) => {
         tokens_impl! {
             @name $name
@@ -95,6 +113,9 @@ macro_rules! tokens_impl {
                 $tag_name::$variant_name =>
                     Ok($name::$variant_name(TokenFromBytes::from_bytes($bytes)?)),
             ]
+            @type_match [
+                $name::$variant_name(_) => $tag_name::$variant_name,
+            ]
         }
     };
 
@@ -109,6 +130,7 @@ macro_rules! tokens_impl {
         @variants [ $( $variants:tt )+ ]
         @token_variants [ $( $tokens:tt )+ ]
         @parse_match [ $( $parse_match:tt )+ ]
+        @type_match [ $( $type_match:tt )+ ]
     ) => {
         tokens_impl! {
             @name $name
@@ -125,6 +147,10 @@ macro_rules! tokens_impl {
                     Err(VoidError)
                 },
             ]
+            @type_match [
+                $( $type_match )*
+                $name::$variant_name => $tag_name::$variant_name,
+            ]
         }
     };
 
@@ -139,6 +165,7 @@ macro_rules! tokens_impl {
         @variants []
         @token_variants []
         @parse_match []
+        @type_match []
     ) => {
         tokens_impl! {
             @name $name
@@ -154,6 +181,9 @@ macro_rules! tokens_impl {
                     Err(VoidError)
                 },
             ]
+            @type_match [
+                $name::$variant_name => $tag_name::$variant_name,
+            ]
         }
     };
 }
@@ -3,32 +3,27 @@ mod error;
 mod table;
 mod token;
 
+use std::io;
 use std::str;
 
-use iter::{StreamAdapter, StreamMap};
+use error::{Error, Result};
+use iter::StreamMap;
+use span::{Position, Span, Spanned};
 
 pub use self::error::*;
 pub use self::token::*;
-use self::table::{LEXER_TABLE, Consume, TableTrans};
-
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
-pub enum LexerState {
-    Ready,
-    Comment,
-    Ident,
-    Hash,
-    Bool,
-    Sign,
-    Int,
-    Float,
-    String,
-    StringEscape,
-}
+use self::table::{LEXER_TABLE, READY, Consume, TableTrans};
+
+/// Index of a row in the lexer table generated from the `tokens` grammar.
+pub type LexerState = usize;
 
+/// Turns a stream of bytes into tokens tagged with their spans. The end of
+/// the input behaves like a final newline.
 #[derive(Debug)]
 pub struct Lexer<'a> {
     file_name: &'a str,
-    line: u32,
+    position: Position,
+    token_start: Position,
     state: LexerState,
     current: Vec<u8>,
 }
@@ -36,32 +31,41 @@ pub struct Lexer<'a> {
 impl<'a> Lexer<'a> {
     pub fn new(file_name: &'a str) -> Self {
         Lexer {
-            file_name: file_name,
-            line: 0,
-            state: LexerState::Ready,
+            file_name,
+            position: Position::start(),
+            token_start: Position::start(),
+            state: READY,
             current: Vec::new(),
         }
     }
 
-    pub fn iter<I>(self, source: I) -> StreamAdapter<Self, I, Result<Token>>
-        where I: Iterator<Item=u8> {
-        StreamAdapter::new(self, source)
+    /// Whether the input so far ends between tokens.
+    pub fn is_ready(&self) -> bool {
+        self.state == READY
     }
 
-    fn push_char(&mut self, c: u8, out: &mut Vec<Token>) -> Result<()> {
-        if c == b'\n' {
-            self.line += 1;
+    fn push_char(&mut self, c: u8, out: &mut impl Extend<Result<Spanned<Token>>>) -> Result<()> {
+        if self.state == READY {
+            self.token_start = self.position;
         }
 
-        match LEXER_TABLE[&self.state][c as usize] {
-            Ok(TableTrans {output, next_state, consume}) => {
+        match LEXER_TABLE[self.state][c as usize] {
+            Ok(TableTrans {output, replace, next_state, consume}) => {
                 if consume == Consume::Append {
                     self.current.push(c);
                 }
 
                 if let Some(output_type) = output {
-                    let token = output_type.parse(&self.current).expect("Invalid lexer table");
-                    out.push(token);
+                    let bytes = replace.unwrap_or(&self.current);
+                    let end = match consume {
+                        Consume::Ungetc => self.position,
+                        _ => self.position.advance(c),
+                    };
+                    let span = Span::new(self.token_start, end);
+                    out.extend(Some(match output_type.parse(bytes) {
+                        Ok(token) => Ok(Spanned::new(token, span)),
+                        Err(_) => Err(self.error(span, ErrorKind::InvalidLiteral(output_type))),
+                    }));
                     self.current.clear();
                 };
 
@@ -72,21 +76,57 @@ impl<'a> Lexer<'a> {
 
                 Ok(())
             }
-            Err(_) => Err(Error {
-                file_name: self.file_name.to_owned(),
-                line: self.line,
-                kind: ErrorKind::InvalidCharacter(c),
-            }),
+            Err(_) => {
+                self.state = READY;
+                self.current.clear();
+                Err(self.error(Span::new(self.position, self.position.advance(c)),
+                               ErrorKind::InvalidCharacter(c)))
+            }
         }
     }
+
+    fn error(&self, span: Span, kind: ErrorKind) -> Error {
+        Error::new(self.file_name, span, kind)
+    }
 }
 
-impl<'a> StreamMap<u8, Result<Token>> for Lexer<'a> {
-    fn produce(&mut self, c: u8) -> Vec<Result<Token>> {
-        let mut v = Vec::new();
-        match self.push_char(c, &mut v) {
-            Ok(_) => v.into_iter().map(|x| Ok(x)).collect(),
-            Err(e) => vec![Err(e)],
+impl<'a> StreamMap<io::Result<u8>, Result<Spanned<Token>>> for Lexer<'a> {
+    fn produce(&mut self, c: io::Result<u8>, out: &mut impl Extend<Result<Spanned<Token>>>) {
+        let res = match c {
+            Ok(c) => self.push_char(c, out).map(|_| c),
+            Err(e) => Err(Error::new(self.file_name, Span::at(self.position), e)),
+        };
+        match res {
+            Ok(c) => self.position = self.position.advance(c),
+            Err(e) => out.extend(Some(Err(e))),
+        }
+    }
+
+    fn finish(&mut self, out: &mut impl Extend<Result<Spanned<Token>>>) {
+        if self.state == READY {
+            return;
+        }
+        if let Err(e) = self.push_char(b'\n', out) {
+            out.extend(Some(Err(e)));
+        } else if self.state != READY {
+            self.state = READY;
+            self.current.clear();
+            let span = Span::new(self.token_start, self.position);
+            out.extend(Some(Err(self.error(span, ErrorKind::UnexpectedEof))));
         }
     }
+
+    /// A byte can end the current token and then complete a one-byte token of
+    /// its own, e.g. the `)` in `x)`.
+    fn max_outputs(&self) -> Option<usize> {
+        Some(2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iter::StreamExt;
+
+    include!(concat!(env!("OUT_DIR"), "/lexer_tests.rs"));
 }
@@ -2,18 +2,29 @@ use std::error::Error;
 use std::fmt::{self, Write};
 use std::str;
 
+use symbol::Symbol;
+
 tokens! {
     Token, TokenType {
         OpenParen: b"",
         CloseParen: b"",
-        Quote: b"quote",
+        OpenVector: b"#(",
+        OpenBytevector: b"#u8(",
+        Dot: b".",
+        Quote: b"",
+        Quasiquote: b"",
+        Unquote: b"",
+        UnquoteSplicing: b"",
         Lambda: b"lambda",
         If: b"if",
-        Ident(String),
+        Ident(Symbol),
         Bool(bool),
         Int(i64),
         Float(f64),
         String(String),
+        Char(char),
+        LabelDef(u32),
+        LabelRef(u32),
     }
 }
 
@@ -30,17 +41,68 @@ pub trait TokenFromBytes: Sized {
     fn from_bytes(bytes: &[u8]) -> Result<Self, VoidError>;
 }
 
+/// Strings and `|symbols|`, with their escapes: `\a`, `\b`, `\t`, `\n`,
+/// `\r`, `\xHH;`, a backslash before any other character stands for that
+/// character, and a backslash ending a line joins it to the next.
 impl TokenFromBytes for String {
     fn from_bytes(bytes: &[u8]) -> Result<Self, VoidError> {
-        Ok(unsafe { String::from_utf8_unchecked(bytes.to_owned()) })
+        let s = str::from_utf8(bytes)?;
+        let mut out = String::with_capacity(s.len());
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                out.push(c);
+                continue;
+            }
+            match chars.next().ok_or(VoidError)? {
+                'a' => out.push('\u{7}'),
+                'b' => out.push('\u{8}'),
+                't' => out.push('\t'),
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                'x' => {
+                    let hex: String = chars.by_ref().take_while(|&c| c != ';').collect();
+                    let code = u32::from_str_radix(&hex, 16)?;
+                    out.push(::std::char::from_u32(code).ok_or(VoidError)?);
+                }
+                c @ '\n' | c @ ' ' | c @ '\t' => {
+                    let mut newline = c == '\n';
+                    while let Some(&c) = chars.peek() {
+                        match c {
+                            '\n' if !newline => newline = true,
+                            ' ' | '\t' => {}
+                            _ => break,
+                        }
+                        chars.next();
+                    }
+                    if !newline {
+                        return Err(VoidError);
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Identifiers are interned as they are read, straight from their bytes.
+/// Only a `|symbol|` can have escapes, and only one with them needs its
+/// name built first.
+impl TokenFromBytes for Symbol {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, VoidError> {
+        if bytes.contains(&b'\\') {
+            return Ok(Symbol::intern(&String::from_bytes(bytes)?));
+        }
+        Ok(Symbol::intern(str::from_utf8(bytes)?))
     }
 }
 
 impl TokenFromBytes for bool {
     fn from_bytes(bytes: &[u8]) -> Result<Self, VoidError> {
-        if bytes == b"t" {
+        if bytes == b"true" {
             Ok(true)
-        } else if bytes == b"f" {
+        } else if bytes == b"false" {
             Ok(false)
         } else {
             Err(VoidError)
@@ -57,25 +119,78 @@ impl TokenFromBytes for i64 {
 
 impl TokenFromBytes for f64 {
     fn from_bytes(bytes: &[u8]) -> Result<Self, VoidError> {
-        let s = unsafe { str::from_utf8_unchecked(bytes) };
+        match bytes {
+            b"+inf.0" => Ok(f64::INFINITY),
+            b"-inf.0" => Ok(f64::NEG_INFINITY),
+            b"+nan.0" | b"-nan.0" => Ok(f64::NAN),
+            _ => {
+                let s = unsafe { str::from_utf8_unchecked(bytes) };
+                Ok(s.parse()?)
+            }
+        }
+    }
+}
+
+/// The number in a datum label, `#n=` or `#n#`.
+impl TokenFromBytes for u32 {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, VoidError> {
+        let s = unsafe { str::from_utf8_unchecked(&bytes[1..bytes.len() - 1]) };
         Ok(s.parse()?)
     }
 }
 
+impl TokenFromBytes for char {
+    /// `#\` followed by the character itself, its name, or `x` and its code
+    /// point in hex.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, VoidError> {
+        let s = &str::from_utf8(bytes)?[2..];
+        let mut chars = s.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return Ok(c);
+        }
+        match s {
+            "alarm" => Ok('\u{7}'),
+            "backspace" => Ok('\u{8}'),
+            "delete" => Ok('\u{7f}'),
+            "escape" => Ok('\u{1b}'),
+            "newline" => Ok('\n'),
+            "null" => Ok('\0'),
+            "return" => Ok('\r'),
+            "space" => Ok(' '),
+            "tab" => Ok('\t'),
+            _ if s.starts_with('x') => {
+                let code = u32::from_str_radix(&s[1..], 16)?;
+                ::std::char::from_u32(code).ok_or(VoidError)
+            }
+            _ => Err(VoidError),
+        }
+    }
+}
+
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Token::OpenParen => f.write_char('('),
             Token::CloseParen => f.write_char(')'),
+            Token::OpenVector => f.write_str("#("),
+            Token::OpenBytevector => f.write_str("#u8("),
+            Token::Dot => f.write_char('.'),
             Token::Quote => f.write_char('\''),
+            Token::Quasiquote => f.write_char('`'),
+            Token::Unquote => f.write_char(','),
+            Token::UnquoteSplicing => f.write_str(",@"),
             Token::Lambda => f.write_str("lambda"),
             Token::If => f.write_str("if"),
-            Token::Ident(ref s) => f.write_str(s),
+            Token::Ident(s) => write!(f, "{}", s),
             Token::Bool(true) => f.write_str("#t"),
             Token::Bool(false) => f.write_str("#f"),
             Token::Int(ref n) => write!(f, "{}", n),
             Token::Float(ref n) => write!(f, "{}", n),
             Token::String(ref s) => write!(f, "{:?}", s),
+            Token::Char(c) if !c.is_whitespace() && !c.is_control() => write!(f, "#\\{}", c),
+            Token::Char(c) => write!(f, "#\\x{:x}", c as u32),
+            Token::LabelDef(n) => write!(f, "#{}=", n),
+            Token::LabelRef(n) => write!(f, "#{}#", n),
         }
     }
 }
@@ -1,27 +1,235 @@
-#![feature(log_syntax)]
-#![feature(ascii_ctype)]
-
-#[macro_use]
-extern crate lazy_static;
+extern crate wasmi;
 
+mod anf;
+mod error;
+mod expander;
+mod ir;
 mod iter;
 mod lexer;
+mod pipeline;
+mod prelude;
+mod primitive;
+mod reader;
+mod resolve;
+mod span;
+mod symbol;
+mod syntax;
+mod wasm;
+
+use std::env;
+use std::fmt::Display;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::process;
+
+use error::{Error, Result};
+use expander::Expander;
+use iter::{StreamAdapter, StreamMap};
+use pipeline::Read;
+use reader::{Datum, Style};
+use resolve::Resolver;
+use span::Span;
+use syntax::Parser;
 
-use std::io::{self, Read};
+/// What to print for each top-level form.
+#[derive(Clone, Copy, Debug)]
+enum Output {
+    Datum(Style),
+    /// The core expression it parses as.
+    Ast,
+    /// The core IR it is lowered to.
+    Ir,
+    /// The whole program in A-normal form, prelude included, rather than
+    /// anything per form.
+    Anf,
+    /// Nothing: the whole program is compiled and run instead.
+    Run,
+}
 
 fn main() {
-    let lexer = lexer::Lexer::new("stdin");
+    let mut output = Output::Datum(Style::Write);
+    let mut target = wasm::Target::TailCalls;
+    // Whether the input is a whole program in A-normal form, as
+    // `--print=anf` prints it, rather than source.
+    let mut from_anf = false;
+    for arg in env::args().skip(1) {
+        if let Some(t) = arg.strip_prefix("--target=").and_then(wasm::Target::named) {
+            target = t;
+            continue;
+        }
+        if arg == "--input=anf" {
+            from_anf = true;
+            continue;
+        }
+        output = match arg.as_str() {
+            "--print=write" => Output::Datum(Style::Write),
+            "--print=write-shared" => Output::Datum(Style::WriteShared),
+            "--print=display" => Output::Datum(Style::Display),
+            "--print=ast" => Output::Ast,
+            "--print=ir" => Output::Ir,
+            "--print=anf" => Output::Anf,
+            "--run" => Output::Run,
+            _ => usage(),
+        };
+    }
+
+    let stdin = io::stdin();
+    if let Output::Anf | Output::Run = output {
+        let program = if from_anf {
+            match io::read_to_string(stdin.lock()) {
+                Ok(text) => anf::parse::parse("stdin", &text).map_err(|e| vec![e]),
+                Err(e) => Err(vec![Error::new("stdin", Span::default(), e)]),
+            }
+        } else {
+            pipeline::compile("stdin", stdin.lock())
+        };
+        let program = match program {
+            Ok(program) => program,
+            Err(errors) => {
+                for e in errors {
+                    println!("{}", e);
+                }
+                process::exit(1);
+            }
+        };
+        if let Output::Anf = output {
+            print!("{}", program);
+        } else {
+            run(&program, target);
+        }
+    } else if from_anf {
+        usage();
+    } else if stdin.is_terminal() {
+        repl(stdin.lock(), output);
+    } else {
+        match output {
+            Output::Datum(style) => batch(pipeline::read("stdin", stdin.lock()), |d| d.printed(style).to_string()),
+            Output::Ast => batch(pipeline::parse("stdin", stdin.lock()), |e| e.to_string()),
+            Output::Ir => {
+                let mut functions = Vec::new();
+                batch(pipeline::resolve("stdin", stdin.lock()), |e| lowered(e.clone(), &mut functions))
+            }
+            Output::Anf | Output::Run => unreachable!(),
+        }
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: scheme_wasm [--print=write|write-shared|display|ast|ir|anf | \
+               --run [--target=tail-calls|trampoline]] [--input=anf] < source");
+    process::exit(2);
+}
+
+/// Compiles `program` to WebAssembly for `target` and runs it, exiting with
+/// its status.
+fn run(program: &anf::Program, target: wasm::Target) {
+    let outcome = wasm::run::run(&wasm::compile(program, target), target);
+    print!("{}", outcome.output);
+    io::stdout().flush().unwrap();
+    match outcome.status {
+        Ok(status) => process::exit(status),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
 
-    let source = io::stdin().bytes().map(|b| b.unwrap()).chain(vec![b'\n'].into_iter());
-    for res in lexer.iter(source) {
+/// Prints every form of a file or pipe.
+fn batch<M, I, T, S>(mut forms: StreamAdapter<M, I, Result<T>>, mut show: S)
+    where I: Iterator, M: StreamMap<I::Item, Result<T>>, S: FnMut(&T) -> String {
+    while let Some(res) = forms.next() {
         match res {
-            Ok(t) => print!("{} ", t),
+            Ok(form) => println!("{}", show(&form)),
             Err(e) => {
-                println!("\nError: {}", e);
+                println!("{}", e);
+                // Errors right after another on the same line are usually
+                // caused by it.
+                let line = e.span.start.line;
+                while let Some(Err(next)) = forms.peek() {
+                    if next.span.start.line != line {
+                        break;
+                    }
+                    forms.next();
+                }
+            }
+        }
+    }
+}
+
+/// Reads a line at a time, echoing each form once it is complete and
+/// prompting for more while one is still open.
+fn repl<R: BufRead>(mut source: R, output: Output) {
+    let mut reader = pipeline::Reader::new("stdin");
+    let mut expander = Expander::new("stdin");
+    let mut resolver = Resolver::new("stdin");
+    let mut functions = Vec::new();
+    let mut line = Vec::new();
+
+    loop {
+        print!("{}", if reader.is_partial() { "... " } else { "> " });
+        io::stdout().flush().unwrap();
+
+        line.clear();
+        match source.read_until(b'\n', &mut line) {
+            Ok(0) => reader.finish(),
+            Ok(_) => reader.feed(&line),
+            Err(e) => {
+                println!("Error: {}", e);
                 return;
             }
         }
+
+        loop {
+            match reader.read() {
+                Read::Complete(datum) => print_form(&datum, &mut expander, &mut resolver, &mut functions, output),
+                Read::Error(e) => println!("{}", e),
+                Read::NeedMoreInput => break,
+            }
+        }
+        if line.is_empty() {
+            println!();
+            if let Output::Ir = output {
+                for e in resolver.check_globals() {
+                    println!("{}", e);
+                }
+            }
+            return;
+        }
+    }
+}
+
+/// Prints a form read by the REPL. Macros and globals it defines are kept
+/// in `expander` and `resolver`, and the functions it is lowered to in
+/// `functions`, for the forms after it.
+fn print_form(datum: &Datum, expander: &mut Expander, resolver: &mut Resolver, functions: &mut Vec<ir::Lambda>,
+              output: Output) {
+    let style = match output {
+        Output::Datum(style) => return println!("{}", datum.printed(style)),
+        output => output,
+    };
+    let expr = match expander.expand_toplevel(datum) {
+        Ok(Some(datum)) => Parser::new("stdin").parse(&datum),
+        Ok(None) => return,
+        Err(e) => Err(e),
+    };
+    match style {
+        Output::Ir => print_result(expr.and_then(|e| resolver.resolve(&e)).map(|e| lowered(e, functions))),
+        _ => print_result(expr),
     }
+}
 
-    println!();
+/// Lowers `expr`, showing the functions added for it before it.
+fn lowered(expr: ir::Expr, functions: &mut Vec<ir::Lambda>) -> String {
+    let start = functions.len();
+    let expr = ir::lower(expr, functions);
+    let mut shown: Vec<_> = (start..functions.len()).map(|i| format!("(#%function {} {})", i, functions[i])).collect();
+    shown.push(expr.to_string());
+    shown.join("\n")
+}
+
+fn print_result<T: Display>(result: Result<T>) {
+    match result {
+        Ok(x) => println!("{}", x),
+        Err(e) => println!("{}", e),
+    }
 }
@@ -0,0 +1,278 @@
+//! The front end as a chain of streaming stages: bytes, then tokens, then
+//! data, then data with macros expanded, then expressions, then expressions
+//! with their variables resolved. Each stage pulls only as much input as it
+//! needs for its next item, so unbounded input like a REPL session is
+//! handled form by form. Every stage yields `error::Result`, passing on
+//! errors from the stages before it.
+//!
+//! `Reader` drives the same stages by pushing input into them instead, for
+//! callers like a REPL that get input in chunks and can't block for more.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+use anf;
+use error::{Error, Result};
+use expander::Expander;
+use ir;
+use iter::{StreamAdapter, StreamExt, StreamMap};
+use lexer::{Lexer, Token};
+use prelude;
+use reader::{self, Datum};
+use resolve::Resolver;
+use span::Spanned;
+use syntax::{Expr, Parser};
+
+pub type Tokens<'a, I> = StreamAdapter<Lexer<'a>, I, Result<Spanned<Token>>>;
+pub type Data<'a, I> = StreamAdapter<reader::Reader<'a>, Tokens<'a, I>, Result<Datum>>;
+pub type Expanded<'a, I> = StreamAdapter<Expander<'a>, Data<'a, I>, Result<Datum>>;
+pub type Exprs<'a, I> = StreamAdapter<Parser<'a>, Expanded<'a, I>, Result<Expr>>;
+pub type Resolved<'a, I> = StreamAdapter<Resolver<'a>, Exprs<'a, I>, Result<ir::Expr>>;
+
+/// Reads data from `source`, naming it `file_name` in diagnostics.
+pub fn read<'a, R: BufRead>(file_name: &'a str, source: R) -> Data<'a, io::Bytes<R>> {
+    source.bytes().then(Lexer::new(file_name)).then(reader::Reader::new(file_name))
+}
+
+/// Reads data from `source` and expands the macros in them.
+pub fn expand<'a, R: BufRead>(file_name: &'a str, source: R) -> Expanded<'a, io::Bytes<R>> {
+    read(file_name, source).then(Expander::new(file_name))
+}
+
+/// Parses top-level forms from `source`, naming it `file_name` in
+/// diagnostics.
+pub fn parse<'a, R: BufRead>(file_name: &'a str, source: R) -> Exprs<'a, io::Bytes<R>> {
+    expand(file_name, source).then(Parser::new(file_name))
+}
+
+/// Parses top-level forms from `source` and resolves their variables. Uses
+/// of undefined globals are reported after the last form.
+pub fn resolve<'a, R: BufRead>(file_name: &'a str, source: R) -> Resolved<'a, io::Bytes<R>> {
+    parse(file_name, source).then(Resolver::new(file_name))
+}
+
+/// Lowers the prelude and the top-level forms of `source` to a whole
+/// program, or reports every error in `source`.
+pub fn compile<R: BufRead>(file_name: &str, source: R) -> ::std::result::Result<anf::Program, Vec<Error>> {
+    let mut functions = Vec::new();
+    let mut forms = Vec::new();
+    for form in resolve(prelude::FILE_NAME, prelude::SOURCE.as_bytes()) {
+        forms.push(ir::lower(form.expect("the prelude is well-formed"), &mut functions));
+    }
+    let mut errors = Vec::new();
+    for form in resolve(file_name, source) {
+        match form {
+            Ok(expr) => forms.push(ir::lower(expr, &mut functions)),
+            Err(e) => errors.push(e),
+        }
+    }
+    if errors.is_empty() {
+        Ok(anf::convert(functions, forms))
+    } else {
+        Err(errors)
+    }
+}
+
+/// The outcome of asking a `Reader` for its next top-level form.
+#[derive(Debug)]
+pub enum Read {
+    Complete(Datum),
+    NeedMoreInput,
+    Error(Error),
+}
+
+/// Reads top-level forms from input fed to it in chunks of any size. Forms
+/// are returned in order as soon as they are complete.
+#[derive(Debug)]
+pub struct Reader<'a> {
+    lexer: Lexer<'a>,
+    reader: reader::Reader<'a>,
+    tokens: Vec<Result<Spanned<Token>>>,
+    ready: VecDeque<Result<Datum>>,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(file_name: &'a str) -> Self {
+        Reader {
+            lexer: Lexer::new(file_name),
+            reader: reader::Reader::new(file_name),
+            tokens: Vec::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) {
+        for &c in chunk {
+            self.lexer.produce(Ok(c), &mut self.tokens);
+            self.pass_tokens();
+        }
+    }
+
+    /// Marks the end of the input, so that a form cut off by it is reported
+    /// as an error. Input fed afterwards starts afresh.
+    pub fn finish(&mut self) {
+        self.lexer.finish(&mut self.tokens);
+        self.pass_tokens();
+        self.reader.finish(&mut self.ready);
+    }
+
+    fn pass_tokens(&mut self) {
+        for token in self.tokens.drain(..) {
+            self.reader.produce(token, &mut self.ready);
+        }
+    }
+
+    /// The next complete form or error. `NeedMoreInput` means everything fed
+    /// so far has been returned, apart from any form still being typed.
+    pub fn read(&mut self) -> Read {
+        match self.ready.pop_front() {
+            Some(Ok(datum)) => Read::Complete(datum),
+            Some(Err(e)) => Read::Error(e),
+            None => Read::NeedMoreInput,
+        }
+    }
+
+    /// Whether the input so far ends partway through a form.
+    pub fn is_partial(&self) -> bool {
+        !self.lexer.is_ready() || self.reader.is_partial()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::ErrorKind;
+    use lexer;
+    use reader;
+
+    fn read_all(input: &str) -> Vec<Result<Datum>> {
+        read("test", input.as_bytes()).collect()
+    }
+
+    fn printed(input: &str) -> Vec<String> {
+        read_all(input).into_iter().map(|r| r.unwrap().to_string()).collect()
+    }
+
+    fn error(input: &str) -> (ErrorKind, String) {
+        let e = read_all(input).into_iter().find(|r| r.is_err()).unwrap().unwrap_err();
+        (e.kind, format!("{}-{}", e.span.start, e.span.end))
+    }
+
+    #[test]
+    fn data() {
+        assert_eq!(printed("(if #t (f 1 2.5) \"s\") x\n'y '(a '())"),
+                   vec!["(if #t (f 1 2.5) \"s\")", "x", "(quote y)", "(quote (a (quote ())))"]);
+        assert_eq!(printed("a ; comment"), vec!["a"]);
+        assert_eq!(printed("quote"), vec!["quote"]);
+    }
+
+    #[test]
+    fn spans() {
+        let data: Vec<_> = read_all("(λ x)\n  'é").into_iter().map(|r| r.unwrap()).collect();
+        let span = |d: &Datum| format!("{}-{}", d.span.start, d.span.end);
+        assert_eq!(span(&data[0]), "1:1-1:6");
+        assert_eq!(span(&data[1]), "2:3-2:5");
+        match data[0].kind {
+            reader::DatumKind::Pair(ref head, ref tail) => {
+                assert_eq!(span(head), "1:2-1:3");
+                assert_eq!(span(tail), "1:4-1:6");
+            }
+            _ => panic!("expected a list"),
+        }
+    }
+
+    #[test]
+    fn errors_from_every_stage() {
+        match error("(a\n #x)") {
+            (ErrorKind::Lex(lexer::ErrorKind::InvalidCharacter(b'x')), ref span) => assert_eq!(span, "2:3-2:4"),
+            e => panic!("{:?}", e),
+        }
+        match error("\"abc") {
+            (ErrorKind::Lex(lexer::ErrorKind::UnexpectedEof), ref span) => assert_eq!(span, "1:1-1:5"),
+            e => panic!("{:?}", e),
+        }
+        match error("99999999999999999999 \"\\x110000;\"") {
+            (ErrorKind::Lex(lexer::ErrorKind::InvalidLiteral(lexer::TokenType::Int)), ref span) =>
+                assert_eq!(span, "1:1-1:21"),
+            e => panic!("{:?}", e),
+        }
+        assert_eq!(read_all("1 \"\\x110000;\"").into_iter().filter(|r| r.is_err()).count(), 1);
+        match error("a)") {
+            (ErrorKind::Read(reader::ErrorKind::UnexpectedCloseParen), ref span) => assert_eq!(span, "1:2-1:3"),
+            e => panic!("{:?}", e),
+        }
+        match error("(a (b)") {
+            (ErrorKind::Read(reader::ErrorKind::UnclosedParen), ref span) => assert_eq!(span, "1:7-1:7"),
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn recovers_after_errors() {
+        let results = read_all("(a #x) b");
+        assert!(results[0].is_err());
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[2].as_ref().unwrap().to_string(), "b");
+    }
+
+    fn complete(reader: &mut Reader) -> Vec<String> {
+        let mut out = Vec::new();
+        loop {
+            match reader.read() {
+                Read::Complete(datum) => out.push(datum.to_string()),
+                Read::Error(e) => out.push(format!("{:?}", e.kind)),
+                Read::NeedMoreInput => return out,
+            }
+        }
+    }
+
+    #[test]
+    fn incremental() {
+        let mut reader = Reader::new("test");
+        assert!(!reader.is_partial());
+
+        reader.feed(b"(define (f x)\n");
+        assert!(complete(&mut reader).is_empty());
+        assert!(reader.is_partial());
+
+        reader.feed(b"  x) 'a \"b");
+        assert_eq!(complete(&mut reader), vec!["(define (f x) x)", "(quote a)"]);
+        assert!(reader.is_partial());
+
+        reader.feed(b"c\" 12");
+        assert_eq!(complete(&mut reader), vec!["\"bc\""]);
+        reader.feed(b"\n");
+        assert_eq!(complete(&mut reader), vec!["12"]);
+        assert!(!reader.is_partial());
+    }
+
+    #[test]
+    fn incremental_errors() {
+        let mut reader = Reader::new("test");
+        reader.feed(b"a) (b\n");
+        assert_eq!(complete(&mut reader), vec!["a", "Read(UnexpectedCloseParen)"]);
+        assert!(reader.is_partial());
+
+        reader.finish();
+        assert_eq!(complete(&mut reader), vec!["Read(UnclosedParen)"]);
+        assert!(!reader.is_partial());
+
+        reader.feed(b"#x c ");
+        assert_eq!(complete(&mut reader), vec!["Lex(InvalidCharacter(120))", "c"]);
+    }
+
+    #[test]
+    fn chunks_match_whole_input() {
+        let source = "(let ((λ 1)) ; comment\n  `(,λ #(#\\x) . \"s\"))\n";
+        let whole: Vec<_> = read("test", source.as_bytes()).map(|r| r.unwrap().to_string()).collect();
+        for size in 1..6 {
+            let mut reader = Reader::new("test");
+            let mut out = Vec::new();
+            for chunk in source.as_bytes().chunks(size) {
+                reader.feed(chunk);
+                out.extend(complete(&mut reader));
+            }
+            assert_eq!(out, whole);
+        }
+    }
+}
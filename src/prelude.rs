@@ -0,0 +1,54 @@
+//! The part of the standard library written in Scheme, in `prelude.scm`.
+
+use std::sync::LazyLock;
+
+use symbol::Symbol;
+use syntax::ExprKind;
+use pipeline;
+
+pub const FILE_NAME: &str = "prelude.scm";
+pub const SOURCE: &str = include_str!("prelude.scm");
+
+static NAMES: LazyLock<Vec<Symbol>> = LazyLock::new(|| {
+    pipeline::parse(FILE_NAME, SOURCE.as_bytes()).filter_map(|expr| {
+        match expr.expect("the prelude is well-formed").kind {
+            ExprKind::Define(name, _) => Some(name.node),
+            _ => None,
+        }
+    }).collect()
+});
+
+/// The globals the prelude defines.
+pub fn names() -> &'static [Symbol] {
+    &NAMES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ir;
+
+    #[test]
+    fn resolves() {
+        for form in pipeline::resolve(FILE_NAME, SOURCE.as_bytes()) {
+            if let Err(e) = form {
+                panic!("{}", e);
+            }
+        }
+    }
+
+    #[test]
+    fn lowers() {
+        let mut functions = Vec::new();
+        for form in pipeline::resolve(FILE_NAME, SOURCE.as_bytes()) {
+            ir::lower(form.unwrap(), &mut functions);
+        }
+    }
+
+    #[test]
+    fn defines_the_library() {
+        for name in &["list", "append", "equal?", "memv", "display", "newline"] {
+            assert!(names().contains(&Symbol::intern(name)), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,220 @@
+;;; The part of the standard library written in Scheme. It is compiled along
+;;; with every program, so it may only use primitives and what it defines
+;;; itself. Names starting with `%` are helpers, not part of the library.
+
+;; Pairs and lists
+
+(define (caar x) (car (car x)))
+(define (cadr x) (car (cdr x)))
+(define (cdar x) (cdr (car x)))
+(define (cddr x) (cdr (cdr x)))
+
+(define (list . elements) elements)
+
+(define (list? x)
+  (let loop ((slow x) (fast x))
+    (cond ((null? fast) #t)
+          ((not (pair? fast)) #f)
+          ((null? (cdr fast)) #t)
+          ((not (pair? (cdr fast))) #f)
+          (else (let ((slow (cdr slow)) (fast (cddr fast)))
+                  (and (not (eq? slow fast)) (loop slow fast)))))))
+
+(define (length list)
+  (let loop ((list list) (n 0))
+    (if (null? list) n (loop (cdr list) (+ n 1)))))
+
+(define (%append2 a b)
+  (if (null? a) b (cons (car a) (%append2 (cdr a) b))))
+
+(define (append . lists)
+  (let loop ((lists lists))
+    (cond ((null? lists) '())
+          ((null? (cdr lists)) (car lists))
+          (else (%append2 (car lists) (loop (cdr lists)))))))
+
+(define (reverse list)
+  (let loop ((list list) (result '()))
+    (if (null? list) result (loop (cdr list) (cons (car list) result)))))
+
+(define (list-tail list k)
+  (if (zero? k) list (list-tail (cdr list) (- k 1))))
+
+(define (list-ref list k)
+  (car (list-tail list k)))
+
+(define (memq x list)
+  (cond ((null? list) #f)
+        ((eq? x (car list)) list)
+        (else (memq x (cdr list)))))
+
+(define (memv x list)
+  (cond ((null? list) #f)
+        ((eqv? x (car list)) list)
+        (else (memv x (cdr list)))))
+
+(define (member x list)
+  (cond ((null? list) #f)
+        ((equal? x (car list)) list)
+        (else (member x (cdr list)))))
+
+(define (assq x alist)
+  (cond ((null? alist) #f)
+        ((eq? x (caar alist)) (car alist))
+        (else (assq x (cdr alist)))))
+
+(define (assv x alist)
+  (cond ((null? alist) #f)
+        ((eqv? x (caar alist)) (car alist))
+        (else (assv x (cdr alist)))))
+
+(define (assoc x alist)
+  (cond ((null? alist) #f)
+        ((equal? x (caar alist)) (car alist))
+        (else (assoc x (cdr alist)))))
+
+(define (map f list)
+  (if (null? list) '() (cons (f (car list)) (map f (cdr list)))))
+
+(define (for-each f list)
+  (if (pair? list)
+      (begin (f (car list)) (for-each f (cdr list)))))
+
+(define (equal? a b)
+  (cond ((eqv? a b) #t)
+        ((pair? a) (and (pair? b) (equal? (car a) (car b)) (equal? (cdr a) (cdr b))))
+        ((string? a) (and (string? b) (string=? a b)))
+        ((vector? a) (and (vector? b) (equal? (vector->list a) (vector->list b))))
+        ((bytevector? a) (and (bytevector? b) (equal? (%bytevector->list a) (%bytevector->list b))))
+        (else #f)))
+
+;; Numbers
+
+(define (zero? n) (= n 0))
+(define (positive? n) (> n 0))
+(define (negative? n) (< n 0))
+(define (even? n) (= (remainder n 2) 0))
+(define (odd? n) (not (even? n)))
+(define (abs n) (if (< n 0) (- 0 n) n))
+
+(define (max n . ns)
+  (let loop ((n n) (ns ns))
+    (cond ((null? ns) n)
+          ((> (car ns) n) (loop (car ns) (cdr ns)))
+          (else (loop n (cdr ns))))))
+
+(define (min n . ns)
+  (let loop ((n n) (ns ns))
+    (cond ((null? ns) n)
+          ((< (car ns) n) (loop (car ns) (cdr ns)))
+          (else (loop n (cdr ns))))))
+
+(define (number->string n)
+  ;; Digits come from n made negative, since not every negative fixnum can
+  ;; be negated.
+  (let loop ((m (if (< n 0) n (- 0 n))) (digits '()))
+    (let ((digits (cons (integer->char (- 48 (remainder m 10))) digits)))
+      (cond ((<= m -10) (loop (quotient m 10) digits))
+            ((< n 0) (list->string (cons #\- digits)))
+            (else (list->string digits))))))
+
+;; Characters, strings and vectors
+
+(define (char=? a b) (= (char->integer a) (char->integer b)))
+(define (char<? a b) (< (char->integer a) (char->integer b)))
+
+(define (string->list s)
+  (let loop ((i (- (string-length s) 1)) (result '()))
+    (if (< i 0) result (loop (- i 1) (cons (string-ref s i) result)))))
+
+(define (list->string list)
+  (let ((s (make-string (length list))))
+    (let loop ((list list) (i 0))
+      (if (null? list)
+          s
+          (begin (string-set! s i (car list))
+                 (loop (cdr list) (+ i 1)))))))
+
+(define (string-append . strings)
+  (list->string (%append-all (map string->list strings))))
+
+(define (%append-all lists)
+  (if (null? lists) '() (%append2 (car lists) (%append-all (cdr lists)))))
+
+(define (string=? a b)
+  (equal? (string->list a) (string->list b)))
+
+(define (vector . elements) (list->vector elements))
+
+(define (vector->list v)
+  (let loop ((i (- (vector-length v) 1)) (result '()))
+    (if (< i 0) result (loop (- i 1) (cons (vector-ref v i) result)))))
+
+(define (%bytevector->list v)
+  (let loop ((i (- (bytevector-length v) 1)) (result '()))
+    (if (< i 0) result (loop (- i 1) (cons (bytevector-u8-ref v i) result)))))
+
+(define (list->vector list)
+  (let ((v (make-vector (length list) #f)))
+    (let loop ((list list) (i 0))
+      (if (null? list)
+          v
+          (begin (vector-set! v i (car list))
+                 (loop (cdr list) (+ i 1)))))))
+
+;; Control
+
+(define call/cc call-with-current-continuation)
+
+;; Output
+
+(define (newline) (write-char #\newline))
+
+(define (write-string s)
+  (for-each write-char (string->list s)))
+
+(define (display x) (%print x #f))
+(define (write x) (%print x #t))
+
+(define (%print x write?)
+  (cond ((eq? x #t) (write-string "#t"))
+        ((eq? x #f) (write-string "#f"))
+        ((null? x) (write-string "()"))
+        ((number? x) (if (exact? x) (write-string (number->string x)) (%write-flonum x)))
+        ((symbol? x) (write-string (symbol->string x)))
+        ((char? x) (if write? (%write-char-literal x) (write-char x)))
+        ((string? x) (if write? (%write-string-literal x) (write-string x)))
+        ((pair? x) (write-char #\() (%print-list x write?) (write-char #\)))
+        ((vector? x) (write-string "#(") (%print-list (vector->list x) write?) (write-char #\)))
+        ((bytevector? x) (write-string "#u8(") (%print-list (%bytevector->list x) write?) (write-char #\)))
+        ((procedure? x) (write-string "#<procedure>"))
+        ((%record-name x) => (lambda (name) (write-string "#<") (write-string (symbol->string name)) (write-char #\>)))
+        (else (write-string "#<unspecified>"))))
+
+(define (%print-list list write?)
+  (when (pair? list)
+    (%print (car list) write?)
+    (cond ((pair? (cdr list))
+           (write-char #\space)
+           (%print-list (cdr list) write?))
+          ((not (null? (cdr list)))
+           (write-string " . ")
+           (%print (cdr list) write?)))))
+
+(define (%write-char-literal c)
+  (write-string "#\\")
+  (case c
+    ((#\space) (write-string "space"))
+    ((#\newline) (write-string "newline"))
+    ((#\tab) (write-string "tab"))
+    (else (write-char c))))
+
+(define (%write-string-literal s)
+  (write-char #\")
+  (for-each (lambda (c)
+              (case c
+                ((#\" #\\) (write-char #\\) (write-char c))
+                ((#\newline) (write-string "\\n"))
+                (else (write-char c))))
+            (string->list s))
+  (write-char #\"))
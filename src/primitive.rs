@@ -0,0 +1,133 @@
+//! Procedures built into the compiler. Everything else in the standard
+//! library is written in Scheme, in the prelude, in terms of these.
+
+macro_rules! primitives {
+    ( $( $name:ident: $text:tt ($min:expr, $max:expr), )* ) => {
+        /// A built-in procedure. Apart from `call-with-current-continuation`,
+        /// `values` and `call-with-values`, which CPS conversion compiles, it
+        /// takes only values, never procedures it has to call, and returns
+        /// one, so a call to it is compiled as a plain operation.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum Primitive {
+            $( $name, )*
+        }
+
+        impl Primitive {
+            pub const ALL: &'static [Primitive] = &[ $( Primitive::$name, )* ];
+
+            /// The name of the global the primitive is bound to.
+            pub fn name(self) -> &'static str {
+                match self {
+                    $( Primitive::$name => $text, )*
+                }
+            }
+
+            /// The primitive bound to the global `name`, if any.
+            pub fn named(name: &str) -> Option<Primitive> {
+                match name {
+                    $( $text => Some(Primitive::$name), )*
+                    _ => None,
+                }
+            }
+
+            /// Whether a call with `count` arguments is compiled as the
+            /// operation. As procedures, `+`, `-` and `*` take any number,
+            /// but CPS conversion folds calls to them into calls with two.
+            pub fn accepts(self, count: usize) -> bool {
+                let (min, max) = self.arity();
+                count >= min && max.map_or(true, |max| count <= max)
+            }
+
+            /// The fewest and most arguments a call compiled as the
+            /// operation passes, if there is a most.
+            pub fn arity(self) -> (usize, Option<usize>) {
+                match self {
+                    $( Primitive::$name => ($min, $max), )*
+                }
+            }
+        }
+    };
+}
+
+primitives! {
+    Add: "+" (2, Some(2)),
+    Subtract: "-" (2, Some(2)),
+    Multiply: "*" (2, Some(2)),
+    Quotient: "quotient" (2, Some(2)),
+    Remainder: "remainder" (2, Some(2)),
+    Modulo: "modulo" (2, Some(2)),
+    NumberEqual: "=" (2, Some(2)),
+    Less: "<" (2, Some(2)),
+    Greater: ">" (2, Some(2)),
+    LessEqual: "<=" (2, Some(2)),
+    GreaterEqual: ">=" (2, Some(2)),
+    IsNumber: "number?" (1, Some(1)),
+    IsInteger: "integer?" (1, Some(1)),
+    IsExact: "exact?" (1, Some(1)),
+    IsEq: "eq?" (2, Some(2)),
+    IsEqv: "eqv?" (2, Some(2)),
+    Not: "not" (1, Some(1)),
+    IsBoolean: "boolean?" (1, Some(1)),
+    Cons: "cons" (2, Some(2)),
+    Car: "car" (1, Some(1)),
+    Cdr: "cdr" (1, Some(1)),
+    SetCar: "set-car!" (2, Some(2)),
+    SetCdr: "set-cdr!" (2, Some(2)),
+    IsPair: "pair?" (1, Some(1)),
+    IsNull: "null?" (1, Some(1)),
+    IsSymbol: "symbol?" (1, Some(1)),
+    SymbolToString: "symbol->string" (1, Some(1)),
+    IsChar: "char?" (1, Some(1)),
+    CharToInteger: "char->integer" (1, Some(1)),
+    IntegerToChar: "integer->char" (1, Some(1)),
+    IsString: "string?" (1, Some(1)),
+    MakeString: "make-string" (1, Some(2)),
+    StringLength: "string-length" (1, Some(1)),
+    StringRef: "string-ref" (2, Some(2)),
+    StringSet: "string-set!" (3, Some(3)),
+    IsVector: "vector?" (1, Some(1)),
+    MakeVector: "make-vector" (1, Some(2)),
+    VectorLength: "vector-length" (1, Some(1)),
+    VectorRef: "vector-ref" (2, Some(2)),
+    VectorSet: "vector-set!" (3, Some(3)),
+    IsBytevector: "bytevector?" (1, Some(1)),
+    BytevectorLength: "bytevector-length" (1, Some(1)),
+    BytevectorU8Ref: "bytevector-u8-ref" (2, Some(2)),
+    MakeRecordType: "%make-record-type" (1, Some(1)),
+    MakeRecord: "%make-record" (1, None),
+    IsRecord: "%record?" (2, Some(2)),
+    RecordRef: "%record-ref" (4, Some(4)),
+    RecordSet: "%record-set!" (5, Some(5)),
+    RecordName: "%record-name" (1, Some(1)),
+    IsProcedure: "procedure?" (1, Some(1)),
+    WriteChar: "write-char" (1, Some(1)),
+    WriteFlonum: "%write-flonum" (1, Some(1)),
+    Error: "error" (1, None),
+    Exit: "exit" (0, Some(1)),
+    CallWithCurrentContinuation: "call-with-current-continuation" (1, Some(1)),
+    Values: "values" (0, None),
+    CallWithValues: "call-with-values" (2, Some(2)),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn names_are_distinct() {
+        let names: HashSet<_> = Primitive::ALL.iter().map(|p| p.name()).collect();
+        assert_eq!(names.len(), Primitive::ALL.len());
+        for &p in Primitive::ALL {
+            assert_eq!(Primitive::named(p.name()), Some(p));
+        }
+    }
+
+    #[test]
+    fn arities() {
+        assert!(Primitive::Car.accepts(1) && !Primitive::Car.accepts(2));
+        assert!(Primitive::MakeVector.accepts(1) && Primitive::MakeVector.accepts(2));
+        assert!(!Primitive::Error.accepts(0) && Primitive::Error.accepts(5));
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+
+use span::Span;
+use symbol::Symbol;
+
+/// A value read from source, with the span it was read from. Data compare
+/// equal the way R7RS `equal?` compares them, whatever their spans.
+#[derive(Clone, Debug)]
+pub struct Datum {
+    pub kind: DatumKind,
+    pub span: Span,
+}
+
+/// The exact integers a program can use. Compiled code keeps them in 61
+/// bits, leaving 3 to tell them apart from other values.
+pub const FIXNUMS: RangeInclusive<i64> = -(1 << 60)..=(1 << 60) - 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DatumKind {
+    Nil,
+    Pair(Box<Datum>, Box<Datum>),
+    Symbol(Symbol),
+    Bool(bool),
+    Fixnum(i64),
+    Flonum(f64),
+    String(String),
+    Char(char),
+    Vector(Vec<Datum>),
+    Bytevector(Vec<u8>),
+    /// `#n=datum`. Labels are unique within a top-level datum.
+    Labeled(u32, Box<Datum>),
+    /// `#n#`, standing for the datum labelled `n`, which encloses or comes
+    /// before it.
+    Reference(u32),
+}
+
+impl Datum {
+    pub fn new(kind: DatumKind, span: Span) -> Self {
+        Datum {
+            kind,
+            span,
+        }
+    }
+
+    /// Builds a list of `items` ending in `tail`, which is `()` for a proper
+    /// list. Each pair spans from its first element to the end of the tail.
+    pub fn list(items: Vec<Datum>, tail: Datum) -> Self {
+        items.into_iter().rev().fold(tail, |tail, head| {
+            let span = head.span.to(tail.span);
+            Datum::new(DatumKind::Pair(Box::new(head), Box::new(tail)), span)
+        })
+    }
+
+    /// Every labelled datum inside `self`, by label.
+    pub fn labels(&self) -> HashMap<u32, &Datum> {
+        let mut labels = HashMap::new();
+        let mut stack = vec![self];
+        while let Some(datum) = stack.pop() {
+            match datum.kind {
+                DatumKind::Pair(ref head, ref tail) => {
+                    stack.push(tail);
+                    stack.push(head);
+                }
+                DatumKind::Vector(ref items) => stack.extend(items),
+                DatumKind::Labeled(n, ref inner) => {
+                    labels.insert(n, datum);
+                    stack.push(inner);
+                }
+                _ => {}
+            }
+        }
+        labels
+    }
+
+    /// The elements of a proper list, or `None` if `self` is not one.
+    pub fn items(&self) -> Option<Vec<&Datum>> {
+        let mut items = Vec::new();
+        let mut datum = self;
+        loop {
+            match datum.kind {
+                DatumKind::Nil => return Some(items),
+                DatumKind::Pair(ref head, ref tail) => {
+                    items.push(&**head);
+                    datum = tail;
+                }
+                _ => return None,
+            }
+        }
+    }
+}
+
+/// Datum labels are followed, so a datum with labels equals the same value
+/// written out without them, and cycles compare equal when they unfold the
+/// same way.
+impl PartialEq for Datum {
+    fn eq(&self, other: &Datum) -> bool {
+        Equal {
+            left: self.labels(),
+            right: other.labels(),
+            assumed: HashSet::new(),
+        }.equal(self, other)
+    }
+}
+
+struct Equal<'a> {
+    left: HashMap<u32, &'a Datum>,
+    right: HashMap<u32, &'a Datum>,
+    /// Pairs of nodes already being compared further up. Meeting one again
+    /// means a cycle, which is equal as far as it has been followed.
+    assumed: HashSet<(*const Datum, *const Datum)>,
+}
+
+/// Follows labels and references to the datum they stand for.
+fn unlabel<'a>(labels: &HashMap<u32, &'a Datum>, mut datum: &'a Datum) -> &'a Datum {
+    for _ in 0..=labels.len() {
+        datum = match datum.kind {
+            DatumKind::Labeled(_, ref inner) => inner,
+            DatumKind::Reference(n) => match labels.get(&n) {
+                Some(labeled) => labeled,
+                None => return datum,
+            },
+            _ => return datum,
+        };
+    }
+    datum
+}
+
+impl<'a> Equal<'a> {
+    fn equal(&mut self, a: &'a Datum, b: &'a Datum) -> bool {
+        let (mut a, mut b) = (a, b);
+        loop {
+            a = unlabel(&self.left, a);
+            b = unlabel(&self.right, b);
+            if !self.assumed.insert((a, b)) {
+                return true;
+            }
+
+            match (&a.kind, &b.kind) {
+                (DatumKind::Pair(h1, t1), DatumKind::Pair(h2, t2)) => {
+                    if !self.equal(h1, h2) {
+                        return false;
+                    }
+                    a = t1;
+                    b = t2;
+                }
+                (DatumKind::Vector(v1), DatumKind::Vector(v2)) =>
+                    return v1.len() == v2.len() && v1.iter().zip(v2).all(|(x, y)| self.equal(x, y)),
+                (&DatumKind::Flonum(x), &DatumKind::Flonum(y)) => return x.to_bits() == y.to_bits(),
+                (ka, kb) => return ka == kb,
+            }
+        }
+    }
+}
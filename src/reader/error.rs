@@ -0,0 +1,36 @@
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnexpectedCloseParen,
+    UnclosedParen,
+    /// A `'`, `` ` ``, `,` or `,@` with nothing after it.
+    MissingQuotedDatum(&'static str),
+    UnexpectedDot,
+    MissingDatumAfterDot,
+    ExtraDatumAfterDot,
+    InvalidByte,
+    MissingLabeledDatum(u32),
+    DuplicateLabel(u32),
+    UndefinedLabel(u32),
+    /// `#n=#n#`, a label standing for nothing but itself.
+    LabelOfItself(u32),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::UnexpectedCloseParen => f.write_str("Unexpected ')' with nothing open"),
+            ErrorKind::UnclosedParen => f.write_str("Unexpected end of input, missing ')'"),
+            ErrorKind::MissingQuotedDatum(prefix) => write!(f, "Expected a datum after '{}'", prefix),
+            ErrorKind::UnexpectedDot => f.write_str("Unexpected '.' outside of a list's tail"),
+            ErrorKind::MissingDatumAfterDot => f.write_str("Expected a datum after '.'"),
+            ErrorKind::ExtraDatumAfterDot => f.write_str("Expected ')' after the datum following '.'"),
+            ErrorKind::InvalidByte => f.write_str("Bytevector elements must be integers from 0 to 255"),
+            ErrorKind::MissingLabeledDatum(n) => write!(f, "Expected a datum after '#{}='", n),
+            ErrorKind::DuplicateLabel(n) => write!(f, "Datum label #{}= is already defined", n),
+            ErrorKind::UndefinedLabel(n) => write!(f, "Datum label #{}# is not defined", n),
+            ErrorKind::LabelOfItself(n) => write!(f, "Datum label #{}= can't stand for itself", n),
+        }
+    }
+}
@@ -0,0 +1,450 @@
+mod datum;
+mod error;
+mod printer;
+
+use std::collections::HashSet;
+
+use error::{Error, Result};
+use iter::StreamMap;
+use lexer::Token;
+use span::{Position, Span, Spanned};
+use symbol::Symbol;
+
+pub use self::datum::*;
+pub use self::error::*;
+pub use self::printer::*;
+
+/// A datum that is still being read.
+#[derive(Debug)]
+struct Frame {
+    /// The span of the `(`, `#(`, `#u8(` or abbreviation prefix.
+    open: Span,
+    /// Where the frame was probably meant to be closed: the end of the line
+    /// before the first line indented no deeper than `open`.
+    dedent: Option<Position>,
+    kind: FrameKind,
+}
+
+#[derive(Debug)]
+enum FrameKind {
+    /// After a `.`, `dot` holds the dot's span and `tail` the datum after it,
+    /// once read.
+    List {
+        items: Vec<Datum>,
+        dot: Option<Span>,
+        tail: Option<Datum>,
+    },
+    Vector(Vec<Datum>),
+    Bytevector(Vec<u8>),
+    /// A `'`, `` ` ``, `,` or `,@` waiting for the datum it abbreviates.
+    Abbreviation(&'static str),
+    /// A `#n=` waiting for the datum it labels.
+    Label(u32),
+}
+
+impl Frame {
+    fn opener(&self) -> String {
+        match self.kind {
+            FrameKind::List { .. } => "(".to_owned(),
+            FrameKind::Vector(_) => "#(".to_owned(),
+            FrameKind::Bytevector(_) => "#u8(".to_owned(),
+            FrameKind::Abbreviation(prefix) => prefix.to_owned(),
+            FrameKind::Label(n) => format!("#{}=", n),
+        }
+    }
+
+    fn is_delimited(&self) -> bool {
+        !matches!(self.kind, FrameKind::Abbreviation(_) | FrameKind::Label(_))
+    }
+}
+
+/// Assembles tokens into top-level data, emitting each one as soon as it is
+/// complete. Errors from earlier stages are passed through, and any error
+/// drops the datum being read.
+#[derive(Debug)]
+pub struct Reader<'a> {
+    file_name: &'a str,
+    stack: Vec<Frame>,
+    /// The end of the last token read.
+    last_end: Option<Position>,
+    /// The opening and closing spans of the last top-level list or vector.
+    last_closed: Option<(Span, Span)>,
+    /// The datum labels defined so far in the current top-level datum.
+    labels: HashSet<u32>,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(file_name: &'a str) -> Self {
+        Reader {
+            file_name,
+            stack: Vec::new(),
+            last_end: None,
+            last_closed: None,
+            labels: HashSet::new(),
+        }
+    }
+
+    /// Whether the tokens so far end partway through a datum.
+    pub fn is_partial(&self) -> bool {
+        !self.stack.is_empty()
+    }
+
+    fn push_token(&mut self, token: Spanned<Token>) -> Result<Option<Datum>> {
+        let span = token.span;
+        if token.node != Token::CloseParen {
+            self.check_indentation(span.start);
+        }
+        self.last_end = Some(span.end);
+
+        let kind = match token.node {
+            Token::OpenParen => return self.open(span, FrameKind::List {
+                items: Vec::new(),
+                dot: None,
+                tail: None,
+            }),
+            Token::OpenVector => return self.open(span, FrameKind::Vector(Vec::new())),
+            Token::OpenBytevector => return self.open(span, FrameKind::Bytevector(Vec::new())),
+            Token::Quote => return self.open(span, FrameKind::Abbreviation("'")),
+            Token::Quasiquote => return self.open(span, FrameKind::Abbreviation("`")),
+            Token::Unquote => return self.open(span, FrameKind::Abbreviation(",")),
+            Token::UnquoteSplicing => return self.open(span, FrameKind::Abbreviation(",@")),
+            Token::LabelDef(n) => {
+                if !self.labels.insert(n) {
+                    return Err(self.error(span, ErrorKind::DuplicateLabel(n)));
+                }
+                return self.open(span, FrameKind::Label(n));
+            }
+            Token::LabelRef(n) if !self.labels.contains(&n) => return Err(self.error(span, ErrorKind::UndefinedLabel(n))),
+            Token::LabelRef(n) => DatumKind::Reference(n),
+            Token::Dot => return self.dot(span),
+            Token::CloseParen => return self.close(span),
+            Token::Lambda => DatumKind::Symbol(Symbol::LAMBDA),
+            Token::If => DatumKind::Symbol(Symbol::IF),
+            Token::Ident(s) => DatumKind::Symbol(s),
+            Token::Bool(b) => DatumKind::Bool(b),
+            Token::Int(n) => DatumKind::Fixnum(n),
+            Token::Float(n) => DatumKind::Flonum(n),
+            Token::String(s) => DatumKind::String(s),
+            Token::Char(c) => DatumKind::Char(c),
+        };
+        self.complete(Datum::new(kind, span))
+    }
+
+    /// A line that starts no deeper than an open `(` usually starts a new
+    /// form, so the `(` was probably meant to be closed on the line before.
+    fn check_indentation(&mut self, start: Position) {
+        let prev = match self.last_end {
+            Some(prev) if prev.line < start.line => prev,
+            _ => return,
+        };
+        for frame in &mut self.stack {
+            if frame.is_delimited() && frame.dedent.is_none() && start.column <= frame.open.start.column {
+                frame.dedent = Some(prev);
+            }
+        }
+    }
+
+    fn open(&mut self, open: Span, kind: FrameKind) -> Result<Option<Datum>> {
+        self.stack.push(Frame {
+            open,
+            dedent: None,
+            kind,
+        });
+        Ok(None)
+    }
+
+    fn dot(&mut self, span: Span) -> Result<Option<Datum>> {
+        if let Some(&mut Frame { kind: FrameKind::List { ref items, ref mut dot, .. }, .. }) = self.stack.last_mut() {
+            if !items.is_empty() && dot.is_none() {
+                *dot = Some(span);
+                return Ok(None);
+            }
+        }
+        Err(self.error(span, ErrorKind::UnexpectedDot))
+    }
+
+    fn close(&mut self, close: Span) -> Result<Option<Datum>> {
+        let frame = match self.stack.pop() {
+            Some(frame) => frame,
+            None => {
+                let mut e = self.error(close, ErrorKind::UnexpectedCloseParen);
+                if let Some((open, end)) = self.last_closed {
+                    e = e.with_note(open, format!("the last top-level '(' here was already closed at {}", end.start));
+                }
+                return Err(e);
+            }
+        };
+
+        let open = frame.open;
+        let span = open.to(close);
+        let datum = match frame.kind {
+            FrameKind::List { items, dot, tail } => {
+                let tail = match (dot, tail) {
+                    (Some(dot), None) => return Err(self.error(close, ErrorKind::MissingDatumAfterDot)
+                        .with_note(dot, "the '.' here needs a datum after it")),
+                    (_, Some(tail)) => tail,
+                    (None, None) => Datum::new(DatumKind::Nil, close),
+                };
+                let mut list = Datum::list(items, tail);
+                list.span = span;
+                list
+            }
+            FrameKind::Vector(items) => Datum::new(DatumKind::Vector(items), span),
+            FrameKind::Bytevector(bytes) => Datum::new(DatumKind::Bytevector(bytes), span),
+            FrameKind::Abbreviation(prefix) =>
+                return Err(self.error(close, ErrorKind::MissingQuotedDatum(prefix))
+                    .with_note(open, format!("the '{}' here needs a datum after it", prefix))),
+            FrameKind::Label(n) =>
+                return Err(self.error(close, ErrorKind::MissingLabeledDatum(n))
+                    .with_note(open, format!("the '#{}=' here needs a datum after it", n))),
+        };
+        if self.stack.is_empty() {
+            self.last_closed = Some((open, close));
+        }
+        self.complete(datum)
+    }
+
+    /// Hands a finished datum to the innermost open frame. Returns it if it
+    /// is a top-level datum.
+    fn complete(&mut self, mut datum: Datum) -> Result<Option<Datum>> {
+        loop {
+            let kind = match self.stack.last_mut().map(|f| (f.open, &mut f.kind)) {
+                None => {
+                    self.labels.clear();
+                    return Ok(Some(datum));
+                }
+                Some((_, &mut FrameKind::List { dot: Some(_), ref mut tail, .. })) => {
+                    if tail.is_some() {
+                        ErrorKind::ExtraDatumAfterDot
+                    } else {
+                        *tail = Some(datum);
+                        return Ok(None);
+                    }
+                }
+                Some((_, &mut FrameKind::List { ref mut items, .. })) | Some((_, &mut FrameKind::Vector(ref mut items))) => {
+                    items.push(datum);
+                    return Ok(None);
+                }
+                Some((_, &mut FrameKind::Bytevector(ref mut bytes))) => match datum.kind {
+                    DatumKind::Fixnum(n) if (0..256).contains(&n) => {
+                        bytes.push(n as u8);
+                        return Ok(None);
+                    }
+                    _ => ErrorKind::InvalidByte,
+                },
+                Some((open, &mut FrameKind::Abbreviation(prefix))) => {
+                    datum = abbreviated(open, prefix, datum);
+                    self.stack.pop();
+                    continue;
+                }
+                Some((_, &mut FrameKind::Label(n))) if datum.kind == DatumKind::Reference(n) =>
+                    ErrorKind::LabelOfItself(n),
+                Some((open, &mut FrameKind::Label(n))) => {
+                    let span = open.to(datum.span);
+                    datum = Datum::new(DatumKind::Labeled(n, Box::new(datum)), span);
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            return Err(self.error(datum.span, kind));
+        }
+    }
+
+    /// The error for input ending inside a datum. It blames the innermost
+    /// frame whose indentation went wrong, if any.
+    fn unclosed(&mut self) -> Option<Error> {
+        let (open, dedent, opener) = {
+            let frame = self.stack.iter().rev().find(|f| f.dedent.is_some())
+                .or_else(|| self.stack.iter().rev().find(|f| f.is_delimited()))?;
+            (frame.open, frame.dedent, frame.opener())
+        };
+        let end = Span::at(self.last_end.unwrap_or(open.end));
+
+        let mut e = self.error(end, ErrorKind::UnclosedParen)
+            .with_note(open, format!("the '{}' here is never closed", opener));
+        if let Some(pos) = dedent {
+            e = e.with_note(Span::at(pos), "it was probably meant to be closed here, as the next line is \
+                                            indented no deeper than it");
+        }
+        Some(e)
+    }
+
+    fn error(&mut self, span: Span, kind: ErrorKind) -> Error {
+        self.stack.clear();
+        self.labels.clear();
+        Error::new(self.file_name, span, kind)
+    }
+}
+
+/// `'datum` as `(quote datum)`, and likewise for `` ` ``, `,` and `,@`.
+fn abbreviated(span: Span, prefix: &str, datum: Datum) -> Datum {
+    let name = match prefix {
+        "'" => Symbol::QUOTE,
+        "`" => Symbol::QUASIQUOTE,
+        "," => Symbol::UNQUOTE,
+        _ => Symbol::UNQUOTE_SPLICING,
+    };
+    let end = Span::at(datum.span.end);
+    let symbol = Datum::new(DatumKind::Symbol(name), span);
+    let mut list = Datum::list(vec![symbol, datum], Datum::new(DatumKind::Nil, end));
+    list.span = span.to(end);
+    list
+}
+
+impl<'a> StreamMap<Result<Spanned<Token>>, Result<Datum>> for Reader<'a> {
+    fn produce(&mut self, token: Result<Spanned<Token>>, out: &mut impl Extend<Result<Datum>>) {
+        let res = match token {
+            Ok(token) => self.push_token(token),
+            Err(e) => {
+                self.stack.clear();
+                self.labels.clear();
+                Err(e)
+            }
+        };
+        match res {
+            Ok(datum) => out.extend(datum.map(Ok)),
+            Err(e) => out.extend(Some(Err(e))),
+        }
+    }
+
+    fn finish(&mut self, out: &mut impl Extend<Result<Datum>>) {
+        let e = match self.unclosed() {
+            Some(e) => e,
+            None => match self.stack.last() {
+                Some(&Frame { open, kind: FrameKind::Abbreviation(prefix), .. }) =>
+                    self.error(open, ErrorKind::MissingQuotedDatum(prefix)),
+                Some(&Frame { open, kind: FrameKind::Label(n), .. }) =>
+                    self.error(open, ErrorKind::MissingLabeledDatum(n)),
+                _ => return,
+            },
+        };
+        out.extend(Some(Err(e)));
+    }
+
+    /// A token completes at most one top-level datum.
+    fn max_outputs(&self) -> Option<usize> {
+        Some(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error;
+    use pipeline;
+
+    fn read(input: &str) -> Vec<error::Result<Datum>> {
+        pipeline::read("test", input.as_bytes()).collect()
+    }
+
+    fn printed(input: &str) -> String {
+        read(input).into_iter().map(|r| r.unwrap().to_string()).collect::<Vec<_>>().join(" ")
+    }
+
+    fn error(input: &str) -> (ErrorKind, String) {
+        match read(input).into_iter().find(|r| r.is_err()).unwrap().unwrap_err() {
+            error::Error { kind: error::ErrorKind::Read(kind), span, .. } =>
+                (kind, format!("{}-{}", span.start, span.end)),
+            e => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(printed("() (a) (a b . c) (a . (b)) ((a . b) . ())"),
+                   "() (a) (a b . c) (a b) ((a . b))");
+    }
+
+    #[test]
+    fn atoms() {
+        assert_eq!(printed("#t #false -12 1.5 \"a b\" #\\a #\\space #\\x41 #\\λ #\\( sym"),
+                   "#t #f -12 1.5 \"a b\" #\\a #\\space #\\A #\\λ #\\( sym");
+        assert_eq!(printed("|sym| |s\\x79;m| |\\sym|"), "sym sym sym");
+    }
+
+    #[test]
+    fn vectors() {
+        assert_eq!(printed("#(1 (a) #(b)) #() #u8(0 255) #u8()"), "#(1 (a) #(b)) #() #u8(0 255) #u8()");
+    }
+
+    #[test]
+    fn abbreviations() {
+        assert_eq!(printed("'a `(b ,c ,@d) '#(e) ''f"),
+                   "(quote a) (quasiquote (b (unquote c) (unquote-splicing d))) (quote #(e)) (quote (quote f))");
+    }
+
+    #[test]
+    fn spans() {
+        let data: Vec<_> = read("(a . b)\n#(x)").into_iter().map(|r| r.unwrap()).collect();
+        assert_eq!((data[0].span.start.column, data[0].span.end.column), (1, 8));
+        match data[0].kind {
+            DatumKind::Pair(_, ref tail) => assert_eq!(tail.span.start.column, 6),
+            _ => panic!("expected a pair"),
+        }
+        match data[1].kind {
+            DatumKind::Vector(ref items) => assert_eq!(items[0].span.start.line, 2),
+            _ => panic!("expected a vector"),
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("(. a)"), (ErrorKind::UnexpectedDot, "1:2-1:3".to_owned()));
+        assert_eq!(error("a . b"), (ErrorKind::UnexpectedDot, "1:3-1:4".to_owned()));
+        assert_eq!(error("#(a . b)"), (ErrorKind::UnexpectedDot, "1:5-1:6".to_owned()));
+        assert_eq!(error("(a . )"), (ErrorKind::MissingDatumAfterDot, "1:6-1:7".to_owned()));
+        assert_eq!(error("(a . b c)"), (ErrorKind::ExtraDatumAfterDot, "1:8-1:9".to_owned()));
+        assert_eq!(error("#u8(1 256)"), (ErrorKind::InvalidByte, "1:7-1:10".to_owned()));
+        assert_eq!(error("(a ,)"), (ErrorKind::MissingQuotedDatum(","), "1:5-1:6".to_owned()));
+        assert_eq!(error("#(a"), (ErrorKind::UnclosedParen, "1:4-1:4".to_owned()));
+        assert_eq!(error("`"), (ErrorKind::MissingQuotedDatum("`"), "1:1-1:2".to_owned()));
+        assert_eq!(error("(#0=a #0=b)"), (ErrorKind::DuplicateLabel(0), "1:7-1:10".to_owned()));
+        assert_eq!(error("(#0# #0=a)"), (ErrorKind::UndefinedLabel(0), "1:2-1:5".to_owned()));
+        assert_eq!(error("#0=a #0#"), (ErrorKind::UndefinedLabel(0), "1:6-1:9".to_owned()));
+        assert_eq!(error("#0=#0#"), (ErrorKind::LabelOfItself(0), "1:4-1:7".to_owned()));
+        assert_eq!(error("(#5=)"), (ErrorKind::MissingLabeledDatum(5), "1:5-1:6".to_owned()));
+    }
+
+    fn messages(input: &str) -> Vec<String> {
+        let e = read(input).into_iter().find(|r| r.is_err()).unwrap().unwrap_err();
+        e.to_string().lines().map(|l| l.trim().to_owned()).collect()
+    }
+
+    #[test]
+    fn unclosed_at_end() {
+        assert_eq!(messages("(define (f x)\n  (g x)"), vec![
+            "Error (test:2:8): Unexpected end of input, missing ')'",
+            "test:1:1: the '(' here is never closed",
+        ]);
+    }
+
+    #[test]
+    fn unclosed_by_indentation() {
+        let source = "(define (f x)\n  (let ((y x))\n    (g y)\n\n(define z 1)\n";
+        assert_eq!(messages(source), vec![
+            "Error (test:5:13): Unexpected end of input, missing ')'",
+            "test:2:3: the '(' here is never closed",
+            "test:3:10: it was probably meant to be closed here, as the next line is indented no deeper than it",
+        ]);
+    }
+
+    #[test]
+    fn closing_lines_are_not_dedents() {
+        assert_eq!(messages("(a\n  (b\n)"), vec![
+            "Error (test:3:2): Unexpected end of input, missing ')'",
+            "test:1:1: the '(' here is never closed",
+        ]);
+    }
+
+    #[test]
+    fn stray_close() {
+        assert_eq!(messages("(define (f x)\n  (g x)))\n"), vec![
+            "Error (test:2:9): Unexpected ')' with nothing open",
+            "test:1:1: the last top-level '(' here was already closed at 2:8",
+        ]);
+        assert_eq!(messages("(a ')"), vec![
+            "Error (test:1:5): Expected a datum after '''",
+            "test:1:4: the ''' here needs a datum after it",
+        ]);
+    }
+}
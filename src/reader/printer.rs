@@ -0,0 +1,362 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write};
+
+use reader::{Datum, DatumKind};
+use symbol::Symbol;
+
+/// How a datum is printed, after the R7RS procedures of the same names.
+/// `Write` and `WriteShared` output reads back as an equal datum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    /// Escapes strings, characters and symbols, and labels only cycles.
+    Write,
+    /// Like `Write`, but labels every datum that is referred to again.
+    WriteShared,
+    /// Prints strings, characters and symbols as their bare contents.
+    Display,
+}
+
+/// A datum printed in some style, for use with `format!` and friends. A
+/// datum's own `Display` is `Style::Write`.
+pub struct Printed<'a> {
+    datum: &'a Datum,
+    style: Style,
+}
+
+impl Datum {
+    pub fn printed(&self, style: Style) -> Printed<'_> {
+        Printed {
+            datum: self,
+            style,
+        }
+    }
+}
+
+impl fmt::Display for Datum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.printed(Style::Write).fmt(f)
+    }
+}
+
+impl<'a> fmt::Display for Printed<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let labels = self.datum.labels();
+        let printed = printed_labels(self.datum, &labels, self.style);
+        Printer {
+            f,
+            style: self.style,
+            labels,
+            printed,
+            defined: HashSet::new(),
+        }.datum(self.datum)
+    }
+}
+
+/// The labels that appear in the output: those referred to at all for
+/// `WriteShared`, and those referred to from inside their own datum
+/// otherwise.
+fn printed_labels(root: &Datum, labels: &HashMap<u32, &Datum>, style: Style) -> HashSet<u32> {
+    let mut references = HashMap::new();
+    let mut stack = vec![(root, Vec::new())];
+    while let Some((datum, enclosing)) = stack.pop() {
+        match datum.kind {
+            DatumKind::Pair(ref head, ref tail) => {
+                stack.push((tail, enclosing.clone()));
+                stack.push((head, enclosing));
+            }
+            DatumKind::Vector(ref items) => stack.extend(items.iter().map(|i| (i, enclosing.clone()))),
+            DatumKind::Labeled(n, ref inner) => {
+                let mut enclosing = enclosing;
+                enclosing.push(n);
+                stack.push((inner, enclosing));
+            }
+            DatumKind::Reference(n) => {
+                let cyclic = enclosing.contains(&n);
+                *references.entry(n).or_insert(false) |= cyclic;
+            }
+            _ => {}
+        }
+    }
+
+    references.into_iter()
+        .filter(|&(n, cyclic)| labels.contains_key(&n) && (cyclic || style == Style::WriteShared))
+        .map(|(n, _)| n)
+        .collect()
+}
+
+/// What to print for a datum once labels that aren't printed are seen
+/// through.
+enum Node<'a> {
+    Reference(u32),
+    Labeled(u32, &'a Datum),
+    Plain(&'a Datum),
+}
+
+struct Printer<'a, 'f, 'b: 'f> {
+    f: &'f mut fmt::Formatter<'b>,
+    style: Style,
+    labels: HashMap<u32, &'a Datum>,
+    printed: HashSet<u32>,
+    /// The printed labels already written out with `#n=`.
+    defined: HashSet<u32>,
+}
+
+impl<'a, 'f, 'b> Printer<'a, 'f, 'b> {
+    fn resolve(&self, mut datum: &'a Datum) -> Node<'a> {
+        loop {
+            datum = match datum.kind {
+                DatumKind::Labeled(n, _) | DatumKind::Reference(n) if self.defined.contains(&n) =>
+                    return Node::Reference(n),
+                DatumKind::Labeled(n, ref inner) if self.printed.contains(&n) => return Node::Labeled(n, inner),
+                DatumKind::Labeled(_, ref inner) => inner,
+                DatumKind::Reference(n) => match self.labels.get(&n) {
+                    Some(labeled) => labeled,
+                    None => return Node::Reference(n),
+                },
+                _ => return Node::Plain(datum),
+            };
+        }
+    }
+
+    fn datum(&mut self, datum: &'a Datum) -> fmt::Result {
+        let node = self.resolve(datum);
+        self.node(node)
+    }
+
+    fn node(&mut self, node: Node<'a>) -> fmt::Result {
+        match node {
+            Node::Reference(n) => write!(self.f, "#{}#", n),
+            Node::Labeled(n, inner) => {
+                self.defined.insert(n);
+                write!(self.f, "#{}=", n)?;
+                self.datum(inner)
+            }
+            Node::Plain(datum) => self.plain(datum),
+        }
+    }
+
+    fn plain(&mut self, datum: &'a Datum) -> fmt::Result {
+        match datum.kind {
+            DatumKind::Nil => self.f.write_str("()"),
+            DatumKind::Pair(ref head, ref tail) => {
+                self.f.write_char('(')?;
+                self.datum(head)?;
+                let mut rest = tail;
+                loop {
+                    match self.resolve(rest) {
+                        Node::Plain(&Datum { kind: DatumKind::Pair(ref head, ref tail), .. }) => {
+                            self.f.write_char(' ')?;
+                            self.datum(head)?;
+                            rest = tail;
+                        }
+                        Node::Plain(&Datum { kind: DatumKind::Nil, .. }) => break,
+                        node => {
+                            self.f.write_str(" . ")?;
+                            self.node(node)?;
+                            break;
+                        }
+                    }
+                }
+                self.f.write_char(')')
+            }
+            DatumKind::Vector(ref items) => {
+                self.f.write_str("#(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        self.f.write_char(' ')?;
+                    }
+                    self.datum(item)?;
+                }
+                self.f.write_char(')')
+            }
+            DatumKind::Bytevector(ref bytes) => {
+                self.f.write_str("#u8(")?;
+                for (i, b) in bytes.iter().enumerate() {
+                    write!(self.f, "{}{}", if i > 0 { " " } else { "" }, b)?;
+                }
+                self.f.write_char(')')
+            }
+            DatumKind::Symbol(s) if self.style == Style::Display => write!(self.f, "{}", s),
+            DatumKind::Symbol(s) => write_symbol(self.f, s),
+            DatumKind::Bool(b) => self.f.write_str(if b { "#t" } else { "#f" }),
+            DatumKind::Fixnum(n) => write!(self.f, "{}", n),
+            DatumKind::Flonum(n) => write_flonum(self.f, n),
+            DatumKind::String(ref s) if self.style == Style::Display => self.f.write_str(s),
+            DatumKind::String(ref s) => write_string(self.f, s),
+            DatumKind::Char(c) if self.style == Style::Display => self.f.write_char(c),
+            DatumKind::Char(c) => write_char(self.f, c),
+            DatumKind::Labeled(..) | DatumKind::Reference(_) => self.datum(datum),
+        }
+    }
+}
+
+/// Writes `c` in a string or `|symbol|` delimited by `quote`.
+fn write_escaped(f: &mut fmt::Formatter, c: char, quote: char) -> fmt::Result {
+    match c {
+        '\\' => f.write_str("\\\\"),
+        '\u{7}' => f.write_str("\\a"),
+        '\u{8}' => f.write_str("\\b"),
+        '\t' => f.write_str("\\t"),
+        '\n' => f.write_str("\\n"),
+        '\r' => f.write_str("\\r"),
+        c if c == quote => write!(f, "\\{}", c),
+        c if c.is_control() => write!(f, "\\x{:x};", c as u32),
+        c => f.write_char(c),
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        write_escaped(f, c, '"')?;
+    }
+    f.write_char('"')
+}
+
+/// Writes `symbol` bare if it reads back as the same symbol, and between
+/// bars otherwise.
+fn write_symbol(f: &mut fmt::Formatter, symbol: Symbol) -> fmt::Result {
+    let s = symbol.as_str();
+    if is_identifier(s) {
+        return f.write_str(s);
+    }
+    f.write_char('|')?;
+    for c in s.chars() {
+        write_escaped(f, c, '|')?;
+    }
+    f.write_char('|')
+}
+
+/// Whether `s` is read as an identifier, by the rules of the token
+/// grammar: no delimiters, whitespace or `#` at the start, and neither `.`
+/// nor a number. Letters and digits outside ASCII are taken to be those
+/// the grammar allows.
+fn is_identifier(s: &str) -> bool {
+    let initial = |c: char| {
+        c.is_ascii_alphabetic() || "!$%&*/:<=>?~_^".contains(c) || !c.is_ascii() && c.is_alphabetic()
+    };
+    let subsequent = |c: char| {
+        initial(c) || c.is_ascii_digit() || ".@+-".contains(c) || !c.is_ascii() && c.is_numeric()
+    };
+    let mut chars = s.chars();
+    match chars.next() {
+        Some('+') | Some('-') => match chars.next() {
+            None => true,
+            Some(c) if initial(c) || "@+-".contains(c) =>
+                chars.all(subsequent) && !matches!(s, "+inf.0" | "-inf.0" | "+nan.0" | "-nan.0"),
+            _ => false,
+        },
+        Some(c) if initial(c) => chars.all(subsequent),
+        _ => s == "...",
+    }
+}
+
+fn write_char(f: &mut fmt::Formatter, c: char) -> fmt::Result {
+    let name = match c {
+        '\u{7}' => "alarm",
+        '\u{8}' => "backspace",
+        '\u{7f}' => "delete",
+        '\u{1b}' => "escape",
+        '\n' => "newline",
+        '\0' => "null",
+        '\r' => "return",
+        ' ' => "space",
+        '\t' => "tab",
+        c if c.is_control() || c.is_whitespace() => return write!(f, "#\\x{:x}", c as u32),
+        c => return write!(f, "#\\{}", c),
+    };
+    write!(f, "#\\{}", name)
+}
+
+fn write_flonum(f: &mut fmt::Formatter, n: f64) -> fmt::Result {
+    if n.is_nan() {
+        f.write_str("+nan.0")
+    } else if n.is_infinite() {
+        f.write_str(if n > 0.0 { "+inf.0" } else { "-inf.0" })
+    } else if n == n.trunc() {
+        write!(f, "{}.0", n)
+    } else {
+        write!(f, "{}", n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pipeline;
+
+    fn read(source: &str) -> Datum {
+        let mut data = pipeline::read("test", source.as_bytes());
+        let datum = data.next().unwrap().unwrap();
+        assert!(data.next().is_none(), "{:?} should hold one datum", source);
+        datum
+    }
+
+    fn print(source: &str, style: Style) -> String {
+        read(source).printed(style).to_string()
+    }
+
+    /// Checks that `source` writes as `expected` and reads back equal.
+    fn round_trip(source: &str, style: Style, expected: &str) {
+        let datum = read(source);
+        let printed = datum.printed(style).to_string();
+        assert_eq!(printed, expected);
+        assert_eq!(read(&printed), datum, "{:?} should read back equal", printed);
+    }
+
+    #[test]
+    fn write_escapes() {
+        round_trip(r#""a\"b\\c\nd\te\x7;f λ""#, Style::Write, r#""a\"b\\c\nd\te\af λ""#);
+        round_trip("\"a\\\n    b\"", Style::Write, r#""ab""#);
+        round_trip(r#"(#\a #\space #\x0 #\x7f #\( #\x3bb #\xa0)"#, Style::Write,
+                   r#"(#\a #\space #\null #\delete #\( #\λ #\xa0)"#);
+    }
+
+    #[test]
+    fn write_symbols() {
+        round_trip("(abc lambda + ... λx)", Style::Write, "(abc lambda + ... λx)");
+        round_trip(r"(|a b| || |1| |#t| |.| |a\|b| |a\x9;|)", Style::Write,
+                   r"(|a b| || |1| |#t| |.| |a\|b| |a\t|)");
+        round_trip("|abc|", Style::Write, "abc");
+        round_trip("(a.b x1 -> +a -@ !$%&*/:<=>?~_^ λ1)", Style::Write, "(a.b x1 -> +a -@ !$%&*/:<=>?~_^ λ1)");
+        round_trip(r"(|+inf.0| |-nan.0| |+1| |+.5| |.a| |1a| |a;b| |a\b| |#a| |a[b|)", Style::Write,
+                   r"(|+inf.0| |-nan.0| |+1| |+.5| |.a| |1a| |a;b| |a\b| |#a| |a[b|)");
+    }
+
+    #[test]
+    fn write_numbers() {
+        round_trip("(1 -2 1.0 1.5 -0.0 1000000000000000000000.0)", Style::Write,
+                   "(1 -2 1.0 1.5 -0.0 1000000000000000000000.0)");
+        round_trip("(+inf.0 -inf.0 +nan.0)", Style::Write, "(+inf.0 -inf.0 +nan.0)");
+        round_trip("#u8(0 255)", Style::Write, "#u8(0 255)");
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(print(r#"("a\"b" #\c |d e| #(1.5 #\space))"#, Style::Display), r#"(a"b c d e #(1.5  ))"#);
+    }
+
+    #[test]
+    fn labels() {
+        // Only cycles are labelled by `write`.
+        round_trip("(#0=(a) #0#)", Style::Write, "((a) (a))");
+        round_trip("#0=(a b . #0#)", Style::Write, "#0=(a b . #0#)");
+        round_trip("(#0=(a . #0#) #0#)", Style::Write, "(#0=(a . #0#) #0#)");
+        round_trip("#1=#(1 #1#)", Style::Write, "#1=#(1 #1#)");
+        round_trip("(#0=x #1=(#1#))", Style::Write, "(x #1=(#1#))");
+
+        round_trip("(#0=(a) #0# #1=b)", Style::WriteShared, "(#0=(a) #0# b)");
+        round_trip("#0=(a #1=(b) #1# . #0#)", Style::WriteShared, "#0=(a #1=(b) #1# . #0#)");
+        assert_eq!(print("#0=(a . #0#)", Style::Display), "#0=(a . #0#)");
+    }
+
+    #[test]
+    fn equality() {
+        assert_eq!(read("(a #(1 \"s\") . b)"), read("( a  #( 1 \"s\" ) . b )"));
+        assert_ne!(read("(a b)"), read("(a . b)"));
+        assert_ne!(read("1"), read("1.0"));
+        assert_eq!(read("#0=(a . #0#)"), read("#1=(a a . #1#)"));
+        assert_ne!(read("#0=(a . #0#)"), read("#1=(a b . #1#)"));
+        assert_eq!(read("(#0=(x) #0#)"), read("((x) (x))"));
+    }
+}
@@ -0,0 +1,44 @@
+use std::fmt;
+
+use symbol::Symbol;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// A reference to a variable bound nowhere, with the closest name that
+    /// is bound, if any is close enough to be a likely typo.
+    UnboundVariable(Symbol, Option<Symbol>),
+    /// A `set!` of a global that is never defined, with a suggestion as for
+    /// `UnboundVariable`.
+    AssignmentToUndefined(Symbol, Option<Symbol>),
+    DuplicateDefinition(Symbol),
+    /// A definition or `set!` of a global bound to a primitive, which calls
+    /// are compiled assuming it always is.
+    PrimitiveRedefinition(Symbol),
+    /// A definition or `set!` of a variable bound to a procedure of a
+    /// record type, which calls are compiled assuming it always is.
+    RecordProcedureRedefinition(Symbol),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let suggestion = match *self {
+            ErrorKind::UnboundVariable(name, suggestion) => {
+                write!(f, "Unbound variable '{}'", name)?;
+                suggestion
+            }
+            ErrorKind::AssignmentToUndefined(name, suggestion) => {
+                write!(f, "Can't set! '{}', which is never defined", name)?;
+                suggestion
+            }
+            ErrorKind::DuplicateDefinition(name) =>
+                return write!(f, "'{}' is defined more than once in the same body", name),
+            ErrorKind::PrimitiveRedefinition(name) => return write!(f, "Can't change '{}', which is built in", name),
+            ErrorKind::RecordProcedureRedefinition(name) =>
+                return write!(f, "Can't change '{}', which is a procedure of a record type", name),
+        };
+        match suggestion {
+            Some(suggestion) => write!(f, "; did you mean '{}'?", suggestion),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Finds the references in a `letrec*` that may run before the variable
+//! they refer to is initialised.
+//!
+//! The inits of a `letrec*` run in order, so a reference to the `i`th
+//! variable can only run too early if it is in the `j`th init for some
+//! `j <= i`, including inside a `lambda` there, since the procedure may be
+//! called before the `i`th init is done. Unless the inits from the `j`th to
+//! the `i`th are all `lambda`s: creating a procedure runs none of its body,
+//! so nothing can call one of those before the `i`th variable is set. This
+//! keeps the usual body of procedure definitions calling each other free of
+//! checks.
+
+use std::collections::HashSet;
+
+use ir::{Expr, ExprKind, Op, Var};
+
+/// Wraps every reference in `bindings` that may run before the variable it
+/// refers to is initialised in an `Op::Check`.
+pub fn check_early_references(bindings: &mut [(Var, Expr)]) {
+    // `safe_from[i]` is where the run of `lambda`s that ends with the `i`th
+    // init starts, or `i + 1` if that init isn't a `lambda`.
+    let mut safe_from = Vec::with_capacity(bindings.len());
+    for (i, binding) in bindings.iter().enumerate() {
+        let start = match binding.1.kind {
+            ExprKind::Lambda(_) => safe_from.last().cloned().filter(|&s| s < i).unwrap_or(i),
+            _ => i + 1,
+        };
+        safe_from.push(start);
+    }
+
+    for j in 0..bindings.len() {
+        let early: HashSet<Var> = (j..bindings.len()).filter(|&i| j < safe_from[i]).map(|i| bindings[i].0).collect();
+        if !early.is_empty() {
+            check(&mut bindings[j].1, &early);
+        }
+    }
+}
+
+fn check(expr: &mut Expr, early: &HashSet<Var>) {
+    match expr.kind {
+        ExprKind::Ref(var) if early.contains(&var) => {
+            let reference = Expr::new(ExprKind::Ref(var), expr.span);
+            expr.kind = ExprKind::Op(Op::Check(var), vec![reference]);
+        }
+        _ => for child in expr.children_mut() {
+            check(child, early);
+        },
+    }
+}
@@ -0,0 +1,513 @@
+//! Resolves the variables in parsed expressions to the bindings they refer
+//! to, producing `ir::Expr`. Locals are looked up in the scopes around
+//! them. Any other name is a global, which only has to be defined somewhere
+//! in the program, so references to globals not defined yet are checked
+//! once the whole program has been seen.
+
+mod error;
+mod letrec;
+mod record;
+
+use std::collections::{HashMap, HashSet};
+
+use error::{Error, Result};
+use ir::{self, Var};
+use iter::StreamMap;
+use prelude;
+use primitive::Primitive;
+use span::{Span, Spanned};
+use symbol::Symbol;
+use syntax::{self, ExprKind};
+use syntax::ErrorKind::EmptyBody;
+
+pub use self::error::*;
+
+#[derive(Debug)]
+pub struct Resolver<'a> {
+    file_name: &'a str,
+    /// Globals defined so far, starting with the builtins.
+    defined: HashSet<Symbol>,
+    /// Uses of globals that weren't defined when they were resolved.
+    pending: Vec<Pending>,
+    /// The local scopes around the expression being resolved, innermost
+    /// last, by the names the parser gave their variables.
+    scopes: Vec<HashMap<Symbol, Var>>,
+    /// The variables bound to the procedures of record types.
+    records: HashMap<Var, record::Procedure>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    name: Symbol,
+    span: Span,
+    is_set: bool,
+    /// The closest local in scope at the use, and how far it is from `name`.
+    local: Option<(usize, Symbol)>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(file_name: &'a str) -> Self {
+        let primitives = Primitive::ALL.iter().map(|p| Symbol::intern(p.name()));
+        Resolver {
+            file_name,
+            defined: primitives.chain(prelude::names().iter().cloned()).collect(),
+            pending: Vec::new(),
+            scopes: Vec::new(),
+            records: HashMap::new(),
+        }
+    }
+
+    /// Resolves a top-level form.
+    pub fn resolve(&mut self, expr: &syntax::Expr) -> Result<ir::Expr> {
+        let kind = match expr.kind {
+            ExprKind::Define(ref name, ref value) => {
+                let var = self.define_global(name)?;
+                ir::ExprKind::Define(var, Box::new(self.expr(value)?))
+            }
+            ExprKind::DefineRecordType(ref record) => {
+                let vars = record.variables().into_iter()
+                    .map(|name| self.define_global(name))
+                    .collect::<Result<Vec<_>>>()?;
+                self.records.extend(record::procedures(record, &vars));
+                let defines = record::bindings(record, &vars, expr.span).into_iter()
+                    .map(|(var, value)| ir::Expr::new(ir::ExprKind::Define(var, Box::new(value)), expr.span))
+                    .collect();
+                ir::ExprKind::Begin(defines)
+            }
+            ExprKind::Begin(ref forms) =>
+                ir::ExprKind::Begin(forms.iter().map(|e| self.resolve(e)).collect::<Result<_>>()?),
+            _ => return self.expr(expr),
+        };
+        Ok(ir::Expr::new(kind, expr.span))
+    }
+
+    /// The global that a top-level definition of `name` defines.
+    fn define_global(&mut self, name: &Spanned<Symbol>) -> Result<Var> {
+        let var = Var::global(name.node);
+        self.check_assignable(var, name.span)?;
+        self.defined.insert(name.node);
+        Ok(var)
+    }
+
+    /// Checks that `var`, defined or `set!` at `span`, isn't one that calls
+    /// are compiled assuming it keeps its value: one bound to a primitive
+    /// or to a procedure of a record type.
+    fn check_assignable(&self, var: Var, span: Span) -> Result<()> {
+        if var.is_global() && Primitive::named(var.symbol().as_str()).is_some() {
+            return Err(self.error(span, ErrorKind::PrimitiveRedefinition(var.symbol())));
+        }
+        if self.records.contains_key(&var) {
+            return Err(self.error(span, ErrorKind::RecordProcedureRedefinition(var.symbol())));
+        }
+        Ok(())
+    }
+
+    /// Reports the uses of globals that are still undefined.
+    pub fn check_globals(&mut self) -> Vec<Error> {
+        let mut globals: Vec<_> = self.defined.iter().cloned().collect();
+        globals.sort_by_key(|s| s.as_str());
+        let defined = &self.defined;
+        let pending: Vec<_> = self.pending.drain(..).filter(|p| !defined.contains(&p.name)).collect();
+        pending.into_iter().map(|p| {
+            let global = closest(p.name, globals.iter().cloned());
+            let suggestion = match (p.local, global) {
+                (Some(local), Some(global)) => Some(if local.0 <= global.0 { local.1 } else { global.1 }),
+                (local, global) => local.or(global).map(|s| s.1),
+            };
+            let kind = match p.is_set {
+                true => ErrorKind::AssignmentToUndefined(p.name, suggestion),
+                false => ErrorKind::UnboundVariable(p.name, suggestion),
+            };
+            self.error(p.span, kind)
+        }).collect()
+    }
+
+    fn expr(&mut self, expr: &syntax::Expr) -> Result<ir::Expr> {
+        let kind = match expr.kind {
+            ExprKind::Const(ref datum) | ExprKind::Quote(ref datum) => ir::ExprKind::Const(datum.clone()),
+            ExprKind::Var(name) => ir::ExprKind::Ref(self.variable(name, expr.span, false)),
+            ExprKind::Set(ref name, ref value) => {
+                let var = self.variable(name.node, name.span, true);
+                self.check_assignable(var, name.span)?;
+                ir::ExprKind::Set(var, Box::new(self.expr(value)?))
+            }
+            ExprKind::Lambda(ref lambda) => ir::ExprKind::Lambda(self.lambda(lambda, expr.span)?),
+            ExprKind::If(ref test, ref consequent, ref alternate) => {
+                let alternate = match *alternate {
+                    Some(ref alternate) => Some(Box::new(self.expr(alternate)?)),
+                    None => None,
+                };
+                ir::ExprKind::If(Box::new(self.expr(test)?), Box::new(self.expr(consequent)?), alternate)
+            }
+            ExprKind::Begin(ref body) => ir::ExprKind::Begin(self.exprs(body)?),
+            ExprKind::Call(ref operator, ref operands) => {
+                let operator = self.expr(operator)?;
+                let operands = self.exprs(operands)?;
+                match operator.kind {
+                    ir::ExprKind::Ref(var) => match self.records.get(&var) {
+                        Some(procedure) if procedure.arity() == operands.len() =>
+                            return Ok(procedure.call(operands, expr.span)),
+                        _ => ir::ExprKind::Call(Box::new(operator), operands),
+                    },
+                    _ => ir::ExprKind::Call(Box::new(operator), operands),
+                }
+            }
+            ExprKind::Define(..) | ExprKind::DefineRecordType(_) =>
+                unreachable!("definitions are only parsed where they are allowed"),
+        };
+        Ok(ir::Expr::new(kind, expr.span))
+    }
+
+    fn exprs(&mut self, exprs: &[syntax::Expr]) -> Result<Vec<ir::Expr>> {
+        exprs.iter().map(|e| self.expr(e)).collect()
+    }
+
+    /// Resolves a `lambda` that spans `span`.
+    fn lambda(&mut self, lambda: &syntax::Lambda, span: Span) -> Result<ir::Lambda> {
+        let mut scope = HashMap::new();
+        let mut bind = |name: &Spanned<Symbol>| {
+            let var = Var::local(name.node.as_str());
+            scope.insert(name.node, var);
+            var
+        };
+        let params = lambda.params.iter().map(&mut bind).collect();
+        let rest = lambda.rest.as_ref().map(bind);
+
+        self.scopes.push(scope);
+        let body = self.body(&lambda.body, span);
+        self.scopes.pop();
+        Ok(ir::Lambda {
+            params,
+            rest,
+            body: body?,
+        })
+    }
+
+    /// Resolves the body of a form that spans `span`. Its definitions become
+    /// a `letrec*` around the expressions after them, binding variables in a
+    /// scope of their own. An expression among the definitions is kept in
+    /// place by binding its value to an unused variable. A `begin` in a body
+    /// is spliced into it, so that it can hold definitions too.
+    fn body(&mut self, body: &[syntax::Expr], span: Span) -> Result<Vec<ir::Expr>> {
+        let mut forms = Vec::new();
+        splice(body, &mut forms);
+        let is_definition =
+            |form: &&syntax::Expr| matches!(form.kind, ExprKind::Define(..) | ExprKind::DefineRecordType(_));
+        let definitions = match forms.iter().rposition(is_definition) {
+            Some(last) if last + 1 == forms.len() => return Err(self.error(forms[last].span, EmptyBody)),
+            Some(last) => last + 1,
+            None if forms.is_empty() => return Err(self.error(span, EmptyBody)),
+            None => return forms.into_iter().map(|e| self.expr(e)).collect(),
+        };
+
+        let mut scope: HashMap<Symbol, Var> = HashMap::new();
+        let mut defined: Vec<&Spanned<Symbol>> = Vec::new();
+        for form in &forms {
+            let names = match form.kind {
+                ExprKind::Define(ref name, _) => vec![name],
+                ExprKind::DefineRecordType(ref record) => record.variables(),
+                _ => continue,
+            };
+            for name in names {
+                if let Some(first) = defined.iter().find(|d| d.node == name.node) {
+                    return Err(self.error(name.span, ErrorKind::DuplicateDefinition(name.node))
+                        .with_note(first.span, "it is first defined here"));
+                }
+                defined.push(name);
+                scope.insert(name.node, Var::local(name.node.as_str()));
+            }
+        }
+
+        self.scopes.push(scope);
+        let letrec = self.letrec(&forms[..definitions], &forms[definitions..]);
+        self.scopes.pop();
+        let span = forms[0].span.to(forms[forms.len() - 1].span);
+        Ok(vec![ir::Expr::new(letrec?, span)])
+    }
+
+    /// The `letrec*` for the `definitions` at the start of a body and the
+    /// expressions in `rest`, in the scope of the definitions. The
+    /// procedures of record types are known before anything is resolved,
+    /// so that every call to them in the body is compiled as their
+    /// operation.
+    fn letrec(&mut self, definitions: &[&syntax::Expr], rest: &[&syntax::Expr]) -> Result<ir::ExprKind> {
+        let records: Vec<_> = definitions.iter().filter_map(|form| match form.kind {
+            ExprKind::DefineRecordType(ref record) => {
+                let scope = self.scopes.last().unwrap();
+                let vars: Vec<_> = record.variables().iter().map(|name| scope[&name.node]).collect();
+                Some((record, vars, form.span))
+            }
+            _ => None,
+        }).collect();
+        for &(record, ref vars, _) in &records {
+            self.records.extend(record::procedures(record, vars));
+        }
+
+        let mut records = records.into_iter();
+        let mut bindings = Vec::new();
+        for form in definitions {
+            match form.kind {
+                ExprKind::Define(ref name, ref value) =>
+                    bindings.push((self.scopes.last().unwrap()[&name.node], self.expr(value)?)),
+                ExprKind::DefineRecordType(_) => {
+                    let (record, vars, span) = records.next().unwrap();
+                    bindings.extend(record::bindings(record, &vars, span));
+                }
+                _ => bindings.push((Var::local("_"), self.expr(form)?)),
+            }
+        }
+        letrec::check_early_references(&mut bindings);
+        let body = rest.iter().map(|e| self.expr(e)).collect::<Result<_>>()?;
+        Ok(ir::ExprKind::Letrec(bindings, body))
+    }
+
+    /// The variable `name` refers to at `span`, which a `set!` assigns if
+    /// `is_set`.
+    fn variable(&mut self, name: Symbol, span: Span, is_set: bool) -> Var {
+        if let Some(&var) = self.scopes.iter().rev().filter_map(|scope| scope.get(&name)).next() {
+            return var;
+        }
+        if !self.defined.contains(&name) {
+            let locals = self.scopes.iter().rev().flat_map(|scope| scope.values().map(|var| var.symbol()));
+            let local = closest(name, locals);
+            self.pending.push(Pending {
+                name,
+                span,
+                is_set,
+                local,
+            });
+        }
+        Var::global(name)
+    }
+
+    fn error<K: Into<::error::ErrorKind>>(&self, span: Span, kind: K) -> Error {
+        Error::new(self.file_name, span, kind)
+    }
+}
+
+/// Collects `body` into `out`, with the forms of every `begin` in place of
+/// it.
+fn splice<'e>(body: &'e [syntax::Expr], out: &mut Vec<&'e syntax::Expr>) {
+    for form in body {
+        match form.kind {
+            ExprKind::Begin(ref forms) => splice(forms, out),
+            _ => out.push(form),
+        }
+    }
+}
+
+/// The candidate closest to `name`, if any is close enough to be a likely
+/// misspelling of it, and how far it is.
+fn closest<I: IntoIterator<Item=Symbol>>(name: Symbol, candidates: I) -> Option<(usize, Symbol)> {
+    let len = name.as_str().chars().count();
+    let max = (len / 3).max(1);
+    let mut best: Option<(usize, Symbol)> = None;
+    for candidate in candidates {
+        let distance = edit_distance(name.as_str(), candidate.as_str());
+        if distance > 0 && distance <= max && distance < len && best.is_none_or(|b| distance < b.0) {
+            best = Some((distance, candidate));
+        }
+    }
+    best
+}
+
+/// How many insertions, deletions, substitutions and swaps of adjacent
+/// characters it takes to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // `d[i][j]` is the distance between the first `i` characters of `a` and
+    // the first `j` of `b`.
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = d[i - 1][j - 1] + (a[i - 1] != b[j - 1]) as usize;
+            let mut distance = substitution.min(d[i - 1][j] + 1).min(d[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(d[i - 2][j - 2] + 1);
+            }
+            d[i][j] = distance;
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+impl<'a> StreamMap<Result<syntax::Expr>, Result<ir::Expr>> for Resolver<'a> {
+    fn produce(&mut self, expr: Result<syntax::Expr>, out: &mut impl Extend<Result<ir::Expr>>) {
+        out.extend(Some(expr.and_then(|e| self.resolve(&e))));
+    }
+
+    fn finish(&mut self, out: &mut impl Extend<Result<ir::Expr>>) {
+        out.extend(self.check_globals().into_iter().map(Err));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pipeline;
+
+    /// Resolves `input` and prints it with every local numbered, so that
+    /// different variables with the same name can be told apart.
+    fn resolved(input: &str) -> String {
+        let mut numbers = HashMap::new();
+        pipeline::resolve("test", input.as_bytes())
+            .map(|r| ir::numbered(&r.unwrap(), &mut numbers).to_string())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn errors(input: &str) -> Vec<String> {
+        pipeline::resolve("test", input.as_bytes()).filter_map(|r| r.err()).map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn bindings_are_distinct() {
+        assert_eq!(resolved("(lambda (x) (lambda (x) x) x)"), "(lambda (x.1) (lambda (x.2) x.2) x.1)");
+        assert_eq!(resolved("(define x 1) (lambda (x) x) x"), "(define x 1) (lambda (x.1) x.1) x");
+        assert_eq!(resolved("(lambda (x . y) (set! y x) (car y))"), "(lambda (x.1 . y.2) (set! y.2 x.1) (car y.2))");
+        assert_eq!(resolved("'x \"s\""), "(quote x) \"s\"");
+    }
+
+    #[test]
+    fn bodies() {
+        // Definitions in a body bind variables of their own, visible
+        // throughout it, even where they shadow a parameter.
+        assert_eq!(resolved("(lambda (x) (define (f) (g x)) (define g car) (define x 1) (f))"),
+                   "(lambda (x.1) (letrec* ((f.2 (lambda () ((#%check g.3 g.3) (#%check x.4 x.4)))) (g.3 car) (x.4 1)) \
+                    (f.2)))");
+        assert_eq!(resolved("(lambda () (begin (define a 1) (begin (define b a))) b)"),
+                   "(lambda () (letrec* ((a.1 1) (b.2 a.1)) b.2))");
+        assert_eq!(resolved("(define (f) (define-syntax def (syntax-rules () ((_ v) (define v 1)))) (def x) x)"),
+                   "(define f (lambda () (letrec* ((x.1 1)) x.1)))");
+        assert_eq!(resolved("(lambda () (define a 1) (display a) (define b a) (list a b))"),
+                   "(lambda () (letrec* ((a.1 1) (_.2 (display a.1)) (b.3 a.1)) (list a.1 b.3)))");
+        assert_eq!(resolved("(lambda () (list))"), "(lambda () (list))");
+    }
+
+    #[test]
+    fn early_references() {
+        // Procedures defined together can refer to each other unchecked,
+        // since none of them can be called until all are defined.
+        assert_eq!(resolved("(lambda () (define (f) (g)) (define (g) (f)) (define x (f)) x)"),
+                   "(lambda () (letrec* ((f.1 (lambda () (g.2))) (g.2 (lambda () (f.1))) (x.3 (f.1))) x.3))");
+        // Until something runs in between.
+        assert_eq!(resolved("(lambda () (define (f) (g)) (define x (f)) (define (g) x) x)"),
+                   "(lambda () (letrec* ((f.1 (lambda () ((#%check g.3 g.3)))) (x.2 (f.1)) (g.3 (lambda () x.2))) x.2))");
+        assert_eq!(resolved("(lambda () (define x (+ x 1)) x)"),
+                   "(lambda () (letrec* ((x.1 (+ (#%check x.1 x.1) 1))) x.1))");
+        assert_eq!(resolved("(letrec ((even? (lambda (n) (odd? n))) (odd? (lambda (n) (even? n)))) (even? 1))"),
+                   "((lambda () (letrec* ((even?.1 (lambda (n.3) (odd?.2 n.3))) (odd?.2 (lambda (n.4) (even?.1 n.4)))) \
+                    ((lambda () (even?.1 1))))))");
+    }
+
+    #[test]
+    fn globals() {
+        // Globals may be used before they are defined, and builtins need no
+        // definition.
+        assert_eq!(resolved("(define (f) (g)) (define (g) (list (car '(1))))"),
+                   "(define f (lambda () (g))) (define g (lambda () (list (car (quote (1))))))");
+        assert_eq!(resolved("(begin (define a 1)) (set! a 2)"), "(begin (define a 1)) (set! a 2)");
+        assert_eq!(resolved("(set! list cdr)"), "(set! list cdr)");
+    }
+
+    #[test]
+    fn unbound_variables() {
+        assert_eq!(errors("(f x)"), ["Error (test:1:2): Unbound variable 'f'", "Error (test:1:4): Unbound variable 'x'"]);
+        assert_eq!(errors("(define (f lst) (lenght lst))"),
+                   ["Error (test:1:18): Unbound variable 'lenght'; did you mean 'length'?"]);
+        assert_eq!(errors("(lambda (count) (+ cont 1))"),
+                   ["Error (test:1:20): Unbound variable 'cont'; did you mean 'count'?"]);
+        assert_eq!(errors("(lambda () (foo))\n(define (fooo) 1)"),
+                   ["Error (test:1:13): Unbound variable 'foo'; did you mean 'fooo'?"]);
+        // Names too short or too different get no suggestion.
+        assert_eq!(errors("(lambda (y) z)"), ["Error (test:1:13): Unbound variable 'z'"]);
+        assert_eq!(errors("(lambda (apple) orange)"), ["Error (test:1:17): Unbound variable 'orange'"]);
+    }
+
+    #[test]
+    fn assignments_to_undefined_globals() {
+        assert_eq!(errors("(set! y 1)"), ["Error (test:1:7): Can't set! 'y', which is never defined"]);
+        assert_eq!(errors("(define total 0) (define (add! n) (set! totl (+ total n)))"),
+                   ["Error (test:1:41): Can't set! 'totl', which is never defined; did you mean 'total'?"]);
+        assert!(errors("(define (f) (set! later 1)) (define later 0)").is_empty());
+    }
+
+    #[test]
+    fn duplicate_definitions() {
+        assert_eq!(errors("(lambda () (define a 1) (begin (define a 2)) a)"),
+                   ["Error (test:1:40): 'a' is defined more than once in the same body\n  \
+                     test:1:20: it is first defined here"]);
+        // At top level, a second definition is an assignment.
+        assert!(errors("(define a 1) (define a 2)").is_empty());
+    }
+
+    #[test]
+    fn primitives_are_fixed() {
+        assert_eq!(errors("(define (car x) x) (set! cdr car)"),
+                   ["Error (test:1:10): Can't change 'car', which is built in",
+                    "Error (test:1:26): Can't change 'cdr', which is built in"]);
+        // Only the globals are.
+        assert!(errors("(lambda (car) (set! car 1) (define (cdr) car) cdr)").is_empty());
+    }
+
+    #[test]
+    fn record_types() {
+        assert_eq!(resolved("(define-record-type <point> (make-point y x) point?
+                               (x point-x set-point-x!) (y point-y))"),
+                   "(begin (define <point> (#%%make-record-type (quote point))) \
+                    (define make-point (lambda (y.1 x.2) (#%%make-record <point> x.2 y.1))) \
+                    (define point? (lambda (object.3) (#%%record? object.3 <point>))) \
+                    (define point-x (lambda (record.4) \
+                    (#%%record-ref record.4 <point> 0 \"Expected a record of type point for field x in point-x\"))) \
+                    (define set-point-x! (lambda (record.5 value.6) \
+                    (#%%record-set! record.5 <point> 0 value.6 \
+                    \"Expected a record of type point for field x in set-point-x!\"))) \
+                    (define point-y (lambda (record.7) \
+                    (#%%record-ref record.7 <point> 1 \"Expected a record of type point for field y in point-y\"))))");
+        // Calls to the procedures are compiled as what they do, even in the
+        // body that defines them, as long as the number of arguments is
+        // right.
+        assert_eq!(resolved("(lambda (n) (define (f) (node? n)) (define-record-type node (make-node) node? (v node-v)) \
+                             (node-v (make-node)) (node-v))"),
+                   "(lambda (n.1) (letrec* ((f.2 (lambda () (#%%record? n.1 (#%check node.3 node.3)))) \
+                    (node.3 (#%%make-record-type (quote node))) \
+                    (make-node.4 (lambda () (#%%make-record node.3 (#%unspecified)))) \
+                    (node?.5 (lambda (object.7) (#%%record? object.7 node.3))) \
+                    (node-v.6 (lambda (record.8) \
+                    (#%%record-ref record.8 node.3 0 \"Expected a record of type node for field v in node-v\")))) \
+                    (#%%record-ref (#%%make-record node.3 (#%unspecified)) node.3 0 \
+                    \"Expected a record of type node for field v in node-v\") \
+                    (node-v.6)))");
+    }
+
+    #[test]
+    fn record_procedures_are_fixed() {
+        assert_eq!(errors("(define-record-type p (make-p) p? (a p-a)) (define (p-a x) x) (set! p? car) (set! p 1)"),
+                   ["Error (test:1:53): Can't change 'p-a', which is a procedure of a record type",
+                    "Error (test:1:69): Can't change 'p?', which is a procedure of a record type"]);
+        assert_eq!(errors("(lambda () (define-record-type p (make-p) p? (a p-a)) (set! make-p 1))"),
+                   ["Error (test:1:61): Can't change 'make-p', which is a procedure of a record type"]);
+    }
+
+    #[test]
+    fn bodies_must_end_with_an_expression() {
+        assert_eq!(errors("(lambda () (define a 1))"),
+                   ["Error (test:1:12): Expected at least one expression in the body"]);
+        assert_eq!(errors("(lambda () (begin))"), ["Error (test:1:1): Expected at least one expression in the body"]);
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("length", "lenght"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("λx", "λy"), 1);
+    }
+}
@@ -0,0 +1,126 @@
+//! Lowers `define-record-type` to definitions of the record type and of its
+//! procedures, each of which does a primitive operation on records of the
+//! type. Calls to the procedures are compiled as the operation itself, so
+//! the variables bound to them can't be changed.
+
+use ir::{self, ExprKind, Op, Var};
+use primitive::Primitive;
+use reader::{Datum, DatumKind};
+use span::Span;
+use symbol::Symbol;
+use syntax::RecordType;
+
+/// A procedure that `define-record-type` defines, for records of the type
+/// bound to its `Var`.
+#[derive(Clone, Debug)]
+pub enum Procedure {
+    /// Takes the fields at the indices, of how many the type has, and
+    /// leaves the others unspecified.
+    Constructor(Var, Vec<usize>, usize),
+    Predicate(Var),
+    /// Takes a record and gets the field at the index, or fails with the
+    /// message if it isn't a record of the type.
+    Accessor(Var, usize, String),
+    /// Takes a record and a value, and sets the field at the index as
+    /// `Accessor` gets it.
+    Modifier(Var, usize, String),
+}
+
+impl Procedure {
+    pub fn arity(&self) -> usize {
+        match *self {
+            Procedure::Constructor(_, ref fields, _) => fields.len(),
+            Procedure::Predicate(_) | Procedure::Accessor(..) => 1,
+            Procedure::Modifier(..) => 2,
+        }
+    }
+
+    /// The operation that a call to the procedure with `args` does.
+    pub fn call(&self, mut args: Vec<ir::Expr>, span: Span) -> ir::Expr {
+        let expr = |kind| ir::Expr::new(kind, span);
+        let constant = |kind| expr(ExprKind::Const(Datum::new(kind, span)));
+        let (p, operands) = match *self {
+            Procedure::Constructor(record_type, ref fields, count) => {
+                let unspecified = || expr(ExprKind::Op(Op::Unspecified, Vec::new()));
+                let mut operands: Vec<_> = (0..count).map(|_| unspecified()).collect();
+                for (arg, &index) in args.into_iter().zip(fields) {
+                    operands[index] = arg;
+                }
+                operands.insert(0, expr(ExprKind::Ref(record_type)));
+                (Primitive::MakeRecord, operands)
+            }
+            Procedure::Predicate(record_type) =>
+                (Primitive::IsRecord, vec![args.remove(0), expr(ExprKind::Ref(record_type))]),
+            Procedure::Accessor(record_type, index, ref message) |
+            Procedure::Modifier(record_type, index, ref message) => {
+                let mut operands = vec![
+                    args.remove(0),
+                    expr(ExprKind::Ref(record_type)),
+                    constant(DatumKind::Fixnum(index as i64)),
+                ];
+                operands.extend(args);
+                operands.push(constant(DatumKind::String(message.clone())));
+                let p = if let Procedure::Accessor(..) = *self { Primitive::RecordRef } else { Primitive::RecordSet };
+                (p, operands)
+            }
+        };
+        expr(ExprKind::Op(Op::Primitive(p), operands))
+    }
+}
+
+/// The procedures `record` defines, by the variables bound to them, where
+/// `vars` are the variables of `RecordType::variables`.
+pub fn procedures(record: &RecordType, vars: &[Var]) -> Vec<(Var, Procedure)> {
+    let (record_type, mut vars) = (vars[0], vars[1..].iter().cloned());
+    let mut procedures = vec![
+        (vars.next().unwrap(), Procedure::Constructor(record_type, record.constructor_fields.clone(),
+                                                      record.fields.len())),
+        (vars.next().unwrap(), Procedure::Predicate(record_type)),
+    ];
+    let name = type_name(record.name.node);
+    for (index, field) in record.fields.iter().enumerate() {
+        let message = |procedure: Symbol| format!("Expected a record of type {} for field {} in {}", name,
+                                                             field.name.node, procedure);
+        procedures.push((vars.next().unwrap(), Procedure::Accessor(record_type, index, message(field.accessor.node))));
+        if let Some(ref modifier) = field.modifier {
+            procedures.push((vars.next().unwrap(), Procedure::Modifier(record_type, index, message(modifier.node))));
+        }
+    }
+    procedures
+}
+
+/// The bindings of the variables `vars` that `record`, spanning `span`,
+/// defines, in order: the type, then its procedures.
+pub fn bindings(record: &RecordType, vars: &[Var], span: Span) -> Vec<(Var, ir::Expr)> {
+    let name = Datum::new(DatumKind::Symbol(Symbol::intern(&type_name(record.name.node))), span);
+    let name = ir::Expr::new(ExprKind::Const(name), span);
+    let mut bindings = vec![(vars[0], ir::Expr::new(ExprKind::Op(Op::Primitive(Primitive::MakeRecordType), vec![name]),
+                                                    span))];
+    for (var, procedure) in procedures(record, vars) {
+        let params: Vec<_> = match procedure {
+            Procedure::Constructor(_, ref fields, _) =>
+                fields.iter().map(|&index| Var::local(record.fields[index].name.node.as_str())).collect(),
+            Procedure::Predicate(_) => vec![Var::local("object")],
+            Procedure::Accessor(..) => vec![Var::local("record")],
+            Procedure::Modifier(..) => vec![Var::local("record"), Var::local("value")],
+        };
+        let args = params.iter().map(|&param| ir::Expr::new(ExprKind::Ref(param), span)).collect();
+        let lambda = ir::Lambda {
+            body: vec![procedure.call(args, span)],
+            params,
+            rest: None,
+        };
+        bindings.push((var, ir::Expr::new(ExprKind::Lambda(lambda), span)));
+    }
+    bindings
+}
+
+/// How records of the type named `name` are described in messages and
+/// printed: `<point>`, as it is often written, as just `point`.
+fn type_name(name: Symbol) -> String {
+    let name = name.to_string();
+    match name.strip_prefix('<').and_then(|n| n.strip_suffix('>')) {
+        Some(stripped) if !stripped.is_empty() => stripped.to_string(),
+        _ => name,
+    }
+}
@@ -0,0 +1,95 @@
+use std::fmt;
+
+/// A place in a source file. `line` and `column` count from 1, and columns
+/// count characters rather than bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub offset: usize,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl Position {
+    pub fn start() -> Self {
+        Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    /// The position after the byte `c`.
+    pub fn advance(self, c: u8) -> Self {
+        if c == b'\n' {
+            Position {
+                offset: self.offset + 1,
+                line: self.line + 1,
+                column: 1,
+            }
+        } else {
+            Position {
+                offset: self.offset + 1,
+                line: self.line,
+                column: if is_continuation(c) { self.column } else { self.column + 1 },
+            }
+        }
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position::start()
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+fn is_continuation(c: u8) -> bool {
+    c & 0xc0 == 0x80
+}
+
+/// The source range `[start, end)` a token, datum or diagnostic covers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Span {
+            start,
+            end,
+        }
+    }
+
+    /// An empty span at `pos`.
+    pub fn at(pos: Position) -> Self {
+        Span::new(pos, pos)
+    }
+
+    /// The span from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Self {
+        Span::new(self.start, other.end)
+    }
+}
+
+/// A value tagged with the span it was read from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Span) -> Self {
+        Spanned {
+            node,
+            span,
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Interned symbols. Every distinct name gets a `Symbol` handle, numbered
+//! densely from 0 in the order names are first seen, so stages compare
+//! symbols as integers and the table can be laid out as an array indexed by
+//! handle.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{LazyLock, Mutex};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32);
+
+struct Table {
+    names: Vec<&'static str>,
+    symbols: HashMap<&'static str, Symbol>,
+}
+
+impl Table {
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol;
+        }
+        let symbol = self.fresh(name);
+        self.symbols.insert(self.names[symbol.0 as usize], symbol);
+        symbol
+    }
+
+    /// Adds a symbol without making `name` intern to it.
+    fn fresh(&mut self, name: &str) -> Symbol {
+        // Names live as long as the table, which is for the whole run. Fresh
+        // symbols share the text of the interned symbol with their name.
+        let name: &'static str = match self.symbols.get_key_value(name) {
+            Some((&name, _)) => name,
+            None => Box::leak(name.to_owned().into_boxed_str()),
+        };
+        let symbol = Symbol(self.names.len() as u32);
+        self.names.push(name);
+        symbol
+    }
+}
+
+macro_rules! known_symbols {
+    ( $( $name:ident: $text:expr, )* ) => {
+        known_symbols!(@consts 0u32; $( $name, )*);
+
+        const KNOWN: &[&str] = &[ $( $text, )* ];
+    };
+
+    (@consts $index:expr; $name:ident, $( $rest:ident, )*) => {
+        pub const $name: Symbol = Symbol($index);
+        known_symbols!(@consts $index + 1; $( $rest, )*);
+    };
+
+    (@consts $index:expr;) => {};
+}
+
+/// Symbols the compiler itself refers to, interned ahead of everything else.
+impl Symbol {
+    known_symbols! {
+        QUOTE: "quote",
+        QUASIQUOTE: "quasiquote",
+        UNQUOTE: "unquote",
+        UNQUOTE_SPLICING: "unquote-splicing",
+        LAMBDA: "lambda",
+        IF: "if",
+        SET: "set!",
+        DEFINE: "define",
+        BEGIN: "begin",
+        LET: "let",
+        LET_STAR: "let*",
+        LETREC: "letrec",
+        LETREC_STAR: "letrec*",
+        COND: "cond",
+        CASE: "case",
+        AND: "and",
+        OR: "or",
+        WHEN: "when",
+        UNLESS: "unless",
+        DO: "do",
+        LET_VALUES: "let-values",
+        LET_STAR_VALUES: "let*-values",
+        RECEIVE: "receive",
+        ELSE: "else",
+        ARROW: "=>",
+        MEMV: "memv",
+        CALL_WITH_VALUES: "call-with-values",
+        DEFINE_SYNTAX: "define-syntax",
+        DEFINE_RECORD_TYPE: "define-record-type",
+        DEFINE_VALUES: "define-values",
+        LET_SYNTAX: "let-syntax",
+        LETREC_SYNTAX: "letrec-syntax",
+        SYNTAX_RULES: "syntax-rules",
+        ELLIPSIS: "...",
+        UNDERSCORE: "_",
+    }
+}
+
+static TABLE: LazyLock<Mutex<Table>> = LazyLock::new(|| {
+    let mut table = Table {
+        names: Vec::new(),
+        symbols: HashMap::new(),
+    };
+    for name in Symbol::KNOWN {
+        table.intern(name);
+    }
+    Mutex::new(table)
+});
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        TABLE.lock().unwrap().intern(name)
+    }
+
+    /// A new symbol that no name interns to, for variables the compiler
+    /// introduces. It prints as `name`, but is distinct from every other
+    /// symbol, so it can't capture or be captured by user variables.
+    pub fn fresh(name: &str) -> Symbol {
+        TABLE.lock().unwrap().fresh(name)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        TABLE.lock().unwrap().names[self.0 as usize]
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Symbol({}, {:?})", self.0, self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interning() {
+        let a = Symbol::intern("interning-test-a");
+        let b = Symbol::intern("interning-test-b");
+        assert_ne!(a, b);
+        assert_eq!(Symbol::intern("interning-test-a"), a);
+        assert_eq!(a.as_str(), "interning-test-a");
+        assert_eq!(b.to_string(), "interning-test-b");
+    }
+
+    #[test]
+    fn known_symbols() {
+        assert_eq!(Symbol::intern("quote"), Symbol::QUOTE);
+        assert_eq!(Symbol::intern("unquote-splicing"), Symbol::UNQUOTE_SPLICING);
+        assert_eq!(Symbol::IF.as_str(), "if");
+    }
+
+    #[test]
+    fn fresh_symbols() {
+        let a = Symbol::fresh("fresh-test");
+        let b = Symbol::fresh("fresh-test");
+        assert_ne!(a, b);
+        assert_ne!(Symbol::intern("fresh-test"), a);
+        assert_eq!(a.as_str(), "fresh-test");
+    }
+}
@@ -0,0 +1,501 @@
+//! The derived expression types of R7RS section 4.2, lowered to core forms
+//! as they are parsed. Every expression built for a derived form spans the
+//! whole form, so anything reported about it points at what was written.
+
+use error::Result;
+use reader::{Datum, DatumKind};
+use span::{Span, Spanned};
+use symbol::Symbol;
+
+use super::{ErrorKind, Expr, ExprKind, Formals, Lambda, Parser};
+
+pub fn is_keyword(name: Symbol) -> bool {
+    matches!(name,
+             Symbol::LET | Symbol::LET_STAR | Symbol::LETREC | Symbol::LETREC_STAR | Symbol::COND |
+             Symbol::CASE | Symbol::AND | Symbol::OR | Symbol::WHEN | Symbol::UNLESS | Symbol::DO |
+             Symbol::LET_VALUES | Symbol::LET_STAR_VALUES | Symbol::RECEIVE)
+}
+
+type Binding = (Spanned<Symbol>, Expr);
+
+/// The formals of a binding of `let-values`, and its init.
+type ValuesBinding = (Formals, Expr);
+
+/// Builds core expressions that all span the derived form they come from.
+struct Build {
+    span: Span,
+}
+
+impl Build {
+    fn expr(&self, kind: ExprKind) -> Expr {
+        Expr::new(kind, self.span)
+    }
+
+    fn name(&self, name: Symbol) -> Spanned<Symbol> {
+        Spanned::new(name, self.span)
+    }
+
+    fn var(&self, name: Symbol) -> Expr {
+        self.expr(ExprKind::Var(name))
+    }
+
+    fn bool(&self, b: bool) -> Expr {
+        self.expr(ExprKind::Const(Datum::new(DatumKind::Bool(b), self.span)))
+    }
+
+    /// `(if #f #f)`, the usual way to write an unspecified value.
+    fn unspecified(&self) -> Expr {
+        self.if_(self.bool(false), self.bool(false), None)
+    }
+
+    fn if_(&self, test: Expr, consequent: Expr, alternate: Option<Expr>) -> Expr {
+        self.expr(ExprKind::If(Box::new(test), Box::new(consequent), alternate.map(Box::new)))
+    }
+
+    /// The expressions in order, without a `begin` around a single one.
+    fn sequence(&self, mut exprs: Vec<Expr>) -> Expr {
+        if exprs.len() == 1 {
+            exprs.pop().unwrap()
+        } else {
+            self.expr(ExprKind::Begin(exprs))
+        }
+    }
+
+    fn call(&self, operator: Expr, operands: Vec<Expr>) -> Expr {
+        self.expr(ExprKind::Call(Box::new(operator), operands))
+    }
+
+    fn lambda(&self, params: Vec<Spanned<Symbol>>, body: Vec<Expr>) -> Expr {
+        self.expr(ExprKind::Lambda(Lambda {
+            params,
+            rest: None,
+            body,
+        }))
+    }
+
+    /// `(call-with-values (lambda () producer) consumer)`, which CPS
+    /// conversion compiles to pass the values straight to `consumer`.
+    fn call_with_values(&self, producer: Expr, consumer: Lambda) -> Expr {
+        let producer = self.lambda(Vec::new(), vec![producer]);
+        self.call(self.var(Symbol::CALL_WITH_VALUES), vec![producer, self.expr(ExprKind::Lambda(consumer))])
+    }
+
+    /// `((lambda (names...) body...) inits...)`.
+    fn let_(&self, bindings: Vec<Binding>, body: Vec<Expr>) -> Expr {
+        let (names, inits) = bindings.into_iter().unzip();
+        self.call(self.lambda(names, body), inits)
+    }
+
+    /// `((lambda () (define name init)... body...))`. Internal definitions
+    /// are `letrec*` in all but name.
+    fn letrec(&self, bindings: Vec<Binding>, body: Vec<Expr>) -> Expr {
+        let mut defines: Vec<_> = bindings.into_iter()
+            .map(|(name, init)| self.expr(ExprKind::Define(name, Box::new(init))))
+            .collect();
+        defines.extend(body);
+        self.let_(Vec::new(), defines)
+    }
+
+    /// A body that can't see the definitions around it, for the bodies of
+    /// `letrec` and friends, which may have definitions of their own.
+    fn scope(&self, body: Vec<Expr>) -> Expr {
+        self.let_(Vec::new(), body)
+    }
+
+    /// `(let name ((variable init)...) body...)`.
+    fn named_let(&self, name: Spanned<Symbol>, bindings: Vec<Binding>, body: Vec<Expr>) -> Expr {
+        let (params, inits) = bindings.into_iter().unzip();
+        let loop_ = self.var(name.node);
+        let procedure = self.letrec(vec![(name, self.lambda(params, body))], vec![loop_]);
+        self.call(procedure, inits)
+    }
+
+    /// `(let ((temp value)) (if temp (then temp) otherwise))`, where `temp`
+    /// is fresh so it can't capture anything in `then` or `otherwise`.
+    fn if_value<F>(&self, value: Expr, then: F, otherwise: Option<Expr>) -> Expr
+        where F: FnOnce(Expr) -> Expr {
+        let temp = Symbol::fresh("temp");
+        let test = self.if_(self.var(temp), then(self.var(temp)), otherwise);
+        self.let_(vec![(self.name(temp), value)], vec![test])
+    }
+}
+
+impl<'a> Parser<'a> {
+    /// Parses the derived form spanning `span` whose elements are `items`,
+    /// starting with `keyword`.
+    pub(super) fn derived_form(&self, keyword: Symbol, items: &[&Datum], span: Span) -> Result<Expr> {
+        let args = &items[1..];
+        let b = Build { span };
+        let malformed = || Err(self.error(span, ErrorKind::MalformedForm(keyword)));
+        match keyword {
+            Symbol::LET => match args.first().map(|d| &d.kind) {
+                Some(&DatumKind::Symbol(name)) if args.len() > 1 => {
+                    let name = Spanned::new(name, args[0].span);
+                    let bindings = self.bindings(keyword, args[1])?;
+                    self.check_distinct(bindings.iter().map(|b| &b.0))?;
+                    Ok(b.named_let(name, bindings, self.body(&args[2..], span)?))
+                }
+                Some(_) => {
+                    let bindings = self.bindings(keyword, args[0])?;
+                    self.check_distinct(bindings.iter().map(|b| &b.0))?;
+                    Ok(b.let_(bindings, self.body(&args[1..], span)?))
+                }
+                None => malformed(),
+            },
+            Symbol::LET_STAR if !args.is_empty() => {
+                let bindings = self.bindings(keyword, args[0])?;
+                let body = self.body(&args[1..], span)?;
+                if bindings.is_empty() {
+                    return Ok(b.let_(bindings, body));
+                }
+                Ok(bindings.into_iter().rev().fold(body, |body, binding| vec![b.let_(vec![binding], body)])
+                    .pop().unwrap())
+            }
+            Symbol::LETREC | Symbol::LETREC_STAR if !args.is_empty() => {
+                let bindings = self.bindings(keyword, args[0])?;
+                self.check_distinct(bindings.iter().map(|b| &b.0))?;
+                let body = self.body(&args[1..], span)?;
+                Ok(b.letrec(bindings, vec![b.scope(body)]))
+            }
+            Symbol::AND => Ok(self.and(&b, args)?.unwrap_or_else(|| b.bool(true))),
+            Symbol::OR => Ok(self.or(&b, args)?.unwrap_or_else(|| b.bool(false))),
+            Symbol::WHEN | Symbol::UNLESS if args.len() > 1 => {
+                let test = self.expr(args[0])?;
+                let body = b.sequence(self.exprs(&args[1..])?);
+                Ok(if keyword == Symbol::WHEN {
+                    b.if_(test, body, None)
+                } else {
+                    b.if_(test, b.unspecified(), Some(body))
+                })
+            }
+            Symbol::COND if !args.is_empty() => self.cond(&b, args),
+            Symbol::CASE if args.len() > 1 => {
+                let key = Symbol::fresh("key");
+                let clauses = self.case(&b, key, &args[1..])?;
+                Ok(b.let_(vec![(b.name(key), self.expr(args[0])?)], vec![clauses]))
+            }
+            Symbol::DO if args.len() > 1 => self.do_(&b, args),
+            // Every variable already has a name of its own, so nesting
+            // doesn't let an init see the formals before it.
+            Symbol::LET_VALUES | Symbol::LET_STAR_VALUES if !args.is_empty() => {
+                let bindings = self.values_bindings(keyword, args[0])?;
+                if keyword == Symbol::LET_VALUES {
+                    self.check_distinct(bindings.iter().flat_map(|b| (b.0).0.iter().chain(&(b.0).1)))?;
+                }
+                let body = self.body(&args[1..], span)?;
+                if bindings.is_empty() {
+                    return Ok(b.let_(Vec::new(), body));
+                }
+                Ok(bindings.into_iter().rev().fold(body, |body, ((params, rest), init)| {
+                    vec![b.call_with_values(init, Lambda { params, rest, body })]
+                }).pop().unwrap())
+            }
+            Symbol::RECEIVE if args.len() > 1 => {
+                let consumer = self.lambda(args[0], &args[2..], span)?;
+                Ok(b.call_with_values(self.expr(args[1])?, consumer))
+            }
+            _ => malformed(),
+        }
+    }
+
+    fn exprs(&self, data: &[&Datum]) -> Result<Vec<Expr>> {
+        data.iter().map(|d| self.expr(d)).collect()
+    }
+
+    /// Parses `((variable init)...)` for the binding form `keyword`.
+    fn bindings(&self, keyword: Symbol, datum: &Datum) -> Result<Vec<Binding>> {
+        let malformed = |d: &Datum| self.error(d.span, ErrorKind::MalformedForm(keyword));
+        datum.items().ok_or_else(|| malformed(datum))?.into_iter().map(|binding| {
+            match binding.items() {
+                Some(ref items) if items.len() == 2 => Ok((self.identifier(items[0])?, self.expr(items[1])?)),
+                _ => Err(malformed(binding)),
+            }
+        }).collect()
+    }
+
+    /// Parses `((formals init)...)` for `let-values` or `let*-values`.
+    fn values_bindings(&self, keyword: Symbol, datum: &Datum) -> Result<Vec<ValuesBinding>> {
+        let malformed = |d: &Datum| self.error(d.span, ErrorKind::MalformedForm(keyword));
+        datum.items().ok_or_else(|| malformed(datum))?.into_iter().map(|binding| {
+            match binding.items() {
+                Some(ref items) if items.len() == 2 => Ok((self.formals(items[0])?, self.expr(items[1])?)),
+                _ => Err(malformed(binding)),
+            }
+        }).collect()
+    }
+
+    /// `(define-values formals expression)`, from its elements `items`. The
+    /// variables after the first are defined first, as unspecified, and
+    /// the first as the value of a `call-with-values` whose consumer
+    /// assigns them the rest of the values, so that each is a definition.
+    pub(super) fn define_values(&self, items: &[&Datum], span: Span) -> Result<Expr> {
+        let b = Build { span };
+        let (params, rest) = self.formals(items[1])?;
+        let producer = self.expr(items[2])?;
+        let names: Vec<_> = params.iter().chain(&rest).cloned().collect();
+        let temp = |name: &Spanned<Symbol>| Spanned::new(Symbol::fresh(name.node.as_str()), name.span);
+        let consumer = Lambda {
+            params: params.iter().map(temp).collect(),
+            rest: rest.as_ref().map(temp),
+            body: Vec::new(),
+        };
+        let temps: Vec<_> = consumer.params.iter().chain(&consumer.rest).map(|t| t.node).collect();
+        let first = match names.first() {
+            Some(first) => first.clone(),
+            None => return Ok(b.call_with_values(producer, Lambda { body: vec![b.unspecified()], ..consumer })),
+        };
+        let mut body: Vec<_> = names[1..].iter().zip(&temps[1..])
+            .map(|(name, &temp)| b.expr(ExprKind::Set(name.clone(), Box::new(b.var(temp)))))
+            .collect();
+        body.push(b.var(temps[0]));
+        let mut definitions: Vec<_> = names[1..].iter()
+            .map(|name| b.expr(ExprKind::Define(name.clone(), Box::new(b.unspecified()))))
+            .collect();
+        let value = b.call_with_values(producer, Lambda { body, ..consumer });
+        definitions.push(b.expr(ExprKind::Define(first, Box::new(value))));
+        Ok(b.expr(ExprKind::Begin(definitions)))
+    }
+
+    /// `(and test...)`, or `None` for `(and)`.
+    fn and(&self, b: &Build, tests: &[&Datum]) -> Result<Option<Expr>> {
+        let first = match tests.first() {
+            Some(d) => self.expr(d)?,
+            None => return Ok(None),
+        };
+        Ok(Some(match self.and(b, &tests[1..])? {
+            Some(rest) => b.if_(first, rest, Some(b.bool(false))),
+            None => first,
+        }))
+    }
+
+    /// `(or test...)`, or `None` for `(or)`.
+    fn or(&self, b: &Build, tests: &[&Datum]) -> Result<Option<Expr>> {
+        let first = match tests.first() {
+            Some(d) => self.expr(d)?,
+            None => return Ok(None),
+        };
+        Ok(Some(match self.or(b, &tests[1..])? {
+            Some(rest) => b.if_value(first, |temp| temp, Some(rest)),
+            None => first,
+        }))
+    }
+
+    fn cond(&self, b: &Build, clauses: &[&Datum]) -> Result<Expr> {
+        let mut rest = None;
+        for (i, clause) in clauses.iter().enumerate().rev() {
+            let items = match clause.items() {
+                Some(ref items) if !items.is_empty() => items.clone(),
+                _ => return Err(self.error(clause.span, ErrorKind::MalformedForm(Symbol::COND))),
+            };
+            let is_last = i == clauses.len() - 1;
+            rest = Some(match (&items[0].kind, items.len()) {
+                (&DatumKind::Symbol(Symbol::ELSE), n) if n > 1 && is_last => b.sequence(self.exprs(&items[1..])?),
+                (&DatumKind::Symbol(Symbol::ELSE), _) =>
+                    return Err(self.error(clause.span, ErrorKind::MalformedForm(Symbol::COND))),
+                (_, 1) => b.if_value(self.expr(items[0])?, |temp| temp, rest),
+                (_, 3) if is_arrow(items[1]) => {
+                    let receiver = self.expr(items[2])?;
+                    b.if_value(self.expr(items[0])?, |temp| b.call(receiver, vec![temp]), rest)
+                }
+                _ => {
+                    let body = b.sequence(self.exprs(&items[1..])?);
+                    b.if_(self.expr(items[0])?, body, rest)
+                }
+            });
+        }
+        Ok(rest.unwrap())
+    }
+
+    /// The clauses of a `case` on the value of `key`.
+    fn case(&self, b: &Build, key: Symbol, clauses: &[&Datum]) -> Result<Expr> {
+        let mut rest = None;
+        for (i, clause) in clauses.iter().enumerate().rev() {
+            let malformed = || self.error(clause.span, ErrorKind::MalformedForm(Symbol::CASE));
+            let items = match clause.items() {
+                Some(ref items) if items.len() > 1 => items.clone(),
+                _ => return Err(malformed()),
+            };
+            let body = if items.len() == 3 && is_arrow(items[1]) {
+                b.call(self.expr(items[2])?, vec![b.var(key)])
+            } else {
+                b.sequence(self.exprs(&items[1..])?)
+            };
+            rest = Some(match items[0].kind {
+                DatumKind::Symbol(Symbol::ELSE) if i == clauses.len() - 1 => body,
+                DatumKind::Nil | DatumKind::Pair(..) if items[0].items().is_some() => {
+                    self.check_integers(items[0])?;
+                    let data = b.expr(ExprKind::Quote(items[0].clone()));
+                    let test = b.call(b.var(Symbol::MEMV), vec![b.var(key), data]);
+                    b.if_(test, body, rest)
+                }
+                _ => return Err(malformed()),
+            });
+        }
+        Ok(rest.unwrap())
+    }
+
+    /// `(do ((variable init [step])...) (test expression...) command...)`
+    /// loops as a named `let`.
+    fn do_(&self, b: &Build, args: &[&Datum]) -> Result<Expr> {
+        let malformed = |d: &Datum| self.error(d.span, ErrorKind::MalformedForm(Symbol::DO));
+        let mut bindings = Vec::new();
+        let mut steps = Vec::new();
+        for spec in args[0].items().ok_or_else(|| malformed(args[0]))? {
+            match spec.items() {
+                Some(ref items) if items.len() == 2 || items.len() == 3 => {
+                    let name = self.identifier(items[0])?;
+                    steps.push(match items.get(2) {
+                        Some(step) => self.expr(step)?,
+                        None => Expr::new(ExprKind::Var(name.node), name.span),
+                    });
+                    bindings.push((name, self.expr(items[1])?));
+                }
+                _ => return Err(malformed(spec)),
+            }
+        }
+        self.check_distinct(bindings.iter().map(|b| &b.0))?;
+
+        let exit = match args[1].items() {
+            Some(ref items) if !items.is_empty() => items.clone(),
+            _ => return Err(malformed(args[1])),
+        };
+        let test = self.expr(exit[0])?;
+        let result = match exit.len() {
+            1 => b.unspecified(),
+            _ => b.sequence(self.exprs(&exit[1..])?),
+        };
+
+        let loop_ = Symbol::fresh("loop");
+        let mut commands = self.exprs(&args[2..])?;
+        commands.push(b.call(b.var(loop_), steps));
+        let body = b.if_(test, result, Some(b.sequence(commands)));
+        Ok(b.named_let(b.name(loop_), bindings, vec![body]))
+    }
+}
+
+fn is_arrow(datum: &Datum) -> bool {
+    matches!(datum.kind, DatumKind::Symbol(Symbol::ARROW))
+}
+
+#[cfg(test)]
+mod tests {
+    use error;
+    use pipeline;
+    use symbol::Symbol;
+    use syntax::{ErrorKind, ExprKind};
+
+    fn printed(input: &str) -> String {
+        pipeline::parse("test", input.as_bytes()).map(|r| r.unwrap().to_string()).collect::<Vec<_>>().join(" ")
+    }
+
+    fn error(input: &str) -> (ErrorKind, String) {
+        match pipeline::parse("test", input.as_bytes()).find(|r| r.is_err()).unwrap().unwrap_err() {
+            error::Error { kind: error::ErrorKind::Syntax(kind), span, .. } =>
+                (kind, format!("{}-{}", span.start, span.end)),
+            e => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn lets() {
+        assert_eq!(printed("(let ((x 1) (y 2)) (f x y))"), "((lambda (x y) (f x y)) 1 2)");
+        assert_eq!(printed("(let () 1)"), "((lambda () 1))");
+        assert_eq!(printed("(let* ((x 1) (y x)) y)"), "((lambda (x) ((lambda (y) y) x)) 1)");
+        assert_eq!(printed("(let* () 1 2)"), "((lambda () 1 2))");
+        assert_eq!(printed("(letrec ((f (lambda () (g))) (g (lambda () 1))) (f))"),
+                   "((lambda () (define f (lambda () (g))) (define g (lambda () 1)) ((lambda () (f)))))");
+        assert_eq!(printed("(letrec* ((x 1)) x)"), "((lambda () (define x 1) ((lambda () x))))");
+        assert_eq!(printed("(let loop ((i 0)) (loop i))"),
+                   "(((lambda () (define loop (lambda (i) (loop i))) loop)) 0)");
+    }
+
+    #[test]
+    fn conditionals() {
+        assert_eq!(printed("(and) (and a) (and a b c)"), "#t a (if a (if b c #f) #f)");
+        assert_eq!(printed("(or) (or a) (or a b)"), "#f a ((lambda (temp) (if temp temp b)) a)");
+        assert_eq!(printed("(when a b c) (unless a b)"), "(if a (begin b c)) (if a (if #f #f) b)");
+        assert_eq!(printed("(cond (a b) (c => f) (d) (else e g))"),
+                   "(if a b ((lambda (temp) (if temp (f temp) ((lambda (temp) (if temp temp (begin e g))) d))) c))");
+        assert_eq!(printed("(cond (a 1))"), "(if a 1)");
+        assert_eq!(printed("(case (f) ((1 2) 'a) ((x) => g) (else => h))"),
+                   "((lambda (key) (if (memv key (quote (1 2))) (quote a) \
+                    (if (memv key (quote (x))) (g key) (h key)))) (f))");
+    }
+
+    #[test]
+    fn temporaries_are_fresh() {
+        // The `temp` that `or` introduces is not the user's `temp`.
+        let expr = pipeline::parse("test", "(or a temp)".as_bytes()).next().unwrap().unwrap();
+        match expr.kind {
+            ExprKind::Call(ref operator, _) => match operator.kind {
+                ExprKind::Lambda(ref lambda) => assert_ne!(lambda.params[0].node, Symbol::intern("temp")),
+                _ => panic!("expected a lambda"),
+            },
+            _ => panic!("expected a call"),
+        }
+    }
+
+    #[test]
+    fn do_loops() {
+        assert_eq!(printed("(do ((i 0 (+ i 1)) (acc '())) ((= i 3) acc) (display i))"),
+                   "(((lambda () (define loop (lambda (i acc) (if (= i 3) acc (begin (display i) (loop (+ i 1) acc))))) \
+                    loop)) 0 (quote ()))");
+        assert_eq!(printed("(do () (#t))"),
+                   "(((lambda () (define loop (lambda () (if #t (if #f #f) (loop)))) loop)))");
+    }
+
+    #[test]
+    fn multiple_values() {
+        assert_eq!(printed("(receive (a . b) (f) b)"), "(call-with-values (lambda () (f)) (lambda (a . b) b))");
+        assert_eq!(printed("(let-values (((a b) (f)) (c (g))) (list a b c))"),
+                   "(call-with-values (lambda () (f)) (lambda (a b) \
+                    (call-with-values (lambda () (g)) (lambda c (list a b c)))))");
+        assert_eq!(printed("(let*-values (((a) (f))) a) (let-values () 1)"),
+                   "(call-with-values (lambda () (f)) (lambda (a) a)) ((lambda () 1))");
+        // The consumer's parameters are fresh, named after the variables
+        // they are assigned to.
+        assert_eq!(printed("(define-values (a b . c) (f))"),
+                   "(begin (define b (if #f #f)) (define c (if #f #f)) \
+                    (define a (call-with-values (lambda () (f)) (lambda (a b . c) (set! b b) (set! c c) a))))");
+        assert_eq!(printed("(define-values () (f))"), "(call-with-values (lambda () (f)) (lambda () (if #f #f)))");
+        assert_eq!(error("(let-values (((a b) (f)) ((a) (g))) a)"),
+                   (ErrorKind::DuplicateBinding(Symbol::intern("a")), "1:28-1:29".to_owned()));
+        assert_eq!(error("(let-values ((a)) a)"),
+                   (ErrorKind::MalformedForm(Symbol::LET_VALUES), "1:14-1:17".to_owned()));
+        assert_eq!(error("(receive (a))"), (ErrorKind::MalformedForm(Symbol::RECEIVE), "1:1-1:14".to_owned()));
+        assert_eq!(error("(define-values (a 1) (f))"), (ErrorKind::ExpectedIdentifier, "1:19-1:20".to_owned()));
+        assert_eq!(error("(f (define-values (a) (g)))"), (ErrorKind::DefinitionInExpression, "1:4-1:27".to_owned()));
+    }
+
+    #[test]
+    fn spans_point_at_the_derived_form() {
+        let expr = pipeline::parse("test", "\n  (let ((x 1))\n    x)".as_bytes()).next().unwrap().unwrap();
+        assert_eq!(format!("{}-{}", expr.span.start, expr.span.end), "2:3-3:7");
+        match expr.kind {
+            ExprKind::Call(ref operator, ref operands) => {
+                assert_eq!(format!("{}-{}", operator.span.start, operator.span.end), "2:3-3:7");
+                assert_eq!(format!("{}-{}", operands[0].span.start, operands[0].span.end), "2:12-2:13");
+            }
+            _ => panic!("expected a call"),
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(error("(let)"), (ErrorKind::MalformedForm(Symbol::LET), "1:1-1:6".to_owned()));
+        assert_eq!(error("(let ((x)) x)"), (ErrorKind::MalformedForm(Symbol::LET), "1:7-1:10".to_owned()));
+        assert_eq!(error("(let x)"), (ErrorKind::MalformedForm(Symbol::LET), "1:6-1:7".to_owned()));
+        assert_eq!(error("(let ((x 1)))"), (ErrorKind::EmptyBody, "1:1-1:14".to_owned()));
+        assert_eq!(error("(let ((x 1) (x 2)) x)"),
+                   (ErrorKind::DuplicateBinding(Symbol::intern("x")), "1:14-1:15".to_owned()));
+        assert_eq!(error("(letrec ((1 2)) 3)"), (ErrorKind::ExpectedIdentifier, "1:11-1:12".to_owned()));
+        assert_eq!(error("(cond)"), (ErrorKind::MalformedForm(Symbol::COND), "1:1-1:7".to_owned()));
+        assert_eq!(error("(cond (else 1) (a 2))"), (ErrorKind::MalformedForm(Symbol::COND), "1:7-1:15".to_owned()));
+        assert_eq!(error("(cond ())"), (ErrorKind::MalformedForm(Symbol::COND), "1:7-1:9".to_owned()));
+        assert_eq!(error("(case x (1 a))"), (ErrorKind::MalformedForm(Symbol::CASE), "1:9-1:14".to_owned()));
+        assert_eq!(error("(when a)"), (ErrorKind::MalformedForm(Symbol::WHEN), "1:1-1:9".to_owned()));
+        assert_eq!(error("(do ((i)) (#t))"), (ErrorKind::MalformedForm(Symbol::DO), "1:6-1:9".to_owned()));
+        assert_eq!(error("(do () ())"), (ErrorKind::MalformedForm(Symbol::DO), "1:8-1:10".to_owned()));
+        assert_eq!(error("(and (define x 1))"), (ErrorKind::DefinitionInExpression, "1:6-1:18".to_owned()));
+    }
+}
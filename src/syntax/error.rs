@@ -0,0 +1,76 @@
+use std::fmt;
+
+use reader::FIXNUMS;
+use symbol::Symbol;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// `()` where an expression was expected.
+    EmptyCombination,
+    /// A form written as a dotted list.
+    ImproperForm,
+    /// A special form with the wrong shape, named by its keyword.
+    MalformedForm(Symbol),
+    ExpectedIdentifier,
+    /// A `lambda` parameter list that is neither a list nor an identifier.
+    InvalidFormals,
+    /// A name bound twice by the same `lambda` or binding form.
+    DuplicateBinding(Symbol),
+    EmptyBody,
+    DefinitionInExpression,
+    /// A field a record type's constructor takes that isn't one of its
+    /// fields.
+    UnknownField(Symbol),
+    /// Datum labels outside of quoted data.
+    LabelInCode,
+    /// An exact integer literal outside of `FIXNUMS`.
+    IntegerOutOfRange,
+}
+
+/// How a special form is meant to be written, for `MalformedForm`.
+fn usage(keyword: Symbol) -> &'static str {
+    match keyword {
+        Symbol::LAMBDA => "(lambda formals body...)",
+        Symbol::IF => "(if test consequent [alternate])",
+        Symbol::SET => "(set! variable expression)",
+        Symbol::DEFINE => "(define variable expression) or (define (variable formals...) body...)",
+        Symbol::BEGIN => "(begin expression...)",
+        Symbol::QUOTE => "(quote datum)",
+        Symbol::LET => "(let [name] ((variable init)...) body...)",
+        Symbol::LET_STAR => "(let* ((variable init)...) body...)",
+        Symbol::LETREC => "(letrec ((variable init)...) body...)",
+        Symbol::LETREC_STAR => "(letrec* ((variable init)...) body...)",
+        Symbol::COND => "(cond (test expression...)... [(else expression...)])",
+        Symbol::CASE => "(case key ((datum...) expression...)... [(else expression...)])",
+        Symbol::WHEN => "(when test expression...)",
+        Symbol::UNLESS => "(unless test expression...)",
+        Symbol::DO => "(do ((variable init [step])...) (test expression...) command...)",
+        Symbol::LET_VALUES => "(let-values ((formals init)...) body...)",
+        Symbol::LET_STAR_VALUES => "(let*-values ((formals init)...) body...)",
+        Symbol::RECEIVE => "(receive formals expression body...)",
+        Symbol::DEFINE_VALUES => "(define-values formals expression)",
+        Symbol::DEFINE_RECORD_TYPE =>
+            "(define-record-type name (constructor field...) predicate (field accessor [modifier])...)",
+        _ => "",
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::EmptyCombination => f.write_str("Expected an expression, found '()'; use '() for the empty list"),
+            ErrorKind::ImproperForm => f.write_str("Expected a proper list, not a dotted one"),
+            ErrorKind::MalformedForm(keyword) => write!(f, "Malformed '{}', expected {}", keyword, usage(keyword)),
+            ErrorKind::ExpectedIdentifier => f.write_str("Expected an identifier"),
+            ErrorKind::InvalidFormals => f.write_str("Expected a parameter list or a single identifier"),
+            ErrorKind::DuplicateBinding(name) => write!(f, "'{}' is bound more than once in the same scope", name),
+            ErrorKind::EmptyBody => f.write_str("Expected at least one expression in the body"),
+            ErrorKind::DefinitionInExpression =>
+                f.write_str("Definitions are only allowed at top level or at the start of a body"),
+            ErrorKind::UnknownField(name) => write!(f, "'{}' is not a field of the record type", name),
+            ErrorKind::LabelInCode => f.write_str("Datum labels can only be used in quoted data"),
+            ErrorKind::IntegerOutOfRange =>
+                write!(f, "Integers must be between {} and {}", FIXNUMS.start(), FIXNUMS.end()),
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt;
+
+use reader::Datum;
+use span::{Span, Spanned};
+use symbol::Symbol;
+
+/// A core expression, with the span of the source it was parsed from.
+/// Everything else in the language is defined in terms of these.
+#[derive(Clone, Debug)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug)]
+pub enum ExprKind {
+    /// A self-evaluating literal: a boolean, number, string, character,
+    /// vector or bytevector.
+    Const(Datum),
+    Var(Symbol),
+    Lambda(Lambda),
+    /// `(if test consequent [alternate])`.
+    If(Box<Expr>, Box<Expr>, Option<Box<Expr>>),
+    Set(Spanned<Symbol>, Box<Expr>),
+    Define(Spanned<Symbol>, Box<Expr>),
+    DefineRecordType(RecordType),
+    Begin(Vec<Expr>),
+    Quote(Datum),
+    /// A procedure call, with the operator first.
+    Call(Box<Expr>, Vec<Expr>),
+}
+
+/// `(lambda (params... . rest) body...)`. The body is never empty.
+#[derive(Clone, Debug)]
+pub struct Lambda {
+    pub params: Vec<Spanned<Symbol>>,
+    pub rest: Option<Spanned<Symbol>>,
+    pub body: Vec<Expr>,
+}
+
+/// `(define-record-type name (constructor field...) predicate (field
+/// accessor [modifier])...)`.
+#[derive(Clone, Debug)]
+pub struct RecordType {
+    pub name: Spanned<Symbol>,
+    pub constructor: Spanned<Symbol>,
+    /// The fields the constructor takes, in order, by their index in
+    /// `fields`.
+    pub constructor_fields: Vec<usize>,
+    pub predicate: Spanned<Symbol>,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Field {
+    pub name: Spanned<Symbol>,
+    pub accessor: Spanned<Symbol>,
+    pub modifier: Option<Spanned<Symbol>>,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr {
+            kind,
+            span,
+        }
+    }
+}
+
+/// Prints the expression as the core form it stands for.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ExprKind::Const(ref datum) => write!(f, "{}", datum),
+            ExprKind::Var(name) => write!(f, "{}", name),
+            ExprKind::Lambda(ref lambda) => write!(f, "{}", lambda),
+            ExprKind::If(ref test, ref consequent, None) => write!(f, "(if {} {})", test, consequent),
+            ExprKind::If(ref test, ref consequent, Some(ref alternate)) =>
+                write!(f, "(if {} {} {})", test, consequent, alternate),
+            ExprKind::Set(ref name, ref value) => write!(f, "(set! {} {})", name.node, value),
+            ExprKind::Define(ref name, ref value) => write!(f, "(define {} {})", name.node, value),
+            ExprKind::DefineRecordType(ref record) => write!(f, "{}", record),
+            ExprKind::Begin(ref body) => {
+                f.write_str("(begin")?;
+                write_all(f, body)?;
+                f.write_str(")")
+            }
+            ExprKind::Quote(ref datum) => write!(f, "(quote {})", datum),
+            ExprKind::Call(ref operator, ref operands) => {
+                write!(f, "({}", operator)?;
+                write_all(f, operands)?;
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Lambda {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("(lambda ")?;
+        match (self.params.is_empty(), &self.rest) {
+            (true, Some(rest)) => write!(f, "{}", rest.node)?,
+            (_, rest) => {
+                let params: Vec<_> = self.params.iter().map(|p| p.node.to_string()).collect();
+                write!(f, "({}", params.join(" "))?;
+                if let Some(rest) = rest {
+                    write!(f, " . {}", rest.node)?;
+                }
+                f.write_str(")")?;
+            }
+        }
+        write_all(f, &self.body)?;
+        f.write_str(")")
+    }
+}
+
+impl RecordType {
+    /// The variables the definition binds: the type, its constructor and
+    /// predicate, then the accessor and any modifier of each field.
+    pub fn variables(&self) -> Vec<&Spanned<Symbol>> {
+        let mut variables = vec![&self.name, &self.constructor, &self.predicate];
+        for field in &self.fields {
+            variables.push(&field.accessor);
+            variables.extend(&field.modifier);
+        }
+        variables
+    }
+}
+
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(define-record-type {} ({}", self.name.node, self.constructor.node)?;
+        for &index in &self.constructor_fields {
+            write!(f, " {}", self.fields[index].name.node)?;
+        }
+        write!(f, ") {}", self.predicate.node)?;
+        for field in &self.fields {
+            write!(f, " ({} {}", field.name.node, field.accessor.node)?;
+            if let Some(ref modifier) = field.modifier {
+                write!(f, " {}", modifier.node)?;
+            }
+            f.write_str(")")?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// Writes each expression preceded by a space.
+fn write_all(f: &mut fmt::Formatter, exprs: &[Expr]) -> fmt::Result {
+    for expr in exprs {
+        write!(f, " {}", expr)?;
+    }
+    Ok(())
+}
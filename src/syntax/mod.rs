@@ -0,0 +1,351 @@
+mod derived;
+mod error;
+mod expr;
+mod record;
+
+use error::{Error, Result};
+use iter::StreamMap;
+use reader::{Datum, DatumKind, FIXNUMS};
+use span::{Span, Spanned};
+use symbol::Symbol;
+
+pub use self::error::*;
+pub use self::expr::*;
+
+/// Where a form appears, which decides whether it may be a definition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Context {
+    /// At top level or in a body, where definitions are allowed.
+    Definition,
+    Expression,
+}
+
+/// The parameters of a `lambda` parameter list, and its rest parameter if
+/// it has one.
+type Formals = (Vec<Spanned<Symbol>>, Option<Spanned<Symbol>>);
+
+/// Parses data into core expressions, checking the syntax of special forms.
+#[derive(Debug)]
+pub struct Parser<'a> {
+    file_name: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(file_name: &'a str) -> Self {
+        Parser {
+            file_name,
+        }
+    }
+
+    /// Parses a top-level form.
+    pub fn parse(&self, datum: &Datum) -> Result<Expr> {
+        self.form(datum, Context::Definition)
+    }
+
+    fn expr(&self, datum: &Datum) -> Result<Expr> {
+        self.form(datum, Context::Expression)
+    }
+
+    fn form(&self, datum: &Datum, context: Context) -> Result<Expr> {
+        let kind = match datum.kind {
+            DatumKind::Symbol(name) => ExprKind::Var(name),
+            DatumKind::Nil => return Err(self.error(datum.span, ErrorKind::EmptyCombination)),
+            DatumKind::Pair(ref head, _) => {
+                let items = match datum.items() {
+                    Some(items) => items,
+                    None => return Err(self.error(datum.span, ErrorKind::ImproperForm)),
+                };
+                match head.kind {
+                    DatumKind::Symbol(keyword) if is_keyword(keyword) =>
+                        return self.special_form(keyword, &items, datum.span, context),
+                    DatumKind::Symbol(keyword) if derived::is_keyword(keyword) =>
+                        return self.derived_form(keyword, &items, datum.span),
+                    _ => {
+                        let operator = self.expr(items[0])?;
+                        let operands = items[1..].iter().map(|d| self.expr(d)).collect::<Result<_>>()?;
+                        ExprKind::Call(Box::new(operator), operands)
+                    }
+                }
+            }
+            DatumKind::Labeled(..) | DatumKind::Reference(_) =>
+                return Err(self.error(datum.span, ErrorKind::LabelInCode)),
+            _ => {
+                self.check_integers(datum)?;
+                ExprKind::Const(datum.clone())
+            }
+        };
+        Ok(Expr::new(kind, datum.span))
+    }
+
+    /// Parses `items`, the elements of a form spanning `span` that starts
+    /// with `keyword`.
+    fn special_form(&self, keyword: Symbol, items: &[&Datum], span: Span, context: Context) -> Result<Expr> {
+        let malformed = || self.error(span, ErrorKind::MalformedForm(keyword));
+        let kind = match (keyword, items.len()) {
+            (Symbol::QUOTE, 2) => {
+                self.check_integers(items[1])?;
+                ExprKind::Quote(items[1].clone())
+            }
+            (Symbol::IF, 3) | (Symbol::IF, 4) => {
+                let alternate = match items.get(3) {
+                    Some(d) => Some(Box::new(self.expr(d)?)),
+                    None => None,
+                };
+                ExprKind::If(Box::new(self.expr(items[1])?), Box::new(self.expr(items[2])?), alternate)
+            }
+            (Symbol::SET, 3) => ExprKind::Set(self.identifier(items[1])?, Box::new(self.expr(items[2])?)),
+            (Symbol::LAMBDA, n) if n >= 2 => ExprKind::Lambda(self.lambda(items[1], &items[2..], span)?),
+            (Symbol::DEFINE, n) if n >= 2 => {
+                if context != Context::Definition {
+                    return Err(self.error(span, ErrorKind::DefinitionInExpression));
+                }
+                match items[1].kind {
+                    // `(define (name . formals) body...)`
+                    DatumKind::Pair(ref name, ref formals) => {
+                        let name = self.identifier(name)?;
+                        let lambda = Expr::new(ExprKind::Lambda(self.lambda(formals, &items[2..], span)?), span);
+                        ExprKind::Define(name, Box::new(lambda))
+                    }
+                    _ if n == 3 => ExprKind::Define(self.identifier(items[1])?, Box::new(self.expr(items[2])?)),
+                    _ => return Err(malformed()),
+                }
+            }
+            (Symbol::DEFINE_RECORD_TYPE, _) => {
+                if context != Context::Definition {
+                    return Err(self.error(span, ErrorKind::DefinitionInExpression));
+                }
+                ExprKind::DefineRecordType(self.record_type(items, span)?)
+            }
+            (Symbol::DEFINE_VALUES, 3) => {
+                if context != Context::Definition {
+                    return Err(self.error(span, ErrorKind::DefinitionInExpression));
+                }
+                return self.define_values(items, span);
+            }
+            (Symbol::BEGIN, n) if n > 1 || context == Context::Definition => {
+                let body = items[1..].iter().map(|d| self.form(d, context)).collect::<Result<_>>()?;
+                ExprKind::Begin(body)
+            }
+            _ => return Err(malformed()),
+        };
+        Ok(Expr::new(kind, span))
+    }
+
+    /// Parses the formals and body of a `lambda`, or of a `define` of a
+    /// procedure, that spans `span`.
+    fn lambda(&self, formals: &Datum, body: &[&Datum], span: Span) -> Result<Lambda> {
+        let (params, rest) = self.formals(formals)?;
+        Ok(Lambda {
+            params,
+            rest,
+            body: self.body(body, span)?,
+        })
+    }
+
+    /// Parses a `lambda` parameter list into its parameters and any rest
+    /// parameter.
+    fn formals(&self, formals: &Datum) -> Result<Formals> {
+        let mut params: Vec<Spanned<Symbol>> = Vec::new();
+        let mut formals = formals;
+        let rest = loop {
+            match formals.kind {
+                DatumKind::Nil => break None,
+                DatumKind::Symbol(name) => break Some(Spanned::new(name, formals.span)),
+                DatumKind::Pair(ref param, ref tail) => {
+                    params.push(self.identifier(param)?);
+                    formals = tail;
+                }
+                _ if params.is_empty() => return Err(self.error(formals.span, ErrorKind::InvalidFormals)),
+                _ => return Err(self.error(formals.span, ErrorKind::ExpectedIdentifier)),
+            }
+        };
+
+        self.check_distinct(params.iter().chain(&rest))?;
+        Ok((params, rest))
+    }
+
+    /// Parses the body of a form spanning `span`.
+    fn body(&self, body: &[&Datum], span: Span) -> Result<Vec<Expr>> {
+        if body.is_empty() {
+            return Err(self.error(span, ErrorKind::EmptyBody));
+        }
+        body.iter().map(|d| self.form(d, Context::Definition)).collect()
+    }
+
+    /// Checks that no name is bound twice in the same scope.
+    fn check_distinct<'b, I>(&self, names: I) -> Result<()>
+        where I: IntoIterator<Item=&'b Spanned<Symbol>> {
+        let mut seen: Vec<&Spanned<Symbol>> = Vec::new();
+        for name in names {
+            if let Some(first) = seen.iter().find(|s| s.node == name.node) {
+                return Err(self.error(name.span, ErrorKind::DuplicateBinding(name.node))
+                    .with_note(first.span, "it is first bound here"));
+            }
+            seen.push(name);
+        }
+        Ok(())
+    }
+
+    /// Checks that the integers in `datum`, a constant, are all in
+    /// `FIXNUMS`.
+    fn check_integers(&self, datum: &Datum) -> Result<()> {
+        let mut stack = vec![datum];
+        while let Some(datum) = stack.pop() {
+            match datum.kind {
+                DatumKind::Fixnum(n) if !FIXNUMS.contains(&n) =>
+                    return Err(self.error(datum.span, ErrorKind::IntegerOutOfRange)),
+                DatumKind::Pair(ref head, ref tail) => {
+                    stack.push(tail);
+                    stack.push(head);
+                }
+                DatumKind::Vector(ref items) => stack.extend(items),
+                DatumKind::Labeled(_, ref datum) => stack.push(datum),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn identifier(&self, datum: &Datum) -> Result<Spanned<Symbol>> {
+        match datum.kind {
+            DatumKind::Symbol(name) => Ok(Spanned::new(name, datum.span)),
+            _ => Err(self.error(datum.span, ErrorKind::ExpectedIdentifier)),
+        }
+    }
+
+    fn error(&self, span: Span, kind: ErrorKind) -> Error {
+        Error::new(self.file_name, span, kind)
+    }
+}
+
+fn is_keyword(name: Symbol) -> bool {
+    matches!(name, Symbol::QUOTE | Symbol::LAMBDA | Symbol::IF | Symbol::SET | Symbol::DEFINE | Symbol::BEGIN |
+                   Symbol::DEFINE_RECORD_TYPE | Symbol::DEFINE_VALUES)
+}
+
+impl<'a> StreamMap<Result<Datum>, Result<Expr>> for Parser<'a> {
+    fn produce(&mut self, datum: Result<Datum>, out: &mut impl Extend<Result<Expr>>) {
+        out.extend(Some(datum.and_then(|d| self.parse(&d))));
+    }
+
+    fn max_outputs(&self) -> Option<usize> {
+        Some(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error;
+    use pipeline;
+
+    fn parse(input: &str) -> Vec<error::Result<Expr>> {
+        pipeline::parse("test", input.as_bytes()).collect()
+    }
+
+    fn printed(input: &str) -> String {
+        parse(input).into_iter().map(|r| r.unwrap().to_string()).collect::<Vec<_>>().join(" ")
+    }
+
+    fn error(input: &str) -> (ErrorKind, String) {
+        match parse(input).into_iter().find(|r| r.is_err()).unwrap().unwrap_err() {
+            error::Error { kind: error::ErrorKind::Syntax(kind), span, .. } =>
+                (kind, format!("{}-{}", span.start, span.end)),
+            e => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn core_forms() {
+        assert_eq!(printed("x 1 \"s\" #(1 2) 'a '() (quote (1 . 2))"),
+                   "x 1 \"s\" #(1 2) (quote a) (quote ()) (quote (1 . 2))");
+        assert_eq!(printed("(if a b) (if a b c) (set! x (f 1)) (begin) (begin 1 2)"),
+                   "(if a b) (if a b c) (set! x (f 1)) (begin) (begin 1 2)");
+        assert_eq!(printed("((lambda (x) x) 1)"), "((lambda (x) x) 1)");
+    }
+
+    #[test]
+    fn lambdas_and_defines() {
+        assert_eq!(printed("(lambda args args) (lambda (a . b) a b) (lambda () 1)"),
+                   "(lambda args args) (lambda (a . b) a b) (lambda () 1)");
+        assert_eq!(printed("(define x 1) (define (f a . b) (define y a) y) (define (g) 2)"),
+                   "(define x 1) (define f (lambda (a . b) (define y a) y)) (define g (lambda () 2))");
+        assert_eq!(printed("(begin (define x 1) x)"), "(begin (define x 1) x)");
+    }
+
+    #[test]
+    fn record_types() {
+        assert_eq!(printed("(define-record-type point (make-point y x) point? (x point-x set-point-x!) (y point-y))"),
+                   "(define-record-type point (make-point y x) point? (x point-x set-point-x!) (y point-y))");
+        assert_eq!(printed("(define-record-type empty (make-empty) empty?)"),
+                   "(define-record-type empty (make-empty) empty?)");
+        assert_eq!(error("(define-record-type p (make-p z) p? (x p-x))"),
+                   (ErrorKind::UnknownField(Symbol::intern("z")), "1:31-1:32".to_owned()));
+        assert_eq!(error("(define-record-type p (make-p) p? (x p-x) (x p-y))"),
+                   (ErrorKind::DuplicateBinding(Symbol::intern("x")), "1:44-1:45".to_owned()));
+        assert_eq!(error("(define-record-type p (make-p) p? (x p?))"),
+                   (ErrorKind::DuplicateBinding(Symbol::intern("p?")), "1:38-1:40".to_owned()));
+        assert_eq!(error("(define-record-type p make-p p?)"),
+                   (ErrorKind::MalformedForm(Symbol::DEFINE_RECORD_TYPE), "1:23-1:29".to_owned()));
+        assert_eq!(error("(define-record-type p (make-p) p? (x))"),
+                   (ErrorKind::MalformedForm(Symbol::DEFINE_RECORD_TYPE), "1:35-1:38".to_owned()));
+        assert_eq!(error("(f (define-record-type p (make-p) p?))"),
+                   (ErrorKind::DefinitionInExpression, "1:4-1:38".to_owned()));
+    }
+
+    #[test]
+    fn spans() {
+        let exprs: Vec<_> = parse("(if a\n  (f b))").into_iter().map(|r| r.unwrap()).collect();
+        let span = |e: &Expr| format!("{}-{}", e.span.start, e.span.end);
+        assert_eq!(span(&exprs[0]), "1:1-2:9");
+        match exprs[0].kind {
+            ExprKind::If(ref test, ref consequent, None) => {
+                assert_eq!(span(test), "1:5-1:6");
+                assert_eq!(span(consequent), "2:3-2:8");
+            }
+            _ => panic!("expected an if"),
+        }
+    }
+
+    #[test]
+    fn malformed_forms() {
+        assert_eq!(error("(if)"), (ErrorKind::MalformedForm(Symbol::IF), "1:1-1:5".to_owned()));
+        assert_eq!(error("(if 1 2 3 4)"), (ErrorKind::MalformedForm(Symbol::IF), "1:1-1:13".to_owned()));
+        assert_eq!(error("(quote)"), (ErrorKind::MalformedForm(Symbol::QUOTE), "1:1-1:8".to_owned()));
+        assert_eq!(error("(set! x)"), (ErrorKind::MalformedForm(Symbol::SET), "1:1-1:9".to_owned()));
+        assert_eq!(error("(set! 1 2)"), (ErrorKind::ExpectedIdentifier, "1:7-1:8".to_owned()));
+        assert_eq!(error("(define x 1 2)"), (ErrorKind::MalformedForm(Symbol::DEFINE), "1:1-1:15".to_owned()));
+        assert_eq!(error("(define (1) 2)"), (ErrorKind::ExpectedIdentifier, "1:10-1:11".to_owned()));
+        assert_eq!(error("(f (begin))"), (ErrorKind::MalformedForm(Symbol::BEGIN), "1:4-1:11".to_owned()));
+        assert_eq!(error("(f (define x 1))"), (ErrorKind::DefinitionInExpression, "1:4-1:16".to_owned()));
+        assert_eq!(error("(if (begin (define x 1)) 2)"),
+                   (ErrorKind::DefinitionInExpression, "1:12-1:24".to_owned()));
+        assert_eq!(error("()"), (ErrorKind::EmptyCombination, "1:1-1:3".to_owned()));
+        assert_eq!(error("(f . x)"), (ErrorKind::ImproperForm, "1:1-1:8".to_owned()));
+        assert_eq!(error("#0=(f)"), (ErrorKind::LabelInCode, "1:1-1:7".to_owned()));
+        assert_eq!(error("(f 1152921504606846976)"), (ErrorKind::IntegerOutOfRange, "1:4-1:23".to_owned()));
+        assert_eq!(error("'(1 #(-1152921504606846977))"), (ErrorKind::IntegerOutOfRange, "1:7-1:27".to_owned()));
+        assert_eq!(error("(case x ((1 4611686018427387904) 2))"),
+                   (ErrorKind::IntegerOutOfRange, "1:13-1:32".to_owned()));
+    }
+
+    #[test]
+    fn malformed_lambdas() {
+        assert_eq!(error("(lambda 1 x)"), (ErrorKind::InvalidFormals, "1:9-1:10".to_owned()));
+        assert_eq!(error("(lambda (a 1) x)"), (ErrorKind::ExpectedIdentifier, "1:12-1:13".to_owned()));
+        assert_eq!(error("(lambda (a . 1) x)"), (ErrorKind::ExpectedIdentifier, "1:14-1:15".to_owned()));
+        assert_eq!(error("(lambda (x))"), (ErrorKind::EmptyBody, "1:1-1:13".to_owned()));
+        assert_eq!(error("(lambda)"), (ErrorKind::MalformedForm(Symbol::LAMBDA), "1:1-1:9".to_owned()));
+        assert_eq!(error("(lambda (a b a) x)"),
+                   (ErrorKind::DuplicateBinding(Symbol::intern("a")), "1:14-1:15".to_owned()));
+        assert_eq!(error("(define (f a . a) x)"),
+                   (ErrorKind::DuplicateBinding(Symbol::intern("a")), "1:16-1:17".to_owned()));
+    }
+
+    #[test]
+    fn duplicate_parameter_note() {
+        let e = parse("(lambda (x y\n         x) 1)").pop().unwrap().unwrap_err();
+        assert_eq!(e.to_string(), "Error (test:2:10): 'x' is bound more than once in the same scope\n  \
+                                   test:1:10: it is first bound here");
+    }
+}
@@ -0,0 +1,56 @@
+//! `define-record-type`, which defines a new type of record along with the
+//! procedures that make records of it, recognise them, and get and set
+//! their fields.
+
+use error::Result;
+use reader::Datum;
+use span::Span;
+use symbol::Symbol;
+
+use super::{ErrorKind, Field, Parser, RecordType};
+
+impl<'a> Parser<'a> {
+    /// Parses the `define-record-type` spanning `span` whose elements are
+    /// `items`.
+    pub(super) fn record_type(&self, items: &[&Datum], span: Span) -> Result<RecordType> {
+        let malformed = |d: &Datum| self.error(d.span, ErrorKind::MalformedForm(Symbol::DEFINE_RECORD_TYPE));
+        if items.len() < 4 {
+            return Err(self.error(span, ErrorKind::MalformedForm(Symbol::DEFINE_RECORD_TYPE)));
+        }
+        let name = self.identifier(items[1])?;
+        let constructor = match items[2].items() {
+            Some(ref constructor) if !constructor.is_empty() => constructor.clone(),
+            _ => return Err(malformed(items[2])),
+        };
+        let arguments = constructor[1..].iter().map(|d| self.identifier(d)).collect::<Result<Vec<_>>>()?;
+        let predicate = self.identifier(items[3])?;
+
+        let mut fields = Vec::new();
+        for spec in &items[4..] {
+            match spec.items() {
+                Some(ref spec) if spec.len() == 2 || spec.len() == 3 => fields.push(Field {
+                    name: self.identifier(spec[0])?,
+                    accessor: self.identifier(spec[1])?,
+                    modifier: spec.get(2).map(|d| self.identifier(d)).transpose()?,
+                }),
+                _ => return Err(malformed(spec)),
+            }
+        }
+        self.check_distinct(fields.iter().map(|f| &f.name))?;
+        self.check_distinct(&arguments)?;
+        let constructor_fields = arguments.iter().map(|argument| {
+            fields.iter().position(|f| f.name.node == argument.node)
+                .ok_or_else(|| self.error(argument.span, ErrorKind::UnknownField(argument.node)))
+        }).collect::<Result<_>>()?;
+
+        let record = RecordType {
+            name,
+            constructor: self.identifier(constructor[0])?,
+            constructor_fields,
+            predicate,
+            fields,
+        };
+        self.check_distinct(record.variables())?;
+        Ok(record)
+    }
+}
@@ -0,0 +1,399 @@
+//! The binary format of WebAssembly modules, for just the instructions and
+//! sections the code generator uses.
+
+/// The most 64 KiB pages memory can grow to, which keeps every address and
+/// every address past the end of an object within an `i32`.
+pub const MAX_MEMORY_PAGES: u32 = 1 << 15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ValType {
+    I32,
+    I64,
+    F64,
+}
+
+impl ValType {
+    fn byte(self) -> u8 {
+        match self {
+            ValType::I32 => 0x7f,
+            ValType::I64 => 0x7e,
+            ValType::F64 => 0x7c,
+        }
+    }
+}
+
+/// The type of what a block leaves on the stack.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockType {
+    Empty,
+    Value(ValType),
+}
+
+/// An instruction. Memory accesses take the offset added to the address.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instr {
+    Unreachable,
+    Block(BlockType),
+    Loop(BlockType),
+    If(BlockType),
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    Return,
+    Call(u32),
+    /// A call through the table, by the index of the function's type.
+    CallIndirect(u32),
+    ReturnCall(u32),
+    /// A tail call through the table, by the index of the function's type.
+    ReturnCallIndirect(u32),
+    Drop,
+    Select,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    I32Load(u32),
+    I64Load(u32),
+    F64Load(u32),
+    I32Load8U(u32),
+    I32Store(u32),
+    I64Store(u32),
+    MemorySize,
+    MemoryGrow,
+    I32Const(i32),
+    I64Const(i64),
+    I32Eqz,
+    I32Eq,
+    I32Ne,
+    I32LeU,
+    I64Eqz,
+    I64Eq,
+    I64Ne,
+    I64LtS,
+    I64LtU,
+    I64GtS,
+    I64LeS,
+    I64GeS,
+    F64Eq,
+    I32Add,
+    I32Sub,
+    I32Mul,
+    I32And,
+    I32Or,
+    I32Shl,
+    I32ShrU,
+    I64Add,
+    I64Sub,
+    I64Mul,
+    I64DivS,
+    I64RemS,
+    I64And,
+    I64Or,
+    I64Xor,
+    I64Shl,
+    I64ShrS,
+    I64ShrU,
+    F64Trunc,
+    I32WrapI64,
+    I64ExtendI32U,
+}
+
+impl Instr {
+    fn encode(self, out: &mut Vec<u8>) {
+        use self::Instr::*;
+        let (opcode, immediate) = match self {
+            Unreachable => (0x00, None),
+            Block(t) => return block(out, 0x02, t),
+            Loop(t) => return block(out, 0x03, t),
+            If(t) => return block(out, 0x04, t),
+            Else => (0x05, None),
+            End => (0x0b, None),
+            Br(depth) => (0x0c, Some(depth)),
+            BrIf(depth) => (0x0d, Some(depth)),
+            Return => (0x0f, None),
+            Call(f) => (0x10, Some(f)),
+            CallIndirect(t) => {
+                out.push(0x11);
+                unsigned(out, t as u64);
+                return out.push(0x00);
+            }
+            ReturnCall(f) => (0x12, Some(f)),
+            ReturnCallIndirect(t) => {
+                out.push(0x13);
+                unsigned(out, t as u64);
+                return out.push(0x00);
+            }
+            Drop => (0x1a, None),
+            Select => (0x1b, None),
+            LocalGet(i) => (0x20, Some(i)),
+            LocalSet(i) => (0x21, Some(i)),
+            LocalTee(i) => (0x22, Some(i)),
+            GlobalGet(i) => (0x23, Some(i)),
+            GlobalSet(i) => (0x24, Some(i)),
+            I32Load(offset) => return memory(out, 0x28, 2, offset),
+            I64Load(offset) => return memory(out, 0x29, 3, offset),
+            F64Load(offset) => return memory(out, 0x2b, 3, offset),
+            I32Load8U(offset) => return memory(out, 0x2d, 0, offset),
+            I32Store(offset) => return memory(out, 0x36, 2, offset),
+            I64Store(offset) => return memory(out, 0x37, 3, offset),
+            MemorySize => return out.extend(&[0x3f, 0x00]),
+            MemoryGrow => return out.extend(&[0x40, 0x00]),
+            I32Const(n) => {
+                out.push(0x41);
+                return signed(out, n as i64);
+            }
+            I64Const(n) => {
+                out.push(0x42);
+                return signed(out, n);
+            }
+            I32Eqz => (0x45, None),
+            I32Eq => (0x46, None),
+            I32Ne => (0x47, None),
+            I32LeU => (0x4d, None),
+            I64Eqz => (0x50, None),
+            I64Eq => (0x51, None),
+            I64Ne => (0x52, None),
+            I64LtS => (0x53, None),
+            I64LtU => (0x54, None),
+            I64GtS => (0x55, None),
+            I64LeS => (0x57, None),
+            I64GeS => (0x59, None),
+            F64Eq => (0x61, None),
+            I32Add => (0x6a, None),
+            I32Sub => (0x6b, None),
+            I32Mul => (0x6c, None),
+            I32And => (0x71, None),
+            I32Or => (0x72, None),
+            I32Shl => (0x74, None),
+            I32ShrU => (0x76, None),
+            I64Add => (0x7c, None),
+            I64Sub => (0x7d, None),
+            I64Mul => (0x7e, None),
+            I64DivS => (0x7f, None),
+            I64RemS => (0x81, None),
+            I64And => (0x83, None),
+            I64Or => (0x84, None),
+            I64Xor => (0x85, None),
+            I64Shl => (0x86, None),
+            I64ShrS => (0x87, None),
+            I64ShrU => (0x88, None),
+            F64Trunc => (0x9d, None),
+            I32WrapI64 => (0xa7, None),
+            I64ExtendI32U => (0xad, None),
+        };
+        out.push(opcode);
+        if let Some(immediate) = immediate {
+            unsigned(out, immediate as u64);
+        }
+    }
+}
+
+fn block(out: &mut Vec<u8>, opcode: u8, t: BlockType) {
+    out.push(opcode);
+    match t {
+        BlockType::Empty => out.push(0x40),
+        BlockType::Value(t) => out.push(t.byte()),
+    }
+}
+
+/// A load or store, aligned to `2^align` bytes.
+fn memory(out: &mut Vec<u8>, opcode: u8, align: u32, offset: u32) {
+    out.push(opcode);
+    unsigned(out, align as u64);
+    unsigned(out, offset as u64);
+}
+
+pub fn unsigned(out: &mut Vec<u8>, mut n: u64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            return out.push(byte);
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+pub fn signed(out: &mut Vec<u8>, mut n: i64) {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if (n == 0 && byte & 0x40 == 0) || (n == -1 && byte & 0x40 != 0) {
+            return out.push(byte);
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Function {
+    pub type_index: u32,
+    /// The types of the locals after the parameters.
+    pub locals: Vec<ValType>,
+    pub body: Vec<Instr>,
+}
+
+/// A module with one memory, one table of functions and mutable `i64`
+/// globals, which imports only functions from `env`.
+#[derive(Clone, Debug, Default)]
+pub struct Module {
+    pub types: Vec<FuncType>,
+    /// The name and type index of each imported function, which come first
+    /// in the function index space.
+    pub imports: Vec<(&'static str, u32)>,
+    pub functions: Vec<Function>,
+    /// The functions in the table, by function index.
+    pub table: Vec<u32>,
+    /// The number of 64 KiB pages of memory at the start, which can grow to
+    /// `MAX_MEMORY_PAGES`.
+    pub memory_pages: u32,
+    /// The initial value of each global.
+    pub globals: Vec<i64>,
+    /// The exported functions, by name and function index. The memory is
+    /// exported as `memory`.
+    pub exports: Vec<(&'static str, u32)>,
+    /// The exported globals, by name and global index.
+    pub global_exports: Vec<(&'static str, u32)>,
+    /// Bytes put in memory at address 0.
+    pub data: Vec<u8>,
+}
+
+impl Module {
+    /// The index of the function type, added if it is new.
+    pub fn func_type(&mut self, params: Vec<ValType>, results: Vec<ValType>) -> u32 {
+        let t = FuncType {
+            params,
+            results,
+        };
+        match self.types.iter().position(|u| *u == t) {
+            Some(index) => index as u32,
+            None => {
+                self.types.push(t);
+                self.types.len() as u32 - 1
+            }
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = b"\0asm\x01\0\0\0".to_vec();
+        section(&mut out, 1, &self.types, |out, t| {
+            out.push(0x60);
+            vector(out, &t.params, |out, p| out.push(p.byte()));
+            vector(out, &t.results, |out, r| out.push(r.byte()));
+        });
+        section(&mut out, 2, &self.imports, |out, &(name, type_index)| {
+            string(out, "env");
+            string(out, name);
+            out.push(0x00);
+            unsigned(out, type_index as u64);
+        });
+        section(&mut out, 3, &self.functions, |out, f| unsigned(out, f.type_index as u64));
+        section(&mut out, 4, &[self.table.len()], |out, &size| {
+            out.extend(&[0x70, 0x00]);
+            unsigned(out, size as u64);
+        });
+        section(&mut out, 5, &[self.memory_pages], |out, &pages| {
+            out.push(0x01);
+            unsigned(out, pages as u64);
+            unsigned(out, MAX_MEMORY_PAGES as u64);
+        });
+        section(&mut out, 6, &self.globals, |out, &value| {
+            out.extend(&[ValType::I64.byte(), 0x01]);
+            Instr::I64Const(value).encode(out);
+            Instr::End.encode(out);
+        });
+        let mut exports: Vec<_> = self.exports.iter().map(|&(name, f)| (name, 0x00, f)).collect();
+        exports.push(("memory", 0x02, 0));
+        exports.extend(self.global_exports.iter().map(|&(name, g)| (name, 0x03, g)));
+        section(&mut out, 7, &exports, |out, &(name, kind, index)| {
+            string(out, name);
+            out.push(kind);
+            unsigned(out, index as u64);
+        });
+        section(&mut out, 9, &[&self.table], |out, table| {
+            out.push(0x00);
+            Instr::I32Const(0).encode(out);
+            Instr::End.encode(out);
+            vector(out, table, |out, &f| unsigned(out, f as u64));
+        });
+        section(&mut out, 10, &self.functions, |out, f| {
+            let mut body = Vec::new();
+            // Runs of locals of the same type are declared together.
+            let mut runs: Vec<(u32, ValType)> = Vec::new();
+            for &t in &f.locals {
+                match runs.last_mut() {
+                    Some(run) if run.1 == t => run.0 += 1,
+                    _ => runs.push((1, t)),
+                }
+            }
+            vector(&mut body, &runs, |out, &(count, t)| {
+                unsigned(out, count as u64);
+                out.push(t.byte());
+            });
+            for &instr in &f.body {
+                instr.encode(&mut body);
+            }
+            Instr::End.encode(&mut body);
+            unsigned(out, body.len() as u64);
+            out.extend(body);
+        });
+        section(&mut out, 11, &[&self.data], |out, data| {
+            out.push(0x00);
+            Instr::I32Const(0).encode(out);
+            Instr::End.encode(out);
+            unsigned(out, data.len() as u64);
+            out.extend(data.iter());
+        });
+        out
+    }
+}
+
+fn section<T, F: Fn(&mut Vec<u8>, &T)>(out: &mut Vec<u8>, id: u8, items: &[T], item: F) {
+    let mut contents = Vec::new();
+    vector(&mut contents, items, item);
+    out.push(id);
+    unsigned(out, contents.len() as u64);
+    out.extend(contents);
+}
+
+fn vector<T, F: Fn(&mut Vec<u8>, &T)>(out: &mut Vec<u8>, items: &[T], item: F) {
+    unsigned(out, items.len() as u64);
+    for i in items {
+        item(out, i);
+    }
+}
+
+fn string(out: &mut Vec<u8>, s: &str) {
+    unsigned(out, s.len() as u64);
+    out.extend(s.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leb(f: fn(&mut Vec<u8>, i64), n: i64) -> Vec<u8> {
+        let mut out = Vec::new();
+        f(&mut out, n);
+        out
+    }
+
+    #[test]
+    fn leb128() {
+        let u = |out: &mut Vec<u8>, n: i64| unsigned(out, n as u64);
+        assert_eq!(leb(u, 0), [0x00]);
+        assert_eq!(leb(u, 624485), [0xe5, 0x8e, 0x26]);
+        assert_eq!(leb(signed, -1), [0x7f]);
+        assert_eq!(leb(signed, 63), [0x3f]);
+        assert_eq!(leb(signed, 64), [0xc0, 0x00]);
+        assert_eq!(leb(signed, -123456), [0xc0, 0xbb, 0x78]);
+    }
+}
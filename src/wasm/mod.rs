@@ -0,0 +1,969 @@
+//! Code generation from the A-normal form to a WebAssembly module. Every
+//! function of the program becomes a function in the module's table, and
+//! every call in it a tail call, so the stack stays the same height however
+//! long a program runs: a call to a closure tail-calls the dispatcher for
+//! its number of arguments, which checks the closure's arity and tail-calls
+//! its function through the table, making a list of the arguments for a rest
+//! parameter if it has one. For engines without tail calls, a trampoline
+//! does the same with plain calls and returns; see `Target`.
+//!
+//! Values are `i64`s, told apart by their low three bits:
+//!
+//! - `000`: a fixnum, in the bits above them.
+//! - `001`: a pointer to an object on the heap, 8-byte aligned, whose first
+//!   word is a header: its type in the low byte and its length above that.
+//! - `010`: a character, by its code point in the bits above them.
+//! - `011`: `#f`, `#t`, `'()`, the unspecified value or the unassigned one,
+//!   by a number in the bits above them.
+//!
+//! Constants, including closures that capture nothing, are laid out in
+//! memory ahead of the heap, which grows upward and is never collected. The
+//! module imports `write_char`, `error`, `exit` and `write_flonum` from
+//! `env` and exports `main`, which runs the top-level forms in order, its
+//! `memory` and `heap_pointer`, the global holding where the heap ends.
+
+mod encode;
+pub mod run;
+mod runtime;
+
+use std::collections::HashMap;
+
+use anf::{Atom, Block, Function, Local, Program, Stmt, Tail, Value};
+use primitive::Primitive;
+use reader::{Datum, DatumKind};
+use symbol::Symbol;
+
+use self::encode::{BlockType, Instr, Module, ValType};
+use self::runtime::Helper;
+
+const FALSE: i64 = 3;
+const TRUE: i64 = 1 << 3 | 3;
+const NIL: i64 = 2 << 3 | 3;
+const UNSPECIFIED: i64 = 3 << 3 | 3;
+const UNASSIGNED: i64 = 4 << 3 | 3;
+
+const PAIR: i64 = 1;
+const FLONUM: i64 = 2;
+const STRING: i64 = 3;
+const SYMBOL: i64 = 4;
+const VECTOR: i64 = 5;
+const BYTEVECTOR: i64 = 6;
+const CLOSURE: i64 = 7;
+const BOX: i64 = 8;
+const RECORD: i64 = 9;
+const RECORD_TYPE: i64 = 10;
+
+/// The imported functions, which come first in the function index space.
+const WRITE_CHAR: u32 = 0;
+const ERROR: u32 = 1;
+const EXIT: u32 = 2;
+const WRITE_FLONUM: u32 = 3;
+const IMPORTS: u32 = 4;
+
+/// The global holding the address of the next free byte on the heap.
+const HEAP_POINTER: u32 = 0;
+
+/// The registers of the trampoline: the globals holding the closure of the
+/// call to make next, or 0 if there is none, how many arguments it has and
+/// the first of them, which the rest follow.
+const CALLEE: u32 = 1;
+const ARGUMENT_COUNT: u32 = 2;
+const ARGUMENTS: u32 = 3;
+
+/// Dispatchers are made for calls with up to at least this many arguments,
+/// which the procedures made for primitives call with.
+const MIN_DISPATCHERS: usize = 3;
+
+/// How calls in tail position are compiled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// With `return_call` and `return_call_indirect`, from the tail call
+    /// proposal.
+    TailCalls,
+    /// For engines without them: a function leaves the closure and
+    /// arguments of its tail call in the registers and returns to a driver
+    /// loop, which makes the call. A tail call to the function's own closure
+    /// with all its arguments is a loop in the function instead.
+    Trampoline,
+}
+
+impl Target {
+    pub const ALL: &'static [Target] = &[Target::TailCalls, Target::Trampoline];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::TailCalls => "tail-calls",
+            Target::Trampoline => "trampoline",
+        }
+    }
+
+    pub fn named(name: &str) -> Option<Target> {
+        Target::ALL.iter().cloned().find(|t| t.name() == name)
+    }
+}
+
+/// Compiles `program` to the bytes of a module for `target`.
+pub fn compile(program: &Program, target: Target) -> Vec<u8> {
+    let max_arguments = program.functions.iter().map(|f| max_arguments(&f.body)).max().unwrap_or(0);
+    let arities = program.functions.iter().map(|f| (f.params - 1, f.rest)).collect();
+    let mut generator = Generator::new(target, arities, max_arguments.max(MIN_DISPATCHERS));
+    generator.generate(program)
+}
+
+/// The most arguments any call in `block` passes, the closure aside.
+fn max_arguments(block: &Block) -> usize {
+    match block.tail {
+        Tail::Call(_, ref args) => args.len(),
+        Tail::If(_, ref consequent, ref alternate) => max_arguments(consequent).max(max_arguments(alternate)),
+    }
+}
+
+/// A procedure in the table that isn't one of the program's functions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Extra {
+    /// The continuation of each top-level form, which returns from `main`
+    /// to run the next, whatever values it is passed.
+    Halt,
+    /// A continuation captured by `call-with-current-continuation`, as a
+    /// procedure, which calls the continuation in its closure.
+    Escape,
+    /// The continuation `call-with-values` passes its producer when the
+    /// consumer isn't known, which calls the consumer in its closure with
+    /// the continuation there and the values it is passed.
+    Receiver,
+    /// A primitive used as a value rather than called.
+    Primitive(Primitive),
+}
+
+impl Extra {
+    /// How many arguments it takes besides the closure, and whether it
+    /// takes a rest list after them.
+    fn arity(self) -> (usize, bool) {
+        match self {
+            Extra::Halt => (0, true),
+            Extra::Escape => (2, false),
+            Extra::Receiver => (0, true),
+            Extra::Primitive(p) => runtime::wrapper_arity(p),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Generator {
+    target: Target,
+    module: Module,
+    data: Data,
+    dispatchers: usize,
+    /// The extra procedures, in the order of their table indices, which
+    /// come after the program's functions.
+    extras: Vec<Extra>,
+    /// How many arguments each of the program's functions takes besides the
+    /// closure, and whether it takes a rest list after them.
+    arities: Vec<(usize, bool)>,
+    globals: HashMap<Symbol, u32>,
+    /// The closures of table entries that capture nothing, by their index.
+    static_closures: HashMap<u32, i64>,
+}
+
+impl Generator {
+    fn new(target: Target, arities: Vec<(usize, bool)>, max_arguments: usize) -> Self {
+        let mut module = Module::default();
+        let write_char = module.func_type(vec![ValType::I32], vec![]);
+        let error = module.func_type(vec![ValType::I64; 2], vec![]);
+        let exit = module.func_type(vec![ValType::I32], vec![]);
+        let write_flonum = module.func_type(vec![ValType::F64], vec![]);
+        module.imports = vec![("write_char", write_char), ("error", error), ("exit", exit),
+                              ("write_flonum", write_flonum)];
+        module.globals.push(0);
+        if target == Target::Trampoline {
+            module.globals.extend(vec![0; 2 + max_arguments]);
+        }
+        Generator {
+            target,
+            module,
+            data: Data::new(),
+            dispatchers: max_arguments + 1,
+            extras: Vec::new(),
+            arities,
+            globals: HashMap::new(),
+            static_closures: HashMap::new(),
+        }
+    }
+
+    fn generate(&mut self, program: &Program) -> Vec<u8> {
+        let mut procedures: Vec<_> = program.functions.iter().map(|f| self.function(f)).collect();
+        let halt = self.extra_closure(Extra::Halt);
+        let mut next = 0;
+        while next < self.extras.len() {
+            let extra = self.extras[next];
+            procedures.push(self.extra(extra));
+            next += 1;
+        }
+
+        let mut functions = Vec::new();
+        for &helper in Helper::ALL {
+            functions.push(self.helper(helper));
+        }
+        for &p in Primitive::ALL {
+            functions.push(self.primitive(p));
+        }
+        for arguments in 0..self.dispatchers {
+            functions.push(self.dispatcher(arguments));
+        }
+        if self.target == Target::Trampoline {
+            functions.push(self.driver());
+        }
+        let base = IMPORTS + functions.len() as u32;
+        self.module.table = (0..procedures.len() as u32).map(|i| base + i).collect();
+        functions.extend(procedures);
+        functions.push(self.main(&program.main, halt));
+        self.module.exports.push(("main", IMPORTS + functions.len() as u32 - 1));
+        self.module.global_exports.push(("heap_pointer", HEAP_POINTER));
+        self.module.functions = functions;
+
+        let heap = self.data.bytes.len() as i64;
+        self.module.globals[HEAP_POINTER as usize] = heap;
+        self.module.memory_pages = (heap as u32 >> 16) + 1;
+        self.module.data = self.data.bytes.clone();
+        self.module.encode()
+    }
+
+    /// The index of the type of a function taking `params` values.
+    fn procedure_type(&mut self, params: usize) -> u32 {
+        self.module.func_type(vec![ValType::I64; params], vec![])
+    }
+
+    fn helper_index(&self, helper: Helper) -> u32 {
+        IMPORTS + helper as u32
+    }
+
+    fn primitive_index(&self, p: Primitive) -> u32 {
+        let position = Primitive::ALL.iter().position(|&q| q == p).expect("every primitive is in `ALL`");
+        IMPORTS + Helper::ALL.len() as u32 + position as u32
+    }
+
+    /// The dispatcher for calls with `arguments` arguments besides the
+    /// closure.
+    fn dispatcher_index(&self, arguments: usize) -> u32 {
+        assert!(arguments < self.dispatchers, "there is a dispatcher for every call");
+        IMPORTS + (Helper::ALL.len() + Primitive::ALL.len() + arguments) as u32
+    }
+
+    /// The driver loop of the trampoline, which comes after the dispatchers.
+    fn driver_index(&self) -> u32 {
+        IMPORTS + (Helper::ALL.len() + Primitive::ALL.len() + self.dispatchers) as u32
+    }
+
+    /// Instructions that call the closure under `arguments` arguments on the
+    /// stack in tail position.
+    fn tail_call(&self, arguments: usize) -> Vec<Instr> {
+        match self.target {
+            Target::TailCalls => vec![Instr::ReturnCall(self.dispatcher_index(arguments))],
+            Target::Trampoline => {
+                let mut instrs = self.schedule(arguments);
+                instrs.push(Instr::Return);
+                instrs
+            }
+        }
+    }
+
+    /// Instructions that move the closure under `arguments` arguments on the
+    /// stack, and them, to the registers of the trampoline.
+    fn schedule(&self, arguments: usize) -> Vec<Instr> {
+        let mut instrs: Vec<_> = (0..arguments as u32).rev().map(|i| Instr::GlobalSet(ARGUMENTS + i)).collect();
+        instrs.extend(&[Instr::GlobalSet(CALLEE), Instr::I64Const(arguments as i64), Instr::GlobalSet(ARGUMENT_COUNT)]);
+        instrs
+    }
+
+    /// Instructions that call the function with the table index on top of
+    /// the stack, of type `type_index`, with the values under it, in tail
+    /// position.
+    fn call_indirect(&self, type_index: u32) -> Vec<Instr> {
+        match self.target {
+            Target::TailCalls => vec![Instr::ReturnCallIndirect(type_index)],
+            Target::Trampoline => vec![Instr::CallIndirect(type_index), Instr::Return],
+        }
+    }
+
+    /// The table index of `extra`, which is added if it is new.
+    fn extra_index(&mut self, extra: Extra) -> u32 {
+        let position = match self.extras.iter().position(|&e| e == extra) {
+            Some(position) => position,
+            None => {
+                self.extras.push(extra);
+                self.extras.len() - 1
+            }
+        };
+        (self.arities.len() + position) as u32
+    }
+
+    /// The word after a closure's header: the table index of its function
+    /// and how many arguments it takes besides the closure, doubled, plus
+    /// one if it takes a rest list after them.
+    fn code(table_index: u32, required: usize, rest: bool) -> i64 {
+        (table_index as i64) << 32 | (required as i64) << 1 | rest as i64
+    }
+
+    fn static_closure(&mut self, table_index: u32, code: i64) -> i64 {
+        if let Some(&closure) = self.static_closures.get(&table_index) {
+            return closure;
+        }
+        let closure = self.data.object(&[CLOSURE, code]);
+        self.static_closures.insert(table_index, closure);
+        closure
+    }
+
+    fn extra_closure(&mut self, extra: Extra) -> i64 {
+        let index = self.extra_index(extra);
+        let (required, rest) = extra.arity();
+        self.static_closure(index, Generator::code(index, required, rest))
+    }
+
+    fn global(&mut self, name: Symbol) -> u32 {
+        let next = self.module.globals.len() as u32;
+        let index = *self.globals.entry(name).or_insert(next);
+        if index == next {
+            self.module.globals.push(UNASSIGNED);
+        }
+        index
+    }
+
+    fn function(&mut self, function: &Function) -> encode::Function {
+        let params = function.params + function.rest as usize;
+        let type_index = self.procedure_type(params);
+        // One more local holds objects while they are filled in.
+        let locals = vec![ValType::I64; function.locals.len() - params + 1];
+        let mut body = Vec::new();
+        let loops = self.target == Target::Trampoline && !function.rest
+            && !assigns(&function.body, Local(0));
+        if loops {
+            body.push(Instr::Loop(BlockType::Empty));
+        }
+        Code {
+            generator: self,
+            params: if loops { function.params } else { 0 },
+            depth: 0,
+            scratch: function.locals.len() as u32,
+            body: &mut body,
+        }.block(&function.body);
+        if loops {
+            body.push(Instr::End);
+        }
+        encode::Function {
+            type_index,
+            locals,
+            body,
+        }
+    }
+
+    /// `main`, which runs the functions of the top-level forms in order,
+    /// each with `halt` as its continuation.
+    fn main(&mut self, forms: &[usize], halt: i64) -> encode::Function {
+        let type_index = self.procedure_type(0);
+        let mut body = Vec::new();
+        for &form in forms {
+            let (required, rest) = self.arities[form];
+            let code = Generator::code(form as u32, required, rest);
+            body.push(Instr::I64Const(self.static_closure(form as u32, code)));
+            body.push(Instr::I64Const(halt));
+            match self.target {
+                Target::TailCalls => body.push(Instr::Call(self.dispatcher_index(1))),
+                Target::Trampoline => {
+                    body.extend(self.schedule(1));
+                    body.push(Instr::Call(self.driver_index()));
+                }
+            }
+        }
+        encode::Function {
+            type_index,
+            locals: Vec::new(),
+            body,
+        }
+    }
+}
+
+/// The code of one of the program's functions being generated.
+struct Code<'a> {
+    generator: &'a mut Generator,
+    /// The number of parameters of the function, closure included, if its
+    /// body is in a loop that calls to its own closure branch back to, or
+    /// 0 if it isn't.
+    params: usize,
+    /// How many blocks the code is nested in inside the loop.
+    depth: u32,
+    scratch: u32,
+    body: &'a mut Vec<Instr>,
+}
+
+impl<'a> Code<'a> {
+    fn emit(&mut self, instrs: &[Instr]) {
+        self.body.extend_from_slice(instrs);
+    }
+
+    fn block(&mut self, block: &Block) {
+        for stmt in &block.stmts {
+            match *stmt {
+                Stmt::Let(local, ref value) => {
+                    self.value(value);
+                    self.emit(&[Instr::LocalSet(local.0 as u32)]);
+                }
+                Stmt::Do(ref value) => {
+                    self.value(value);
+                    self.emit(&[Instr::Drop]);
+                }
+            }
+        }
+        match block.tail {
+            // Functions that assign their closure parameter have no loop,
+            // so this calls the function itself.
+            Tail::Call(Atom::Local(Local(0)), ref args) if args.len() + 1 == self.params => {
+                for arg in args {
+                    self.atom(arg);
+                }
+                for param in (1..self.params as u32).rev() {
+                    self.emit(&[Instr::LocalSet(param)]);
+                }
+                self.emit(&[Instr::Br(self.depth)]);
+            }
+            Tail::Call(ref operator, ref args) => {
+                self.atom(operator);
+                for arg in args {
+                    self.atom(arg);
+                }
+                let call = self.generator.tail_call(args.len());
+                self.emit(&call);
+            }
+            Tail::If(ref test, ref consequent, ref alternate) => {
+                self.atom(test);
+                self.emit(&[Instr::I64Const(FALSE), Instr::I64Ne, Instr::If(BlockType::Empty)]);
+                self.depth += 1;
+                self.block(consequent);
+                self.emit(&[Instr::Else]);
+                self.block(alternate);
+                self.depth -= 1;
+                self.emit(&[Instr::End]);
+            }
+        }
+    }
+
+    fn atom(&mut self, atom: &Atom) {
+        let instr = match *atom {
+            Atom::Local(local) => Instr::LocalGet(local.0 as u32),
+            Atom::Const(ref datum) => Instr::I64Const(self.generator.data.constant(datum)),
+            Atom::Unspecified => Instr::I64Const(UNSPECIFIED),
+            Atom::Unassigned => Instr::I64Const(UNASSIGNED),
+        };
+        self.emit(&[instr]);
+    }
+
+    fn value(&mut self, value: &Value) {
+        match *value {
+            Value::Atom(ref atom) => self.atom(atom),
+            Value::Primitive(p, ref args) => self.primitive(p, args),
+            Value::Global(name) => match Primitive::named(name.as_str()) {
+                Some(p) => {
+                    let closure = self.generator.extra_closure(Extra::Primitive(p));
+                    self.emit(&[Instr::I64Const(closure)]);
+                }
+                None => {
+                    let global = self.generator.global(name);
+                    let message = self.generator.data.message("Global used before it is defined");
+                    let name = self.generator.data.symbol(name);
+                    let check = self.generator.helper_index(Helper::CheckDefined);
+                    self.emit(&[Instr::GlobalGet(global), Instr::I64Const(message), Instr::I64Const(name),
+                                Instr::Call(check)]);
+                }
+            },
+            Value::SetGlobal(name, ref value) => {
+                let global = self.generator.global(name);
+                self.atom(value);
+                self.emit(&[Instr::GlobalSet(global), Instr::I64Const(UNSPECIFIED)]);
+            }
+            Value::SetLocal(local, ref value) => {
+                self.atom(value);
+                self.emit(&[Instr::LocalSet(local.0 as u32), Instr::I64Const(UNSPECIFIED)]);
+            }
+            Value::Closure(index, ref captured) if captured.is_empty() => {
+                let code = self.code(index);
+                let closure = self.generator.static_closure(index as u32, code);
+                self.emit(&[Instr::I64Const(closure)]);
+            }
+            Value::Closure(index, ref captured) => {
+                let code = self.code(index);
+                self.allocate(CLOSURE, captured.len());
+                self.emit(&[Instr::LocalGet(self.scratch), Instr::I32WrapI64, Instr::I64Const(code),
+                            Instr::I64Store(7)]);
+                for (slot, value) in captured.iter().enumerate() {
+                    self.emit(&[Instr::LocalGet(self.scratch), Instr::I32WrapI64]);
+                    self.atom(value);
+                    self.emit(&[Instr::I64Store(closure_slot(slot))]);
+                }
+                self.emit(&[Instr::LocalGet(self.scratch)]);
+            }
+            Value::ClosureRef(ref closure, slot) => {
+                self.atom(closure);
+                self.emit(&[Instr::I32WrapI64, Instr::I64Load(closure_slot(slot))]);
+            }
+            Value::ClosureSet(ref closure, slot, ref value) => {
+                self.atom(closure);
+                self.emit(&[Instr::I32WrapI64]);
+                self.atom(value);
+                self.emit(&[Instr::I64Store(closure_slot(slot)), Instr::I64Const(UNSPECIFIED)]);
+            }
+            Value::Box(ref value) => {
+                self.allocate(BOX, 0);
+                self.emit(&[Instr::LocalGet(self.scratch), Instr::I32WrapI64]);
+                match *value {
+                    Some(ref value) => self.atom(value),
+                    None => self.emit(&[Instr::I64Const(UNASSIGNED)]),
+                }
+                self.emit(&[Instr::I64Store(7), Instr::LocalGet(self.scratch)]);
+            }
+            Value::Unbox(ref b) => {
+                self.atom(b);
+                self.emit(&[Instr::I32WrapI64, Instr::I64Load(7)]);
+            }
+            Value::SetBox(ref b, ref value) => {
+                self.atom(b);
+                self.emit(&[Instr::I32WrapI64]);
+                self.atom(value);
+                self.emit(&[Instr::I64Store(7), Instr::I64Const(UNSPECIFIED)]);
+            }
+            Value::Check(name, ref value) => {
+                self.atom(value);
+                let message = self.generator.data.message("Variable used before it is initialised");
+                let name = self.generator.data.symbol(name);
+                let check = self.generator.helper_index(Helper::CheckDefined);
+                self.emit(&[Instr::I64Const(message), Instr::I64Const(name), Instr::Call(check)]);
+            }
+        }
+    }
+
+    /// Calls the function for `p` with `args`, filling in optional ones and
+    /// gathering any after the first for `error` into a list. A record is
+    /// made in place instead, with no list of its fields.
+    fn primitive(&mut self, p: Primitive, args: &[Atom]) {
+        assert!(!matches!(p, Primitive::CallWithCurrentContinuation | Primitive::Values | Primitive::CallWithValues),
+                "CPS conversion compiles calls to {}", p.name());
+        match p {
+            Primitive::MakeRecord => {
+                let message = self.generator.expected(p, "a record type");
+                let check = self.generator.helper_index(Helper::Check);
+                self.allocate(RECORD, args.len() - 1);
+                self.emit(&[Instr::LocalGet(self.scratch), Instr::I32WrapI64]);
+                self.atom(&args[0]);
+                self.emit(&[Instr::I32Const(RECORD_TYPE as i32), Instr::I64Const(message), Instr::Call(check),
+                            Instr::I64Store(7)]);
+                for (field, value) in args[1..].iter().enumerate() {
+                    self.emit(&[Instr::LocalGet(self.scratch), Instr::I32WrapI64]);
+                    self.atom(value);
+                    self.emit(&[Instr::I64Store(closure_slot(field))]);
+                }
+                return self.emit(&[Instr::LocalGet(self.scratch)]);
+            }
+            Primitive::Error => {
+                self.atom(&args[0]);
+                self.emit(&[Instr::I64Const(NIL)]);
+                let cons = self.generator.helper_index(Helper::Cons);
+                for arg in args[1..].iter().rev() {
+                    self.emit(&[Instr::LocalSet(self.scratch)]);
+                    self.atom(arg);
+                    self.emit(&[Instr::LocalGet(self.scratch), Instr::Call(cons)]);
+                }
+            }
+            _ => {
+                for arg in args {
+                    self.atom(arg);
+                }
+                for &default in &runtime::defaults(p)[args.len() - p.arity().0..] {
+                    self.emit(&[Instr::I64Const(default)]);
+                }
+            }
+        }
+        let function = self.generator.primitive_index(p);
+        self.emit(&[Instr::Call(function)]);
+    }
+
+    /// Allocates an object of type `kind` with `length` words after its
+    /// header, or one if it has none, into the scratch local. The code of a
+    /// closure and the type of a record take a word before them.
+    fn allocate(&mut self, kind: i64, length: usize) {
+        let size = 8 + 8 * length.max(1) + if kind == CLOSURE || kind == RECORD { 8 } else { 0 };
+        let alloc = self.generator.helper_index(Helper::Alloc);
+        self.emit(&[Instr::I32Const(size as i32), Instr::I64Const(kind | (length as i64) << 8), Instr::Call(alloc),
+                    Instr::LocalSet(self.scratch)]);
+    }
+
+    fn code(&self, index: usize) -> i64 {
+        let (required, rest) = self.generator.arities[index];
+        Generator::code(index as u32, required, rest)
+    }
+}
+
+/// Whether `block` assigns `local` anywhere.
+fn assigns(block: &Block, local: Local) -> bool {
+    let stmts = block.stmts.iter().any(|stmt| {
+        let (bound, value) = match *stmt {
+            Stmt::Let(l, ref value) => (Some(l), value),
+            Stmt::Do(ref value) => (None, value),
+        };
+        bound == Some(local) || matches!(*value, Value::SetLocal(l, _) if l == local)
+    });
+    stmts || match block.tail {
+        Tail::If(_, ref consequent, ref alternate) => assigns(consequent, local) || assigns(alternate, local),
+        Tail::Call(..) => false,
+    }
+}
+
+/// The offset from a closure's pointer of one of its slots, which is where
+/// a record's fields are as well.
+fn closure_slot(slot: usize) -> u32 {
+    15 + 8 * slot as u32
+}
+
+/// The constants in memory, laid out from address 8 so that no pointer is
+/// null.
+#[derive(Debug)]
+struct Data {
+    bytes: Vec<u8>,
+    symbols: HashMap<&'static str, i64>,
+    messages: HashMap<String, i64>,
+}
+
+impl Data {
+    fn new() -> Self {
+        Data {
+            bytes: vec![0; 8],
+            symbols: HashMap::new(),
+            messages: HashMap::new(),
+        }
+    }
+
+    /// Reserves `size` bytes, rounded up to a multiple of 8, returning the
+    /// address.
+    fn reserve(&mut self, size: usize) -> usize {
+        let address = self.bytes.len();
+        self.bytes.resize(address + size.div_ceil(8) * 8, 0);
+        address
+    }
+
+    fn write(&mut self, address: usize, word: i64) {
+        self.bytes[address..address + 8].copy_from_slice(&word.to_le_bytes());
+    }
+
+    /// An object made of `words`, header first.
+    fn object(&mut self, words: &[i64]) -> i64 {
+        let address = self.reserve(8 * words.len());
+        for (i, &word) in words.iter().enumerate() {
+            self.write(address + 8 * i, word);
+        }
+        address as i64 | 1
+    }
+
+    fn string(&mut self, s: &str) -> i64 {
+        let chars: Vec<char> = s.chars().collect();
+        let address = self.reserve(8 + 4 * chars.len());
+        self.write(address, STRING | (chars.len() as i64) << 8);
+        for (i, &c) in chars.iter().enumerate() {
+            let at = address + 8 + 4 * i;
+            self.bytes[at..at + 4].copy_from_slice(&(c as u32).to_le_bytes());
+        }
+        address as i64 | 1
+    }
+
+    /// A string for an error message, shared by every use of it.
+    fn message(&mut self, message: &str) -> i64 {
+        if let Some(&string) = self.messages.get(message) {
+            return string;
+        }
+        let string = self.string(message);
+        self.messages.insert(message.to_string(), string);
+        string
+    }
+
+    /// The one symbol with the name of `symbol`.
+    fn symbol(&mut self, symbol: Symbol) -> i64 {
+        let name = symbol.as_str();
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol;
+        }
+        let string = self.string(name);
+        let symbol = self.object(&[SYMBOL, string]);
+        self.symbols.insert(name, symbol);
+        symbol
+    }
+
+    fn constant(&mut self, datum: &Datum) -> i64 {
+        self.datum(datum, &mut HashMap::new())
+    }
+
+    /// `datum` in memory, with `labels` holding the objects labelled so far.
+    fn datum(&mut self, datum: &Datum, labels: &mut HashMap<u32, i64>) -> i64 {
+        match datum.kind {
+            DatumKind::Nil => NIL,
+            DatumKind::Bool(b) => if b { TRUE } else { FALSE },
+            DatumKind::Fixnum(n) => n << 3,
+            DatumKind::Char(c) => (c as i64) << 3 | 2,
+            DatumKind::Flonum(n) => self.object(&[FLONUM, n.to_bits() as i64]),
+            DatumKind::String(ref s) => self.string(s),
+            DatumKind::Symbol(s) => self.symbol(s),
+            DatumKind::Bytevector(ref bytes) => {
+                let address = self.reserve(8 + bytes.len());
+                self.write(address, BYTEVECTOR | (bytes.len() as i64) << 8);
+                self.bytes[address + 8..address + 8 + bytes.len()].copy_from_slice(bytes);
+                address as i64 | 1
+            }
+            DatumKind::Pair(..) | DatumKind::Vector(_) => {
+                let object = self.reserve_compound(datum);
+                self.fill(datum, object, labels);
+                object
+            }
+            DatumKind::Labeled(label, ref inner) => match inner.kind {
+                DatumKind::Pair(..) | DatumKind::Vector(_) => {
+                    let object = self.reserve_compound(inner);
+                    labels.insert(label, object);
+                    self.fill(inner, object, labels);
+                    object
+                }
+                _ => {
+                    let value = self.datum(inner, labels);
+                    labels.insert(label, value);
+                    value
+                }
+            },
+            DatumKind::Reference(label) => labels[&label],
+        }
+    }
+
+    /// Memory for a pair or vector, with its header written.
+    fn reserve_compound(&mut self, datum: &Datum) -> i64 {
+        let (header, words) = match datum.kind {
+            DatumKind::Pair(..) => (PAIR, 2),
+            DatumKind::Vector(ref items) => (VECTOR | (items.len() as i64) << 8, items.len()),
+            _ => unreachable!(),
+        };
+        let address = self.reserve(8 + 8 * words);
+        self.write(address, header);
+        address as i64 | 1
+    }
+
+    fn fill(&mut self, datum: &Datum, object: i64, labels: &mut HashMap<u32, i64>) {
+        let items: Vec<&Datum> = match datum.kind {
+            DatumKind::Pair(ref car, ref cdr) => vec![car, cdr],
+            DatumKind::Vector(ref items) => items.iter().collect(),
+            _ => unreachable!(),
+        };
+        for (i, item) in items.into_iter().enumerate() {
+            let value = self.datum(item, labels);
+            self.write((object - 1) as usize + 8 + 8 * i, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anf::{self, Program};
+    use pipeline;
+
+    use super::Target;
+
+    /// What `source` writes when it is compiled with the prelude and run,
+    /// and the status it exits with or its error, which are the same for
+    /// every target.
+    fn run(source: &str) -> (String, Result<i32, String>) {
+        run_program(&pipeline::compile("test", source.as_bytes()).expect("the program compiles"))
+    }
+
+    /// Like `run`, for a program that is already in A-normal form.
+    fn run_program(program: &Program) -> (String, Result<i32, String>) {
+        let mut outcomes = Target::ALL.iter().map(|&target| {
+            let outcome = super::run::run(&super::compile(program, target), target);
+            (outcome.output, outcome.status)
+        });
+        let outcome = outcomes.next().unwrap();
+        for other in outcomes {
+            assert_eq!(other, outcome, "every target runs the program the same");
+        }
+        outcome
+    }
+
+    fn output(source: &str) -> String {
+        let (output, status) = run(source);
+        assert_eq!(status, Ok(0), "{}", output);
+        output
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(output("(write (list 1 -2 #\\a \"b\\n\" 'c #(1 #t) '(1 . 2) '() car))"),
+                   "(1 -2 #\\a \"b\\n\" c #(1 #t) (1 . 2) () #<procedure>)");
+        assert_eq!(output("(display (list (quotient -7 2) (remainder -7 2) (modulo -7 2) (* 6 -7) (- 5)))"),
+                   "(-3 -1 1 -42 -5)");
+        assert_eq!(output("(display (list (+ 1152921504606846974 1) (- -1 1152921504606846975)
+                                         (* 1073741824 -1073741824)))"),
+                   "(1152921504606846975 -1152921504606846976 -1152921504606846976)");
+        assert_eq!(output("(display 1.5) (newline) (write '#u8(1 255)) (write (list 2.0 #u8() (exact? -0.5)))"),
+                   "1.5\n#u8(1 255)(2.0 #u8() #f)");
+        assert_eq!(output("(display (list (equal? #u8(1 2) #u8(1 2)) (equal? #u8(1) #u8(2))
+                                         (bytevector-u8-ref #u8(7 8) 1)))"),
+                   "(#t #f 8)");
+        assert_eq!(output("(display (string-append \"ab\" (make-string 2 #\\c) (symbol->string 'd)))"), "abccd");
+        assert_eq!(output("(define v (make-vector 2 0)) (vector-set! v 1 'x) (write v)"), "#(0 x)");
+        assert_eq!(output("(write (list (eqv? 1.5 1.5) (eq? '() '()) (integer? 2.0) (char->integer #\\A)))"),
+                   "(#t #t #t 65)");
+    }
+
+    #[test]
+    fn procedures() {
+        // Primitives, variadic ones included, are procedures like any other.
+        assert_eq!(output("(display (map car '((1) (2))))"), "(1 2)");
+        assert_eq!(output("(display (map - '(1 2)))"), "(-1 -2)");
+        assert_eq!(output("(display (list (+) (*) (+ 1 2 3) (- 10 1 2) (* 2 3 4)))"), "(0 1 6 7 24)");
+        assert_eq!(output("(define (f . xs) xs) (define (g x . ys) ys) (write (list (f) (f 1 2) (g 1) (g 1 2 3)))"),
+                   "(() (1 2) () (2 3))");
+        assert_eq!(output("(let ((k #f) (n 0)) (display (+ 1 (call/cc (lambda (c) (set! k c) 1)))) \
+                           (set! n (+ n 1)) (if (< n 3) (k n)))"), "223");
+        assert_eq!(output("(display (call-with-current-continuation (lambda (k) (+ 1 (k 42)))))"), "42");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(run("(display 1) (car 5) (display 2)"), ("1".to_string(), Err("Expected a pair in car: 5".into())));
+        assert_eq!(run("(error \"Bad thing\" 1 \"two\")").1, Err("Bad thing: 1 \"two\"".into()));
+        assert_eq!(run("(vector-ref (vector 1) 1)").1, Err("Expected an index in range in vector-ref: 1".into()));
+        assert_eq!(run("(bytevector-u8-ref #u8(1) 1)").1,
+                   Err("Expected an index in range in bytevector-u8-ref: 1".into()));
+        assert_eq!(run("((lambda (x) x))").1, Err("Wrong number of arguments to: #<procedure>".into()));
+        assert_eq!(run("(1 2)").1, Err("Expected a procedure to call: 1".into()));
+        assert_eq!(run("(+ 1152921504606846975 1)").1,
+                   Err("Integer overflow in +: 1152921504606846975 1".into()));
+        assert_eq!(run("(- -1152921504606846976 1)").1,
+                   Err("Integer overflow in -: -1152921504606846976 1".into()));
+        assert_eq!(run("(* 1073741824 1073741824)").1, Err("Integer overflow in *: 1073741824 1073741824".into()));
+        assert_eq!(run("(- -1152921504606846976)").1, Err("Integer overflow in -: 0 -1152921504606846976".into()));
+        assert_eq!(run("(* -1 -1152921504606846976)").1,
+                   Err("Integer overflow in *: -1 -1152921504606846976".into()));
+        assert_eq!(run("(quotient -1152921504606846976 -1)").1,
+                   Err("Integer overflow in quotient: -1152921504606846976 -1".into()));
+        assert_eq!(run("(define (f) (define a b) (define b 1) a) (f)").1,
+                   Err("Variable used before it is initialised: b".into()));
+        assert_eq!(run("(display 1) (exit 3) (display 2)"), ("1".to_string(), Ok(3)));
+    }
+
+    #[test]
+    fn records() {
+        assert_eq!(output("\
+(define-record-type <point> (make-point y x) point? (x point-x set-point-x!) (y point-y))
+(define p (make-point 1 2))
+(set-point-x! p 10)
+(write (list (point-x p) (point-y p) (point? p) (point? (vector 1 2)) p))
+(write (map point? (list p 1)))"), "(10 1 #t #f #<point>)(#t #f)");
+        // Each evaluation of a definition makes a new type, even with the
+        // same name.
+        assert_eq!(output("\
+(define (make-box-type)
+  (define-record-type box (make-box v) box? (v unbox))
+  (cons make-box box?))
+(define a (make-box-type))
+(define b (make-box-type))
+(write (list ((cdr a) ((car a) 1)) ((cdr b) ((car a) 1))))"), "(#t #f)");
+        assert_eq!(run("(define-record-type point (make-point x) point? (x point-x)) (point-x (vector 1))").1,
+                   Err("Expected a record of type point for field x in point-x: #(1)".into()));
+        assert_eq!(run("(define-record-type a (make-a x) a? (x a-x)) (define-record-type b (make-b) b? (x b-x set-b-x!))
+                        (set-b-x! (make-a 1) 2)").1,
+                   Err("Expected a record of type b for field x in set-b-x!: #<a>".into()));
+    }
+
+    #[test]
+    fn multiple_values() {
+        assert_eq!(output("\
+(define (split n) (values (quotient n 10) (remainder n 10)))
+(let-values (((q r) (split 42)) ((a . b) (values 1 2 3))) (write (list q r a b)))
+(let*-values (((q r) (split 42)) ((s) (+ q r))) (write s))
+(receive all (values) (write all))
+(define-values (x y . z) (split 97))
+(write (list x y z))
+(define (product) (define-values (a b) (split 67)) (* a b))
+(write (product))
+(write (call-with-values (lambda () (if (pair? '()) (split 1) (values 7 8))) (lambda (a b) (- a b))))"),
+                   "(4 2 1 (2 3))6()(9 7 ())42-1");
+        // Values go through `values` and `call-with-values` as procedures,
+        // to consumers that aren't known and to continuations captured with
+        // `call/cc`.
+        assert_eq!(output("\
+(define cwv call-with-values)
+(write (cwv (lambda () (values 1 2 3)) list))
+(write (call-with-values (lambda () ((car (list values)) 1 2)) +))
+(write (map values '(1 2)))
+(write (call-with-values (lambda () (call/cc (lambda (k) (k 5)))) (lambda args args)))
+(values 1 2)
+(values)"), "(1 2 3)3(1 2)(5)");
+        assert_eq!(run("(call-with-values (lambda () (values 1 2)) (lambda (a) a))").1,
+                   Err("Wrong number of arguments to: #<procedure>".into()));
+        assert_eq!(run("(define (f) (values 1 2)) (car (f))").1,
+                   Err("Wrong number of arguments to: #<procedure>".into()));
+        assert_eq!(run("(call-with-values (lambda () 1) 2)").1, Err("Expected a procedure to call: 2".into()));
+    }
+
+    #[test]
+    fn known_consumers_allocate_nothing() {
+        // How many bytes `source` allocates on each target.
+        let allocated = |source: &str| -> Vec<u64> {
+            let program = pipeline::compile("test", source.as_bytes()).expect("the program compiles");
+            Target::ALL.iter().map(|&target| {
+                let (outcome, allocated) = super::run::run_allocating(&super::compile(&program, target), target);
+                assert_eq!(outcome.status, Ok(0), "{}", outcome.output);
+                allocated
+            }).collect()
+        };
+        let none = allocated("(write 42)");
+        assert_eq!(allocated("(write (call-with-values (lambda () (values 40 2)) (lambda (a b) (+ a b))))"), none);
+        assert_eq!(allocated("(write (let-values (((a b) (values 40 2))) (+ a b)))"), none);
+        assert_eq!(allocated("(write (+ 1 (receive (a b) (values 40 1) (+ a b))))"), none);
+        // Passing them to a consumer that isn't known makes a list of them.
+        let unknown = allocated("(write (call-with-values (lambda () (values 40 2)) +))");
+        assert!(unknown.iter().zip(&none).all(|(unknown, none)| unknown > none));
+    }
+
+    #[test]
+    fn tail_calls_run_in_constant_space() {
+        assert_eq!(output("\
+(define (my-even? n) (if (= n 0) #t (my-odd? (- n 1))))
+(define (my-odd? n) (if (= n 0) #f (my-even? (- n 1))))
+(display (my-even? 10000000))"), "#t");
+        // Calls in the branches of every conditional form are tail calls.
+        assert_eq!(output("\
+(define (count-cond n) (cond ((= n 0) 'cond) ((odd? n) (count-cond (- n 1))) (else (count-cond (- n 1)))))
+(define (count-case n) (case n ((0) 'case) ((1 3 5) (count-case (- n 1))) (else (count-case (- n 1)))))
+(define (count-and n) (and (> n -1) (if (= n 0) 'and (count-and (- n 1)))))
+(define (count-or n) (or (and (= n 0) 'or) (count-or (- n 1))))
+(define (count-when n) (when #t (if (= n 0) 'when (count-when (- n 1)))))
+(define (count-let n) (let loop ((i n)) (if (= i 0) 'let (loop (- i 1)))))
+(for-each (lambda (count) (display (count 100000))) (list count-cond count-case count-and count-or count-when count-let))
+"), "condcaseandorwhenlet");
+    }
+
+    #[test]
+    fn assigned_closure_parameters_are_not_loops() {
+        // Function 0 calls its closure parameter after setting it to a
+        // closure of function 1, which writes `b`.
+        let program = anf::parse::parse("test", "
+            (function 0 (closure.0 k.1 n.2)
+              (let t.3 (prim = n.2 0))
+              (if t.3
+                (call k.1 'done)
+                (begin
+                  (let m.4 (prim - n.2 1))
+                  (let g.5 (closure 1))
+                  (do (set! closure.0 g.5))
+                  (call closure.0 k.1 m.4))))
+            (function 1 (closure.0 k.1 n.2)
+              (do (prim write-char #\\b))
+              (call k.1 n.2))
+            (function 2 (closure.0 k.1)
+              (let f.2 (closure 0))
+              (call f.2 k.1 1))
+            (main 2)").unwrap();
+        assert_eq!(run_program(&program), ("b".to_string(), Ok(0)));
+    }
+}
@@ -0,0 +1,219 @@
+//! Runs compiled modules in an embedded interpreter, which provides the
+//! imports: `write_char` and `write_flonum` collect the output, `error`
+//! formats the message and irritants from memory and stops the program, and
+//! `exit` stops it with a status.
+
+use std::fmt::Write;
+
+use wasmi::{Caller, Config, Engine, Extern, Linker, Module, Store};
+
+use reader::{Datum, DatumKind, Style};
+use span::Span;
+use symbol::Symbol;
+
+use super::{Target, BYTEVECTOR, CLOSURE, FALSE, FLONUM, NIL, PAIR, RECORD, RECORD_TYPE, STRING, SYMBOL, TRUE,
+            UNSPECIFIED, VECTOR};
+
+/// How many elements and how deep an irritant is printed, since it may be
+/// circular.
+const PRINT_LIMIT: usize = 100;
+
+/// What running a program did.
+#[derive(Debug)]
+pub struct Outcome {
+    /// Everything it wrote.
+    pub output: String,
+    /// The status it exited with, or the error that stopped it.
+    pub status: Result<i32, String>,
+}
+
+#[derive(Debug, Default)]
+struct Host {
+    output: String,
+    /// The message of the error the program raised, if it did.
+    error: Option<String>,
+}
+
+/// Runs `main` of `module`, which has to be one `compile` made for `target`.
+/// Tail calls are only enabled for the target that uses them.
+pub fn run(module: &[u8], target: Target) -> Outcome {
+    run_allocating(module, target).0
+}
+
+/// Runs `module` like `run`, also giving how many bytes `main` allocated on
+/// the heap.
+pub fn run_allocating(module: &[u8], target: Target) -> (Outcome, u64) {
+    let mut config = Config::default();
+    config.wasm_tail_call(target == Target::TailCalls);
+    let engine = Engine::new(&config);
+    let mut store = Store::new(&engine, Host::default());
+    let mut allocated = 0;
+    let status = instantiate_and_run(&engine, &mut store, module, &mut allocated);
+    let host = store.into_data();
+    let outcome = Outcome {
+        output: host.output,
+        status: match (status, host.error) {
+            (_, Some(error)) => Err(error),
+            (Ok(status), None) => Ok(status),
+            (Err(e), None) => Err(e.to_string()),
+        },
+    };
+    (outcome, allocated)
+}
+
+fn instantiate_and_run(engine: &Engine, store: &mut Store<Host>, module: &[u8], allocated: &mut u64)
+                       -> Result<i32, wasmi::Error> {
+    let module = Module::new(engine, module)?;
+    let mut linker = <Linker<Host>>::new(engine);
+    linker.func_wrap("env", "write_char", |mut caller: Caller<Host>, c: i32| {
+        caller.data_mut().output.push(::std::char::from_u32(c as u32).unwrap_or('\u{fffd}'));
+    })?;
+    linker.func_wrap("env", "error", |mut caller: Caller<Host>, message: i64, irritants: i64| {
+        let memory = caller.get_export("memory").and_then(Extern::into_memory).expect("modules export memory");
+        let error = Heap(memory.data(&caller)).error(message, irritants);
+        caller.data_mut().error = Some(error.clone());
+        Err::<(), _>(wasmi::Error::new(error))
+    })?;
+    linker.func_wrap("env", "exit", |_: Caller<Host>, status: i32| Err::<(), _>(wasmi::Error::i32_exit(status)))?;
+    linker.func_wrap("env", "write_flonum", |mut caller: Caller<Host>, n: f64| {
+        let printed = Datum::new(DatumKind::Flonum(n), Span::default()).printed(Style::Display).to_string();
+        caller.data_mut().output.push_str(&printed);
+    })?;
+    let instance = linker.instantiate(&mut *store, &module)?.start(&mut *store)?;
+    let main = instance.get_typed_func::<(), ()>(&*store, "main")?;
+    let heap_pointer = instance.get_global(&*store, "heap_pointer").expect("modules export the heap pointer");
+    let heap = |store: &Store<Host>| heap_pointer.get(store).i64().expect("the heap pointer is an i64");
+    let start = heap(store);
+    let result = main.call(&mut *store, ());
+    *allocated = (heap(store) - start) as u64;
+    match result {
+        Ok(()) => Ok(0),
+        Err(e) => match e.i32_exit_status() {
+            Some(status) => Ok(status),
+            None => Err(e),
+        },
+    }
+}
+
+/// The memory of a module, read as values.
+struct Heap<'a>(&'a [u8]);
+
+impl<'a> Heap<'a> {
+    fn word(&self, address: usize) -> i64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&self.0[address..address + 8]);
+        i64::from_le_bytes(bytes)
+    }
+
+    /// The header word and the address of the object `value` points to.
+    fn object(&self, value: i64) -> (i64, usize) {
+        let address = (value - 1) as usize;
+        (self.word(address), address)
+    }
+
+    /// The message of an error: `message` displayed, then each irritant
+    /// written.
+    fn error(&self, message: i64, irritants: i64) -> String {
+        let mut text = String::new();
+        self.print(message, Style::Display, 0, &mut text);
+        let mut list = irritants;
+        for i in 0..PRINT_LIMIT {
+            if list == NIL {
+                break;
+            }
+            let (_, address) = self.object(list);
+            text.push_str(if i == 0 { ": " } else { " " });
+            self.print(self.word(address + 8), Style::Write, 0, &mut text);
+            list = self.word(address + 16);
+        }
+        text
+    }
+
+    fn string(&self, value: i64) -> String {
+        let (header, address) = self.object(value);
+        (0..(header >> 8) as usize).map(|i| {
+            let at = address + 8 + 4 * i;
+            let code = u32::from(self.0[at]) | u32::from(self.0[at + 1]) << 8 | u32::from(self.0[at + 2]) << 16 |
+                       u32::from(self.0[at + 3]) << 24;
+            ::std::char::from_u32(code).unwrap_or('\u{fffd}')
+        }).collect()
+    }
+
+    /// The name of the symbol `value`.
+    fn symbol(&self, value: i64) -> String {
+        let (_, address) = self.object(value);
+        self.string(self.word(address + 8))
+    }
+
+    fn print(&self, value: i64, style: Style, depth: usize, out: &mut String) {
+        let datum = |kind| Datum::new(kind, Span::default());
+        let leaf = match value & 7 {
+            0 => datum(DatumKind::Fixnum(value >> 3)),
+            2 => datum(DatumKind::Char(::std::char::from_u32((value >> 3) as u32).unwrap_or('\u{fffd}'))),
+            3 => {
+                return out.push_str(match value {
+                    FALSE => "#f",
+                    TRUE => "#t",
+                    NIL => "()",
+                    UNSPECIFIED => "#<unspecified>",
+                    _ => "#<unassigned>",
+                });
+            }
+            _ if depth >= PRINT_LIMIT => return out.push_str("..."),
+            _ => {
+                let (header, address) = self.object(value);
+                match header & 0xff {
+                    PAIR => return self.print_list(value, style, depth, out),
+                    FLONUM => datum(DatumKind::Flonum(f64::from_bits(self.word(address + 8) as u64))),
+                    STRING => datum(DatumKind::String(self.string(value))),
+                    SYMBOL => datum(DatumKind::Symbol(Symbol::intern(&self.symbol(value)))),
+                    VECTOR => {
+                        out.push_str("#(");
+                        for i in 0..((header >> 8) as usize).min(PRINT_LIMIT) {
+                            if i > 0 {
+                                out.push(' ');
+                            }
+                            self.print(self.word(address + 8 + 8 * i), style, depth + 1, out);
+                        }
+                        return out.push(')');
+                    }
+                    BYTEVECTOR => {
+                        let bytes = &self.0[address + 8..address + 8 + (header >> 8) as usize];
+                        datum(DatumKind::Bytevector(bytes.to_vec()))
+                    }
+                    CLOSURE => return out.push_str("#<procedure>"),
+                    RECORD => {
+                        let (_, record_type) = self.object(self.word(address + 8));
+                        return write!(out, "#<{}>", self.symbol(self.word(record_type + 8))).unwrap();
+                    }
+                    RECORD_TYPE =>
+                        return write!(out, "#<record-type {}>", self.symbol(self.word(address + 8))).unwrap(),
+                    _ => return out.push_str("#<object>"),
+                }
+            }
+        };
+        write!(out, "{}", leaf.printed(style)).unwrap();
+    }
+
+    fn print_list(&self, mut list: i64, style: Style, depth: usize, out: &mut String) {
+        out.push('(');
+        for i in 0..PRINT_LIMIT {
+            let (_, address) = self.object(list);
+            if i > 0 {
+                out.push(' ');
+            }
+            self.print(self.word(address + 8), style, depth + 1, out);
+            list = self.word(address + 16);
+            if list & 7 != 1 || self.object(list).0 & 0xff != PAIR {
+                break;
+            }
+        }
+        if list & 7 == 1 && self.object(list).0 & 0xff == PAIR {
+            out.push_str(" ...");
+        } else if list != NIL {
+            out.push_str(" . ");
+            self.print(list, style, depth + 1, out);
+        }
+        out.push(')');
+    }
+}
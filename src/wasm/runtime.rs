@@ -0,0 +1,788 @@
+//! The functions every module has: helpers for allocating and checking
+//! values, one function for each primitive, which checks its operands and
+//! returns its result, and the dispatchers that call closures, with the
+//! driver loop that calls them for a trampoline. Also the functions of the
+//! procedures in the table that aren't the program's.
+
+use std::collections::BTreeSet;
+
+use primitive::Primitive;
+
+use super::encode::{BlockType, Function, Instr, ValType};
+use super::encode::Instr::*;
+use super::{Extra, Generator, ARGUMENTS, ARGUMENT_COUNT, CALLEE, ERROR, EXIT, HEAP_POINTER, WRITE_CHAR,
+            WRITE_FLONUM};
+use super::{BYTEVECTOR, CLOSURE, FALSE, FLONUM, NIL, PAIR, RECORD, RECORD_TYPE, STRING, SYMBOL, TRUE, UNASSIGNED,
+            UNSPECIFIED, VECTOR};
+
+/// The most elements a string or vector made at run time can have.
+const MAX_LENGTH: i64 = 1 << 24;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Helper {
+    /// Takes a size in bytes, a multiple of 8, and a header, and returns a
+    /// pointer to a new object with the header.
+    Alloc,
+    /// Takes a message and a list of irritants, and never returns.
+    Raise,
+    /// Raises an error with the message and the one irritant it takes.
+    Fail,
+    Cons,
+    /// The type in the header of a pointer's object, or 0 if it isn't a
+    /// pointer.
+    TypeOf,
+    /// The length in the header of a pointer's object.
+    Length,
+    /// Takes a value, a type and a message, and returns the value if it
+    /// points to an object of the type, failing with the message if not.
+    Check,
+    CheckFixnum,
+    CheckChar,
+    /// Takes a string or vector, a value and a message, and returns the
+    /// value as an index into it, failing with the message if it isn't one.
+    CheckIndex,
+    /// Takes a value, a message and a name, and returns the value unless it
+    /// is unassigned.
+    CheckDefined,
+    /// The boolean for an `i32` condition.
+    Bool,
+}
+
+impl Helper {
+    pub const ALL: &'static [Helper] = &[
+        Helper::Alloc,
+        Helper::Raise,
+        Helper::Fail,
+        Helper::Cons,
+        Helper::TypeOf,
+        Helper::Length,
+        Helper::Check,
+        Helper::CheckFixnum,
+        Helper::CheckChar,
+        Helper::CheckIndex,
+        Helper::CheckDefined,
+        Helper::Bool,
+    ];
+}
+
+/// The values of the optional operands of `p`, in order, for calls that
+/// leave them out.
+pub fn defaults(p: Primitive) -> &'static [i64] {
+    const SPACE: i64 = (' ' as i64) << 3 | 2;
+    match p {
+        Primitive::MakeString => &[SPACE],
+        Primitive::MakeVector => &[FALSE],
+        Primitive::Exit => &[UNSPECIFIED],
+        _ => &[],
+    }
+}
+
+/// How many arguments the procedure for `p` takes besides the closure, the
+/// continuation first, and whether it takes a rest list after them.
+pub fn wrapper_arity(p: Primitive) -> (usize, bool) {
+    match (p, p.arity()) {
+        (Primitive::Add, _) | (Primitive::Multiply, _) => (1, true),
+        (Primitive::Subtract, _) => (2, true),
+        (_, (min, Some(max))) if min == max => (1 + min, false),
+        (_, (min, _)) => (1 + min, true),
+    }
+}
+
+impl Generator {
+    fn wasm_function(&mut self, params: &[ValType], results: &[ValType], locals: Vec<ValType>, body: Vec<Instr>)
+                -> Function {
+        Function {
+            type_index: self.module.func_type(params.to_vec(), results.to_vec()),
+            locals,
+            body,
+        }
+    }
+
+    fn call(&self, helper: Helper) -> Instr {
+        Call(self.helper_index(helper))
+    }
+
+    pub fn helper(&mut self, helper: Helper) -> Function {
+        use super::encode::ValType::*;
+        let (params, results, locals, body): (&[ValType], &[ValType], _, _) = match helper {
+            Helper::Alloc => {
+                let out_of_memory = self.data.message("Out of memory");
+                (&[I32, I64], &[I64], vec![I32], vec![
+                    GlobalGet(HEAP_POINTER), I32WrapI64, LocalTee(2), LocalGet(0), I32Add, I64ExtendI32U,
+                    GlobalSet(HEAP_POINTER),
+                    Block(BlockType::Empty),
+                    GlobalGet(HEAP_POINTER), I32WrapI64, MemorySize, I32Const(16), I32Shl, I32LeU, BrIf(0),
+                    // Grows memory by the pages the object doesn't fit in.
+                    GlobalGet(HEAP_POINTER), I32WrapI64, I32Const(16), I32ShrU, I32Const(1), I32Add, MemorySize,
+                    I32Sub, MemoryGrow, I32Const(-1), I32Ne, BrIf(0),
+                    I64Const(out_of_memory), I64Const(NIL), self.call(Helper::Raise),
+                    End,
+                    LocalGet(2), LocalGet(1), I64Store(0),
+                    LocalGet(2), I64ExtendI32U, I64Const(1), I64Or,
+                ])
+            }
+            Helper::Raise => (&[I64, I64], &[], vec![], vec![LocalGet(0), LocalGet(1), Call(ERROR), Unreachable]),
+            Helper::Fail => (&[I64, I64], &[], vec![], vec![
+                LocalGet(0), LocalGet(1), I64Const(NIL), self.call(Helper::Cons), self.call(Helper::Raise),
+            ]),
+            Helper::Cons => (&[I64, I64], &[I64], vec![I64], vec![
+                I32Const(24), I64Const(PAIR), self.call(Helper::Alloc), LocalTee(2),
+                I32WrapI64, LocalGet(0), I64Store(7),
+                LocalGet(2), I32WrapI64, LocalGet(1), I64Store(15),
+                LocalGet(2),
+            ]),
+            Helper::TypeOf => (&[I64], &[I32], vec![], vec![
+                LocalGet(0), I64Const(7), I64And, I64Const(1), I64Ne,
+                If(BlockType::Value(I32)),
+                I32Const(0),
+                Else,
+                LocalGet(0), I32WrapI64, I32Const(1), I32Sub, I32Load8U(0),
+                End,
+            ]),
+            Helper::Length => (&[I64], &[I64], vec![], vec![
+                LocalGet(0), I32WrapI64, I32Const(1), I32Sub, I64Load(0), I64Const(8), I64ShrU,
+            ]),
+            Helper::Check => (&[I64, I32, I64], &[I64], vec![], vec![
+                LocalGet(0), self.call(Helper::TypeOf), LocalGet(1), I32Ne,
+                If(BlockType::Empty), LocalGet(2), LocalGet(0), self.call(Helper::Fail), End,
+                LocalGet(0),
+            ]),
+            Helper::CheckFixnum => (&[I64, I64], &[I64], vec![], vec![
+                LocalGet(0), I64Const(7), I64And, I64Eqz, I32Eqz,
+                If(BlockType::Empty), LocalGet(1), LocalGet(0), self.call(Helper::Fail), End,
+                LocalGet(0),
+            ]),
+            Helper::CheckChar => (&[I64, I64], &[I64], vec![], vec![
+                LocalGet(0), I64Const(7), I64And, I64Const(2), I64Ne,
+                If(BlockType::Empty), LocalGet(1), LocalGet(0), self.call(Helper::Fail), End,
+                LocalGet(0),
+            ]),
+            Helper::CheckIndex => (&[I64, I64, I64], &[I32], vec![], vec![
+                LocalGet(1), LocalGet(2), self.call(Helper::CheckFixnum), I64Const(3), I64ShrS,
+                LocalGet(0), self.call(Helper::Length), I64LtU, I32Eqz,
+                If(BlockType::Empty), LocalGet(2), LocalGet(1), self.call(Helper::Fail), End,
+                LocalGet(1), I64Const(3), I64ShrS, I32WrapI64,
+            ]),
+            Helper::CheckDefined => (&[I64, I64, I64], &[I64], vec![], vec![
+                LocalGet(0), I64Const(UNASSIGNED), I64Eq,
+                If(BlockType::Empty), LocalGet(1), LocalGet(2), self.call(Helper::Fail), End,
+                LocalGet(0),
+            ]),
+            Helper::Bool => (&[I32], &[I64], vec![], vec![I64Const(TRUE), I64Const(FALSE), LocalGet(0), Select]),
+        };
+        self.wasm_function(params, results, locals, body)
+    }
+
+    /// Instructions that push the operand `index` after checking it is an
+    /// object of type `kind`, where an error says it expected `what`.
+    fn checked(&mut self, p: Primitive, index: u32, kind: i64, what: &str) -> Vec<Instr> {
+        let message = self.expected(p, what);
+        vec![LocalGet(index), I32Const(kind as i32), I64Const(message), self.call(Helper::Check)]
+    }
+
+    fn fixnum(&mut self, p: Primitive, index: u32) -> Vec<Instr> {
+        let message = self.expected(p, "an exact integer");
+        vec![LocalGet(index), I64Const(message), self.call(Helper::CheckFixnum)]
+    }
+
+    fn character(&mut self, p: Primitive, index: u32) -> Vec<Instr> {
+        let message = self.expected(p, "a character");
+        vec![LocalGet(index), I64Const(message), self.call(Helper::CheckChar)]
+    }
+
+    /// Instructions that push the operand `index` as an index into the
+    /// object checked in operand 0.
+    fn index(&mut self, p: Primitive, index: u32) -> Vec<Instr> {
+        let message = self.expected(p, "an index in range");
+        vec![LocalGet(0), LocalGet(index), I64Const(message), self.call(Helper::CheckIndex)]
+    }
+
+    pub fn expected(&mut self, p: Primitive, what: &str) -> i64 {
+        self.data.message(&format!("Expected {} in {}", what, p.name()))
+    }
+
+    /// Instructions that push whether the operand `index` points to an
+    /// object of type `kind`.
+    fn has_type(&self, index: u32, kind: i64) -> Vec<Instr> {
+        vec![LocalGet(index), self.call(Helper::TypeOf), I32Const(kind as i32), I32Eq, self.call(Helper::Bool)]
+    }
+
+    /// Instructions that push whether operand 0 is a record of the type in
+    /// operand 1, as an `i32`.
+    fn is_record(&self) -> Vec<Instr> {
+        vec![
+            LocalGet(0), self.call(Helper::TypeOf), I32Const(RECORD as i32), I32Eq,
+            If(BlockType::Value(ValType::I32)),
+            LocalGet(0), I32WrapI64, I64Load(7), LocalGet(1), I64Eq,
+            Else,
+            I32Const(0),
+            End,
+        ]
+    }
+
+    /// Instructions that raise an overflow in `p`, with both operands, if
+    /// the `i32` on the stack is true.
+    fn overflow(&mut self, p: Primitive) -> Vec<Instr> {
+        let message = self.data.message(&format!("Integer overflow in {}", p.name()));
+        vec![
+            If(BlockType::Empty),
+            I64Const(message), LocalGet(0), LocalGet(1), I64Const(NIL), self.call(Helper::Cons),
+            self.call(Helper::Cons), self.call(Helper::Raise),
+            End,
+        ]
+    }
+
+    /// The function computing `p`, which takes as many operands as it can
+    /// be called with, or the first and a list of the rest for `error` and
+    /// `%make-record`.
+    pub fn primitive(&mut self, p: Primitive) -> Function {
+        use super::encode::ValType::*;
+        let params = match p.arity() {
+            (_, Some(max)) => max,
+            (_, None) => 2,
+        };
+        let mut locals = Vec::new();
+        let bool = self.call(Helper::Bool);
+        let body = match p {
+            Primitive::Add | Primitive::Subtract | Primitive::Quotient | Primitive::Remainder | Primitive::Modulo |
+            Primitive::Multiply | Primitive::NumberEqual | Primitive::Less | Primitive::Greater |
+            Primitive::LessEqual | Primitive::GreaterEqual => {
+                let mut body = self.fixnum(p, 0);
+                if p == Primitive::Multiply {
+                    locals.extend(&[I64, I64]);
+                    body.extend(&[I64Const(3), I64ShrS, LocalTee(3)]);
+                }
+                body.extend(self.fixnum(p, 1));
+                if let Primitive::Quotient | Primitive::Remainder | Primitive::Modulo = p {
+                    let message = self.data.message(&format!("Division by zero in {}", p.name()));
+                    body.extend(&[LocalGet(1), I64Eqz, If(BlockType::Empty), I64Const(message), LocalGet(0),
+                                  self.call(Helper::Fail), End]);
+                }
+                if p == Primitive::Quotient {
+                    // Only the smallest fixnum divided by -1 overflows.
+                    body.extend(&[LocalGet(0), I64Const(i64::MIN), I64Eq, LocalGet(1), I64Const(-8), I64Eq, I32And]);
+                    body.extend(self.overflow(p));
+                }
+                body.extend(match p {
+                    Primitive::Add | Primitive::Subtract => {
+                        // A sum overflows if its sign differs from both
+                        // operands', and a difference if it differs from the
+                        // first's when the operands' signs differ.
+                        locals.push(I64);
+                        let mut code = vec![if p == Primitive::Add { I64Add } else { I64Sub }, LocalTee(2), LocalGet(0),
+                                            I64Xor];
+                        code.extend(if p == Primitive::Add {
+                            [LocalGet(2), LocalGet(1), I64Xor]
+                        } else {
+                            [LocalGet(0), LocalGet(1), I64Xor]
+                        });
+                        code.extend(&[I64And, I64Const(0), I64LtS]);
+                        code.extend(self.overflow(p));
+                        code.push(LocalGet(2));
+                        code
+                    }
+                    Primitive::Multiply => {
+                        // Unless the untagged first operand in local 3 is 0
+                        // or -1, the product overflowed if dividing it by
+                        // that doesn't give back the second. Dividing by -1
+                        // would trap, but only negating the smallest fixnum
+                        // overflows.
+                        let mut code = vec![
+                            I64Mul, LocalSet(2),
+                            I64Const(1), LocalGet(3), I64Const(1), I64Add, I64LtU,
+                            If(BlockType::Value(I32)),
+                            LocalGet(2), LocalGet(3), I64DivS, LocalGet(1), I64Ne,
+                            Else,
+                            LocalGet(3), I64Const(-1), I64Eq, LocalGet(1), I64Const(i64::MIN), I64Eq, I32And,
+                            End,
+                        ];
+                        code.extend(self.overflow(p));
+                        code.push(LocalGet(2));
+                        code
+                    }
+                    Primitive::Quotient => vec![I64DivS, I64Const(3), I64Shl],
+                    Primitive::Remainder => vec![I64RemS],
+                    Primitive::Modulo => {
+                        // The remainder, plus the divisor if their signs
+                        // differ.
+                        locals.push(I64);
+                        vec![I64RemS, LocalTee(2), LocalGet(1), I64Add, LocalGet(2),
+                             LocalGet(2), I64Const(0), I64Ne, LocalGet(2), LocalGet(1), I64Xor, I64Const(0), I64LtS,
+                             I32And, Select]
+                    }
+                    Primitive::NumberEqual => vec![I64Eq, bool],
+                    Primitive::Less => vec![I64LtS, bool],
+                    Primitive::Greater => vec![I64GtS, bool],
+                    Primitive::LessEqual => vec![I64LeS, bool],
+                    _ => vec![I64GeS, bool],
+                });
+                body
+            }
+            Primitive::IsNumber => vec![
+                LocalGet(0), I64Const(7), I64And, I64Eqz,
+                LocalGet(0), self.call(Helper::TypeOf), I32Const(FLONUM as i32), I32Eq,
+                I32Or, bool,
+            ],
+            Primitive::IsInteger => vec![
+                LocalGet(0), I64Const(7), I64And, I64Eqz,
+                If(BlockType::Value(I32)),
+                I32Const(1),
+                Else,
+                LocalGet(0), self.call(Helper::TypeOf), I32Const(FLONUM as i32), I32Eq,
+                If(BlockType::Value(I32)),
+                LocalGet(0), I32WrapI64, F64Load(7), F64Trunc, LocalGet(0), I32WrapI64, F64Load(7), F64Eq,
+                Else,
+                I32Const(0),
+                End,
+                End,
+                bool,
+            ],
+            Primitive::IsExact => {
+                let mut body = vec![LocalGet(0), I64Const(7), I64And, I64Eqz, If(BlockType::Value(I32)), I32Const(1),
+                                    Else];
+                body.extend(self.checked(p, 0, FLONUM, "a number"));
+                body.extend(&[Drop, I32Const(0), End, bool]);
+                body
+            }
+            Primitive::IsEq => vec![LocalGet(0), LocalGet(1), I64Eq, bool],
+            Primitive::IsEqv => vec![
+                LocalGet(0), LocalGet(1), I64Eq,
+                If(BlockType::Value(I32)),
+                I32Const(1),
+                Else,
+                // Flonums are the only objects that are eqv? when they are
+                // equal rather than the same.
+                LocalGet(0), self.call(Helper::TypeOf), I32Const(FLONUM as i32), I32Eq,
+                LocalGet(1), self.call(Helper::TypeOf), I32Const(FLONUM as i32), I32Eq,
+                I32And,
+                If(BlockType::Value(I32)),
+                LocalGet(0), I32WrapI64, I64Load(7), LocalGet(1), I32WrapI64, I64Load(7), I64Eq,
+                Else,
+                I32Const(0),
+                End,
+                End,
+                bool,
+            ],
+            Primitive::Not => vec![LocalGet(0), I64Const(FALSE), I64Eq, bool],
+            Primitive::IsBoolean => vec![
+                LocalGet(0), I64Const(TRUE), I64Eq, LocalGet(0), I64Const(FALSE), I64Eq, I32Or, bool,
+            ],
+            Primitive::Cons => vec![LocalGet(0), LocalGet(1), self.call(Helper::Cons)],
+            Primitive::Car | Primitive::Cdr => {
+                let mut body = self.checked(p, 0, PAIR, "a pair");
+                body.extend(&[I32WrapI64, I64Load(if p == Primitive::Car { 7 } else { 15 })]);
+                body
+            }
+            Primitive::SetCar | Primitive::SetCdr => {
+                let mut body = self.checked(p, 0, PAIR, "a pair");
+                body.extend(&[I32WrapI64, LocalGet(1), I64Store(if p == Primitive::SetCar { 7 } else { 15 }),
+                              I64Const(UNSPECIFIED)]);
+                body
+            }
+            Primitive::IsPair => self.has_type(0, PAIR),
+            Primitive::IsNull => vec![LocalGet(0), I64Const(NIL), I64Eq, bool],
+            Primitive::IsSymbol => self.has_type(0, SYMBOL),
+            Primitive::SymbolToString => {
+                let mut body = self.checked(p, 0, SYMBOL, "a symbol");
+                body.extend(&[I32WrapI64, I64Load(7)]);
+                body
+            }
+            Primitive::IsChar => vec![LocalGet(0), I64Const(7), I64And, I64Const(2), I64Eq, bool],
+            Primitive::CharToInteger => {
+                let mut body = self.character(p, 0);
+                body.extend(&[I64Const(2), I64Xor]);
+                body
+            }
+            Primitive::IntegerToChar => {
+                let message = self.expected(p, "a Unicode code point");
+                let mut body = self.fixnum(p, 0);
+                body.extend(&[I64Const(0x11_0000 << 3), I64LtU, I32Eqz,
+                              If(BlockType::Empty), I64Const(message), LocalGet(0), self.call(Helper::Fail), End,
+                              LocalGet(0), I64Const(2), I64Or]);
+                body
+            }
+            Primitive::IsString => self.has_type(0, STRING),
+            Primitive::MakeString | Primitive::MakeVector => {
+                locals.extend(&[I64, I32]);
+                let (fill, size, kind, store) = if p == Primitive::MakeString {
+                    let mut fill = self.character(p, 1);
+                    fill.extend(&[I64Const(3), I64ShrU, I32WrapI64]);
+                    (fill, 4, STRING, I32Store(7))
+                } else {
+                    (vec![LocalGet(1)], 8, VECTOR, I64Store(7))
+                };
+                let message = self.expected(p, "a length");
+                let mut body = self.fixnum(p, 0);
+                body.extend(&[I64Const(MAX_LENGTH << 3), I64LtU, I32Eqz,
+                              If(BlockType::Empty), I64Const(message), LocalGet(0), self.call(Helper::Fail), End,
+                              // The object, rounded up to a multiple of 8 bytes.
+                              LocalGet(0), I64Const(3), I64ShrU, I32WrapI64, I32Const(size), I32Mul, I32Const(15),
+                              I32Add, I32Const(-8), I32And,
+                              LocalGet(0), I64Const(5), I64Shl, I64Const(kind), I64Or,
+                              self.call(Helper::Alloc), LocalSet(2),
+                              Block(BlockType::Empty), Loop(BlockType::Empty),
+                              LocalGet(3), I64ExtendI32U, I64Const(3), I64Shl, LocalGet(0), I64GeS, BrIf(1),
+                              LocalGet(2), I32WrapI64, LocalGet(3), I32Const(size), I32Mul, I32Add]);
+                body.extend(fill);
+                body.extend(&[store,
+                              LocalGet(3), I32Const(1), I32Add, LocalSet(3), Br(0),
+                              End, End,
+                              LocalGet(2)]);
+                body
+            }
+            Primitive::StringLength | Primitive::VectorLength => {
+                let (kind, what) = if p == Primitive::StringLength { (STRING, "a string") } else { (VECTOR, "a vector") };
+                let mut body = self.checked(p, 0, kind, what);
+                body.extend(&[self.call(Helper::Length), I64Const(3), I64Shl]);
+                body
+            }
+            Primitive::StringRef | Primitive::StringSet => {
+                let mut body = self.checked(p, 0, STRING, "a string");
+                body.extend(&[Drop, LocalGet(0), I32WrapI64]);
+                body.extend(self.index(p, 1));
+                body.extend(&[I32Const(4), I32Mul, I32Add]);
+                if p == Primitive::StringRef {
+                    body.extend(&[I32Load(7), I64ExtendI32U, I64Const(3), I64Shl, I64Const(2), I64Or]);
+                } else {
+                    body.extend(self.character(p, 2));
+                    body.extend(&[I64Const(3), I64ShrU, I32WrapI64, I32Store(7), I64Const(UNSPECIFIED)]);
+                }
+                body
+            }
+            Primitive::IsVector => self.has_type(0, VECTOR),
+            Primitive::VectorRef | Primitive::VectorSet => {
+                let mut body = self.checked(p, 0, VECTOR, "a vector");
+                body.extend(&[Drop, LocalGet(0), I32WrapI64]);
+                body.extend(self.index(p, 1));
+                body.extend(&[I32Const(8), I32Mul, I32Add]);
+                if p == Primitive::VectorRef {
+                    body.push(I64Load(7));
+                } else {
+                    body.extend(&[LocalGet(2), I64Store(7), I64Const(UNSPECIFIED)]);
+                }
+                body
+            }
+            Primitive::IsBytevector => self.has_type(0, BYTEVECTOR),
+            Primitive::BytevectorLength => {
+                let mut body = self.checked(p, 0, BYTEVECTOR, "a bytevector");
+                body.extend(&[self.call(Helper::Length), I64Const(3), I64Shl]);
+                body
+            }
+            Primitive::BytevectorU8Ref => {
+                let mut body = self.checked(p, 0, BYTEVECTOR, "a bytevector");
+                body.extend(&[Drop, LocalGet(0), I32WrapI64]);
+                body.extend(self.index(p, 1));
+                body.extend(&[I32Add, I32Load8U(7), I64ExtendI32U, I64Const(3), I64Shl]);
+                body
+            }
+            Primitive::MakeRecordType => {
+                locals.push(I64);
+                let mut body = vec![I32Const(16), I64Const(RECORD_TYPE), self.call(Helper::Alloc), LocalTee(1),
+                                    I32WrapI64];
+                body.extend(self.checked(p, 0, SYMBOL, "a symbol"));
+                body.extend(&[I64Store(7), LocalGet(1)]);
+                body
+            }
+            Primitive::MakeRecord => {
+                // Counts the fields in the list, then copies them into the
+                // record after its type.
+                locals.extend(&[I64, I64, I32]);
+                let (record, list, count) = (2, 3, 4);
+                let mut body = self.checked(p, 0, RECORD_TYPE, "a record type");
+                body.extend(&[Drop,
+                              LocalGet(1), LocalSet(list),
+                              Block(BlockType::Empty), Loop(BlockType::Empty),
+                              LocalGet(list), I64Const(NIL), I64Eq, BrIf(1),
+                              LocalGet(count), I32Const(1), I32Add, LocalSet(count),
+                              LocalGet(list), I32WrapI64, I64Load(15), LocalSet(list), Br(0),
+                              End, End,
+                              LocalGet(count), I32Const(8), I32Mul, I32Const(16), I32Add,
+                              LocalGet(count), I64ExtendI32U, I64Const(8), I64Shl, I64Const(RECORD), I64Or,
+                              self.call(Helper::Alloc), LocalTee(record),
+                              I32WrapI64, LocalGet(0), I64Store(7),
+                              LocalGet(1), LocalSet(list),
+                              LocalGet(record), I32WrapI64, LocalSet(count),
+                              Block(BlockType::Empty), Loop(BlockType::Empty),
+                              LocalGet(list), I64Const(NIL), I64Eq, BrIf(1),
+                              LocalGet(count), LocalGet(list), I32WrapI64, I64Load(7), I64Store(15),
+                              LocalGet(count), I32Const(8), I32Add, LocalSet(count),
+                              LocalGet(list), I32WrapI64, I64Load(15), LocalSet(list), Br(0),
+                              End, End,
+                              LocalGet(record)]);
+                body
+            }
+            Primitive::IsRecord => {
+                let mut body = self.is_record();
+                body.push(bool);
+                body
+            }
+            Primitive::RecordRef | Primitive::RecordSet => {
+                // The last operand is the message for a value that isn't a
+                // record of the type, which names the type and the field.
+                let message = if p == Primitive::RecordRef { 3 } else { 4 };
+                let mut body = self.is_record();
+                body.extend(&[I32Eqz,
+                              If(BlockType::Empty), LocalGet(message), LocalGet(0), self.call(Helper::Fail), End,
+                              LocalGet(0), I32WrapI64]);
+                body.extend(self.index(p, 2));
+                body.extend(&[I32Const(8), I32Mul, I32Add]);
+                if p == Primitive::RecordRef {
+                    body.push(I64Load(15));
+                } else {
+                    body.extend(&[LocalGet(3), I64Store(15), I64Const(UNSPECIFIED)]);
+                }
+                body
+            }
+            Primitive::RecordName => vec![
+                LocalGet(0), self.call(Helper::TypeOf), I32Const(RECORD as i32), I32Eq,
+                If(BlockType::Value(I64)),
+                LocalGet(0), I32WrapI64, I64Load(7), I32WrapI64, I64Load(7),
+                Else,
+                I64Const(FALSE),
+                End,
+            ],
+            Primitive::IsProcedure => self.has_type(0, CLOSURE),
+            Primitive::WriteChar => {
+                let mut body = self.character(p, 0);
+                body.extend(&[I64Const(3), I64ShrU, I32WrapI64, Call(WRITE_CHAR), I64Const(UNSPECIFIED)]);
+                body
+            }
+            Primitive::WriteFlonum => {
+                let mut body = self.checked(p, 0, FLONUM, "a flonum");
+                body.extend(&[I32WrapI64, F64Load(7), Call(WRITE_FLONUM), I64Const(UNSPECIFIED)]);
+                body
+            }
+            Primitive::Error => vec![LocalGet(0), LocalGet(1), self.call(Helper::Raise), Unreachable],
+            Primitive::Exit => {
+                // `#t` and no status exit with 0, `#f` with 1.
+                let mut body = vec![
+                    LocalGet(0), I64Const(TRUE), I64Eq, LocalGet(0), I64Const(UNSPECIFIED), I64Eq, I32Or,
+                    If(BlockType::Value(I32)),
+                    I32Const(0),
+                    Else,
+                    LocalGet(0), I64Const(FALSE), I64Eq,
+                    If(BlockType::Value(I32)),
+                    I32Const(1),
+                    Else,
+                ];
+                body.extend(self.fixnum(p, 0));
+                body.extend(&[I64Const(3), I64ShrS, I32WrapI64, End, End, Call(EXIT), Unreachable]);
+                body
+            }
+            Primitive::CallWithCurrentContinuation | Primitive::Values | Primitive::CallWithValues =>
+                vec![Unreachable],
+        };
+        self.wasm_function(&vec![I64; params], &[I64], locals, body)
+    }
+
+    /// The dispatcher for calls with `arguments` arguments besides the
+    /// closure, which takes the closure and them.
+    pub fn dispatcher(&mut self, arguments: usize) -> Function {
+        use super::encode::ValType::*;
+        let m = arguments as u32;
+        let (code, arity, list) = (m + 1, m + 2, m + 3);
+        let not_procedure = self.data.message("Expected a procedure to call");
+        let wrong_arguments = self.data.message("Wrong number of arguments to");
+        let exact = self.procedure_type(arguments + 1);
+        let function = [LocalGet(code), I64Const(32), I64ShrU, I32WrapI64];
+        let mut body = vec![
+            LocalGet(0), I32Const(CLOSURE as i32), I64Const(not_procedure), self.call(Helper::Check),
+            I32WrapI64, I64Load(7), LocalTee(code), I32WrapI64, LocalTee(arity),
+            I32Const((m << 1) as i32), I32Eq,
+            If(BlockType::Empty),
+        ];
+        body.extend((0..=m).map(LocalGet));
+        body.extend(&function);
+        body.extend(self.call_indirect(exact));
+        body.push(End);
+        // A procedure with a rest parameter gets the arguments after those
+        // it requires as a list.
+        for required in 0..=m {
+            let with_rest = self.procedure_type(required as usize + 2);
+            body.extend(&[LocalGet(arity), I32Const((required << 1 | 1) as i32), I32Eq, If(BlockType::Empty),
+                          I64Const(NIL), LocalSet(list)]);
+            for argument in (required + 1..=m).rev() {
+                body.extend(&[LocalGet(argument), LocalGet(list), self.call(Helper::Cons), LocalSet(list)]);
+            }
+            body.extend((0..=required).map(LocalGet));
+            body.push(LocalGet(list));
+            body.extend(&function);
+            body.extend(self.call_indirect(with_rest));
+            body.push(End);
+        }
+        body.extend(&[I64Const(wrong_arguments), LocalGet(0), self.call(Helper::Fail)]);
+        self.wasm_function(&vec![I64; arguments + 1], &[], vec![I64, I32, I64], body)
+    }
+
+    /// The function of a procedure in the table that isn't the program's.
+    pub fn extra(&mut self, extra: Extra) -> Function {
+        use super::encode::ValType::*;
+        let (required, rest) = extra.arity();
+        let params = 1 + required + rest as usize;
+        let mut locals = Vec::new();
+        let ret = self.tail_call(1);
+        let body = match extra {
+            Extra::Halt => Vec::new(),
+            Extra::Escape => [&[LocalGet(0), I32WrapI64, I64Load(15), LocalGet(2)], &ret[..]].concat(),
+            Extra::Receiver => {
+                // Calls the consumer with the continuation and the values.
+                locals.extend(&[I64, I64, I64, I32, I32]);
+                let mut body = vec![LocalGet(0), I32WrapI64, I64Load(15), LocalGet(1), self.call(Helper::Cons),
+                                    LocalSet(1),
+                                    LocalGet(0), I32WrapI64, I64Load(23), LocalSet(2)];
+                body.extend(self.spread(2, 1, 3));
+                body
+            }
+            Extra::Primitive(Primitive::Values) => {
+                locals.extend(&[I64, I64, I32, I32]);
+                self.spread(1, 2, 3)
+            }
+            Extra::Primitive(Primitive::CallWithValues) => {
+                // Calls the producer with a receiver holding the
+                // continuation and the consumer.
+                locals.push(I64);
+                let code = self.extra_index(Extra::Receiver);
+                let code = Generator::code(code, 0, true);
+                let mut body = vec![LocalGet(2),
+                                    I32Const(32), I64Const(CLOSURE | 2 << 8), self.call(Helper::Alloc), LocalTee(4),
+                                    I32WrapI64, I64Const(code), I64Store(7),
+                                    LocalGet(4), I32WrapI64, LocalGet(1), I64Store(15),
+                                    LocalGet(4), I32WrapI64, LocalGet(3), I64Store(23),
+                                    LocalGet(4)];
+                body.extend(self.tail_call(1));
+                body
+            }
+            Extra::Primitive(Primitive::CallWithCurrentContinuation) => {
+                // Calls the procedure with an escape procedure for the
+                // continuation.
+                locals.push(I64);
+                let code = self.extra_index(Extra::Escape);
+                let code = Generator::code(code, 2, false);
+                let mut body = vec![LocalGet(2), LocalGet(1),
+                                    I32Const(24), I64Const(CLOSURE | 1 << 8), self.call(Helper::Alloc), LocalTee(3),
+                                    I32WrapI64, I64Const(code), I64Store(7),
+                                    LocalGet(3), I32WrapI64, LocalGet(1), I64Store(15),
+                                    LocalGet(3)];
+                body.extend(self.tail_call(2));
+                body
+            }
+            Extra::Primitive(p) if rest && (p == Primitive::Add || p == Primitive::Multiply ||
+                                            p == Primitive::Subtract) => {
+                // Folds the operation over the arguments.
+                let (list, acc) = (params as u32 - 1, params as u32);
+                locals.push(I64);
+                let (car, cdr, op) = (self.primitive_index(Primitive::Car), self.primitive_index(Primitive::Cdr),
+                                      self.primitive_index(p));
+                let mut body = if p == Primitive::Subtract {
+                    let mut body = vec![LocalGet(list), I64Const(NIL), I64Eq, If(BlockType::Empty),
+                                        LocalGet(1), I64Const(0), LocalGet(2), Call(op)];
+                    body.extend(&ret);
+                    body.extend(&[End, LocalGet(2), LocalSet(acc)]);
+                    body
+                } else {
+                    vec![I64Const(if p == Primitive::Add { 0 } else { 1 << 3 }), LocalSet(acc)]
+                };
+                body.extend(&[Block(BlockType::Empty), Loop(BlockType::Empty),
+                              LocalGet(list), I64Const(NIL), I64Eq, BrIf(1),
+                              LocalGet(acc), LocalGet(list), Call(car), Call(op), LocalSet(acc),
+                              LocalGet(list), Call(cdr), LocalSet(list), Br(0),
+                              End, End,
+                              LocalGet(1), LocalGet(acc)]);
+                body.extend(ret);
+                body
+            }
+            Extra::Primitive(p) if rest && p.arity().1.is_some() => {
+                // Passes the one optional argument, if there is one.
+                let list = params as u32 - 1;
+                let wrong_arguments = self.data.message("Wrong number of arguments to");
+                let (car, cdr) = (self.primitive_index(Primitive::Car), self.primitive_index(Primitive::Cdr));
+                let mut body: Vec<_> = (1..list).map(LocalGet).collect();
+                body.extend(&[LocalGet(list), I64Const(NIL), I64Eq, If(BlockType::Value(I64)),
+                              I64Const(defaults(p)[0]),
+                              Else,
+                              LocalGet(list), Call(cdr), I64Const(NIL), I64Ne,
+                              If(BlockType::Empty), I64Const(wrong_arguments), LocalGet(0), self.call(Helper::Fail), End,
+                              LocalGet(list), Call(car),
+                              End,
+                              Call(self.primitive_index(p))]);
+                body.extend(ret);
+                body
+            }
+            Extra::Primitive(p) => {
+                let mut body: Vec<_> = (1..params as u32).map(LocalGet).collect();
+                body.push(Call(self.primitive_index(p)));
+                body.extend(ret);
+                body
+            }
+        };
+        self.wasm_function(&vec![I64; params], &[], locals, body)
+    }
+
+    /// Instructions that call the closure in the local `callee` with the
+    /// elements of the list in the local `list` as its arguments, in tail
+    /// position. Like a dispatcher, but for a number of arguments only known
+    /// at run time, so with a branch for each arity of a procedure in the
+    /// table. They use the four locals from `scratch` on, two `i64`s and
+    /// then two `i32`s.
+    fn spread(&mut self, callee: u32, list: u32, scratch: u32) -> Vec<Instr> {
+        let (code, cursor, arity, count) = (scratch, scratch + 1, scratch + 2, scratch + 3);
+        let not_procedure = self.data.message("Expected a procedure to call");
+        let wrong_arguments = self.data.message("Wrong number of arguments to");
+        let mut arities: BTreeSet<_> = self.arities.iter().cloned().collect();
+        arities.extend(&[Extra::Halt.arity(), Extra::Escape.arity(), Extra::Receiver.arity()]);
+        arities.extend(Primitive::ALL.iter().map(|&p| wrapper_arity(p)));
+        let function = [LocalGet(code), I64Const(32), I64ShrU, I32WrapI64];
+        let mut body = vec![
+            LocalGet(callee), I32Const(CLOSURE as i32), I64Const(not_procedure), self.call(Helper::Check),
+            I32WrapI64, I64Load(7), LocalTee(code), I32WrapI64, LocalSet(arity),
+            LocalGet(list), LocalSet(cursor),
+            Block(BlockType::Empty), Loop(BlockType::Empty),
+            LocalGet(cursor), I64Const(NIL), I64Eq, BrIf(1),
+            LocalGet(count), I32Const(1), I32Add, LocalSet(count),
+            LocalGet(cursor), I32WrapI64, I64Load(15), LocalSet(cursor), Br(0),
+            End, End,
+        ];
+        for (required, rest) in arities {
+            let n = required as i32;
+            body.extend(&[LocalGet(arity), I32Const(n << 1 | rest as i32), I32Eq]);
+            body.extend(match rest {
+                true => [I32Const(n), LocalGet(count), I32LeU],
+                false => [LocalGet(count), I32Const(n), I32Eq],
+            }.iter());
+            body.extend(&[I32And, If(BlockType::Empty), LocalGet(callee), LocalGet(list), LocalSet(cursor)]);
+            for _ in 0..required {
+                body.extend(&[LocalGet(cursor), I32WrapI64, I64Load(7),
+                              LocalGet(cursor), I32WrapI64, I64Load(15), LocalSet(cursor)]);
+            }
+            if rest {
+                body.push(LocalGet(cursor));
+            }
+            body.extend(&function);
+            let type_index = self.procedure_type(1 + required + rest as usize);
+            body.extend(self.call_indirect(type_index));
+            body.push(End);
+        }
+        body.extend(&[I64Const(wrong_arguments), LocalGet(callee), self.call(Helper::Fail)]);
+        body
+    }
+
+    /// The driver loop of the trampoline, which makes the call in the
+    /// registers, with the dispatcher for its arguments, until the function
+    /// called returns without leaving another.
+    pub fn driver(&mut self) -> Function {
+        use super::encode::ValType::*;
+        let mut body = vec![
+            Block(BlockType::Empty), Loop(BlockType::Empty),
+            GlobalGet(CALLEE), LocalTee(0), I64Eqz, BrIf(1),
+            I64Const(0), GlobalSet(CALLEE),
+        ];
+        for arguments in 0..self.dispatchers {
+            body.extend(&[GlobalGet(ARGUMENT_COUNT), I64Const(arguments as i64), I64Eq, If(BlockType::Empty),
+                          LocalGet(0)]);
+            body.extend((0..arguments as u32).map(|i| GlobalGet(ARGUMENTS + i)));
+            body.extend(&[Call(self.dispatcher_index(arguments)), Br(1), End]);
+        }
+        body.extend(&[Unreachable, End, End]);
+        self.wasm_function(&[], &[], vec![I64], body)
+    }
+}